@@ -0,0 +1,517 @@
+//! Generates `rust_fst/_native.pyi` from the `#[pyclass]`, `#[pymethods]`,
+//! `#[pyfunction]` and `create_exception!` items in `src/`.
+//!
+//! Python types are derived from the Rust signatures. Where the Rust type is
+//! too loose (`PyObject`, `&PyAny`, ...), a `:type name: ...` or `:rtype: ...`
+//! line in the doc comment overrides it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use quote::ToTokens;
+use syn::parse::{ParseStream, Parser};
+use syn::{
+    Attribute, Expr, FnArg, GenericArgument, Ident, ImplItem, Item, ItemImpl, Lit, Meta, Pat,
+    PathArguments, ReturnType, Token, Type,
+};
+
+const HEADER: &str = "# This file is generated by rust/build.rs from the Rust sources. Do not edit.
+from types import NotImplementedType
+from typing import Any, Awaitable, BinaryIO, Callable, Dict, FrozenSet, Iterable, Iterator, List, Optional, Tuple, Union
+";
+
+#[derive(Default)]
+struct Class {
+    name: String,
+    base: Option<String>,
+    methods: Vec<Method>,
+}
+
+struct Method {
+    name: String,
+    decorator: Option<&'static str>,
+    params: Vec<String>,
+    ret: String,
+}
+
+#[derive(Default)]
+struct Stubs {
+    classes: Vec<Class>,
+    functions: Vec<Method>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    // PyPy exports the C API under `PyPy`-prefixed names; see `buffer.rs`.
+    println!("cargo::rustc-check-cfg=cfg(PyPy)");
+    if pyo3_build_config::get().implementation.is_pypy() {
+        println!("cargo:rustc-cfg=PyPy");
+    }
+    let stub_path = Path::new("../rust_fst/_native.pyi");
+    if !stub_path.parent().is_some_and(Path::exists) {
+        return;
+    }
+
+    let mut files: Vec<_> = fs::read_dir("src")
+        .expect("read src/")
+        .map(|entry| entry.expect("read src/ entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .collect();
+    files.sort();
+
+    let mut stubs = Stubs::default();
+    let mut impls = Vec::new();
+    for path in &files {
+        let source = fs::read_to_string(path).expect("read source file");
+        let file = syn::parse_file(&source).expect("parse source file");
+        for item in file.items {
+            match item {
+                Item::Struct(item) if has_attr(&item.attrs, "pyclass") => {
+                    stubs.classes.push(Class {
+                        name: item.ident.to_string(),
+                        ..Class::default()
+                    });
+                }
+                Item::Impl(item) if has_attr(&item.attrs, "pymethods") => impls.push((item, None)),
+                Item::Fn(item) if has_attr(&item.attrs, "pyfunction") => {
+                    let docs = DocTypes::new(&item.attrs);
+                    stubs
+                        .functions
+                        .push(method(&item.sig, &item.attrs, &docs, None));
+                }
+                Item::Macro(item) if item.mac.path.is_ident("stream_class") => {
+                    let (kind, item) = stream_class
+                        .parse2(item.mac.tokens)
+                        .expect("parse stream_class!");
+                    impls.push((item, Some(kind)));
+                }
+                Item::Macro(item) if item.mac.path.is_ident("create_exception") => {
+                    let args: Vec<String> = item
+                        .mac
+                        .tokens
+                        .to_string()
+                        .split(',')
+                        .map(|arg| arg.trim().to_string())
+                        .collect();
+                    let base = match args[2].as_str() {
+                        "PyValueError" => "ValueError".to_string(),
+                        other => other.to_string(),
+                    };
+                    stubs.classes.push(Class {
+                        name: args[1].clone(),
+                        base: Some(base),
+                        methods: Vec::new(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (item, stream_kind) in impls {
+        let class_name = item.self_ty.to_token_stream().to_string();
+        let Some(class) = stubs.classes.iter_mut().find(|c| c.name == class_name) else {
+            continue;
+        };
+        for (name, ret) in stream_kind.as_deref().map_or(Vec::new(), stream_methods) {
+            class.methods.push(Method {
+                name: name.to_string(),
+                decorator: None,
+                params: vec!["self".to_string()],
+                ret: ret.replace("Self", &class_name),
+            });
+        }
+        for item in &item.items {
+            if let ImplItem::Fn(func) = item {
+                let docs = DocTypes::new(&func.attrs);
+                let method = method(&func.sig, &func.attrs, &docs, Some(&class_name));
+                // A no-op `__init__` next to `#[new]` only exists for subclasses.
+                if !class.methods.iter().any(|m| m.name == method.name) {
+                    class.methods.push(method);
+                }
+            }
+        }
+        if class.base.is_none() {
+            if let Some(next) = class.methods.iter().find(|m| m.name == "__next__") {
+                class.base = Some(format!("Iterator[{}]", next.ret));
+            }
+        }
+    }
+
+    let rendered = stubs.render();
+    if fs::read_to_string(stub_path).ok().as_deref() != Some(rendered.as_str()) {
+        fs::write(stub_path, rendered).expect("write stub file");
+    }
+}
+
+/// The kind and the `impl` block of a `stream_class!` invocation.
+fn stream_class(input: ParseStream) -> syn::Result<(String, ItemImpl)> {
+    let kind = if input.peek(Token![impl]) {
+        String::new()
+    } else {
+        input.parse::<Ident>()?.to_string()
+    };
+    Ok((kind, input.parse()?))
+}
+
+/// The methods `stream_class!` in `src/stream.rs` adds to a stream of `kind`,
+/// as names and return types.
+fn stream_methods(kind: &str) -> Vec<(&'static str, &'static str)> {
+    let mut methods = vec![
+        ("__iter__", "Self"),
+        ("__length_hint__", "Union[int, NotImplementedType]"),
+    ];
+    match kind {
+        "" => {}
+        "raw" => methods.push(("raw", "Self")),
+        "keys" => methods.extend([("raw", "Self"), ("to_list", "List[Union[str, bytes]]")]),
+        "items" => methods.extend([
+            ("raw", "Self"),
+            ("to_dict", "Dict[Union[str, bytes], Union[int, float]]"),
+        ]),
+        other => panic!("unknown stream kind {:?}", other),
+    }
+    methods
+}
+
+impl Stubs {
+    fn render(&self) -> String {
+        let mut out = String::from(HEADER);
+        let names: Vec<String> = self
+            .classes
+            .iter()
+            .map(|c| format!("{:?}", c.name))
+            .chain(self.functions.iter().map(|f| format!("{:?}", f.name)))
+            .collect();
+        out.push_str(&format!("\n__all__ = [{}]\n", names.join(", ")));
+        for class in &self.classes {
+            out.push_str(&format!("\nclass {}", class.name));
+            if let Some(base) = &class.base {
+                out.push_str(&format!("({})", base));
+            }
+            if class.methods.is_empty() {
+                out.push_str(": ...\n");
+                continue;
+            }
+            out.push_str(":\n");
+            for method in &class.methods {
+                out.push_str(&method.render("    "));
+            }
+        }
+        for function in &self.functions {
+            out.push('\n');
+            out.push_str(&function.render(""));
+        }
+        out
+    }
+}
+
+impl Method {
+    fn render(&self, indent: &str) -> String {
+        let mut out = String::new();
+        if let Some(decorator) = self.decorator {
+            out.push_str(&format!("{}@{}\n", indent, decorator));
+        }
+        out.push_str(&format!(
+            "{}def {}({}) -> {}: ...\n",
+            indent,
+            self.name,
+            self.params.join(", "),
+            self.ret
+        ));
+        out
+    }
+}
+
+/// Type overrides given as `:type name: ...` / `:rtype: ...` doc comment lines.
+struct DocTypes {
+    params: HashMap<String, String>,
+    ret: Option<String>,
+}
+
+impl DocTypes {
+    fn new(attrs: &[Attribute]) -> Self {
+        let mut types = DocTypes {
+            params: HashMap::new(),
+            ret: None,
+        };
+        for attr in attrs.iter().filter(|a| a.path().is_ident("doc")) {
+            let Meta::NameValue(meta) = &attr.meta else {
+                continue;
+            };
+            let Expr::Lit(lit) = &meta.value else {
+                continue;
+            };
+            let Lit::Str(line) = &lit.lit else {
+                continue;
+            };
+            let line = line.value();
+            let line = line.trim();
+            if let Some(ret) = line.strip_prefix(":rtype:") {
+                types.ret = Some(ret.trim().to_string());
+            } else if let Some(rest) = line.strip_prefix(":type ") {
+                if let Some((name, ty)) = rest.split_once(':') {
+                    types
+                        .params
+                        .insert(name.trim().to_string(), ty.trim().to_string());
+                }
+            }
+        }
+        types
+    }
+}
+
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .any(|a| a.path().segments.last().is_some_and(|s| s.ident == name))
+}
+
+/// The `key = value` pairs and the `signature` of `#[pyo3(...)]` attributes.
+fn pyo3_options(attrs: &[Attribute]) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut signature = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("pyo3")) {
+        let tokens = attr.meta.require_list().expect("pyo3(...)").tokens.clone();
+        let text = tokens.to_string();
+        for option in split_top_level(&text) {
+            if let Some((key, value)) = option.split_once('=') {
+                match key.trim() {
+                    "name" => name = Some(value.trim().trim_matches('"').to_string()),
+                    "signature" => {
+                        let value = value.trim();
+                        signature = Some(value[1..value.len() - 1].to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    (name, signature)
+}
+
+fn split_top_level(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for ch in text.chars() {
+        match ch {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+fn python_default(value: &str) -> String {
+    match value.replace(' ', "").as_str() {
+        "true" => "True".to_string(),
+        "false" => "False".to_string(),
+        "None" => "None".to_string(),
+        other if other.starts_with('"') => other.replace('"', "'"),
+        other => other.to_string(),
+    }
+}
+
+fn method(
+    sig: &syn::Signature,
+    attrs: &[Attribute],
+    docs: &DocTypes,
+    class: Option<&str>,
+) -> Method {
+    let (rename, signature) = pyo3_options(attrs);
+    let is_new = has_attr(attrs, "new");
+    let decorator = if has_attr(attrs, "staticmethod") {
+        Some("staticmethod")
+    } else if has_attr(attrs, "classmethod") {
+        Some("classmethod")
+    } else if has_attr(attrs, "getter") {
+        Some("property")
+    } else {
+        None
+    };
+    let name = if is_new {
+        "__init__".to_string()
+    } else {
+        rename.unwrap_or_else(|| sig.ident.to_string())
+    };
+
+    // Python-visible arguments with their Rust types.
+    let mut args: Vec<(String, &Type)> = Vec::new();
+    let mut has_receiver = false;
+    for (i, input) in sig.inputs.iter().enumerate() {
+        match input {
+            FnArg::Receiver(_) => has_receiver = true,
+            FnArg::Typed(arg) => {
+                let Pat::Ident(ident) = &*arg.pat else {
+                    continue;
+                };
+                let arg_name = ident.ident.to_string();
+                let ty_text = arg.ty.to_token_stream().to_string();
+                let is_slf = i == 0 && (arg_name == "slf" || ty_text.contains("Self"));
+                let is_cls = i == 0 && decorator == Some("classmethod");
+                if is_slf {
+                    has_receiver = true;
+                } else if is_cls || ty_text.starts_with("Python") {
+                    continue;
+                } else {
+                    args.push((arg_name, &arg.ty));
+                }
+            }
+        }
+    }
+
+    let mut params = Vec::new();
+    if decorator == Some("classmethod") {
+        params.push("cls".to_string());
+    } else if has_receiver || is_new {
+        params.push("self".to_string());
+    }
+    let arg_type = |name: &str| -> String {
+        if let Some(ty) = docs.params.get(name) {
+            return ty.clone();
+        }
+        args.iter()
+            .find(|(arg, _)| arg == name)
+            .map_or("Any".to_string(), |(_, ty)| py_type(ty, class))
+    };
+    match signature {
+        Some(signature) => {
+            for param in split_top_level(&signature) {
+                let param = param.replace(' ', "");
+                if param == "*" || param == "/" {
+                    params.push(param);
+                } else if let Some(name) = param.strip_prefix("**") {
+                    params.push(format!("**{}: {}", name, arg_type(name)));
+                } else if let Some(name) = param.strip_prefix('*') {
+                    params.push(format!("*{}: {}", name, arg_type(name)));
+                } else if let Some((name, default)) = param.split_once('=') {
+                    params.push(format!(
+                        "{}: {} = {}",
+                        name,
+                        arg_type(name),
+                        python_default(default)
+                    ));
+                } else {
+                    params.push(format!("{}: {}", param, arg_type(&param)));
+                }
+            }
+        }
+        None => {
+            // PyO3 makes trailing `Option` arguments default to `None`.
+            let required = args
+                .iter()
+                .rposition(|(_, ty)| !py_type(ty, class).starts_with("Optional["))
+                .map_or(0, |i| i + 1);
+            for (i, (arg, _)) in args.iter().enumerate() {
+                if i < required {
+                    params.push(format!("{}: {}", arg, arg_type(arg)));
+                } else {
+                    params.push(format!("{}: {} = None", arg, arg_type(arg)));
+                }
+            }
+        }
+    }
+
+    let ret = if is_new {
+        "None".to_string()
+    } else if let Some(ret) = &docs.ret {
+        ret.clone()
+    } else {
+        match &sig.output {
+            ReturnType::Default => "None".to_string(),
+            ReturnType::Type(_, ty) if name == "__next__" => {
+                strip_option(&py_type(ty, class)).to_string()
+            }
+            ReturnType::Type(_, ty) => py_type(ty, class),
+        }
+    };
+    Method {
+        name,
+        decorator,
+        params,
+        ret,
+    }
+}
+
+fn strip_option(ty: &str) -> &str {
+    ty.strip_prefix("Optional[")
+        .and_then(|ty| ty.strip_suffix(']'))
+        .unwrap_or(ty)
+}
+
+fn generic_args(args: &PathArguments) -> Vec<&Type> {
+    match args {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn py_type(ty: &Type, class: Option<&str>) -> String {
+    match ty {
+        Type::Reference(reference) => py_type(&reference.elem, class),
+        Type::Slice(slice) if slice.elem.to_token_stream().to_string() == "u8" => {
+            "bytes".to_string()
+        }
+        Type::Tuple(tuple) if tuple.elems.is_empty() => "None".to_string(),
+        Type::Tuple(tuple) => {
+            let elems: Vec<String> = tuple.elems.iter().map(|t| py_type(t, class)).collect();
+            format!("Tuple[{}]", elems.join(", "))
+        }
+        Type::Path(path) => {
+            let segment = path.path.segments.last().expect("type path");
+            let args = generic_args(&segment.arguments);
+            let arg = |i: usize| {
+                args.get(i)
+                    .map_or("Any".to_string(), |ty| py_type(ty, class))
+            };
+            match segment.ident.to_string().as_str() {
+                "PyResult" | "Py" | "PyRef" | "PyRefMut" | "Bound" | "Cow" | "Box" | "Arc" => {
+                    arg(0)
+                }
+                "Option" => format!("Optional[{}]", arg(0)),
+                "Vec" if arg(0) == "int" && args[0].to_token_stream().to_string() == "u8" => {
+                    "bytes".to_string()
+                }
+                "Vec" => format!("List[{}]", arg(0)),
+                "HashMap" | "BTreeMap" => format!("Dict[{}, {}]", arg(0), arg(1)),
+                "Self" => class.unwrap_or("Any").to_string(),
+                "String" | "str" | "PyString" => "str".to_string(),
+                "bool" => "bool".to_string(),
+                "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize"
+                | "PyLong" => "int".to_string(),
+                "f32" | "f64" | "PyFloat" => "float".to_string(),
+                "PyBytes" => "bytes".to_string(),
+                "PyDict" => "Dict[Any, Any]".to_string(),
+                "PyList" => "List[Any]".to_string(),
+                "PyTuple" => "Tuple[Any, ...]".to_string(),
+                "PyFrozenSet" => "FrozenSet[Any]".to_string(),
+                "PyType" => "type".to_string(),
+                "PyCapsule" => "Any".to_string(),
+                "Key" | "KeyObject" => "Union[str, bytes]".to_string(),
+                "PyObject" | "PyAny" => "Any".to_string(),
+                other if other.chars().next().is_some_and(char::is_uppercase) => other.to_string(),
+                _ => "Any".to_string(),
+            }
+        }
+        _ => "Any".to_string(),
+    }
+}
@@ -0,0 +1,42 @@
+//! Support for the `a*` methods, which run blocking FST work off the event
+//! loop and hand the result back as an awaitable.
+
+use pyo3::prelude::*;
+use pyo3::types::PyCFunction;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::trace;
+
+/// Schedule `work` on the running event loop's default executor and return
+/// the resulting future, which resolves to `convert(result)`. `work` runs
+/// without the GIL; the elapsed time is reported to the trace hook as `op`.
+pub fn run<'py, T, W, C>(
+    py: Python<'py>,
+    op: &'static str,
+    work: W,
+    convert: C,
+) -> PyResult<&'py PyAny>
+where
+    T: Send + 'static,
+    W: FnOnce() -> PyResult<T> + Send + 'static,
+    C: FnOnce(Python, T) -> PyResult<(PyObject, usize)> + Send + 'static,
+{
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    // The executor calls the job exactly once, but closures have to be `Fn`.
+    let job = Mutex::new(Some((work, convert)));
+    let call = PyCFunction::new_closure(py, None, None, move |args, _| -> PyResult<PyObject> {
+        let py = args.py();
+        let (work, convert) = job
+            .lock()
+            .unwrap()
+            .take()
+            .expect("executor job called twice");
+        let start = Instant::now();
+        let value = py.allow_threads(work)?;
+        let (value, count) = convert(py, value)?;
+        trace::emit(py, op, start.elapsed(), count)?;
+        Ok(value)
+    })?;
+    event_loop.call_method1("run_in_executor", (py.None(), call))
+}
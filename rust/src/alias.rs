@@ -0,0 +1,90 @@
+//! Lookups through a map of aliases to canonical ids.
+
+use fst::{Map as FstMap, Streamer};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::map::{Map, MapData};
+use crate::util::Key;
+
+/// A Map of canonical keys to ids together with a Map of aliases to the same
+/// ids, looked up as one: a key is found under its canonical name or any of
+/// its aliases, with canonical names taking precedence.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct AliasedMap {
+    main: FstMap<MapData>,
+    aliases: FstMap<MapData>,
+    /// The canonical key of every id, built on the first `canonicalize`.
+    canonical: OnceLock<HashMap<u64, Vec<u8>>>,
+}
+
+impl AliasedMap {
+    fn lookup(&self, key: &[u8]) -> Option<u64> {
+        self.main.get(key).or_else(|| self.aliases.get(key))
+    }
+
+    /// Map every id to the first key of the main Map holding it.
+    fn canonical_keys(&self) -> HashMap<u64, Vec<u8>> {
+        let mut keys = HashMap::with_capacity(self.main.len());
+        let mut stream = self.main.stream();
+        while let Some((key, id)) = stream.next() {
+            keys.entry(id).or_insert_with(|| key.to_vec());
+        }
+        keys
+    }
+
+    /// The index of `canonical_keys`, built on the first call. It is built
+    /// with the GIL released outside the cell, since a thread blocked in
+    /// `get_or_init` would hold the GIL the building thread needs back;
+    /// threads racing here each build it and the first one stored is kept.
+    fn canonical_index(&self, py: Python) -> &HashMap<u64, Vec<u8>> {
+        if let Some(index) = self.canonical.get() {
+            return index;
+        }
+        let built = py.allow_threads(|| self.canonical_keys());
+        self.canonical.get_or_init(|| built)
+    }
+}
+
+#[pymethods]
+impl AliasedMap {
+    #[new]
+    fn new(main: PyRef<Map>, aliases: PyRef<Map>) -> PyResult<Self> {
+        main.check_open()?;
+        aliases.check_open()?;
+        main.value_type.require_u64("AliasedMap()")?;
+        aliases.value_type.require_u64("AliasedMap()")?;
+        Ok(AliasedMap {
+            main: main.inner.clone(),
+            aliases: aliases.inner.clone(),
+            canonical: OnceLock::new(),
+        })
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.lookup(key.as_bytes()).is_some()
+    }
+
+    fn __getitem__(&self, key: Key) -> PyResult<u64> {
+        self.lookup(key.as_bytes()).ok_or_else(|| key.key_error())
+    }
+
+    fn get(&self, key: Key, default: Option<u64>) -> Option<u64> {
+        self.lookup(key.as_bytes()).or(default)
+    }
+
+    /// The canonical key for `key`: `key` itself if it is canonical, the key
+    /// of the main Map with its id if it is an alias, or `None`. Resolving an
+    /// alias the first time indexes all canonical keys by id, in memory.
+    fn canonicalize(&self, py: Python, key: Key) -> Option<String> {
+        let key = key.as_bytes();
+        if self.main.contains_key(key) {
+            return Some(String::from_utf8_lossy(key).into_owned());
+        }
+        let id = self.aliases.get(key)?;
+        self.canonical_index(py)
+            .get(&id)
+            .map(|key| String::from_utf8_lossy(key).into_owned())
+    }
+}
@@ -0,0 +1,473 @@
+//! A minimal implementation of the Arrow C stream interface, enough to hand
+//! string columns to pyarrow, polars and other consumers of
+//! `__arrow_c_stream__` without a dependency on the arrow crates.
+//!
+//! See <https://arrow.apache.org/docs/format/CStreamInterface.html>.
+
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::ptr;
+use std::sync::Arc;
+
+use fst::raw::Fst;
+use fst::{IntoStreamer, Streamer};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+
+use crate::lossy::LossyLog;
+use crate::values::{self, ValueType};
+
+/// Keys per exported chunk.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// The errno reported when producing a chunk fails.
+const EIO: c_int = 5;
+
+#[repr(C)]
+pub struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut ArrowSchema,
+    dictionary: *mut ArrowSchema,
+    release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut ArrowArray,
+    dictionary: *mut ArrowArray,
+    release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ArrowArrayStream {
+    get_schema: Option<unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowSchema) -> c_int>,
+    get_next: Option<unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowArray) -> c_int>,
+    get_last_error: Option<unsafe extern "C" fn(*mut ArrowArrayStream) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut ArrowArrayStream)>,
+    private_data: *mut c_void,
+}
+
+// The stream only carries owned data behind its private pointer, and the
+// consumer is required to serialize calls on it.
+unsafe impl Send for ArrowArrayStream {}
+
+/// The type of an exported column.
+pub enum DataType {
+    Utf8,
+    LargeUtf8,
+    UInt64,
+    Int64,
+    Float64,
+    Struct(Vec<(&'static str, DataType)>),
+}
+
+impl DataType {
+    fn format(&self) -> &'static str {
+        match self {
+            DataType::Utf8 => "u",
+            DataType::LargeUtf8 => "U",
+            DataType::UInt64 => "L",
+            DataType::Int64 => "l",
+            DataType::Float64 => "g",
+            DataType::Struct(_) => "+s",
+        }
+    }
+}
+
+/// An owned column, exported as one Arrow array.
+pub enum Column {
+    Utf8 { offsets: Vec<i32>, data: Vec<u8> },
+    LargeUtf8 { offsets: Vec<i64>, data: Vec<u8> },
+    UInt64(Vec<u64>),
+    Int64(Vec<i64>),
+    Float64(Vec<f64>),
+    Struct { len: usize, children: Vec<Column> },
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Utf8 { offsets, .. } => offsets.len() - 1,
+            Column::LargeUtf8 { offsets, .. } => offsets.len() - 1,
+            Column::UInt64(values) => values.len(),
+            Column::Int64(values) => values.len(),
+            Column::Float64(values) => values.len(),
+            Column::Struct { len, .. } => *len,
+        }
+    }
+
+    fn buffers(&self) -> Vec<*const c_void> {
+        match self {
+            Column::Utf8 { offsets, data } => vec![
+                ptr::null(),
+                offsets.as_ptr() as *const c_void,
+                data.as_ptr() as *const c_void,
+            ],
+            Column::LargeUtf8 { offsets, data } => vec![
+                ptr::null(),
+                offsets.as_ptr() as *const c_void,
+                data.as_ptr() as *const c_void,
+            ],
+            Column::UInt64(values) => vec![ptr::null(), values.as_ptr() as *const c_void],
+            Column::Int64(values) => vec![ptr::null(), values.as_ptr() as *const c_void],
+            Column::Float64(values) => vec![ptr::null(), values.as_ptr() as *const c_void],
+            Column::Struct { .. } => vec![ptr::null()],
+        }
+    }
+}
+
+/// Produces the chunks of an exported stream.
+pub trait ChunkSource: Send {
+    fn data_type(&self) -> DataType;
+    fn next_chunk(&mut self) -> Option<Column>;
+}
+
+/// Walks an FST in chunks, resuming after the last visited key each time so
+/// that no stream has to outlive a single call.
+struct Chunker<D> {
+    fst: Fst<D>,
+    after: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<D: AsRef<[u8]>> Chunker<D> {
+    fn new(fst: Fst<D>) -> Self {
+        Chunker {
+            fst,
+            after: None,
+            done: false,
+        }
+    }
+
+    /// Feed the next keys to `push` until it reports the chunk as full.
+    /// Returns `false` once the FST is exhausted and nothing was pushed.
+    fn next(&mut self, mut push: impl FnMut(&[u8], u64) -> bool) -> bool {
+        if self.done {
+            return false;
+        }
+        let mut range = self.fst.range();
+        if let Some(after) = &self.after {
+            range = range.gt(after);
+        }
+        let mut stream = range.into_stream();
+        let mut pushed = false;
+        while let Some((key, out)) = stream.next() {
+            pushed = true;
+            if push(key, out.value()) {
+                self.after = Some(key.to_vec());
+                return true;
+            }
+        }
+        self.done = true;
+        pushed || self.after.is_none()
+    }
+}
+
+/// The keys of an FST as chunks of a string array. Keys that are not valid
+/// UTF-8 are counted in `log`, since chunks are read without the GIL.
+pub struct KeyChunks<D> {
+    chunker: Chunker<D>,
+    log: Arc<LossyLog>,
+}
+
+impl<D: AsRef<[u8]>> KeyChunks<D> {
+    pub fn new(fst: Fst<D>, log: Arc<LossyLog>) -> Self {
+        KeyChunks {
+            chunker: Chunker::new(fst),
+            log,
+        }
+    }
+}
+
+impl<D: AsRef<[u8]> + Send> ChunkSource for KeyChunks<D> {
+    fn data_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn next_chunk(&mut self) -> Option<Column> {
+        let mut offsets = vec![0i32];
+        let mut data = Vec::new();
+        let log = &self.log;
+        let more = self.chunker.next(|key, _| {
+            data.extend_from_slice(log.decode(key).as_bytes());
+            offsets.push(data.len() as i32);
+            offsets.len() > CHUNK_LEN || data.len() > (i32::MAX as usize) / 2
+        });
+        more.then_some(Column::Utf8 { offsets, data })
+    }
+}
+
+/// The items of a Map's FST as batches of `(key: large_string, value)`,
+/// with a `uint64`, `int64` or `float64` value as its `value_type` says.
+/// Like `KeyChunks`, it counts lossy keys in `log`.
+pub struct ItemChunks<D> {
+    chunker: Chunker<D>,
+    batch_size: usize,
+    value_type: ValueType,
+    log: Arc<LossyLog>,
+}
+
+impl<D: AsRef<[u8]>> ItemChunks<D> {
+    pub fn new(fst: Fst<D>, batch_size: usize, value_type: ValueType, log: Arc<LossyLog>) -> Self {
+        ItemChunks {
+            chunker: Chunker::new(fst),
+            batch_size: batch_size.max(1),
+            value_type,
+            log,
+        }
+    }
+}
+
+impl<D: AsRef<[u8]> + Send> ChunkSource for ItemChunks<D> {
+    fn data_type(&self) -> DataType {
+        DataType::Struct(vec![
+            ("key", DataType::LargeUtf8),
+            (
+                "value",
+                match self.value_type {
+                    ValueType::U64 => DataType::UInt64,
+                    ValueType::I64 => DataType::Int64,
+                    ValueType::F64 => DataType::Float64,
+                },
+            ),
+        ])
+    }
+
+    fn next_chunk(&mut self) -> Option<Column> {
+        let mut offsets = vec![0i64];
+        let mut data = Vec::new();
+        let mut values = Vec::new();
+        let batch_size = self.batch_size;
+        let log = &self.log;
+        let more = self.chunker.next(|key, value| {
+            data.extend_from_slice(log.decode(key).as_bytes());
+            offsets.push(data.len() as i64);
+            values.push(value);
+            values.len() >= batch_size
+        });
+        more.then(|| Column::Struct {
+            len: values.len(),
+            children: vec![
+                Column::LargeUtf8 { offsets, data },
+                self.value_column(values),
+            ],
+        })
+    }
+}
+
+impl<D> ItemChunks<D> {
+    /// The column of the values stored as the outputs `values`.
+    fn value_column(&self, values: Vec<u64>) -> Column {
+        match self.value_type {
+            ValueType::U64 => Column::UInt64(values),
+            ValueType::I64 => Column::Int64(values.into_iter().map(values::decode_i64).collect()),
+            ValueType::F64 => Column::Float64(values.into_iter().map(values::decode_f64).collect()),
+        }
+    }
+}
+
+/// A one-shot Arrow stream, for handing to consumers that expect an object
+/// with `__arrow_c_stream__` rather than a capsule.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct ArrowStream {
+    source: Option<Box<dyn ChunkSource>>,
+}
+
+impl ArrowStream {
+    pub fn new(source: Box<dyn ChunkSource>) -> Self {
+        ArrowStream {
+            source: Some(source),
+        }
+    }
+}
+
+#[pymethods]
+impl ArrowStream {
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &mut self,
+        py: Python<'py>,
+        requested_schema: Option<&PyAny>,
+    ) -> PyResult<&'py PyCapsule> {
+        let _ = requested_schema;
+        let source = self
+            .source
+            .take()
+            .ok_or_else(|| PyValueError::new_err("Arrow stream already consumed"))?;
+        stream_capsule(py, source)
+    }
+}
+
+/// A `arrow_array_stream` PyCapsule over the chunks of `source`.
+pub fn stream_capsule<'py>(
+    py: Python<'py>,
+    source: Box<dyn ChunkSource>,
+) -> PyResult<&'py PyCapsule> {
+    let private = Box::new(StreamPrivate {
+        source,
+        error: None,
+    });
+    let stream = ArrowArrayStream {
+        get_schema: Some(stream_get_schema),
+        get_next: Some(stream_get_next),
+        get_last_error: Some(stream_get_last_error),
+        release: Some(stream_release),
+        private_data: Box::into_raw(private) as *mut c_void,
+    };
+    let name = CString::new("arrow_array_stream").unwrap();
+    PyCapsule::new_with_destructor(py, stream, Some(name), |mut stream, _| {
+        // Consumers move the stream out and mark it released; release it
+        // here only if nobody did.
+        if let Some(release) = stream.release {
+            unsafe { release(&mut stream) };
+        }
+    })
+}
+
+struct StreamPrivate {
+    source: Box<dyn ChunkSource>,
+    error: Option<CString>,
+}
+
+unsafe extern "C" fn stream_get_schema(
+    stream: *mut ArrowArrayStream,
+    out: *mut ArrowSchema,
+) -> c_int {
+    let private = &*((*stream).private_data as *const StreamPrivate);
+    out.write(export_schema(&private.source.data_type(), ""));
+    0
+}
+
+unsafe extern "C" fn stream_get_next(stream: *mut ArrowArrayStream, out: *mut ArrowArray) -> c_int {
+    let private = &mut *((*stream).private_data as *mut StreamPrivate);
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| private.source.next_chunk())) {
+        Ok(Some(column)) => out.write(export_array(column)),
+        // A released array marks the end of the stream.
+        Ok(None) => out.write(ArrowArray {
+            length: 0,
+            null_count: 0,
+            offset: 0,
+            n_buffers: 0,
+            n_children: 0,
+            buffers: ptr::null_mut(),
+            children: ptr::null_mut(),
+            dictionary: ptr::null_mut(),
+            release: None,
+            private_data: ptr::null_mut(),
+        }),
+        Err(_) => {
+            private.error = CString::new("panic while reading the FST").ok();
+            return EIO;
+        }
+    }
+    0
+}
+
+unsafe extern "C" fn stream_get_last_error(stream: *mut ArrowArrayStream) -> *const c_char {
+    let private = &*((*stream).private_data as *const StreamPrivate);
+    private.error.as_ref().map_or(ptr::null(), |e| e.as_ptr())
+}
+
+unsafe extern "C" fn stream_release(stream: *mut ArrowArrayStream) {
+    drop(Box::from_raw((*stream).private_data as *mut StreamPrivate));
+    (*stream).release = None;
+}
+
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut ArrowSchema>,
+}
+
+fn export_schema(data_type: &DataType, name: &str) -> ArrowSchema {
+    let children = match data_type {
+        DataType::Struct(fields) => fields
+            .iter()
+            .map(|(name, field)| Box::into_raw(Box::new(export_schema(field, name))))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let mut private = Box::new(SchemaPrivate {
+        format: CString::new(data_type.format()).unwrap(),
+        name: CString::new(name).unwrap(),
+        children,
+    });
+    ArrowSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: ptr::null(),
+        flags: 0,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(schema_release),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn schema_release(schema: *mut ArrowSchema) {
+    let private = Box::from_raw((*schema).private_data as *mut SchemaPrivate);
+    for child in private.children {
+        let mut child = Box::from_raw(child);
+        if let Some(release) = child.release {
+            release(&mut *child);
+        }
+    }
+    (*schema).release = None;
+}
+
+struct ArrayPrivate {
+    column: Column,
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+}
+
+fn export_array(mut column: Column) -> ArrowArray {
+    let children = match &mut column {
+        Column::Struct { children, .. } => std::mem::take(children)
+            .into_iter()
+            .map(|child| Box::into_raw(Box::new(export_array(child))))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let mut private = Box::new(ArrayPrivate {
+        buffers: column.buffers(),
+        column,
+        children,
+    });
+    ArrowArray {
+        length: private.column.len() as i64,
+        null_count: 0,
+        offset: 0,
+        n_buffers: private.buffers.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.buffers.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(array_release),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn array_release(array: *mut ArrowArray) {
+    let private = Box::from_raw((*array).private_data as *mut ArrayPrivate);
+    for child in private.children {
+        let mut child = Box::from_raw(child);
+        if let Some(release) = child.release {
+            release(&mut *child);
+        }
+    }
+    (*array).release = None;
+}
@@ -0,0 +1,67 @@
+//! Top-k completion of prefixes against a weighted Map.
+
+use fst::automaton::{AlwaysMatch, Automaton, Levenshtein};
+use fst::Map as FstMap;
+use pyo3::prelude::*;
+use std::time::Instant;
+
+use crate::error;
+use crate::map::{Map, MapData};
+use crate::trace;
+use crate::util::{self, Key};
+use crate::values::ValueType;
+
+/// Completes prefixes to the keys of a Map, ranked by the value stored for
+/// each key as its weight. Values of any `value_type` are ranked as stored,
+/// since their outputs keep their order.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct Autocomplete {
+    map: FstMap<MapData>,
+    value_type: ValueType,
+}
+
+#[pymethods]
+impl Autocomplete {
+    #[new]
+    fn new(map: PyRef<Map>) -> PyResult<Self> {
+        map.check_open()?;
+        Ok(Autocomplete {
+            map: map.inner.clone(),
+            value_type: map.value_type,
+        })
+    }
+
+    /// The `k` heaviest keys starting with `prefix` as `(key, weight)`
+    /// tuples, heaviest first. With `fuzzy > 0`, keys starting with anything
+    /// within `fuzzy` edits of `prefix` are completed too.
+    ///
+    /// :rtype: List[Tuple[str, Union[int, float]]]
+    #[pyo3(signature = (prefix, k=10, fuzzy=0))]
+    fn complete(
+        &self,
+        py: Python,
+        prefix: Key,
+        k: usize,
+        fuzzy: u32,
+    ) -> PyResult<Vec<(String, PyObject)>> {
+        let start = Instant::now();
+        let fst = self.map.as_fst();
+        let matches = if fuzzy == 0 {
+            let range = util::bounded_range(Some(prefix.as_bytes()), None, None);
+            py.allow_threads(|| util::top_k(fst, &AlwaysMatch, range, k))
+        } else {
+            let lev = Levenshtein::new(prefix.as_str()?, fuzzy)
+                .map_err(error::lev_error)?
+                .starts_with();
+            py.allow_threads(|| util::top_k(fst, &lev, (None, None), k))
+        };
+        trace::emit(py, "Autocomplete.complete", start.elapsed(), matches.len())?;
+        Ok(matches
+            .into_iter()
+            .map(|(key, weight)| {
+                let key = String::from_utf8_lossy(&key).into_owned();
+                (key, self.value_type.decode(py, weight))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,156 @@
+//! Streaming the keys of an FST in descending order.
+//!
+//! The `fst` crate only streams forwards, so the nodes are walked here
+//! directly: transitions are followed from the highest byte down, and a key
+//! is yielded after all of its extensions, which sort above it.
+
+use fst::raw::{CompiledAddr, Fst, Node, Output};
+use fst::Streamer;
+use std::ops::Bound;
+
+struct Frame {
+    addr: CompiledAddr,
+    /// The output accumulated on the path to the node.
+    out: Output,
+    /// The transitions below this index are still to be visited.
+    next: usize,
+    /// Whether the path to the node is a prefix of the upper bound.
+    tight: bool,
+}
+
+/// The keys of an FST between two bounds, in descending order, with their
+/// outputs.
+pub struct Backward<'f, D> {
+    fst: &'f Fst<D>,
+    key: Vec<u8>,
+    stack: Vec<Frame>,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+    /// The top frame was yielded and is popped on the next call.
+    yielded: bool,
+}
+
+impl<'f, D: AsRef<[u8]>> Backward<'f, D> {
+    pub fn new(fst: &'f Fst<D>, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>) -> Self {
+        let mut backward = Backward {
+            fst,
+            key: Vec::new(),
+            stack: Vec::new(),
+            lower,
+            upper,
+            yielded: false,
+        };
+        let root = fst.root();
+        let tight = !matches!(backward.upper, Bound::Unbounded);
+        let next = backward.limit(&root, tight);
+        backward.stack.push(Frame {
+            addr: root.addr(),
+            out: Output::zero(),
+            next,
+            tight,
+        });
+        backward
+    }
+
+    /// The bytes of the upper bound, if any.
+    fn upper_bytes(&self) -> &[u8] {
+        match &self.upper {
+            Bound::Included(upper) | Bound::Excluded(upper) => upper,
+            Bound::Unbounded => &[],
+        }
+    }
+
+    /// The number of transitions of `node`, at the end of `key`, that do not
+    /// lead past the upper bound.
+    fn limit(&self, node: &Node, tight: bool) -> usize {
+        if !tight {
+            return node.len();
+        }
+        match self.upper_bytes().get(self.key.len()) {
+            // Every extension of the bound itself is above it.
+            None => 0,
+            Some(&b) => node.transitions().take_while(|t| t.inp <= b).count(),
+        }
+    }
+
+    /// Whether `key` is within the upper bound, given whether it is a prefix
+    /// of it; keys off the bound's path are below it by construction.
+    fn below_upper(&self, tight: bool) -> bool {
+        match &self.upper {
+            Bound::Excluded(upper) if tight => self.key.len() < upper.len(),
+            _ => true,
+        }
+    }
+
+    fn above_lower(&self) -> bool {
+        match &self.lower {
+            Bound::Included(lower) => self.key.as_slice() >= lower.as_slice(),
+            Bound::Excluded(lower) => self.key.as_slice() > lower.as_slice(),
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+        if !self.stack.is_empty() {
+            self.key.pop();
+        }
+    }
+}
+
+impl<'a, 'f, D: AsRef<[u8]>> Streamer<'a> for Backward<'f, D> {
+    type Item = (&'a [u8], Output);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if std::mem::take(&mut self.yielded) {
+            self.pop();
+        }
+        while let Some(frame) = self.stack.last_mut() {
+            if frame.next > 0 {
+                frame.next -= 1;
+                let t = self.fst.node(frame.addr).transition(frame.next);
+                let out = frame.out.cat(t.out);
+                let tight = frame.tight && self.upper_bytes().get(self.key.len()) == Some(&t.inp);
+                self.key.push(t.inp);
+                let next = self.limit(&self.fst.node(t.addr), tight);
+                self.stack.push(Frame {
+                    addr: t.addr,
+                    out,
+                    next,
+                    tight,
+                });
+                continue;
+            }
+            // Every extension of the key has been visited, so the key itself
+            // comes next.
+            let (out, tight) = (frame.out, frame.tight);
+            let node = self.fst.node(frame.addr);
+            if node.is_final() && self.below_upper(tight) {
+                if !self.above_lower() {
+                    self.stack.clear();
+                    return None;
+                }
+                self.yielded = true;
+                return Some((&self.key, out.cat(node.final_output())));
+            }
+            self.pop();
+        }
+        None
+    }
+}
+
+/// The lower and upper bound of a range given like the `range()` methods,
+/// where `gt` takes precedence over `ge` and `lt` over `le`.
+pub fn bounds(
+    ge: Option<&[u8]>,
+    gt: Option<&[u8]>,
+    le: Option<&[u8]>,
+    lt: Option<&[u8]>,
+) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let bound = |inclusive: Option<&[u8]>, exclusive: Option<&[u8]>| match (inclusive, exclusive) {
+        (_, Some(key)) => Bound::Excluded(key.to_vec()),
+        (Some(key), None) => Bound::Included(key.to_vec()),
+        (None, None) => Bound::Unbounded,
+    };
+    (bound(ge, gt), bound(le, lt))
+}
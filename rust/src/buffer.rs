@@ -0,0 +1,76 @@
+//! Zero-copy access to objects implementing the buffer protocol.
+//!
+//! PyO3 only offers `PyBuffer` under the limited API from Python 3.11 on, when
+//! the buffer functions joined the stable ABI. Every CPython 3 exports them
+//! with the same `Py_buffer` layout, so they are declared here instead.
+
+use pyo3::ffi;
+use pyo3::prelude::*;
+use std::ops::Deref;
+use std::os::raw::{c_char, c_int, c_void};
+
+/// `Py_buffer`.
+#[repr(C)]
+struct RawBuffer {
+    buf: *mut c_void,
+    obj: *mut ffi::PyObject,
+    len: ffi::Py_ssize_t,
+    itemsize: ffi::Py_ssize_t,
+    readonly: c_int,
+    ndim: c_int,
+    format: *mut c_char,
+    shape: *mut ffi::Py_ssize_t,
+    strides: *mut ffi::Py_ssize_t,
+    suboffsets: *mut ffi::Py_ssize_t,
+    internal: *mut c_void,
+}
+
+/// A contiguous run of bytes, possibly read-only.
+const PYBUF_SIMPLE: c_int = 0;
+
+extern "C" {
+    #[cfg_attr(PyPy, link_name = "PyPyObject_GetBuffer")]
+    fn PyObject_GetBuffer(obj: *mut ffi::PyObject, view: *mut RawBuffer, flags: c_int) -> c_int;
+    #[cfg_attr(PyPy, link_name = "PyPyBuffer_Release")]
+    fn PyBuffer_Release(view: *mut RawBuffer);
+}
+
+/// The bytes exported by a Python object such as `bytes`, a `memoryview`, an
+/// `mmap.mmap` or a numpy array. The exporter is kept alive, and locked
+/// against resizing where it supports that, until the buffer is dropped; its
+/// contents must not be modified meanwhile.
+pub struct Buffer(Box<RawBuffer>);
+
+// The view only points into memory owned by the exporter, which it keeps
+// alive, and is released with the GIL held.
+unsafe impl Send for Buffer {}
+unsafe impl Sync for Buffer {}
+
+impl Buffer {
+    /// The buffer of `obj`, or a `TypeError` if it does not export one.
+    pub fn get(obj: &PyAny) -> PyResult<Self> {
+        // The view is boxed because exporters may keep pointers into it.
+        let mut view = Box::new(unsafe { std::mem::zeroed::<RawBuffer>() });
+        if unsafe { PyObject_GetBuffer(obj.as_ptr(), &mut *view, PYBUF_SIMPLE) } != 0 {
+            return Err(PyErr::fetch(obj.py()));
+        }
+        Ok(Buffer(view))
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.0.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.0.buf as *const u8, self.0.len as usize) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        Python::with_gil(|_| unsafe { PyBuffer_Release(&mut *self.0) });
+    }
+}
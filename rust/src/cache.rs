@@ -0,0 +1,54 @@
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+/// A size-bounded LRU cache of point lookup results, keyed by the raw key
+/// bytes. Shared between clones of the same `Set`/`Map`.
+pub struct LookupCache<V> {
+    entries: Mutex<LruCache<Vec<u8>, V>>,
+}
+
+impl<V: Copy> LookupCache<V> {
+    /// Create a cache holding up to `size` entries, or `None` if `size` is 0.
+    pub fn new(size: usize) -> Option<Self> {
+        let size = NonZeroUsize::new(size)?;
+        Some(LookupCache {
+            entries: Mutex::new(LruCache::new(size)),
+        })
+    }
+
+    /// A new, empty cache with the same capacity.
+    pub fn empty_copy(&self) -> Self {
+        let entries = self.entries.lock().unwrap();
+        LookupCache {
+            entries: Mutex::new(LruCache::new(entries.cap())),
+        }
+    }
+
+    /// The number of entries the cache holds at most.
+    pub fn capacity(&self) -> usize {
+        self.entries.lock().unwrap().cap().get()
+    }
+
+    /// Approximate number of heap bytes held by the cached entries.
+    pub fn heap_bytes(&self) -> usize {
+        let entries = self.entries.lock().unwrap();
+        let per_entry = std::mem::size_of::<Vec<u8>>() + std::mem::size_of::<V>();
+        entries
+            .iter()
+            .map(|(key, _)| per_entry + key.capacity())
+            .sum()
+    }
+
+    /// Return the cached result for `key`, computing and caching it with
+    /// `lookup` on a miss, and whether it was cached.
+    pub fn get_or_insert_with(&self, key: &[u8], lookup: impl FnOnce() -> V) -> (V, bool) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(val) = entries.get(key) {
+            return (*val, true);
+        }
+        let val = lookup();
+        entries.put(key.to_vec(), val);
+        (val, false)
+    }
+}
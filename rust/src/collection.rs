@@ -0,0 +1,620 @@
+//! Several Sets or Maps queried as one.
+//!
+//! Data that is updated incrementally is often kept as one large FST plus
+//! small segments of recent changes. A collection answers lookups, iteration
+//! and searches over the union of its segments, merged in one pass with the
+//! `OpBuilder` of `fst`, and can compact them into a single FST.
+
+use fst::automaton::Levenshtein;
+use fst::map::IndexedValue;
+use fst::{Automaton, Map as FstMap, Set as FstSet, Streamer};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::time::Instant;
+
+use crate::counters::Tally;
+use crate::error;
+use crate::export::KeyBuffer;
+use crate::lossy::{self, KeyObject};
+use crate::map::{Map, MapBuilder, MapCollectedStream, MapData, MapUnion, Merge};
+use crate::send::SendOp;
+use crate::set::{Set, SetBuilder, SetCollectedStream, SetData, SetUnion};
+use crate::stream::{stream_class, Collect, Remaining};
+use crate::trace;
+use crate::util::{self, Key, KeyRange, MatchMode};
+use crate::values::ValueType;
+use crate::window::Window;
+
+/// The union of the matches of `aut` within `bounds` in every one of `sets`,
+/// restricted to `window`.
+fn search_sets<A: Automaton>(
+    sets: &[FstSet<SetData>],
+    aut: &A,
+    bounds: &KeyRange,
+    mut window: Window,
+) -> Vec<(Vec<u8>, u64)> {
+    let (ge, lt) = bounds;
+    let mut op = fst::set::OpBuilder::new();
+    for set in sets {
+        let mut builder = set.search(aut);
+        if let Some(ge) = ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = lt {
+            builder = builder.lt(lt);
+        }
+        op = op.add(builder);
+    }
+    let mut union = op.union();
+    let mut keys = Vec::new();
+    while let Some(key) = union.next() {
+        if window.skip() {
+            continue;
+        }
+        if !window.take() {
+            break;
+        }
+        keys.push((key.to_vec(), 0));
+    }
+    keys
+}
+
+/// The union of the matches of `aut` within `bounds` in every one of `maps`,
+/// with the values of a key combined by `merge`, restricted to `window`.
+fn search_maps<A: Automaton>(
+    maps: &[FstMap<MapData>],
+    aut: &A,
+    bounds: &KeyRange,
+    merge: Merge,
+    mut window: Window,
+) -> Vec<(Vec<u8>, u64)> {
+    let (ge, lt) = bounds;
+    let mut op = fst::map::OpBuilder::new();
+    for map in maps {
+        let mut builder = map.search(aut);
+        if let Some(ge) = ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = lt {
+            builder = builder.lt(lt);
+        }
+        op = op.add(builder);
+    }
+    let mut union = op.union();
+    let mut items = Vec::new();
+    while let Some((key, values)) = union.next() {
+        if window.skip() {
+            continue;
+        }
+        if !window.take() {
+            break;
+        }
+        items.push((key.to_vec(), merge.apply(values)));
+    }
+    items
+}
+
+/// The union of the streams of the keys within `bounds` in every one of
+/// `sets`.
+///
+/// # Safety
+///
+/// The union borrows `sets`, so they must be boxed and kept, unmoved, for
+/// as long as it is used.
+unsafe fn set_union(
+    sets: &[FstSet<SetData>],
+    bounds: &KeyRange,
+) -> SendOp<fst::set::Union<'static>> {
+    let (ge, lt) = bounds;
+    let mut op = fst::set::OpBuilder::new();
+    for set in sets {
+        let mut builder = set.range();
+        if let Some(ge) = ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = lt {
+            builder = builder.lt(lt);
+        }
+        op = op.add(builder);
+    }
+    SendOp::new(std::mem::transmute::<
+        fst::set::Union<'_>,
+        fst::set::Union<'static>,
+    >(op.union()))
+}
+
+/// The union of the streams of the items within `bounds` in every one of
+/// `maps`, like `set_union`.
+///
+/// # Safety
+///
+/// As for `set_union`.
+unsafe fn map_union(
+    maps: &[FstMap<MapData>],
+    bounds: &KeyRange,
+) -> SendOp<fst::map::Union<'static>> {
+    let (ge, lt) = bounds;
+    let mut op = fst::map::OpBuilder::new();
+    for map in maps {
+        let mut builder = map.range();
+        if let Some(ge) = ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = lt {
+            builder = builder.lt(lt);
+        }
+        op = op.add(builder);
+    }
+    SendOp::new(std::mem::transmute::<
+        fst::map::Union<'_>,
+        fst::map::Union<'static>,
+    >(op.union()))
+}
+
+/// Several Sets, such as a large base Set and smaller segments of later
+/// additions, queried as the Set of all of their keys.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct SetCollection {
+    sets: Box<[FstSet<SetData>]>,
+}
+
+impl SetCollection {
+    fn union(&self, op_name: &'static str, bounds: KeyRange, window: Window) -> SetUnion {
+        let sets = self.sets.clone();
+        let op = unsafe { set_union(&sets, &bounds) };
+        SetUnion::new(op, sets, op_name, window)
+    }
+
+    fn collected(
+        &self,
+        py: Python,
+        op_name: &str,
+        search: impl FnOnce(&[FstSet<SetData>]) -> Vec<(Vec<u8>, u64)> + Send,
+    ) -> PyResult<SetCollectedStream> {
+        let start = Instant::now();
+        let keys = py.allow_threads(|| search(&self.sets));
+        trace::emit(py, op_name, start.elapsed(), keys.len())?;
+        Ok(SetCollectedStream::new(keys, Tally::default()))
+    }
+}
+
+#[pymethods]
+impl SetCollection {
+    /// Query `segments` together. The Sets stay usable on their own, and
+    /// closing one does not affect the collection.
+    ///
+    /// :type segments: Iterable[Set]
+    #[new]
+    fn new(segments: &PyAny) -> PyResult<Self> {
+        let mut sets = Vec::new();
+        for set in segments.iter()? {
+            let set: PyRef<Set> = set?.extract()?;
+            set.check_open()?;
+            sets.push(set.inner.clone());
+        }
+        Ok(SetCollection {
+            sets: sets.into_boxed_slice(),
+        })
+    }
+
+    /// The number of segments.
+    #[getter]
+    fn num_segments(&self) -> usize {
+        self.sets.len()
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.sets.iter().any(|set| set.contains(key.as_bytes()))
+    }
+
+    fn __iter__(&self) -> SetUnion {
+        self.union("SetCollection.iter", (None, None), Window::default())
+    }
+
+    /// The distinct keys at least `ge` and less than `lt`, in order.
+    /// `offset` and `limit` skip and cap the keys yielded.
+    #[pyo3(signature = (ge=None, lt=None, limit=None, offset=0))]
+    fn range(
+        &self,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> SetUnion {
+        let bounds = util::bounded_range(
+            None,
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        self.union("SetCollection.range", bounds, Window::new(offset, limit))
+    }
+
+    /// The distinct keys starting with `prefix`, in order.
+    #[pyo3(signature = (prefix, limit=None, offset=0))]
+    fn search_prefix(&self, prefix: Key, limit: Option<usize>, offset: usize) -> SetUnion {
+        let bounds = util::bounded_range(Some(prefix.as_bytes()), None, None);
+        self.union(
+            "SetCollection.search_prefix",
+            bounds,
+            Window::new(offset, limit),
+        )
+    }
+
+    /// The distinct keys of any segment matching `regex`, with the options
+    /// of `Set.search_re`. The matches are collected before they are yielded.
+    ///
+    /// :rtype: Iterator[str]
+    #[pyo3(signature = (
+        regex,
+        prefix=None,
+        ge=None,
+        lt=None,
+        match_mode="anchored",
+        case_insensitive=false,
+        limit=None,
+        offset=0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_re(
+        &self,
+        py: Python,
+        regex: Key,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        match_mode: &str,
+        case_insensitive: bool,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetCollectedStream> {
+        let mode = MatchMode::parse(match_mode)?;
+        let dfa = util::search_dfa(regex.as_str()?, mode, case_insensitive)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        self.collected(py, "SetCollection.search_re", |sets| {
+            search_sets(sets, &dfa, &bounds, window)
+        })
+    }
+
+    /// The distinct keys of any segment within Levenshtein distance
+    /// `max_dist` of `key`. The matches are collected before they are
+    /// yielded.
+    ///
+    /// :rtype: Iterator[str]
+    #[pyo3(signature = (key, max_dist, prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_lev(
+        &self,
+        py: Python,
+        key: Key,
+        max_dist: u32,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetCollectedStream> {
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        self.collected(py, "SetCollection.search_lev", |sets| {
+            search_sets(sets, &lev, &bounds, window)
+        })
+    }
+
+    /// Merge the segments into a single Set, in memory or, if `path` is
+    /// given, on disk.
+    #[pyo3(signature = (path=None))]
+    fn compact(&self, py: Python, path: Option<String>) -> PyResult<Set> {
+        let mut builder = SetBuilder::new(path.clone())?;
+        py.allow_threads(|| {
+            let mut union = self.sets.iter().collect::<fst::set::OpBuilder>().union();
+            while let Some(key) = union.next() {
+                builder.insert_bytes(key)?;
+            }
+            Ok::<_, PyErr>(())
+        })?;
+        Set::finish_open(py, builder, path)
+    }
+}
+
+/// Several Maps, such as a large base Map and smaller segments of later
+/// updates, queried as one Map of all of their keys. The values of a key
+/// found in several segments are combined by `merge`, which by default
+/// takes the one from the last segment, so later segments override
+/// earlier ones.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct MapCollection {
+    maps: Box<[FstMap<MapData>]>,
+    merge: Merge,
+    value_type: ValueType,
+}
+
+impl MapCollection {
+    fn union(&self, op_name: &'static str, bounds: KeyRange, window: Window) -> MapUnion {
+        let maps = self.maps.clone();
+        let op = unsafe { map_union(&maps, &bounds) };
+        MapUnion::new(op, maps, self.merge, self.value_type, op_name, window)
+    }
+
+    fn collected(
+        &self,
+        py: Python,
+        op_name: &str,
+        search: impl FnOnce(&[FstMap<MapData>]) -> Vec<(Vec<u8>, u64)> + Send,
+    ) -> PyResult<MapCollectedStream> {
+        let start = Instant::now();
+        let items = py.allow_threads(|| search(&self.maps));
+        trace::emit(py, op_name, start.elapsed(), items.len())?;
+        Ok(MapCollectedStream::new(
+            items,
+            Tally::default(),
+            self.value_type,
+        ))
+    }
+
+    /// The merged output of `key`, if any segment has it.
+    fn lookup(&self, key: &[u8]) -> Option<u64> {
+        let values: Vec<IndexedValue> = self
+            .maps
+            .iter()
+            .enumerate()
+            .filter_map(|(index, map)| map.get(key).map(|value| IndexedValue { index, value }))
+            .collect();
+        (!values.is_empty()).then(|| self.merge.apply(&values))
+    }
+}
+
+#[pymethods]
+impl MapCollection {
+    /// Query `segments` together, combining the values of a key found in
+    /// several of them with `merge`, as in `Map.union`. The Maps must have
+    /// the same `value_type`.
+    ///
+    /// :type segments: Iterable[Map]
+    #[new]
+    #[pyo3(signature = (segments, merge="last"))]
+    fn new(segments: &PyAny, merge: &str) -> PyResult<Self> {
+        let mut maps = Vec::new();
+        let mut value_type = None;
+        for map in segments.iter()? {
+            let map: PyRef<Map> = map?.extract()?;
+            map.check_open()?;
+            if *value_type.get_or_insert(map.value_type) != map.value_type {
+                return Err(PyValueError::new_err(
+                    "the Maps of a collection must have the same value_type",
+                ));
+            }
+            maps.push(map.inner.clone());
+        }
+        let value_type = value_type.unwrap_or_default();
+        Ok(MapCollection {
+            maps: maps.into_boxed_slice(),
+            merge: Merge::parse(merge, value_type)?,
+            value_type,
+        })
+    }
+
+    /// The number of segments.
+    #[getter]
+    fn num_segments(&self) -> usize {
+        self.maps.len()
+    }
+
+    #[getter]
+    fn value_type(&self) -> &'static str {
+        self.value_type.name()
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.maps.iter().any(|map| map.contains_key(key.as_bytes()))
+    }
+
+    /// :rtype: Union[int, float]
+    fn __getitem__(&self, py: Python, key: Key) -> PyResult<PyObject> {
+        let value = self.lookup(key.as_bytes()).ok_or_else(|| key.key_error())?;
+        Ok(self.value_type.decode(py, value))
+    }
+
+    /// The merged value of `key`, or `default` if no segment has it.
+    ///
+    /// :rtype: Optional[Union[int, float]]
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, py: Python, key: Key, default: Option<PyObject>) -> Option<PyObject> {
+        match self.lookup(key.as_bytes()) {
+            Some(value) => Some(self.value_type.decode(py, value)),
+            None => default,
+        }
+    }
+
+    fn __iter__(&self) -> MapCollectionKeys {
+        self.keys()
+    }
+
+    /// Stream the distinct keys in order.
+    fn keys(&self) -> MapCollectionKeys {
+        let maps = self.maps.clone();
+        let stream = unsafe { map_union(&maps, &(None, None)) };
+        MapCollectionKeys {
+            stream,
+            _maps: maps,
+            raw: false,
+        }
+    }
+
+    /// Stream the `(key, value)` items in key order, only those with keys
+    /// starting with `prefix`, `>= ge` and `< lt` when those are given.
+    #[pyo3(signature = (prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    fn items(
+        &self,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> MapUnion {
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        self.union("MapCollection.items", bounds, Window::new(offset, limit))
+    }
+
+    /// The items of the keys at least `ge` and less than `lt`, in order.
+    #[pyo3(signature = (ge=None, lt=None, limit=None, offset=0))]
+    fn range(
+        &self,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> MapUnion {
+        let bounds = util::bounded_range(
+            None,
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        self.union("MapCollection.range", bounds, Window::new(offset, limit))
+    }
+
+    /// The items of the keys starting with `prefix`, in order.
+    #[pyo3(signature = (prefix, limit=None, offset=0))]
+    fn search_prefix(&self, prefix: Key, limit: Option<usize>, offset: usize) -> MapUnion {
+        let bounds = util::bounded_range(Some(prefix.as_bytes()), None, None);
+        self.union(
+            "MapCollection.search_prefix",
+            bounds,
+            Window::new(offset, limit),
+        )
+    }
+
+    /// The items of the keys of any segment matching `regex`, with the
+    /// options of `Map.search_re`. The matches are collected before they are
+    /// yielded.
+    ///
+    /// :rtype: Iterator[Tuple[str, Union[int, float]]]
+    #[pyo3(signature = (
+        regex,
+        prefix=None,
+        ge=None,
+        lt=None,
+        match_mode="anchored",
+        case_insensitive=false,
+        limit=None,
+        offset=0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_re(
+        &self,
+        py: Python,
+        regex: Key,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        match_mode: &str,
+        case_insensitive: bool,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapCollectedStream> {
+        let mode = MatchMode::parse(match_mode)?;
+        let dfa = util::search_dfa(regex.as_str()?, mode, case_insensitive)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let (merge, window) = (self.merge, Window::new(offset, limit));
+        self.collected(py, "MapCollection.search_re", |maps| {
+            search_maps(maps, &dfa, &bounds, merge, window)
+        })
+    }
+
+    /// The items of the keys of any segment within Levenshtein distance
+    /// `max_dist` of `key`. The matches are collected before they are
+    /// yielded.
+    ///
+    /// :rtype: Iterator[Tuple[str, Union[int, float]]]
+    #[pyo3(signature = (key, max_dist, prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_lev(
+        &self,
+        py: Python,
+        key: Key,
+        max_dist: u32,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapCollectedStream> {
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let (merge, window) = (self.merge, Window::new(offset, limit));
+        self.collected(py, "MapCollection.search_lev", |maps| {
+            search_maps(maps, &lev, &bounds, merge, window)
+        })
+    }
+
+    /// Merge the segments into a single Map with the merged values, in
+    /// memory or, if `path` is given, on disk.
+    #[pyo3(signature = (path=None))]
+    fn compact(&self, py: Python, path: Option<String>) -> PyResult<Map> {
+        let mut builder = MapBuilder::create(path.clone(), self.value_type)?;
+        let merge = self.merge;
+        py.allow_threads(|| {
+            let mut union = self.maps.iter().collect::<fst::map::OpBuilder>().union();
+            while let Some((key, values)) = union.next() {
+                builder.insert_bytes(key, merge.apply(values))?;
+            }
+            Ok::<_, PyErr>(())
+        })?;
+        Map::finish_open(py, builder, path)
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapCollectionKeys {
+    stream: SendOp<fst::map::Union<'static>>,
+    _maps: Box<[FstMap<MapData>]>,
+    raw: bool,
+}
+
+impl Collect for MapCollectionKeys {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let stream = &mut self.stream;
+        let keys = py.allow_threads(|| {
+            let mut keys = KeyBuffer::default();
+            while let Some((key, _)) = stream.next() {
+                keys.push(key);
+            }
+            keys
+        });
+        (keys, Vec::new())
+    }
+}
+
+impl Remaining for MapCollectionKeys {}
+
+stream_class! {
+    keys impl MapCollectionKeys {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let (key, _) = this.stream.next()?;
+            Some(lossy::key_object(py, key, this.raw))
+        }
+    }
+}
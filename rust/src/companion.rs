@@ -0,0 +1,95 @@
+//! Companion indexes of a Set, such as the reversed keys behind `ends_with`.
+//!
+//! A companion stored in a file is only reused for the Set it was built
+//! from. Its FST type, which plain Sets leave at 0, holds a fingerprint of
+//! the Set's bytes and of the kind of companion, and a file with any other
+//! fingerprint is rebuilt in place.
+
+use fst::raw::{self, Fst};
+use fst::Set as FstSet;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error;
+use crate::set::{Set, SetData};
+
+/// The fingerprint of a companion of `kind` built from `fst`. It is an
+/// FNV-1a hash rather than a `Hasher`, so that it stays the same across
+/// processes and Rust releases, and never 0.
+pub fn fingerprint<D: AsRef<[u8]>>(fst: &Fst<D>, kind: &[u8]) -> u64 {
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    let len = (kind.len() as u64).to_le_bytes();
+    for byte in len.iter().chain(kind).chain(fst.as_bytes()) {
+        hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3);
+    }
+    hash.max(1)
+}
+
+/// The kind of a companion built with the Python callable `func`, naming
+/// it by its module, name and code, or `None` if that does not pin down what
+/// it computes: for anything but a plain function, such as a bound method
+/// or a configured stemmer object, and for closures.
+pub fn callable_kind(func: &PyAny) -> PyResult<Option<Vec<u8>>> {
+    if !func.hasattr("__code__")?
+        || func.hasattr("__self__")?
+        || !func.getattr("__closure__")?.is_none()
+    {
+        return Ok(None);
+    }
+    let code = func.getattr("__code__")?;
+    let mut kind = Vec::new();
+    for part in [
+        func.getattr("__module__")?.str()?,
+        func.getattr("__qualname__")?.str()?,
+        func.getattr("__defaults__")?.repr()?,
+        code.getattr("co_consts")?.repr()?,
+        code.getattr("co_names")?.repr()?,
+    ] {
+        kind.extend_from_slice(part.to_str()?.as_bytes());
+        kind.push(0);
+    }
+    kind.extend_from_slice(code.getattr("co_code")?.extract()?);
+    Ok(Some(kind))
+}
+
+/// The companion at `path`, if there is one carrying `fingerprint`.
+pub fn load(py: Python, path: Option<&str>, fingerprint: u64) -> PyResult<Option<FstSet<SetData>>> {
+    let Some(path) = path.filter(|path| Path::new(path).exists()) else {
+        return Ok(None);
+    };
+    let companion = Set::open(PyString::new(py, path))?.inner;
+    Ok((companion.as_fst().fst_type() == fingerprint).then_some(companion))
+}
+
+/// Build the companion of sorted, distinct `entries` carrying `fingerprint`,
+/// in memory or at `path`, and open it.
+pub fn build(
+    py: Python,
+    entries: &[Vec<u8>],
+    path: Option<String>,
+    fingerprint: u64,
+) -> PyResult<FstSet<SetData>> {
+    let Some(path) = path else {
+        let bytes = py.allow_threads(|| write(Vec::new(), entries, fingerprint))?;
+        let fst = Fst::new(SetData::Vec(Arc::new(bytes))).map_err(error::fst_error)?;
+        return Ok(FstSet::from(fst));
+    };
+    py.allow_threads(|| -> PyResult<()> {
+        let out = write(BufWriter::new(File::create(&path)?), entries, fingerprint)?;
+        out.into_inner().map_err(|err| err.into_error())?.flush()?;
+        Ok(())
+    })?;
+    Ok(Set::open(PyString::new(py, &path))?.inner)
+}
+
+fn write<W: Write>(wtr: W, entries: &[Vec<u8>], fingerprint: u64) -> PyResult<W> {
+    let mut builder = raw::Builder::new_type(wtr, fingerprint).map_err(error::fst_error)?;
+    for entry in entries {
+        builder.add(entry).map_err(error::fst_error)?;
+    }
+    builder.into_inner().map_err(error::fst_error)
+}
@@ -0,0 +1,85 @@
+//! Opt-in usage counters of a Set or Map, for exporting to a metrics system.
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Counts of the lookups and streams of one Set or Map, shared with its
+/// shallow copies.
+#[derive(Default)]
+pub struct Counters {
+    lookups: AtomicU64,
+    hits: AtomicU64,
+    cache_hits: AtomicU64,
+    streams: AtomicU64,
+    keys: AtomicU64,
+}
+
+impl Counters {
+    /// Count a point lookup that found its key or not, and was answered by
+    /// the lookup cache or not.
+    pub fn lookup(&self, found: bool, cached: bool) {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if found {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        if cached {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a newly opened stream, returning the tally of its keys.
+    pub fn stream(counters: &Option<Arc<Counters>>) -> Tally {
+        if let Some(counters) = counters {
+            counters.streams.fetch_add(1, Ordering::Relaxed);
+        }
+        Tally(counters.clone())
+    }
+
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let lookups = self.lookups.load(Ordering::Relaxed);
+        let hits = self.hits.load(Ordering::Relaxed);
+        let dict = PyDict::new(py);
+        dict.set_item("lookups", lookups)?;
+        dict.set_item("hits", hits)?;
+        dict.set_item("misses", lookups - hits)?;
+        dict.set_item("cache_hits", self.cache_hits.load(Ordering::Relaxed))?;
+        dict.set_item("streams", self.streams.load(Ordering::Relaxed))?;
+        dict.set_item("keys", self.keys.load(Ordering::Relaxed))?;
+        Ok(dict)
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.lookups,
+            &self.hits,
+            &self.cache_hits,
+            &self.streams,
+            &self.keys,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Counts the keys a stream yields towards the counters of the Set or Map it
+/// was opened on, if they are enabled.
+#[derive(Default)]
+pub struct Tally(Option<Arc<Counters>>);
+
+impl Tally {
+    pub fn count<T>(&self, item: Option<T>) -> Option<T> {
+        if let (Some(counters), Some(_)) = (&self.0, &item) {
+            counters.keys.fetch_add(1, Ordering::Relaxed);
+        }
+        item
+    }
+
+    /// Count `n` keys yielded at once.
+    pub fn count_n(&self, n: usize) {
+        if let Some(counters) = &self.0 {
+            counters.keys.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Searching with automata written in Python.
+//!
+//! Every byte the FST walk tries calls back into Python, so these searches
+//! hold the GIL throughout and are far slower than regular expressions or
+//! Levenshtein automata; they are for matching neither can express.
+
+use fst::raw::Fst;
+use fst::{Automaton, Streamer};
+use pyo3::prelude::*;
+use std::cell::RefCell;
+use std::time::Instant;
+
+use crate::trace;
+use crate::util::{self, KeyRange};
+use crate::window::Window;
+
+/// A Python object with `start()`, `is_match(state)`, `accept(state, byte)`
+/// and optionally `can_match(state)` methods, as an `Automaton`.
+///
+/// A callback that raises stops the walk: its state and every state after
+/// it are dead, and the exception is kept to be raised once the walk ends.
+struct PyAutomaton<'py> {
+    obj: &'py PyAny,
+    can_match: bool,
+    error: RefCell<Option<PyErr>>,
+}
+
+impl<'py> PyAutomaton<'py> {
+    fn new(obj: &'py PyAny) -> PyResult<Self> {
+        Ok(PyAutomaton {
+            obj,
+            can_match: obj.hasattr("can_match")?,
+            error: RefCell::new(None),
+        })
+    }
+
+    /// The result of a callback, or `None` after recording its exception.
+    fn call<T>(&self, result: PyResult<T>) -> Option<T> {
+        result
+            .map_err(|err| {
+                self.error.borrow_mut().get_or_insert(err);
+            })
+            .ok()
+    }
+
+    /// The truth of `method(state)`, or false if it raises.
+    fn test(&self, method: &str, state: &PyAny) -> bool {
+        self.call(
+            self.obj
+                .call_method1(method, (state,))
+                .and_then(PyAny::is_true),
+        )
+        .unwrap_or(false)
+    }
+}
+
+impl<'py> Automaton for PyAutomaton<'py> {
+    type State = Option<&'py PyAny>;
+
+    fn start(&self) -> Self::State {
+        self.call(self.obj.call_method0("start"))
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        state.is_some_and(|state| self.test("is_match", state))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        match state {
+            None => false,
+            Some(_) if !self.can_match => true,
+            Some(state) => self.test("can_match", state),
+        }
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let state = (*state)?;
+        self.call(self.obj.call_method1("accept", (state, byte)))
+    }
+}
+
+/// The keys of `fst` within `bounds` that `automaton` matches, with their
+/// outputs, in key order and restricted to `window`. `op` names the search
+/// for the trace hook.
+pub fn search<D: AsRef<[u8]>>(
+    py: Python,
+    op: &str,
+    fst: &Fst<D>,
+    automaton: &PyAny,
+    bounds: KeyRange,
+    mut window: Window,
+) -> PyResult<Vec<(Vec<u8>, u64)>> {
+    let start = Instant::now();
+    let aut = PyAutomaton::new(automaton)?;
+    let mut stream = util::search_range(fst, &aut, bounds);
+    let mut matches = Vec::new();
+    while let Some((key, out)) = stream.next() {
+        if window.skip() {
+            continue;
+        }
+        if !window.take() {
+            break;
+        }
+        matches.push((key.to_vec(), out.value()));
+    }
+    drop(stream);
+    if let Some(err) = aut.error.into_inner() {
+        return Err(err);
+    }
+    trace::emit(py, op, start.elapsed(), matches.len())?;
+    Ok(matches)
+}
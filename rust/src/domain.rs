@@ -0,0 +1,116 @@
+//! Suffix rules over domain names, for blocklist-style lookups.
+//!
+//! Rules are stored with their labels reversed (`example.com` becomes
+//! `com.example`), so that a domain and all of its parents share key
+//! prefixes. A wildcard rule `*.example.com` is stored as `com.example.*`.
+
+use fst::Set as FstSet;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::set::{Set, SetData};
+
+/// Lowercase `domain`, drop a trailing dot and reverse its labels.
+fn reverse_labels(domain: &str) -> String {
+    let domain = domain.strip_suffix('.').unwrap_or(domain).to_lowercase();
+    domain.rsplit('.').collect::<Vec<_>>().join(".")
+}
+
+/// The key of an exact rule such as `example.com` or a wildcard rule such as
+/// `*.example.com`.
+fn rule_key(rule: &str) -> PyResult<String> {
+    let (wildcard, domain) = match rule.strip_prefix("*.") {
+        Some(domain) => (true, domain),
+        None => (false, rule),
+    };
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    if domain.is_empty()
+        || domain
+            .split('.')
+            .any(|label| label.is_empty() || label == "*")
+    {
+        return Err(PyValueError::new_err(format!(
+            "invalid domain rule {:?}",
+            rule
+        )));
+    }
+    let key = reverse_labels(domain);
+    Ok(if wildcard { key + ".*" } else { key })
+}
+
+/// Turn a rule key back into the rule it was built from.
+fn key_rule(key: &str) -> String {
+    match key.strip_suffix(".*") {
+        Some(domain) => format!("*.{}", reverse_labels(domain)),
+        None => reverse_labels(key),
+    }
+}
+
+/// Matches domain names against exact rules (`example.com`, matching only
+/// itself) and wildcard rules (`*.example.com`, matching every subdomain).
+#[pyclass(weakref, module = "rust_fst")]
+pub struct DomainMatcher {
+    set: FstSet<SetData>,
+}
+
+#[pymethods]
+impl DomainMatcher {
+    /// Wrap a Set written by `DomainMatcher.build`.
+    #[new]
+    fn new(set: PyRef<Set>) -> PyResult<Self> {
+        set.check_open()?;
+        Ok(DomainMatcher {
+            set: set.inner.clone(),
+        })
+    }
+
+    /// Build a matcher from `rules`, in memory or, if `path` is given, in a
+    /// file that can later be opened with `DomainMatcher(Set(path))`. Rules
+    /// are case-insensitive.
+    ///
+    /// :type rules: Iterable[str]
+    #[staticmethod]
+    #[pyo3(signature = (rules, path=None))]
+    fn build(py: Python, rules: &PyAny, path: Option<String>) -> PyResult<DomainMatcher> {
+        let mut keys = Vec::new();
+        for rule in rules.iter()? {
+            keys.push(rule_key(rule?.extract()?)?.into_bytes());
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        let set = Set::build_sorted(py, &keys, path)?;
+        Ok(DomainMatcher { set: set.inner })
+    }
+
+    /// The rule matching `domain`, or `None`. An exact rule takes precedence
+    /// over wildcard rules, and more specific wildcards over less specific
+    /// ones.
+    fn match_rule(&self, domain: &str) -> Option<String> {
+        let key = reverse_labels(domain);
+        if self.set.contains(&key) {
+            return Some(key_rule(&key));
+        }
+        let mut parent = key.as_str();
+        while let Some((rest, _)) = parent.rsplit_once('.') {
+            let wildcard = format!("{}.*", rest);
+            if self.set.contains(&wildcard) {
+                return Some(key_rule(&wildcard));
+            }
+            parent = rest;
+        }
+        None
+    }
+
+    /// Whether any rule matches `domain`.
+    fn is_blocked(&self, domain: &str) -> bool {
+        self.match_rule(domain).is_some()
+    }
+
+    fn __contains__(&self, domain: &str) -> bool {
+        self.is_blocked(domain)
+    }
+
+    fn __len__(&self) -> usize {
+        self.set.len()
+    }
+}
@@ -0,0 +1,150 @@
+use fst::automaton::LevenshteinError;
+use fst::raw;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex_automata::ErrorKind;
+
+create_exception!(
+    _native,
+    FstError,
+    PyValueError,
+    "Base class for FST errors."
+);
+create_exception!(
+    _native,
+    OutOfOrderError,
+    FstError,
+    "A key was inserted out of lexicographic order, or twice."
+);
+create_exception!(
+    _native,
+    FormatError,
+    FstError,
+    "The data is not a valid FST of the expected type and version."
+);
+create_exception!(
+    _native,
+    RegexTooLargeError,
+    FstError,
+    "The regular expression compiles to too large an automaton."
+);
+create_exception!(
+    _native,
+    LevenshteinTooLargeError,
+    FstError,
+    "The Levenshtein automaton exceeds its state limit."
+);
+create_exception!(
+    _native,
+    ClosedError,
+    FstError,
+    "The Set or Map has been closed."
+);
+
+/// Map an error from the `fst` crate to the matching Python exception.
+pub fn fst_error(err: fst::Error) -> PyErr {
+    match err {
+        fst::Error::Io(err) => err.into(),
+        fst::Error::Fst(err) => {
+            let msg = err.to_string();
+            match err {
+                raw::Error::OutOfOrder { .. } | raw::Error::DuplicateKey { .. } => {
+                    OutOfOrderError::new_err(msg)
+                }
+                raw::Error::Version { .. }
+                | raw::Error::Format { .. }
+                | raw::Error::ChecksumMismatch { .. }
+                | raw::Error::ChecksumMissing
+                | raw::Error::WrongType { .. } => FormatError::new_err(msg),
+                _ => FstError::new_err(msg),
+            }
+        }
+    }
+}
+
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+/// Check the header and footer of `data` before opening it as an FST,
+/// raising `FormatError` with the offending offset and the values expected
+/// and found there. Data with a bad header is reported as not an FST; data
+/// with a good header but an inconsistent footer as truncated or corrupt.
+/// `source` names the data, such as its path, for the message.
+///
+/// `fst` itself checks less: it misses most truncated files, which then fail
+/// or misbehave on first use.
+pub fn check_fst_data(data: &[u8], source: &str) -> PyResult<()> {
+    let size = data.len();
+    let fail = |detail: String| {
+        Err(FormatError::new_err(format!(
+            "cannot open {} ({} bytes) as an FST: {}",
+            source, size, detail
+        )))
+    };
+    if size < 8 {
+        return fail("truncated: too short to hold the format version".to_string());
+    }
+    let version = read_u64_le(data);
+    if version == 0 || version > raw::VERSION {
+        return fail(format!(
+            "not an FST: expected a format version from 1 to {} at offset 0, found {} \
+             (bytes {})",
+            raw::VERSION,
+            version,
+            data[..8]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        ));
+    }
+    // A 16-byte header (version and type) and a footer of the key count and
+    // the root address, followed by a checksum since version 3.
+    let footer = if version >= 3 { 20 } else { 16 };
+    if size < 16 + footer {
+        return fail(format!(
+            "truncated: a version {} FST is at least {} bytes",
+            version,
+            16 + footer
+        ));
+    }
+    let root_offset = size - footer + 8;
+    let root_addr = read_u64_le(&data[root_offset..]);
+    // The root node is written last and its address is that of its last
+    // byte, except for the empty root of an empty FST, at address 0.
+    let expected = (size - footer - 1) as u64;
+    if root_addr != expected && !(root_addr == 0 && size == 16 + footer) {
+        return fail(format!(
+            "truncated or corrupt: expected the root address {} at offset {}, found {}",
+            expected, root_offset, root_addr
+        ));
+    }
+    Ok(())
+}
+
+pub fn lev_error(err: LevenshteinError) -> PyErr {
+    LevenshteinTooLargeError::new_err(err.to_string())
+}
+
+pub fn regex_error(err: regex_automata::Error) -> PyErr {
+    match err.kind() {
+        ErrorKind::StateIDOverflow { .. } | ErrorKind::PremultiplyOverflow { .. } => {
+            RegexTooLargeError::new_err(err.to_string())
+        }
+        _ => FstError::new_err(err.to_string()),
+    }
+}
+
+pub fn register(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("FstError", py.get_type::<FstError>())?;
+    m.add("OutOfOrderError", py.get_type::<OutOfOrderError>())?;
+    m.add("FormatError", py.get_type::<FormatError>())?;
+    m.add("RegexTooLargeError", py.get_type::<RegexTooLargeError>())?;
+    m.add(
+        "LevenshteinTooLargeError",
+        py.get_type::<LevenshteinTooLargeError>(),
+    )?;
+    m.add("ClosedError", py.get_type::<ClosedError>())?;
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use fst::raw::{CompiledAddr, Fst};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyByteArray;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+
+use crate::util::Key;
+use crate::values::ValueType;
+
+/// Build a writable numpy array of `dtype` over a fresh bytearray of `len`
+/// bytes, letting `fill` write the array contents directly.
+pub fn ndarray<'py>(
+    py: Python<'py>,
+    dtype: &str,
+    len: usize,
+    fill: impl FnOnce(&mut [u8]),
+) -> PyResult<&'py PyAny> {
+    let numpy = py.import("numpy")?;
+    let buf = PyByteArray::new_with(py, len, |buf| {
+        fill(buf);
+        Ok(())
+    })?;
+    numpy.call_method1("frombuffer", (buf, dtype))
+}
+
+/// A `numpy.uint64` array holding `values`.
+pub fn u64_array<'py>(py: Python<'py>, values: &[u64]) -> PyResult<&'py PyAny> {
+    value_array(py, values, ValueType::U64)
+}
+
+/// A numpy array of the Map values stored as the outputs `values`, of
+/// `uint64`, `int64` or `float64` as `value_type` says.
+pub fn value_array<'py>(
+    py: Python<'py>,
+    values: &[u64],
+    value_type: ValueType,
+) -> PyResult<&'py PyAny> {
+    ndarray(
+        py,
+        value_type.dtype(),
+        std::mem::size_of_val(values),
+        |buf| {
+            for (chunk, val) in buf.chunks_exact_mut(8).zip(values) {
+                chunk.copy_from_slice(&value_type.native_bytes(*val));
+            }
+        },
+    )
+}
+
+/// The values of an array-like of non-negative integers, read through a
+/// contiguous `numpy.uint64` copy.
+pub fn u64_values(array: &PyAny) -> PyResult<Vec<u64>> {
+    let numpy = array.py().import("numpy")?;
+    let array = numpy.call_method1("ascontiguousarray", (array, "=u8"))?;
+    let bytes: Vec<u8> = array.call_method0("tobytes")?.extract()?;
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+/// A `numpy.bool_` array holding `values`.
+pub fn bool_array<'py>(py: Python<'py>, values: &[bool]) -> PyResult<&'py PyAny> {
+    ndarray(py, "?", values.len(), |buf| {
+        for (slot, val) in buf.iter_mut().zip(values) {
+            *slot = *val as u8;
+        }
+    })
+}
+
+/// Keys laid out back to back, as collected from a stream.
+#[derive(Default)]
+pub struct KeyBuffer {
+    data: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl KeyBuffer {
+    /// The keys of an iterable of `str` or `bytes`, for looking them up with
+    /// the GIL released.
+    pub fn from_keys(keys: &PyAny) -> PyResult<Self> {
+        let mut buffer = KeyBuffer::default();
+        for key in keys.iter()? {
+            buffer.push(key?.extract::<Key>()?.as_bytes());
+        }
+        Ok(buffer)
+    }
+
+    pub fn push(&mut self, key: &[u8]) {
+        self.data.extend_from_slice(key);
+        self.ends.push(self.data.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        (0..self.ends.len()).map(|i| {
+            let start = if i == 0 { 0 } else { self.ends[i - 1] };
+            &self.data[start..self.ends[i]]
+        })
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.iter().map(|key| key.len()).max().unwrap_or(0)
+    }
+
+    /// A fixed-width `numpy.bytes_` array of the keys, NUL-padded to `width`
+    /// bytes or, by default, to the longest key.
+    pub fn to_ndarray<'py>(&self, py: Python<'py>, width: Option<usize>) -> PyResult<&'py PyAny> {
+        let width = match width {
+            Some(width) => {
+                self.check_width(width, |key| key.len(), "bytes")?;
+                width
+            }
+            None => self.max_len(),
+        }
+        .max(1);
+        ndarray(py, &format!("S{}", width), width * self.len(), |buf| {
+            for (chunk, key) in buf.chunks_exact_mut(width).zip(self.iter()) {
+                chunk[..key.len()].copy_from_slice(key);
+            }
+        })
+    }
+
+    /// A fixed-width `numpy.str_` array of the keys, padded to `width`
+    /// characters or, by default, to the longest key.
+    pub fn to_unicode_ndarray<'py>(
+        &self,
+        py: Python<'py>,
+        width: Option<usize>,
+    ) -> PyResult<&'py PyAny> {
+        let chars = |key: &[u8]| String::from_utf8_lossy(key).chars().count();
+        let width = match width {
+            Some(width) => {
+                self.check_width(width, chars, "characters")?;
+                width
+            }
+            None => self.iter().map(chars).max().unwrap_or(0),
+        }
+        .max(1);
+        ndarray(py, &format!("=U{}", width), 4 * width * self.len(), |buf| {
+            for (chunk, key) in buf.chunks_exact_mut(4 * width).zip(self.iter()) {
+                let key = String::from_utf8_lossy(key);
+                for (slot, ch) in chunk.chunks_exact_mut(4).zip(key.chars()) {
+                    slot.copy_from_slice(&(ch as u32).to_ne_bytes());
+                }
+            }
+        })
+    }
+
+    fn check_width(&self, width: usize, len: impl Fn(&[u8]) -> usize, unit: &str) -> PyResult<()> {
+        match self.iter().find(|key| len(key) > width) {
+            Some(key) => Err(PyValueError::new_err(format!(
+                "key {:?} is {} {} long, longer than the array width of {}",
+                String::from_utf8_lossy(key),
+                len(key),
+                unit,
+                width
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Write `text` as a JSON string literal.
+pub fn write_json_str<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, ch) in text.char_indices() {
+        if ch != '"' && ch != '\\' && ch >= ' ' {
+            continue;
+        }
+        out.write_all(&text.as_bytes()[start..i])?;
+        match ch {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            _ => write!(out, "\\u{:04x}", ch as u32)?,
+        }
+        // Every escaped character is a single byte.
+        start = i + 1;
+    }
+    out.write_all(&text.as_bytes()[start..])?;
+    out.write_all(b"\"")
+}
+
+/// A transition label for DOT: printable ASCII as itself, other bytes as
+/// `\xNN`, escaped for a quoted DOT string.
+fn dot_label(byte: u8) -> String {
+    match byte {
+        b'"' => "\\\"".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b' '..=b'~' => (byte as char).to_string(),
+        _ => format!("\\\\x{:02x}", byte),
+    }
+}
+
+/// Write the nodes and transitions of `fst` as a Graphviz DOT digraph, in
+/// breadth-first order from the root. At most `max_nodes` nodes are drawn;
+/// transitions into the rest lead to a single `...` node. Final nodes are
+/// double circles, and nonzero outputs follow a `/` in the labels.
+pub fn write_dot<D: AsRef<[u8]>, W: Write>(
+    out: &mut W,
+    fst: &Fst<D>,
+    max_nodes: usize,
+) -> io::Result<()> {
+    writeln!(out, "digraph fst {{")?;
+    writeln!(out, "  rankdir = LR;")?;
+    writeln!(out, "  node [shape = circle];")?;
+    let mut ids: HashMap<CompiledAddr, usize> = HashMap::new();
+    let mut queue = VecDeque::new();
+    let mut truncated = false;
+    ids.insert(fst.root().addr(), 0);
+    queue.push_back(fst.root().addr());
+    while let Some(addr) = queue.pop_front() {
+        let node = fst.node(addr);
+        let id = ids[&addr];
+        let out_value = node.final_output().value();
+        if node.is_final() {
+            if out_value == 0 {
+                writeln!(out, "  {} [shape = doublecircle];", id)?;
+            } else {
+                writeln!(
+                    out,
+                    "  {} [shape = doublecircle, label = \"{}/{}\"];",
+                    id, id, out_value
+                )?;
+            }
+        } else {
+            writeln!(out, "  {};", id)?;
+        }
+        for t in node.transitions() {
+            let target = match ids.get(&t.addr) {
+                Some(target) => target.to_string(),
+                None if ids.len() < max_nodes => {
+                    let target = ids.len();
+                    ids.insert(t.addr, target);
+                    queue.push_back(t.addr);
+                    target.to_string()
+                }
+                None => {
+                    truncated = true;
+                    "more".to_string()
+                }
+            };
+            let label = dot_label(t.inp);
+            match t.out.value() {
+                0 => writeln!(out, "  {} -> {} [label = \"{}\"];", id, target, label)?,
+                value => writeln!(
+                    out,
+                    "  {} -> {} [label = \"{}/{}\"];",
+                    id, target, label, value
+                )?,
+            }
+        }
+    }
+    if truncated {
+        writeln!(out, "  more [shape = plaintext, label = \"...\"];")?;
+    }
+    writeln!(out, "}}")
+}
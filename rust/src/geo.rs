@@ -0,0 +1,198 @@
+//! Geohash keys and spatial queries over them.
+//!
+//! A geohash names a cell of the latitude/longitude grid, and every longer
+//! geohash starting with it names a cell inside it, so the points in an area
+//! are a handful of prefix ranges of an FST keyed by geohash.
+
+use fst::automaton::AlwaysMatch;
+use fst::Streamer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+
+use crate::lexicon::{with_fst, Lexicon};
+use crate::util;
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The `(south, north, west, east)` bounds of a cell.
+type Bounds = (f64, f64, f64, f64);
+
+fn check_precision(precision: usize) -> PyResult<()> {
+    if !(1..=12).contains(&precision) {
+        return Err(PyValueError::new_err(format!(
+            "precision must be between 1 and 12, not {}",
+            precision
+        )));
+    }
+    Ok(())
+}
+
+fn encode(lat: f64, lon: f64, precision: usize) -> PyResult<String> {
+    check_precision(precision)?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(PyValueError::new_err(format!(
+            "({}, {}) is not a valid latitude and longitude",
+            lat, lon
+        )));
+    }
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(precision);
+    let mut even = true;
+    for _ in 0..precision {
+        let mut index = 0;
+        for _ in 0..5 {
+            let (value, range): (f64, &mut (f64, f64)) = if even {
+                (lon, &mut lon_range)
+            } else {
+                (lat, &mut lat_range)
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(BASE32[index] as char);
+    }
+    Ok(hash)
+}
+
+fn bounds(hash: &str) -> PyResult<Bounds> {
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut even = true;
+    for ch in hash.bytes() {
+        let index = BASE32
+            .iter()
+            .position(|c| *c == ch.to_ascii_lowercase())
+            .ok_or_else(|| PyValueError::new_err(format!("invalid geohash {:?}", hash)))?;
+        for bit in (0..5).rev() {
+            let range: &mut (f64, f64) = if even { &mut lon_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if index >> bit & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Ok((lat_range.0, lat_range.1, lon_range.0, lon_range.1))
+}
+
+/// The cell of `precision` characters containing the point and the (up to)
+/// eight cells around it, without duplicates.
+fn cells_around(lat: f64, lon: f64, precision: usize) -> PyResult<Vec<String>> {
+    let center = encode(lat, lon, precision)?;
+    let (south, north, west, east) = bounds(&center)?;
+    let (lat, lon) = ((south + north) / 2.0, (west + east) / 2.0);
+    let (height, width) = (north - south, east - west);
+    let mut cells = Vec::with_capacity(9);
+    for dlat in [-1.0, 0.0, 1.0] {
+        let lat = lat + dlat * height;
+        if !(-90.0..=90.0).contains(&lat) {
+            continue;
+        }
+        for dlon in [-1.0, 0.0, 1.0] {
+            let mut lon = lon + dlon * width;
+            if lon > 180.0 {
+                lon -= 360.0;
+            } else if lon < -180.0 {
+                lon += 360.0;
+            }
+            cells.push(encode(lat, lon, precision)?);
+        }
+    }
+    cells.sort_unstable();
+    cells.dedup();
+    Ok(cells)
+}
+
+/// The geohash of `precision` characters of the cell containing the point.
+#[pyfunction]
+#[pyo3(signature = (lat, lon, precision=9))]
+pub fn geohash_encode(lat: f64, lon: f64, precision: usize) -> PyResult<String> {
+    encode(lat, lon, precision)
+}
+
+/// The `(lat, lon)` center of the cell named by `geohash`.
+#[pyfunction]
+pub fn geohash_decode(geohash: &str) -> PyResult<(f64, f64)> {
+    let (south, north, west, east) = bounds(geohash)?;
+    Ok(((south + north) / 2.0, (west + east) / 2.0))
+}
+
+/// Spatial queries over a Set or Map whose keys start with geohashes, such as
+/// `"u4pruydqq"` or `"u4pruydqq:station-12"`.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct GeoIndex {
+    lexicon: Lexicon,
+}
+
+impl GeoIndex {
+    /// The keys starting with any of `prefixes`, in key order, as a list of
+    /// keys for a Set or of `(key, value)` tuples for a Map.
+    fn collect<'py>(&self, py: Python<'py>, prefixes: &[String]) -> PyResult<&'py PyList> {
+        let matches = py.allow_threads(|| {
+            let mut matches = Vec::new();
+            for prefix in prefixes {
+                let range = util::bounded_range(Some(prefix.as_bytes()), None, None);
+                with_fst!(&self.lexicon, fst => {
+                    let mut stream = util::search_range(fst, &AlwaysMatch, range);
+                    while let Some((key, out)) = stream.next() {
+                        matches.push((key.to_vec(), out.value()));
+                    }
+                })
+            }
+            matches
+        });
+        let list = PyList::empty(py);
+        for (key, value) in matches {
+            let key = String::from_utf8_lossy(&key);
+            match self.lexicon.value_type() {
+                Some(value_type) => list.append((key, value_type.decode(py, value)))?,
+                None => list.append(key)?,
+            }
+        }
+        Ok(list)
+    }
+}
+
+#[pymethods]
+impl GeoIndex {
+    /// :type lexicon: Union[Set, Map]
+    #[new]
+    fn new(lexicon: &PyAny) -> PyResult<Self> {
+        Ok(GeoIndex {
+            lexicon: Lexicon::extract(lexicon)?,
+        })
+    }
+
+    /// The entries inside the cell named by `geohash`.
+    ///
+    /// :rtype: Union[List[str], List[Tuple[str, Union[int, float]]]]
+    fn within_cell<'py>(&self, py: Python<'py>, geohash: &str) -> PyResult<&'py PyList> {
+        bounds(geohash)?;
+        self.collect(py, &[geohash.to_ascii_lowercase()])
+    }
+
+    /// The entries in the cell of `precision` characters containing the
+    /// point and in the eight cells around it, so that every entry within
+    /// one cell size of the point is included.
+    ///
+    /// :rtype: Union[List[str], List[Tuple[str, Union[int, float]]]]
+    #[pyo3(signature = (lat, lon, precision=6))]
+    fn near<'py>(
+        &self,
+        py: Python<'py>,
+        lat: f64,
+        lon: f64,
+        precision: usize,
+    ) -> PyResult<&'py PyList> {
+        self.collect(py, &cells_around(lat, lon, precision)?)
+    }
+}
@@ -0,0 +1,113 @@
+//! Longest-prefix matching of IP addresses against CIDR networks.
+//!
+//! A network is stored as a key holding its address family (4 or 6) followed
+//! by one byte per bit of its prefix, so that the networks containing an
+//! address are exactly the keys that are prefixes of the address's own key.
+
+use fst::Map as FstMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::net::IpAddr;
+
+use crate::map::{Map, MapData};
+use crate::util;
+
+/// The key of the network made of the first `prefix_len` bits of `addr`.
+fn network_key(addr: IpAddr, prefix_len: usize) -> Vec<u8> {
+    let (family, octets) = match addr {
+        IpAddr::V4(addr) => (4, addr.octets().to_vec()),
+        IpAddr::V6(addr) => (6, addr.octets().to_vec()),
+    };
+    let mut key = Vec::with_capacity(1 + prefix_len);
+    key.push(family);
+    key.extend((0..prefix_len).map(|i| (octets[i / 8] >> (7 - i % 8)) & 1));
+    key
+}
+
+/// Parse `text` as an address or a network in CIDR notation, returning the
+/// address and the prefix length (the full address length for a bare
+/// address).
+fn parse_network(text: &str) -> PyResult<(IpAddr, usize)> {
+    let invalid = || PyValueError::new_err(format!("invalid IP network {:?}", text));
+    let (addr, prefix_len) = match text.split_once('/') {
+        Some((addr, len)) => (addr, Some(len)),
+        None => (text, None),
+    };
+    let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+    let max_len = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match prefix_len {
+        Some(len) => len.parse().ok().filter(|len| *len <= max_len),
+        None => Some(max_len),
+    }
+    .ok_or_else(invalid)?;
+    Ok((addr, prefix_len))
+}
+
+/// Maps IPv4 and IPv6 networks to values, answering lookups with the value
+/// of the most specific network containing an address.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct IpMatcher {
+    map: FstMap<MapData>,
+}
+
+#[pymethods]
+impl IpMatcher {
+    /// Wrap a Map written by `IpMatcher.build`.
+    #[new]
+    fn new(map: PyRef<Map>) -> PyResult<Self> {
+        map.check_open()?;
+        map.value_type.require_u64("IpMatcher()")?;
+        Ok(IpMatcher {
+            map: map.inner.clone(),
+        })
+    }
+
+    /// Build a matcher from `(network, value)` pairs, in memory or, if `path`
+    /// is given, in a file that can later be opened with
+    /// `IpMatcher(Map(path))`. Networks are strings in CIDR notation (or
+    /// anything whose `str()` is, such as `ipaddress` objects); bits past
+    /// the prefix length are ignored. If a network is given twice, the last
+    /// value wins.
+    ///
+    /// :type networks: Iterable[Tuple[Any, int]]
+    #[staticmethod]
+    #[pyo3(signature = (networks, path=None))]
+    fn build(py: Python, networks: &PyAny, path: Option<String>) -> PyResult<IpMatcher> {
+        let mut items = Vec::new();
+        for item in networks.iter()? {
+            let (network, value): (&PyAny, &PyAny) = item?.extract()?;
+            let (addr, prefix_len) = parse_network(network.str()?.to_str()?)?;
+            let key = network_key(addr, prefix_len);
+            let value = value.extract::<u64>()?;
+            items.push((key, value));
+        }
+        // A stable sort keeps duplicates in input order, so the last is kept.
+        items.sort_by(|(a, _), (b, _)| a.cmp(b));
+        items.reverse();
+        items.dedup_by(|(a, _), (b, _)| a == b);
+        items.reverse();
+        let map = Map::build_sorted(py, &items, path)?;
+        Ok(IpMatcher { map: map.inner })
+    }
+
+    /// The value of the most specific network containing the address `ip`,
+    /// or `None` if no network does.
+    ///
+    /// :type ip: Any
+    fn lookup(&self, ip: &PyAny) -> PyResult<Option<u64>> {
+        let text = ip.str()?;
+        let text = text.to_str()?;
+        let addr: IpAddr = text
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid IP address {:?}", text)))?;
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let key = network_key(addr, prefix_len);
+        Ok(util::prefixes_of(self.map.as_fst(), &key)
+            .last()
+            .map(|(_, value)| *value))
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.len()
+    }
+}
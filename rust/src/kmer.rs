@@ -0,0 +1,112 @@
+//! DNA k-mers packed at 2 bits per base.
+//!
+//! A k-mer of up to 32 bases is packed into a u64 code, with `A`, `C`, `G` and
+//! `T` as 0 to 3 and the last base in the lowest bits. Its FST key is the
+//! big-endian code cut to the `ceil(k / 4)` bytes it needs, so that keys of
+//! k-mers of the same length sort like the k-mers themselves.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::export;
+
+const BASES: [u8; 4] = *b"ACGT";
+
+/// Raise `ValueError` unless `k` is a supported k-mer length.
+pub fn check_k(k: usize) -> PyResult<()> {
+    if !(1..=32).contains(&k) {
+        return Err(PyValueError::new_err(format!(
+            "k must be between 1 and 32, not {}",
+            k
+        )));
+    }
+    Ok(())
+}
+
+/// The 2-bit code of `base`, in either case.
+fn base_bits(base: u8) -> Option<u64> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// The code of the k-mer `seq`.
+fn encode(seq: &[u8]) -> PyResult<u64> {
+    check_k(seq.len())?;
+    seq.iter().try_fold(0, |code, base| {
+        let bits = base_bits(*base).ok_or_else(|| {
+            PyValueError::new_err(format!("invalid base {:?} in k-mer", *base as char))
+        })?;
+        Ok(code << 2 | bits)
+    })
+}
+
+/// The FST key of the k-mer with `code`.
+pub fn key(code: u64, k: usize) -> PyResult<Vec<u8>> {
+    if k < 32 && code >> (2 * k) != 0 {
+        return Err(PyValueError::new_err(format!(
+            "{} is not the code of a {}-mer",
+            code, k
+        )));
+    }
+    Ok(code.to_be_bytes()[8 - k.div_ceil(4)..].to_vec())
+}
+
+/// Pack the k-mer `seq` (1 to 32 bases of `ACGT`, in either case) into its
+/// binary FST key.
+#[pyfunction]
+pub fn pack_kmer<'py>(py: Python<'py>, seq: &str) -> PyResult<&'py PyBytes> {
+    let code = encode(seq.as_bytes())?;
+    Ok(PyBytes::new(py, &key(code, seq.len())?))
+}
+
+/// Unpack a key made by `pack_kmer` back into a `k`-mer.
+#[pyfunction]
+pub fn unpack_kmer(key: &[u8], k: usize) -> PyResult<String> {
+    check_k(k)?;
+    if key.len() != k.div_ceil(4) {
+        return Err(PyValueError::new_err(format!(
+            "a {}-mer key is {} bytes long, not {}",
+            k,
+            k.div_ceil(4),
+            key.len()
+        )));
+    }
+    let code = key.iter().fold(0u64, |code, b| code << 8 | *b as u64);
+    Ok((0..k)
+        .rev()
+        .map(|i| BASES[(code >> (2 * i)) as usize & 3] as char)
+        .collect())
+}
+
+/// The codes of all k-mers of `seq`, in order, as a `numpy.uint64` array.
+/// Windows containing a base other than `ACGT` are skipped.
+#[pyfunction]
+pub fn kmer_codes<'py>(py: Python<'py>, seq: &str, k: usize) -> PyResult<&'py PyAny> {
+    check_k(k)?;
+    let mask = if k == 32 {
+        u64::MAX
+    } else {
+        (1 << (2 * k)) - 1
+    };
+    let mut codes = Vec::new();
+    let (mut code, mut valid) = (0u64, 0);
+    for base in seq.bytes() {
+        match base_bits(base) {
+            Some(bits) => {
+                code = (code << 2 | bits) & mask;
+                valid += 1;
+            }
+            None => valid = 0,
+        }
+        if valid >= k {
+            codes.push(code);
+        }
+    }
+    export::u64_array(py, &codes)
+}
@@ -0,0 +1,220 @@
+//! Byte string values, kept beside a Map of keys.
+//!
+//! The Map holds, for every key, the offset of its value in a payload stored
+//! next to it (`<path>.values` for a store on disk). A value is its length as
+//! a LEB128 varint followed by its bytes; keys with equal values share one
+//! copy.
+
+use fst::map::Stream;
+use fst::{IntoStreamer, Map as FstMap, Streamer};
+use memmap2::Mmap;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::sync::Arc;
+
+use crate::error::FormatError;
+use crate::lossy::{self, KeyObject};
+use crate::map::{Map, MapData};
+use crate::postings::{read_varint, write_varint};
+use crate::stream::{stream_class, Remaining};
+use crate::util::{self, Key};
+
+fn payload_path(path: &str) -> String {
+    format!("{}.values", path)
+}
+
+/// The value starting at `offset` in `buf`.
+fn decode(buf: &[u8], offset: u64) -> PyResult<&[u8]> {
+    let corrupt = || FormatError::new_err(format!("corrupt value at offset {}", offset));
+    let mut pos = usize::try_from(offset).map_err(|_| corrupt())?;
+    let len = read_varint(buf, &mut pos).ok_or_else(corrupt)?;
+    let end = usize::try_from(len)
+        .ok()
+        .and_then(|len| pos.checked_add(len))
+        .filter(|end| *end <= buf.len())
+        .ok_or_else(corrupt)?;
+    Ok(&buf[pos..end])
+}
+
+/// A mapping from keys to `bytes` values of any length, for payloads that do
+/// not fit a Map's integers. Lookups and iteration are as fast as a Map's,
+/// plus a copy of the value.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct KeyValueStore {
+    keys: FstMap<MapData>,
+    payload: MapData,
+}
+
+impl KeyValueStore {
+    fn lookup(&self, key: &[u8]) -> PyResult<Option<&[u8]>> {
+        self.keys
+            .get(key)
+            .map(|offset| decode(self.payload.as_ref(), offset))
+            .transpose()
+    }
+}
+
+#[pymethods]
+impl KeyValueStore {
+    /// Open a store written by `KeyValueStore.build` to `path`, along with
+    /// its values at `<path>.values`.
+    #[new]
+    fn new(py: Python, path: &str) -> PyResult<Self> {
+        let keys = Map::open(PyString::new(py, path))?.inner;
+        let file = File::open(payload_path(path))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(KeyValueStore {
+            keys,
+            payload: MapData::Mmap(Arc::new(mmap)),
+        })
+    }
+
+    /// Build a store from `(key, value)` pairs, in any order, where the last
+    /// value given for a key wins, in memory or, if `path` is given, in files
+    /// at `path` and `<path>.values`.
+    ///
+    /// :type items: Iterable[Tuple[Union[str, bytes], bytes]]
+    #[staticmethod]
+    #[pyo3(signature = (items, path=None))]
+    fn build(py: Python, items: &PyAny, path: Option<String>) -> PyResult<KeyValueStore> {
+        let mut values: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+        for item in items.iter()? {
+            let (key, value): (Key, &PyBytes) = item?.extract()?;
+            values.insert(key.as_bytes().to_vec(), value.as_bytes().to_vec());
+        }
+        let (offsets, payload) = py.allow_threads(|| {
+            let mut offsets = Vec::with_capacity(values.len());
+            let mut payload = Vec::new();
+            let mut seen: HashMap<&[u8], u64> = HashMap::new();
+            for (key, value) in &values {
+                let offset = *seen.entry(value).or_insert_with(|| {
+                    let offset = payload.len() as u64;
+                    write_varint(&mut payload, value.len() as u64);
+                    payload.extend_from_slice(value);
+                    offset
+                });
+                offsets.push((key.clone(), offset));
+            }
+            (offsets, payload)
+        });
+        if let Some(path) = &path {
+            std::fs::write(payload_path(path), &payload)?;
+        }
+        let keys = Map::build_sorted(py, &offsets, path)?.inner;
+        Ok(KeyValueStore {
+            keys,
+            payload: MapData::Vec(Arc::new(payload)),
+        })
+    }
+
+    fn __getitem__<'py>(&self, py: Python<'py>, key: Key) -> PyResult<&'py PyBytes> {
+        let value = self
+            .lookup(key.as_bytes())?
+            .ok_or_else(|| key.key_error())?;
+        Ok(PyBytes::new(py, value))
+    }
+
+    /// The value of `key`, or `default` if it is missing.
+    ///
+    /// :rtype: Optional[bytes]
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, py: Python, key: Key, default: Option<PyObject>) -> PyResult<Option<PyObject>> {
+        Ok(match self.lookup(key.as_bytes())? {
+            Some(value) => Some(PyBytes::new(py, value).into()),
+            None => default,
+        })
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.keys.contains_key(key.as_bytes())
+    }
+
+    fn __len__(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn __iter__(&self) -> KeyValueStream {
+        KeyValueStream::new(self, (None, None), false)
+    }
+
+    /// Stream the keys in order, as strings.
+    ///
+    /// :rtype: Iterator[str]
+    fn keys(&self) -> KeyValueStream {
+        KeyValueStream::new(self, (None, None), false)
+    }
+
+    /// Stream the `(key, value)` pairs in key order, only those with keys
+    /// starting with `prefix`, `>= ge` and `< lt` when those are given.
+    ///
+    /// :rtype: Iterator[Tuple[str, bytes]]
+    #[pyo3(signature = (prefix=None, ge=None, lt=None))]
+    fn items(&self, prefix: Option<Key>, ge: Option<Key>, lt: Option<Key>) -> KeyValueStream {
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        KeyValueStream::new(self, bounds, true)
+    }
+
+    /// The total size of the values, in bytes, after sharing equal ones.
+    #[getter]
+    fn payload_bytes(&self) -> usize {
+        self.payload.as_ref().len()
+    }
+}
+
+#[pyclass(weakref)]
+pub struct KeyValueStream {
+    stream: Stream<'static>,
+    _keys: Box<FstMap<MapData>>,
+    payload: MapData,
+    values: bool,
+    raw: bool,
+}
+
+impl KeyValueStream {
+    fn new(store: &KeyValueStore, bounds: util::KeyRange, values: bool) -> Self {
+        let keys = Box::new(store.keys.clone());
+        let (ge, lt) = bounds;
+        let mut builder = keys.range();
+        if let Some(ge) = &ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = &lt {
+            builder = builder.lt(lt);
+        }
+        let stream =
+            unsafe { std::mem::transmute::<Stream<'_>, Stream<'static>>(builder.into_stream()) };
+        KeyValueStream {
+            stream,
+            _keys: keys,
+            payload: store.payload.clone(),
+            values,
+            raw: false,
+        }
+    }
+}
+
+impl Remaining for KeyValueStream {}
+
+stream_class! {
+    raw impl KeyValueStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let Some((key, offset)) = this.stream.next() else {
+                return Ok(None);
+            };
+            let key: KeyObject = lossy::key_object(py, key, this.raw);
+            if !this.values {
+                return Ok(Some(key));
+            }
+            let value = PyBytes::new(py, decode(this.payload.as_ref(), offset)?);
+            Ok(Some((key, value).into_py(py)))
+        }
+    }
+}
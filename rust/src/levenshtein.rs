@@ -0,0 +1,66 @@
+//! Levenshtein automata built once and searched with across many FSTs.
+
+use fst::automaton::Levenshtein;
+use pyo3::prelude::*;
+use std::sync::Arc;
+
+use crate::error;
+use crate::util::Key;
+
+/// The states `fst` allows a Levenshtein automaton by default.
+const DEFAULT_STATE_LIMIT: usize = 10_000;
+
+/// The keys within `max_dist` edits of `key`, as an automaton for
+/// `Set.search` and `Map.search`. Building it is the expensive part of a
+/// fuzzy search, so a query kept around can be searched with across many
+/// Sets and Maps for free.
+///
+/// Building fails with `LevenshteinTooLargeError` if the automaton needs
+/// more than `state_limit` states; each takes a couple of kilobytes.
+#[pyclass(frozen, weakref, module = "rust_fst")]
+pub struct LevenshteinQuery {
+    lev: Arc<Levenshtein>,
+    key: String,
+    max_dist: u32,
+}
+
+impl LevenshteinQuery {
+    pub fn automaton(&self) -> Arc<Levenshtein> {
+        Arc::clone(&self.lev)
+    }
+}
+
+#[pymethods]
+impl LevenshteinQuery {
+    #[new]
+    #[pyo3(signature = (key, max_dist, state_limit=None))]
+    fn new(py: Python, key: Key, max_dist: u32, state_limit: Option<usize>) -> PyResult<Self> {
+        let key = key.as_str()?.to_owned();
+        let limit = state_limit.unwrap_or(DEFAULT_STATE_LIMIT);
+        let lev = py
+            .allow_threads(|| Levenshtein::new_with_limit(&key, max_dist, limit))
+            .map_err(error::lev_error)?;
+        Ok(LevenshteinQuery {
+            lev: Arc::new(lev),
+            key,
+            max_dist,
+        })
+    }
+
+    #[getter]
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    #[getter]
+    fn max_dist(&self) -> u32 {
+        self.max_dist
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<rust_fst.LevenshteinQuery key={:?} max_dist={}>",
+            self.key, self.max_dist
+        )
+    }
+}
@@ -0,0 +1,63 @@
+//! A Set or a Map, for helpers that work on the keys of either.
+
+use fst::{Map as FstMap, Set as FstSet};
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+
+use crate::map::{Map, MapData};
+use crate::set::{Set, SetData};
+use crate::values::ValueType;
+
+/// The FST of a Set or a Map. Map values are carried along as encoded
+/// outputs, with the `ValueType` that decodes them; every key of a Set has the
+/// output 0.
+pub enum Lexicon {
+    Set(FstSet<SetData>),
+    Map(FstMap<MapData>, ValueType),
+}
+
+impl Lexicon {
+    /// Take the FST of an open Set or Map, raising `TypeError` for anything
+    /// else.
+    pub fn extract(obj: &PyAny) -> PyResult<Self> {
+        if let Ok(set) = obj.extract::<PyRef<Set>>() {
+            set.check_open()?;
+            Ok(Lexicon::Set(set.inner.clone()))
+        } else if let Ok(map) = obj.extract::<PyRef<Map>>() {
+            map.check_open()?;
+            Ok(Lexicon::Map(map.inner.clone(), map.value_type))
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "expected a Set or a Map, not {}",
+                obj.get_type().name()?
+            )))
+        }
+    }
+
+    /// The type of the values of a Map, or `None` for a Set.
+    pub fn value_type(&self) -> Option<ValueType> {
+        match self {
+            Lexicon::Set(_) => None,
+            Lexicon::Map(_, value_type) => Some(*value_type),
+        }
+    }
+}
+
+/// Evaluate `$body` with `$fst` bound to the raw FST of `$lexicon`, whichever
+/// kind it is.
+macro_rules! with_fst {
+    ($lexicon:expr, $fst:ident => $body:expr) => {
+        match $lexicon {
+            $crate::lexicon::Lexicon::Set(set) => {
+                let $fst = set.as_fst();
+                $body
+            }
+            $crate::lexicon::Lexicon::Map(map, _) => {
+                let $fst = map.as_fst();
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) use with_fst;
@@ -1,29 +1,126 @@
 #![allow(deprecated)]
 use pyo3::prelude::*;
 
+mod aio;
+mod alias;
+mod arrow;
+mod autocomplete;
+mod backward;
+mod buffer;
+mod cache;
+mod collection;
+mod companion;
+mod counters;
+mod custom;
+mod domain;
+mod error;
+mod export;
+mod geo;
+mod ip;
+mod kmer;
+mod kvstore;
+mod levenshtein;
+mod lexicon;
+mod lossy;
 mod map;
+mod ngram;
+mod node;
+mod ordinal;
+mod postings;
+mod prefetch;
+mod residency;
+mod send;
 mod set;
+mod setlike;
+mod sort;
+mod spell;
+mod state;
+mod stream;
+mod tools;
+mod trace;
+mod translit;
 mod util;
+mod values;
+mod window;
+mod writer;
 
+// Mutable state that Python can see, the trace hook and the counts of lossy
+// decodes, is kept per module object (`state.rs`) rather than in statics, so
+// each (sub)interpreter that imports the module gets its own. The module is
+// not yet declared safe for more than one interpreter (PEP 684): PyO3 0.21
+// refuses to initialize a module in a second interpreter, so that takes a
+// newer PyO3.
+//
+// Every class is `Send`, streams included, so Sets, Maps and their streams
+// can be created on one thread and used on another. Declaring the module
+// safe for free-threaded CPython takes PyO3 0.23 and a build without the
+// stable ABI, so for now it still runs with the GIL, released for the work
+// done in Rust.
 #[pymodule]
-fn _native(_py: Python, m: &PyModule) -> PyResult<()> {
+fn _native(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<arrow::ArrowStream>()?;
     m.add_class::<map::Map>()?;
     m.add_class::<map::MapBuilder>()?;
+    m.add_class::<map::MapKeysView>()?;
+    m.add_class::<map::MapValuesView>()?;
+    m.add_class::<map::MapItemsView>()?;
     m.add_class::<map::MapKeys>()?;
+    m.add_class::<map::MapSplitKeys>()?;
     m.add_class::<map::MapValues>()?;
     m.add_class::<map::MapItems>()?;
     m.add_class::<map::MapRegexStream>()?;
     m.add_class::<map::MapLevStream>()?;
+    m.add_class::<map::MapSubsequenceStream>()?;
+    m.add_class::<map::MapCollectedStream>()?;
+    m.add_class::<map::MapDiff>()?;
+    m.add_class::<map::MapUnion>()?;
+    m.add_class::<map::MapIntersection>()?;
+    m.add_class::<map::MapDifference>()?;
+    m.add_class::<map::MapSymmetricDifference>()?;
 
     m.add_class::<set::Set>()?;
     m.add_class::<set::SetBuilder>()?;
     m.add_class::<set::SetStream>()?;
+    m.add_class::<set::SetSplitStream>()?;
+    m.add_class::<set::SetSuffixStream>()?;
     m.add_class::<set::SetRegexStream>()?;
     m.add_class::<set::SetLevStream>()?;
+    m.add_class::<set::SetSubsequenceStream>()?;
+    m.add_class::<set::SetCollectedStream>()?;
     m.add_class::<set::SetUnion>()?;
     m.add_class::<set::SetIntersection>()?;
     m.add_class::<set::SetDifference>()?;
     m.add_class::<set::SetSymmetricDifference>()?;
 
+    m.add_class::<alias::AliasedMap>()?;
+    m.add_class::<autocomplete::Autocomplete>()?;
+    m.add_class::<domain::DomainMatcher>()?;
+    m.add_class::<geo::GeoIndex>()?;
+    m.add_class::<ip::IpMatcher>()?;
+    m.add_class::<levenshtein::LevenshteinQuery>()?;
+    m.add_class::<ngram::NgramModel>()?;
+    m.add_class::<node::Node>()?;
+    m.add_class::<postings::PostingsIndex>()?;
+    m.add_class::<kvstore::KeyValueStore>()?;
+    m.add_class::<kvstore::KeyValueStream>()?;
+    m.add_class::<collection::SetCollection>()?;
+    m.add_class::<collection::MapCollection>()?;
+    m.add_class::<collection::MapCollectionKeys>()?;
+    m.add_class::<spell::SpellChecker>()?;
+
+    m.add_function(wrap_pyfunction!(trace::set_trace_hook, m)?)?;
+    m.add_function(wrap_pyfunction!(tools::build_file, m)?)?;
+    m.add_function(wrap_pyfunction!(tools::merge_files, m)?)?;
+    m.add_function(wrap_pyfunction!(tools::compare, m)?)?;
+    m.add_function(wrap_pyfunction!(lossy::lossy_decodes, m)?)?;
+    m.add_function(wrap_pyfunction!(lossy::reset_lossy_decodes, m)?)?;
+    m.add_function(wrap_pyfunction!(kmer::pack_kmer, m)?)?;
+    m.add_function(wrap_pyfunction!(kmer::unpack_kmer, m)?)?;
+    m.add_function(wrap_pyfunction!(kmer::kmer_codes, m)?)?;
+    m.add_function(wrap_pyfunction!(geo::geohash_encode, m)?)?;
+    m.add_function(wrap_pyfunction!(geo::geohash_decode, m)?)?;
+    error::register(py, m)?;
+    state::init(py, m)?;
+
     Ok(())
 }
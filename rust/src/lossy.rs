@@ -0,0 +1,102 @@
+//! Accounting for keys that are not valid UTF-8.
+//!
+//! Keys are returned to Python as `str`, with invalid UTF-8 replaced by
+//! U+FFFD. That hides corrupt keys, so every replacement made while iterating
+//! is counted, per interpreter, along with the first few offending keys.
+
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::state;
+
+/// How many offending keys are kept as samples.
+const MAX_SAMPLES: usize = 10;
+
+/// The count and samples of one interpreter. Code that decodes without the
+/// GIL, such as an Arrow export, holds on to the log it was created under.
+#[derive(Default)]
+pub struct LossyLog {
+    count: AtomicU64,
+    samples: Mutex<Vec<Vec<u8>>>,
+}
+
+impl LossyLog {
+    fn record(&self, key: &[u8]) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        if samples.len() < MAX_SAMPLES {
+            samples.push(key.to_vec());
+        }
+    }
+
+    /// `key` as a string, counting it here if it is not valid UTF-8.
+    pub fn decode<'k>(&self, key: &'k [u8]) -> Cow<'k, str> {
+        match std::str::from_utf8(key) {
+            Ok(key) => Cow::Borrowed(key),
+            Err(_) => {
+                self.record(key);
+                String::from_utf8_lossy(key)
+            }
+        }
+    }
+}
+
+/// `key` as a string, counting it in the current interpreter's log if it is
+/// not valid UTF-8.
+pub fn decode<'k>(py: Python, key: &'k [u8]) -> Cow<'k, str> {
+    match std::str::from_utf8(key) {
+        Ok(key) => Cow::Borrowed(key),
+        Err(_) => {
+            if let Ok(state) = state::get(py) {
+                state.lossy.record(key);
+            }
+            String::from_utf8_lossy(key)
+        }
+    }
+}
+
+/// The current interpreter's log, for decoding without the GIL.
+pub fn log(py: Python) -> PyResult<Arc<LossyLog>> {
+    Ok(state::get(py)?.lossy.clone())
+}
+
+/// Like `decode`, but owned.
+pub fn decode_owned(py: Python, key: &[u8]) -> String {
+    decode(py, key).into_owned()
+}
+
+/// A key returned by a stream: `str`, or `bytes` in raw mode.
+pub type KeyObject = PyObject;
+
+/// `key` as `bytes`, exactly as stored, for a stream in raw mode, or else
+/// as a string like `decode`.
+pub fn key_object(py: Python, key: &[u8], raw: bool) -> KeyObject {
+    if raw {
+        PyBytes::new(py, key).into()
+    } else {
+        decode(py, key).into_py(py)
+    }
+}
+
+/// The number of keys decoded with replacement characters since the last
+/// reset, and up to ten of them as bytes.
+#[pyfunction]
+pub fn lossy_decodes<'py>(py: Python<'py>) -> PyResult<(u64, Vec<&'py PyBytes>)> {
+    let log = &state::get(py)?.lossy;
+    let samples = log.samples.lock().unwrap_or_else(|e| e.into_inner());
+    let samples = samples.iter().map(|key| PyBytes::new(py, key)).collect();
+    Ok((log.count.load(Ordering::Relaxed), samples))
+}
+
+/// Reset the count and samples of `lossy_decodes()`.
+#[pyfunction]
+pub fn reset_lossy_decodes(py: Python) -> PyResult<()> {
+    let log = &state::get(py)?.lossy;
+    let mut samples = log.samples.lock().unwrap_or_else(|e| e.into_inner());
+    samples.clear();
+    log.count.store(0, Ordering::Relaxed);
+    Ok(())
+}
@@ -1,17 +1,95 @@
-use fst::automaton::Levenshtein;
+use fst::automaton::{AlwaysMatch, Levenshtein, Subsequence};
+use fst::map::{IndexedValue, OpBuilder};
 use fst::{IntoStreamer, Map as FstMap, MapBuilder as FstMapBuilder, Streamer};
 use memmap2::Mmap;
-use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
+use pyo3::exceptions::{PyIndexError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::pyclass::CompareOp;
+use pyo3::types::{IntoPyDict, PyBytes, PyCapsule, PyDict, PyList, PyString, PyTuple, PyType};
 use regex_automata::DenseDFA;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::BufWriter;
-use std::sync::Arc;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use crate::aio;
+use crate::arrow;
+use crate::backward::{self, Backward};
+use crate::buffer::Buffer;
+use crate::cache::LookupCache;
+use crate::counters::{Counters, Tally};
+use crate::custom;
+use crate::error;
+use crate::export::{self, KeyBuffer};
+use crate::levenshtein::LevenshteinQuery;
+use crate::lexicon::Lexicon;
+use crate::lossy::{self, KeyObject};
+use crate::node::Node;
+use crate::ordinal::{self, OrdinalIndex};
+use crate::prefetch::Prefetch;
+use crate::residency;
+use crate::send::SendOp;
+use crate::setlike;
+use crate::sort::{ExternalSorter, DEFAULT_MEMORY_LIMIT};
+use crate::stream::{self, stream_class, Collect, Remaining};
+use crate::tools;
+use crate::trace::{self, Span};
+use crate::util::{self, Key, KeyRange, MatchMode, PrefixDecoder};
+use crate::values::{ValueFilter, ValueType};
+use crate::window::Window;
+use crate::writer::{self, PyWriter, Target};
 
 #[derive(Clone)]
 pub enum MapData {
     Vec(Arc<Vec<u8>>),
     Mmap(Arc<Mmap>),
+    Buffer(Arc<Buffer>),
+}
+
+impl MapData {
+    /// Bytes of FST data held on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            MapData::Vec(v) => v.len(),
+            MapData::Mmap(_) | MapData::Buffer(_) => 0,
+        }
+    }
+
+    /// Bytes of FST data backed by a memory-mapped file.
+    pub fn mapped_bytes(&self) -> usize {
+        match self {
+            MapData::Mmap(m) => m.len(),
+            MapData::Vec(_) | MapData::Buffer(_) => 0,
+        }
+    }
+
+    /// Bytes of FST data borrowed from another Python object's buffer.
+    pub fn buffer_bytes(&self) -> usize {
+        match self {
+            MapData::Buffer(b) => b.len(),
+            MapData::Vec(_) | MapData::Mmap(_) => 0,
+        }
+    }
+
+    /// Where the FST data lives: `"memory"`, `"mmap"` or `"buffer"`.
+    pub fn storage(&self) -> &'static str {
+        match self {
+            MapData::Vec(_) => "memory",
+            MapData::Mmap(_) => "mmap",
+            MapData::Buffer(_) => "buffer",
+        }
+    }
+
+    /// The fraction of the FST data resident in memory; always 1 for data
+    /// this process allocated.
+    pub fn resident_fraction(&self) -> PyResult<f64> {
+        match self {
+            MapData::Vec(_) => Ok(1.0),
+            MapData::Mmap(m) => residency::resident_fraction(m),
+            MapData::Buffer(b) => residency::resident_fraction(b),
+        }
+    }
 }
 
 impl AsRef<[u8]> for MapData {
@@ -19,110 +97,245 @@ impl AsRef<[u8]> for MapData {
         match self {
             MapData::Vec(v) => v,
             MapData::Mmap(m) => m,
+            MapData::Buffer(b) => b,
         }
     }
 }
 
-#[pyclass]
+#[pyclass(subclass, weakref, module = "rust_fst")]
 #[derive(Clone)]
 pub struct Map {
-    inner: FstMap<MapData>,
+    pub inner: FstMap<MapData>,
+    cache: Option<Arc<LookupCache<Option<u64>>>>,
+    /// Usage counters, when enabled with `counters=True`.
+    counters: Option<Arc<Counters>>,
+    path: Option<String>,
+    /// Sampled keys for ordinal lookups, built on the first one and shared
+    /// with shallow copies.
+    ordinals: Arc<OnceLock<OrdinalIndex>>,
+    pub value_type: ValueType,
+    closed: bool,
 }
 
-#[pymethods]
 impl Map {
-    #[new]
-    fn new(path: &PyAny) -> PyResult<Self> {
-        if let Ok(p) = path.extract::<String>() {
-            let file = File::open(p)?;
-            let mmap = unsafe { Mmap::map(&file)? };
-            let map = FstMap::new(MapData::Mmap(Arc::new(mmap)))
-                .map_err(|e| PyValueError::new_err(e.to_string()))?;
-            Ok(Map { inner: map })
-        } else if let Ok(bytes) = path.extract::<&[u8]>() {
-            let map = FstMap::new(MapData::Vec(Arc::new(bytes.to_vec())))
-                .map_err(|e| PyValueError::new_err(e.to_string()))?;
-            Ok(Map { inner: map })
+    /// Raise `ClosedError`, a `ValueError`, if the Map has been closed.
+    pub fn check_open(&self) -> PyResult<()> {
+        if self.closed {
+            return Err(error::ClosedError::new_err("FST is closed"));
+        }
+        Ok(())
+    }
+
+    fn ordinal_index(&self, py: Python) -> &OrdinalIndex {
+        if let Some(index) = self.ordinals.get() {
+            return index;
+        }
+        // Built outside the cell: a thread blocked in `get_or_init` holds the
+        // GIL, which the building thread would need back. Threads racing here
+        // each build the index and the first one stored is kept.
+        let fst = self.inner.as_fst();
+        let built = py.allow_threads(|| OrdinalIndex::build(fst));
+        self.ordinals.get_or_init(|| built)
+    }
+
+    fn lookup(&self, key: &[u8]) -> Option<u64> {
+        let (val, cached) = match &self.cache {
+            Some(cache) => cache.get_or_insert_with(key, || self.inner.get(key)),
+            None => (self.inner.get(key), false),
+        };
+        if let Some(counters) = &self.counters {
+            counters.lookup(val.is_some(), cached);
+        }
+        val
+    }
+
+    /// The FSTs of this Map and the Maps in `others`, in order, for a set
+    /// operation over all of them.
+    fn operands(&self, others: &PyTuple) -> PyResult<Box<[FstMap<MapData>]>> {
+        self.check_open()?;
+        let mut maps = vec![self.inner.clone()];
+        for other in others {
+            let other: PyRef<Map> = other.extract()?;
+            other.check_open()?;
+            if other.value_type != self.value_type {
+                return Err(PyValueError::new_err(format!(
+                    "cannot combine Maps with value types {:?} and {:?}",
+                    self.value_type.name(),
+                    other.value_type.name()
+                )));
+            }
+            maps.push(other.inner.clone());
+        }
+        Ok(maps.into_boxed_slice())
+    }
+
+    /// Stream the items within the given bounds.
+    fn range_stream(
+        &self,
+        ge: Option<&[u8]>,
+        gt: Option<&[u8]>,
+        le: Option<&[u8]>,
+        lt: Option<&[u8]>,
+        reverse: bool,
+    ) -> MapItems {
+        let map = Box::new(self.inner.clone());
+        let stream = if reverse {
+            let (lower, upper) = backward::bounds(ge, gt, le, lt);
+            let stream = Backward::new(map.as_fst(), lower, upper);
+            MapEntries::Backward(unsafe {
+                std::mem::transmute::<Backward<'_, MapData>, Backward<'static, MapData>>(stream)
+            })
         } else {
-            Err(PyTypeError::new_err(
-                "Argument must be a path (str) or bytes",
-            ))
+            let mut builder = map.range();
+            if let Some(ge) = ge {
+                builder = builder.ge(ge);
+            }
+            if let Some(gt) = gt {
+                builder = builder.gt(gt);
+            }
+            if let Some(le) = le {
+                builder = builder.le(le);
+            }
+            if let Some(lt) = lt {
+                builder = builder.lt(lt);
+            }
+            let stream = builder.into_stream();
+            MapEntries::Forward(unsafe {
+                std::mem::transmute::<fst::map::Stream<'_>, fst::map::Stream<'static>>(stream)
+            })
+        };
+        let unbounded = ge.is_none() && gt.is_none() && le.is_none() && lt.is_none();
+        MapItems {
+            stream,
+            _map: map,
+            window: if unbounded {
+                Window::default().with_len(self.inner.len())
+            } else {
+                Window::default()
+            },
+            filter: ValueFilter::default(),
+            tally: Counters::stream(&self.counters),
+            value_type: self.value_type,
+            raw: false,
         }
     }
 
-    fn __contains__(&self, key: &str) -> bool {
-        self.inner.contains_key(key)
+    /// An item found by key and output, as a `(key, value)` tuple.
+    fn decode_item(&self, py: Python, (key, val): (Vec<u8>, u64)) -> (String, PyObject) {
+        (
+            lossy::decode_owned(py, &key),
+            self.value_type.decode(py, val),
+        )
     }
 
-    fn __getitem__(&self, key: &str) -> PyResult<u64> {
-        self.inner
-            .get(key)
-            .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+    fn enabled_counters(&self) -> PyResult<&Counters> {
+        self.counters.as_deref().ok_or_else(|| {
+            PyValueError::new_err("counters are not enabled, open the Map with counters=True")
+        })
     }
 
-    fn __len__(&self) -> usize {
-        self.inner.len()
+    fn cache_bytes(&self) -> usize {
+        self.cache.as_ref().map_or(0, |cache| cache.heap_bytes())
+    }
+
+    fn item_buffers(&self) -> (KeyBuffer, Vec<u64>) {
+        let mut keys = KeyBuffer::default();
+        let mut values = Vec::with_capacity(self.inner.len());
+        let mut stream = self.inner.stream();
+        while let Some((key, val)) = stream.next() {
+            keys.push(key);
+            values.push(val);
+        }
+        (keys, values)
     }
 
-    fn get(&self, key: &str, default: Option<u64>) -> Option<u64> {
-        self.inner.get(key).or(default)
+    /// Build a Map from parallel iterables of keys and values, sorting the
+    /// items by key first.
+    fn build_from_columns(
+        py: Python,
+        keys: &PyAny,
+        values: &PyAny,
+        path: Option<String>,
+        value_type: &str,
+    ) -> PyResult<Map> {
+        let value_type = ValueType::parse(value_type)?;
+        let mut items = Vec::new();
+        for (key, val) in keys.iter()?.zip(values.iter()?) {
+            let key: Key = key?.extract()?;
+            let val = value_type.encode(&key, val?)?;
+            items.push((key.as_bytes().to_vec(), val));
+        }
+        py.allow_threads(|| items.sort_unstable());
+        let mut builder = MapBuilder::create(path.clone(), value_type)?;
+        for (key, val) in &items {
+            builder.insert_bytes(key, *val)?;
+        }
+        Map::finish_open(py, builder, path)
     }
 
-    fn keys(&self) -> MapKeys {
-        let stream = self.inner.keys();
+    fn key_stream(&self) -> MapKeys {
+        let map = Box::new(self.inner.clone());
+        let stream = map.keys();
         let stream =
             unsafe { std::mem::transmute::<fst::map::Keys<'_>, fst::map::Keys<'static>>(stream) };
         MapKeys {
-            _map: self.inner.clone(),
             stream,
+            window: Window::default().with_len(self.inner.len()),
+            _map: map,
+            tally: Counters::stream(&self.counters),
+            raw: false,
         }
     }
 
-    fn values(&self) -> MapValues {
-        let stream = self.inner.values();
+    fn value_stream(&self) -> MapValues {
+        let map = Box::new(self.inner.clone());
+        let stream = map.values();
         let stream = unsafe {
             std::mem::transmute::<fst::map::Values<'_>, fst::map::Values<'static>>(stream)
         };
         MapValues {
-            _map: self.inner.clone(),
             stream,
+            window: Window::default().with_len(self.inner.len()),
+            _map: map,
+            tally: Counters::stream(&self.counters),
+            value_type: self.value_type,
         }
     }
 
-    fn items(&self) -> MapItems {
-        let stream = self.inner.stream();
-        let stream = unsafe {
-            std::mem::transmute::<fst::map::Stream<'_>, fst::map::Stream<'static>>(stream)
-        };
-        MapItems {
-            _map: self.inner.clone(),
-            stream,
+    /// Stream the matches of `lev` within `bounds`, `filter` and `window`,
+    /// or collect them on the rayon pool if `parallel`.
+    fn lev_search(
+        &self,
+        py: Python,
+        lev: Arc<Levenshtein>,
+        parallel: bool,
+        bounds: KeyRange,
+        filter: ValueFilter,
+        window: Window,
+    ) -> PyResult<PyObject> {
+        if parallel {
+            let fst = self.inner.as_fst();
+            let start = Instant::now();
+            let items = py.allow_threads(|| {
+                let mut items = util::par_search(fst, &*lev, &bounds);
+                items.retain(|&(_, val)| filter.admits(val));
+                items
+            });
+            trace::emit(py, "Map.search_lev", start.elapsed(), items.len())?;
+            let tally = Counters::stream(&self.counters);
+            let stream = MapCollectedStream::new(window.apply(items), tally, self.value_type);
+            return Ok(stream.into_py(py));
         }
-    }
-
-    fn search_re(&self, regex: &str) -> PyResult<MapRegexStream> {
-        let dfa = regex_automata::dense::Builder::new()
-            .anchored(true)
-            .build(regex)
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
-        let stream = self.inner.search(&dfa).into_stream();
-        let stream = unsafe {
-            std::mem::transmute::<
-                fst::map::Stream<'_, &DenseDFA<Vec<usize>, usize>>,
-                fst::map::Stream<'static, &'static DenseDFA<Vec<usize>, usize>>,
-            >(stream)
-        };
-        Ok(MapRegexStream {
-            _map: self.inner.clone(),
-            _dfa: dfa,
-            stream,
-        })
-    }
-
-    fn search_lev(&self, key: &str, max_dist: u32) -> PyResult<MapLevStream> {
-        let lev =
-            Levenshtein::new(key, max_dist).map_err(|e| PyValueError::new_err(e.to_string()))?;
-        let stream = self.inner.search(&lev).into_stream();
+        let map = Box::new(self.inner.clone());
+        let (ge, lt) = bounds;
+        let mut builder = map.search(&*lev);
+        if let Some(ge) = &ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = &lt {
+            builder = builder.lt(lt);
+        }
+        let stream = builder.into_stream();
         let stream = unsafe {
             std::mem::transmute::<
                 fst::map::Stream<'_, &Levenshtein>,
@@ -130,154 +343,2690 @@ impl Map {
             >(stream)
         };
         Ok(MapLevStream {
-            _map: self.inner.clone(),
-            _lev: lev,
             stream,
-        })
+            _map: map,
+            _lev: lev,
+            span: Span::new("Map.search_lev"),
+            prefetch: Prefetch::new(window),
+            filter,
+            tally: Counters::stream(&self.counters),
+            value_type: self.value_type,
+            raw: false,
+        }
+        .into_py(py))
     }
-}
 
-#[pyclass(unsendable)]
-pub struct MapKeys {
-    _map: FstMap<MapData>,
-    stream: fst::map::Keys<'static>,
-}
+    fn from_fst(inner: FstMap<MapData>) -> Self {
+        Map {
+            inner,
+            cache: None,
+            counters: None,
+            path: None,
+            ordinals: Arc::default(),
+            value_type: ValueType::U64,
+            closed: false,
+        }
+    }
 
-#[pymethods]
-impl MapKeys {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+    /// Whether `other` has the same keys with equal values. Values stored
+    /// as different types are compared as Python objects, with the GIL held.
+    fn same_items(&self, py: Python, other: &Map) -> PyResult<bool> {
+        let (a, b) = (self.inner.as_fst(), other.inner.as_fst());
+        if self.value_type == other.value_type {
+            return Ok(py.allow_threads(|| util::fst_eq(a, b)));
+        }
+        if a.len() != b.len() {
+            return Ok(false);
+        }
+        let (mut items_a, mut items_b) = (a.stream(), b.stream());
+        while let (Some((key_a, out_a)), Some((key_b, out_b))) = (items_a.next(), items_b.next()) {
+            let val_a = self.value_type.decode(py, out_a.value());
+            let val_b = other.value_type.decode(py, out_b.value());
+            if key_a != key_b || !val_a.as_ref(py).eq(val_b)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+
+    /// Build a Map from `items` sorted by distinct keys, in memory or at
+    /// `path`, and open it.
+    pub fn build_sorted(
+        py: Python,
+        items: &[(Vec<u8>, u64)],
+        path: Option<String>,
+    ) -> PyResult<Map> {
+        let mut builder = MapBuilder::create(path.clone(), ValueType::U64)?;
+        for (key, val) in items {
+            builder.insert_bytes(key, *val)?;
+        }
+        Map::finish_open(py, builder, path)
     }
-}
 
-#[pyclass(unsendable)]
-pub struct MapValues {
-    _map: FstMap<MapData>,
-    stream: fst::map::Values<'static>,
+    /// A copy of this Map that shares its FST data, which never changes, but
+    /// has its own lookup cache and counters.
+    fn deep_copy(&self) -> Map {
+        Map {
+            cache: self
+                .cache
+                .as_ref()
+                .map(|cache| Arc::new(cache.empty_copy())),
+            counters: self.counters.as_ref().map(|_| Arc::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Finish `builder`, started with `path`, and open the Map it built.
+    pub fn finish_open(py: Python, mut builder: MapBuilder, path: Option<String>) -> PyResult<Map> {
+        match (builder.finish(py)?, path) {
+            (Some(map), _) => Ok(map),
+            (None, Some(path)) => Ok(Map {
+                value_type: builder.value_type,
+                ..Map::open(PyString::new(py, &path))?
+            }),
+            (None, None) => unreachable!("an in-memory build returns its Map"),
+        }
+    }
+
+    pub fn open(path: &PyAny) -> PyResult<Self> {
+        if let Ok(p) = path.extract::<String>() {
+            let file = File::open(&p)?;
+            let mmap = unsafe { Mmap::map(&file)? };
+            error::check_fst_data(&mmap, &format!("{:?}", p))?;
+            let map = FstMap::new(MapData::Mmap(Arc::new(mmap))).map_err(error::fst_error)?;
+            Ok(Map {
+                path: Some(p),
+                ..Map::from_fst(map)
+            })
+        } else {
+            let buffer = Buffer::get(path).map_err(|err| {
+                if err.is_instance_of::<PyTypeError>(path.py()) {
+                    PyTypeError::new_err("Argument must be a path (str) or a bytes-like object")
+                } else {
+                    err
+                }
+            })?;
+            error::check_fst_data(&buffer, "bytes")?;
+            let map = FstMap::new(MapData::Buffer(Arc::new(buffer))).map_err(error::fst_error)?;
+            Ok(Map::from_fst(map))
+        }
+    }
 }
 
 #[pymethods]
-impl MapValues {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Map {
+    /// `value_type` says how the values were stored when the Map was built:
+    /// `"u64"` (the default), `"i64"` or `"f64"`. The FST does not record it.
+    ///
+    /// :type path: Union[str, bytes, bytearray, memoryview]
+    #[new]
+    #[pyo3(signature = (path, cache_size=None, counters=false, value_type="u64"))]
+    fn new(
+        path: &PyAny,
+        cache_size: Option<usize>,
+        counters: bool,
+        value_type: &str,
+    ) -> PyResult<Self> {
+        let mut map = Self::open(path)?;
+        map.cache = cache_size.and_then(LookupCache::new).map(Arc::new);
+        map.counters = counters.then(Arc::default);
+        map.value_type = ValueType::parse(value_type)?;
+        Ok(map)
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<u64> {
-        slf.stream.next()
+
+    /// The FST is opened in `__new__`; this only lets subclasses call
+    /// `super().__init__(...)` with the constructor arguments.
+    #[pyo3(signature = (*_args, **_kwargs))]
+    fn __init__(&self, _args: &PyTuple, _kwargs: Option<&PyDict>) {}
+
+    fn __contains__(&self, key: Key) -> PyResult<bool> {
+        self.check_open()?;
+        Ok(self.lookup(key.as_bytes()).is_some())
     }
-}
 
-#[pyclass(unsendable)]
-pub struct MapItems {
-    _map: FstMap<MapData>,
-    stream: fst::map::Stream<'static>,
-}
+    /// :rtype: Union[int, float]
+    fn __getitem__(&self, py: Python, key: Key) -> PyResult<PyObject> {
+        self.check_open()?;
+        let val = self.lookup(key.as_bytes()).ok_or_else(|| key.key_error())?;
+        Ok(self.value_type.decode(py, val))
+    }
 
-#[pymethods]
-impl MapItems {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+    /// How the values are stored: `"u64"`, `"i64"` or `"f64"`.
+    #[getter]
+    fn value_type(&self) -> &'static str {
+        self.value_type.name()
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<(String, u64)> {
-        let (bytes, val) = slf.stream.next()?;
-        Some((String::from_utf8_lossy(bytes).into_owned(), val))
+
+    fn __len__(&self) -> PyResult<usize> {
+        self.check_open()?;
+        Ok(self.inner.len())
     }
-}
 
-#[pyclass(unsendable)]
-pub struct MapRegexStream {
-    _map: FstMap<MapData>,
-    _dfa: DenseDFA<Vec<usize>, usize>,
-    stream: fst::map::Stream<'static, &'static DenseDFA<Vec<usize>, usize>>,
-}
+    /// Maps compare like dicts: equal if they have the same keys with equal
+    /// values, and not ordered. Between two Maps the items are compared in
+    /// Rust, in one pass over both; any other `collections.abc.Mapping`,
+    /// such as a dict, is compared with the Map's items as a dict.
+    fn __richcmp__(&self, py: Python, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        let eq = match op {
+            CompareOp::Eq => true,
+            CompareOp::Ne => false,
+            _ => return Ok(py.NotImplemented()),
+        };
+        if self.closed {
+            return Ok(py.NotImplemented());
+        }
+        let Ok(other) = other.extract::<PyRef<Map>>() else {
+            if !setlike::is_abc(other, "Mapping")? {
+                return Ok(py.NotImplemented());
+            }
+            let other = py
+                .get_type::<PyDict>()
+                .call1((other.call_method0("items")?,))?;
+            return Ok((self.to_dict(py)?.eq(other)? == eq).into_py(py));
+        };
+        if other.closed {
+            return Ok(py.NotImplemented());
+        }
+        Ok((self.same_items(py, &other)? == eq).into_py(py))
+    }
 
-#[pymethods]
-impl MapRegexStream {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+    fn is_empty(&self) -> PyResult<bool> {
+        self.check_open()?;
+        Ok(self.inner.is_empty())
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<(String, u64)> {
-        let (bytes, val) = slf.stream.next()?;
-        Some((String::from_utf8_lossy(bytes).into_owned(), val))
+
+    /// An empty in-memory Map.
+    ///
+    /// :rtype: Map
+    #[classmethod]
+    fn empty(cls: &PyType) -> PyResult<PyObject> {
+        let bytes = FstMapBuilder::memory()
+            .into_inner()
+            .map_err(error::fst_error)?;
+        let inner = FstMap::new(MapData::Vec(Arc::new(bytes))).map_err(error::fst_error)?;
+        util::instance_of(cls, Map::from_fst(inner))
     }
-}
 
-#[pyclass(unsendable)]
-pub struct MapLevStream {
-    _map: FstMap<MapData>,
-    _lev: Levenshtein,
-    stream: fst::map::Stream<'static, &'static Levenshtein>,
-}
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let this = slf.borrow();
+        if this.closed {
+            return Ok(format!(
+                "<{}.{} closed>",
+                slf.get_type().getattr("__module__")?,
+                slf.get_type().getattr("__qualname__")?,
+            ));
+        }
+        let data = this.inner.as_fst().as_inner();
+        let mut repr = format!(
+            "<{}.{} len={} size={}",
+            slf.get_type().getattr("__module__")?,
+            slf.get_type().getattr("__qualname__")?,
+            this.inner.len(),
+            util::human_size(data.as_ref().len()),
+        );
+        if let Some(path) = &this.path {
+            let path = PyString::new(slf.py(), path).repr()?;
+            repr.push_str(&format!(" source={}", path));
+        }
+        if this.value_type != ValueType::U64 {
+            repr.push_str(&format!(" value_type={}", this.value_type.name()));
+        }
+        repr.push_str(&format!(" {}>", data.storage()));
+        Ok(repr)
+    }
 
-#[pymethods]
-impl MapLevStream {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+    /// Release the FST data, unmapping the file once no iterator or view
+    /// created from this Map is still alive. Any later use of the Map raises
+    /// `ClosedError`, a `ValueError`. Closing twice is allowed.
+    fn close(&mut self) -> PyResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let bytes = FstMapBuilder::memory()
+            .into_inner()
+            .map_err(error::fst_error)?;
+        self.inner = FstMap::new(MapData::Vec(Arc::new(bytes))).map_err(error::fst_error)?;
+        self.cache = None;
+        self.ordinals = Arc::default();
+        self.closed = true;
+        Ok(())
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<(String, u64)> {
-        let (bytes, val) = slf.stream.next()?;
-        Some((String::from_utf8_lossy(bytes).into_owned(), val))
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.closed
     }
-}
 
-enum BuilderInner {
-    Memory(FstMapBuilder<Vec<u8>>),
-    File(FstMapBuilder<BufWriter<File>>),
-}
+    fn __enter__(slf: PyRef<Self>) -> PyResult<PyRef<Self>> {
+        slf.check_open()?;
+        Ok(slf)
+    }
 
-#[pyclass]
-pub struct MapBuilder {
-    inner: Option<BuilderInner>,
-}
+    fn __exit__(
+        &mut self,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
 
-#[pymethods]
-impl MapBuilder {
-    #[new]
-    fn new(path: Option<String>) -> PyResult<Self> {
-        let inner = if let Some(p) = path {
-            let file = File::create(p)?;
-            let wtr = BufWriter::new(file);
-            let builder =
-                FstMapBuilder::new(wtr).map_err(|e| PyValueError::new_err(e.to_string()))?;
-            BuilderInner::File(builder)
-        } else {
-            let builder = FstMapBuilder::memory();
-            BuilderInner::Memory(builder)
+    fn __sizeof__(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.inner.as_fst().as_inner().heap_bytes()
+            + self.cache_bytes()
+    }
+
+    /// Pickle a Map opened from a file as its absolute path, to be mapped
+    /// again when unpickled, and an in-memory Map as its bytes. The lookup
+    /// cache size, whether counters are enabled and the value type carry
+    /// over; the search indexes built on the Map do not.
+    ///
+    /// :rtype: Tuple[Any, Tuple[Union[str, bytes], Optional[int], bool, str]]
+    fn __reduce__(slf: &PyCell<Self>) -> PyResult<(PyObject, PyObject)> {
+        let py = slf.py();
+        let this = slf.borrow();
+        this.check_open()?;
+        let source = match (&this.path, this.inner.as_fst().as_inner()) {
+            (Some(path), MapData::Mmap(_)) => std::path::absolute(path)?.into_py(py),
+            (_, data) => PyBytes::new(py, data.as_ref()).into(),
         };
-        Ok(MapBuilder { inner: Some(inner) })
+        let cache_size = this.cache.as_ref().map(|cache| cache.capacity());
+        let args = (
+            source,
+            cache_size,
+            this.counters.is_some(),
+            this.value_type.name(),
+        );
+        Ok((slf.get_type().into(), args.into_py(py)))
     }
 
-    fn insert(&mut self, key: &str, val: u64) -> PyResult<()> {
-        match self.inner.as_mut() {
-            Some(BuilderInner::Memory(b)) => b
-                .insert(key, val)
-                .map_err(|e| PyValueError::new_err(e.to_string())),
-            Some(BuilderInner::File(b)) => b
-                .insert(key, val)
-                .map_err(|e| PyValueError::new_err(e.to_string())),
-            None => Err(PyValueError::new_err("Builder already finished")),
+    /// The position of `key` in key order, counting from 0. The first
+    /// ordinal lookup samples the keys in one pass; after that a lookup scans
+    /// a few hundred keys at most.
+    fn get_index(&self, py: Python, key: Key) -> PyResult<usize> {
+        self.check_open()?;
+        self.ordinal_index(py)
+            .index_of(self.inner.as_fst(), key.as_bytes())
+            .ok_or_else(|| key.key_error())
+    }
+
+    /// The key at position `index` in key order, where a negative index
+    /// counts from the end. The inverse of `get_index()`.
+    fn get_key(&self, py: Python, index: isize) -> PyResult<String> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        ordinal::resolve(index, fst.len())
+            .and_then(|index| self.ordinal_index(py).key_at(fst, index))
+            .map(|key| lossy::decode_owned(py, &key))
+            .ok_or_else(|| PyIndexError::new_err("Map index out of range"))
+    }
+
+    /// The items of `k` distinct keys drawn uniformly at random, in key
+    /// order. Passing `seed` makes the draw repeatable. Each key is found by
+    /// its position, walking down the FST with the keys below each node
+    /// counted, so the keys are neither scanned nor loaded.
+    ///
+    /// :type k: int
+    /// :type seed: Optional[int]
+    /// :rtype: List[Tuple[str, Union[int, float]]]
+    #[pyo3(signature = (k, seed=None))]
+    fn sample(&self, py: Python, k: u64, seed: Option<u64>) -> PyResult<Vec<(String, PyObject)>> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        if k > fst.len() as u64 {
+            return Err(PyValueError::new_err("sample larger than the Map"));
+        }
+        let items = py
+            .allow_threads(|| ordinal::items_at(fst, &ordinal::sample(fst.len() as u64, k, seed)));
+        Ok(items
+            .into_iter()
+            .map(|item| self.decode_item(py, item))
+            .collect())
+    }
+
+    /// :rtype: Map
+    fn __copy__(slf: &PyCell<Self>) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        this.check_open()?;
+        let copy = util::instance_of(slf.get_type(), this.clone())?;
+        util::copy_attrs(slf, copy.as_ref(slf.py()), None)?;
+        Ok(copy)
+    }
+
+    /// Like `copy.copy()`, this shares the FST data rather than copying its
+    /// bytes: the data is never modified, so a byte copy would only cost
+    /// memory. Only the lookup cache and the usage counters are new.
+    ///
+    /// :rtype: Map
+    fn __deepcopy__(slf: &PyCell<Self>, memo: &PyAny) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        this.check_open()?;
+        let copy = util::instance_of(slf.get_type(), this.deep_copy())?;
+        util::copy_attrs(slf, copy.as_ref(slf.py()), Some(memo))?;
+        Ok(copy)
+    }
+
+    /// Structural metrics of the FST: the number of keys, nodes,
+    /// transitions and final nodes, the largest number of transitions out of
+    /// a node, the length of the longest key and the serialized size, both
+    /// in bytes, plus the `fst` format version and where the data lives
+    /// (`"memory"`, `"mmap"` or `"buffer"`). Walks every node once.
+    ///
+    /// :rtype: Dict[str, Union[int, str]]
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        let stats = util::stats(py, fst)?;
+        stats.set_item("storage", fst.as_inner().storage())?;
+        Ok(stats)
+    }
+
+    /// Check the FST data against the checksum written when it was built,
+    /// raising `FormatError` if it does not match, to catch a corrupted file
+    /// before it returns wrong results. Reads all of the data; FSTs written
+    /// by `fst` before format version 3 have no checksum and raise too.
+    fn verify(&self, py: Python) -> PyResult<()> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        py.allow_threads(|| fst.verify()).map_err(error::fst_error)
+    }
+
+    /// The root node of the FST, for walking it node by node.
+    fn root(&self) -> PyResult<Node> {
+        self.check_open()?;
+        Ok(Node::root(Lexicon::Map(
+            self.inner.clone(),
+            self.value_type,
+        )))
+    }
+
+    /// The `count`, `min`, `max` and `mean` of the key lengths in bytes,
+    /// and a `histogram` of the number of keys of each length, from one pass
+    /// over the keys.
+    ///
+    /// :rtype: Dict[str, Any]
+    fn key_length_stats<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        util::key_length_stats(py, self.inner.as_fst())
+    }
+
+    /// The number of keys starting with each prefix of `depth` characters,
+    /// in key order, for a quick look at how the keys are spread out. Keys
+    /// shorter than `depth` characters count under themselves.
+    ///
+    /// :rtype: Dict[str, int]
+    #[pyo3(signature = (depth=1))]
+    fn prefix_histogram<'py>(&self, py: Python<'py>, depth: usize) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        let buckets = py.allow_threads(|| util::prefix_histogram(fst, depth));
+        let histogram = PyDict::new(py);
+        for (prefix, count) in buckets {
+            histogram.set_item(String::from_utf8_lossy(&prefix), count)?;
+        }
+        Ok(histogram)
+    }
+
+    /// The number of `lookups` (with their `hits`, `misses` and
+    /// `cache_hits`), of `streams` opened and of `keys` they yielded, shared
+    /// with shallow copies of this Map. Requires `counters=True`.
+    ///
+    /// :rtype: Dict[str, int]
+    fn counters<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.enabled_counters()?.to_dict(py)
+    }
+
+    /// Set all of `counters()` back to zero.
+    fn reset_counters(&self) -> PyResult<()> {
+        self.enabled_counters()?.reset();
+        Ok(())
+    }
+
+    /// :rtype: Dict[str, int]
+    fn memory_usage<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let data = self.inner.as_fst().as_inner();
+        let usage = PyDict::new(py);
+        usage.set_item("heap", data.heap_bytes())?;
+        usage.set_item("mmap", data.mapped_bytes())?;
+        usage.set_item("buffer", data.buffer_bytes())?;
+        usage.set_item("cache", self.cache_bytes())?;
+        Ok(usage)
+    }
+
+    /// The fraction of the FST's pages currently resident in RAM, from 0 to
+    /// 1, for checking that a memory-mapped file has been warmed up. An
+    /// in-memory Map is always fully resident.
+    fn residency(&self, py: Python) -> PyResult<f64> {
+        self.check_open()?;
+        let data = self.inner.as_fst().as_inner();
+        py.allow_threads(|| data.resident_fraction())
+    }
+
+    /// :rtype: Optional[Union[int, float]]
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, py: Python, key: Key, default: Option<PyObject>) -> PyResult<Option<PyObject>> {
+        self.check_open()?;
+        Ok(match self.lookup(key.as_bytes()) {
+            Some(val) => Some(self.value_type.decode(py, val)),
+            None => default,
+        })
+    }
+
+    /// The values of `keys`, with `default` for missing keys, as a list, all
+    /// looked up with the GIL released. With `numpy=True` they are returned
+    /// as a numpy array instead, of `uint64`, `int64` or `float64` as the
+    /// `value_type` says, where missing keys are `default`, or 0 if it is
+    /// not given.
+    ///
+    /// :type keys: Iterable[Union[str, bytes]]
+    /// :rtype: Union[List[Optional[Union[int, float]]], Any]
+    #[pyo3(signature = (keys, default=None, numpy=false))]
+    fn get_many(
+        &self,
+        py: Python,
+        keys: &PyAny,
+        default: Option<PyObject>,
+        numpy: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let lookups = KeyBuffer::from_keys(keys)?;
+        let values: Vec<_> =
+            py.allow_threads(|| lookups.iter().map(|key| self.lookup(key)).collect());
+        if numpy {
+            let default = default.unwrap_or_else(|| 0.into_py(py));
+            let default = default.as_ref(py);
+            let default = self.value_type.try_encode(default).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "default {} is not a value of value_type {:?}",
+                    default,
+                    self.value_type.name()
+                ))
+            })?;
+            let values: Vec<_> = values.iter().map(|val| val.unwrap_or(default)).collect();
+            Ok(export::value_array(py, &values, self.value_type)?.into())
+        } else {
+            Ok(value_list(py, &values, default, self.value_type).into())
         }
     }
 
-    fn finish(&mut self) -> PyResult<Option<Map>> {
-        match self.inner.take() {
-            Some(BuilderInner::Memory(b)) => {
-                let bytes = b
-                    .into_inner()
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                let map = FstMap::new(MapData::Vec(Arc::new(bytes)))
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                Ok(Some(Map { inner: map }))
+    fn __iter__(&self) -> PyResult<MapKeys> {
+        self.check_open()?;
+        Ok(self.key_stream())
+    }
+
+    fn keys(&self) -> PyResult<MapKeysView> {
+        self.check_open()?;
+        Ok(MapKeysView { map: self.clone() })
+    }
+
+    #[pyo3(signature = (prefix_len, limit=None, offset=0))]
+    fn keys_split(
+        &self,
+        prefix_len: usize,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapSplitKeys> {
+        self.check_open()?;
+        let window = Window::new(offset, limit).with_len(self.inner.len());
+        let map = Box::new(self.inner.clone());
+        let stream = map.keys();
+        let stream =
+            unsafe { std::mem::transmute::<fst::map::Keys<'_>, fst::map::Keys<'static>>(stream) };
+        Ok(MapSplitKeys {
+            stream,
+            _map: map,
+            window,
+            decoder: PrefixDecoder::new(prefix_len),
+        })
+    }
+
+    fn values(&self) -> PyResult<MapValuesView> {
+        self.check_open()?;
+        Ok(MapValuesView { map: self.clone() })
+    }
+
+    /// A view of the `(key, value)` items, iterated in key order, or in
+    /// descending order if `reverse` is true.
+    #[pyo3(signature = (reverse=false))]
+    fn items(&self, reverse: bool) -> PyResult<MapItemsView> {
+        self.check_open()?;
+        Ok(MapItemsView {
+            map: self.clone(),
+            reverse,
+        })
+    }
+
+    /// The `(key, value)` items whose keys are within the given bounds, in
+    /// key order, or in descending order if `reverse` is true: at least `ge`,
+    /// greater than `gt`, at most `le` and less than `lt`. Only the items in
+    /// the range are visited. `offset` and `limit` skip and cap the items
+    /// yielded, and `min_value` and `max_value` drop the items with values
+    /// outside them, inclusive, before they reach Python.
+    ///
+    /// :type min_value: Optional[Union[int, float]]
+    /// :type max_value: Optional[Union[int, float]]
+    #[pyo3(signature = (
+        ge=None,
+        gt=None,
+        le=None,
+        lt=None,
+        reverse=false,
+        limit=None,
+        offset=0,
+        min_value=None,
+        max_value=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn range(
+        &self,
+        ge: Option<Key>,
+        gt: Option<Key>,
+        le: Option<Key>,
+        lt: Option<Key>,
+        reverse: bool,
+        limit: Option<usize>,
+        offset: usize,
+        min_value: Option<&PyAny>,
+        max_value: Option<&PyAny>,
+    ) -> PyResult<MapItems> {
+        self.check_open()?;
+        let filter = ValueFilter::parse(self.value_type, min_value, max_value)?;
+        let mut stream = self
+            .range_stream(
+                ge.as_ref().map(Key::as_bytes),
+                gt.as_ref().map(Key::as_bytes),
+                le.as_ref().map(Key::as_bytes),
+                lt.as_ref().map(Key::as_bytes),
+                reverse,
+            )
+            .filtered(filter);
+        stream.window.narrow(offset, limit);
+        Ok(stream)
+    }
+
+    /// Stream the `(key, value)` items of the keys starting with `prefix`, in
+    /// key order, or in descending order if `reverse` is true. This is a range
+    /// scan, so it is much cheaper than an equivalent `search_re`.
+    /// `min_value` and `max_value` filter the items as for `range`.
+    ///
+    /// :type min_value: Optional[Union[int, float]]
+    /// :type max_value: Optional[Union[int, float]]
+    #[pyo3(signature = (prefix, reverse=false, limit=None, offset=0, min_value=None, max_value=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_prefix(
+        &self,
+        prefix: Key,
+        reverse: bool,
+        limit: Option<usize>,
+        offset: usize,
+        min_value: Option<&PyAny>,
+        max_value: Option<&PyAny>,
+    ) -> PyResult<MapItems> {
+        self.check_open()?;
+        let filter = ValueFilter::parse(self.value_type, min_value, max_value)?;
+        let (ge, lt) = util::bounded_range(Some(prefix.as_bytes()), None, None);
+        let mut stream = self
+            .range_stream(ge.as_deref(), None, None, lt.as_deref(), reverse)
+            .filtered(filter);
+        stream.window.narrow(offset, limit);
+        Ok(stream)
+    }
+
+    /// The item of the longest key that is a prefix of `text`, or `None` if
+    /// no key is. The FST is walked along `text` once, so this is as cheap
+    /// as a lookup.
+    ///
+    /// :rtype: Optional[Tuple[str, Union[int, float]]]
+    fn longest_prefix(&self, py: Python, text: Key) -> PyResult<Option<(String, PyObject)>> {
+        self.check_open()?;
+        let text = text.as_bytes();
+        let prefixes = util::prefixes_of(self.inner.as_fst(), text);
+        Ok(prefixes.last().map(|&(len, value)| {
+            (
+                lossy::decode_owned(py, &text[..len]),
+                self.value_type.decode(py, value),
+            )
+        }))
+    }
+
+    /// Split `text` into tokens by greedy longest match against the keys,
+    /// like `Set.segment`, returning the `(start, end)` character offsets of
+    /// each token with the value of its key, or `None` for a character that
+    /// no key matches.
+    ///
+    /// :rtype: List[Tuple[int, int, Optional[Union[int, float]]]]
+    fn segment(&self, py: Python, text: &str) -> PyResult<Vec<(usize, usize, Option<PyObject>)>> {
+        self.check_open()?;
+        let tokens = py.allow_threads(|| util::segment(self.inner.as_fst(), text));
+        Ok(tokens
+            .into_iter()
+            .map(|(start, end, value)| {
+                (
+                    start,
+                    end,
+                    value.map(|value| self.value_type.decode(py, value)),
+                )
+            })
+            .collect())
+    }
+
+    /// The items of every key that is a prefix of `text`, shortest first.
+    ///
+    /// :rtype: List[Tuple[str, Union[int, float]]]
+    fn all_prefixes(&self, py: Python, text: Key) -> PyResult<Vec<(String, PyObject)>> {
+        self.check_open()?;
+        let text = text.as_bytes();
+        let prefixes = util::prefixes_of(self.inner.as_fst(), text);
+        Ok(prefixes
+            .into_iter()
+            .map(|(len, value)| {
+                (
+                    lossy::decode_owned(py, &text[..len]),
+                    self.value_type.decode(py, value),
+                )
+            })
+            .collect())
+    }
+
+    /// The item of the smallest key, or `None` if the Map is empty. Like
+    /// `last`, `floor` and `ceiling`, this walks one path of the FST rather
+    /// than iterating.
+    ///
+    /// :rtype: Optional[Tuple[str, Union[int, float]]]
+    fn first(&self, py: Python) -> PyResult<Option<(String, PyObject)>> {
+        self.check_open()?;
+        let first = util::ceiling(self.inner.as_fst(), None);
+        Ok(first.map(|item| self.decode_item(py, item)))
+    }
+
+    /// The item of the largest key, or `None` if the Map is empty.
+    ///
+    /// :rtype: Optional[Tuple[str, Union[int, float]]]
+    fn last(&self, py: Python) -> PyResult<Option<(String, PyObject)>> {
+        self.check_open()?;
+        let last = util::floor(self.inner.as_fst(), None);
+        Ok(last.map(|item| self.decode_item(py, item)))
+    }
+
+    /// The item of the greatest key at most `key`, or `None` if there is
+    /// none.
+    ///
+    /// :rtype: Optional[Tuple[str, Union[int, float]]]
+    fn floor(&self, py: Python, key: Key) -> PyResult<Option<(String, PyObject)>> {
+        self.check_open()?;
+        let floor = util::floor(self.inner.as_fst(), Some(key.as_bytes()));
+        Ok(floor.map(|item| self.decode_item(py, item)))
+    }
+
+    /// The item of the least key at least `key`, or `None` if there is none.
+    ///
+    /// :rtype: Optional[Tuple[str, Union[int, float]]]
+    fn ceiling(&self, py: Python, key: Key) -> PyResult<Option<(String, PyObject)>> {
+        self.check_open()?;
+        let ceiling = util::ceiling(self.inner.as_fst(), Some(key.as_bytes()));
+        Ok(ceiling.map(|item| self.decode_item(py, item)))
+    }
+
+    /// Build a new Map of these items with the `(key, value)` pairs of `add`
+    /// inserted or, for keys already present, updated, and the keys of
+    /// `remove` taken out, in memory or, if `path` is given, on disk. The
+    /// edits are sorted in memory and merged with this Map in one pass. The
+    /// last value given for a key wins, a key in both `add` and `remove` is
+    /// removed, and the new Map keeps this one's `value_type`.
+    ///
+    /// :type add: Optional[Iterable[Tuple[Union[str, bytes], Union[int, float]]]]
+    /// :type remove: Optional[Iterable[Union[str, bytes]]]
+    /// :rtype: Map
+    #[pyo3(signature = (add=None, remove=None, path=None))]
+    fn rebuild(
+        slf: &PyCell<Self>,
+        add: Option<&PyAny>,
+        remove: Option<&PyAny>,
+        path: Option<String>,
+    ) -> PyResult<PyObject> {
+        let py = slf.py();
+        let this = slf.borrow();
+        this.check_open()?;
+        let mut edits = BTreeMap::new();
+        if let Some(add) = add {
+            for item in add.iter()? {
+                let (key, val): (Key, &PyAny) = item?.extract()?;
+                let val = this.value_type.encode(&key, val)?;
+                edits.insert(key.as_bytes().to_vec(), val);
             }
-            Some(BuilderInner::File(b)) => {
-                b.finish()
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                Ok(None)
+        }
+        let remove = util::key_set(remove)?;
+        let fst = this.inner.as_fst();
+        let mut builder = MapBuilder::create(path.clone(), this.value_type)?;
+        py.allow_threads(|| {
+            let add = edits.iter().map(|(key, val)| (key.as_slice(), *val));
+            util::merge_edits(fst, add, &remove, |key, val| builder.insert_bytes(key, val))
+        })?;
+        util::instance_of(slf.get_type(), Map::finish_open(py, builder, path)?)
+    }
+
+    /// The items as a dict, walking the FST with the GIL released.
+    ///
+    /// :rtype: Dict[Union[str, bytes], Union[int, float]]
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let (keys, values) = py.allow_threads(|| self.item_buffers());
+        stream::item_dict(py, &keys, &values, self.value_type, false)
+    }
+
+    fn to_arrays<'py>(&self, py: Python<'py>) -> PyResult<(&'py PyAny, &'py PyAny)> {
+        self.check_open()?;
+        let (keys, values) = py.allow_threads(|| self.item_buffers());
+        let values = export::value_array(py, &values, self.value_type)?;
+        Ok((keys.to_ndarray(py, None)?, values))
+    }
+
+    /// The items as a `pandas.Series` of values indexed by key.
+    #[pyo3(signature = (name=None))]
+    fn to_series<'py>(&self, py: Python<'py>, name: Option<&PyAny>) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let (keys, values) = py.allow_threads(|| self.item_buffers());
+        let pandas = py.import("pandas")?;
+        let index = pandas
+            .getattr("Index")?
+            .call1((keys.to_unicode_ndarray(py, None)?,))?;
+        let kwargs = [("index", index), ("name", name.into_py(py).into_ref(py))].into_py_dict(py);
+        pandas.getattr("Series")?.call(
+            (export::value_array(py, &values, self.value_type)?,),
+            Some(kwargs),
+        )
+    }
+
+    /// The items as a `pandas.DataFrame` with a key and a value column.
+    #[pyo3(signature = (key_column="key", value_column="value"))]
+    fn to_frame<'py>(
+        &self,
+        py: Python<'py>,
+        key_column: &str,
+        value_column: &str,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let (keys, values) = py.allow_threads(|| self.item_buffers());
+        let columns = [
+            (key_column, keys.to_unicode_ndarray(py, None)?),
+            (
+                value_column,
+                export::value_array(py, &values, self.value_type)?,
+            ),
+        ]
+        .into_py_dict(py);
+        py.import("pandas")?.getattr("DataFrame")?.call1((columns,))
+    }
+
+    /// Build a Map from an iterable of `(key, value)` pairs, in memory or, if
+    /// `path` is given, on disk, without a Python-level loop. Unless `sorted`
+    /// is true, the pairs are sorted by key first, spilling to temporary
+    /// files for large inputs. Repeated keys raise `OutOfOrderError`. The
+    /// values are stored as `value_type`, like with `MapBuilder`.
+    ///
+    /// :type iterable: Iterable[Tuple[Union[str, bytes], Union[int, float]]]
+    /// :rtype: Map
+    #[classmethod]
+    #[pyo3(signature = (iterable, path=None, sorted=false, value_type="u64"))]
+    fn from_iter(
+        cls: &PyType,
+        iterable: &PyAny,
+        path: Option<String>,
+        sorted: bool,
+        value_type: &str,
+    ) -> PyResult<PyObject> {
+        let py = cls.py();
+        let value_type = ValueType::parse(value_type)?;
+        let mut builder = MapBuilder::create(path.clone(), value_type)?;
+        if sorted {
+            for item in iterable.iter()? {
+                let (key, val): (Key, &PyAny) = item?.extract()?;
+                builder.insert_bytes(key.as_bytes(), value_type.encode(&key, val)?)?;
             }
-            None => Err(PyValueError::new_err("Builder already finished")),
+        } else {
+            let mut sorter = ExternalSorter::new(DEFAULT_MEMORY_LIMIT);
+            for item in iterable.iter()? {
+                let (key, val): (Key, &PyAny) = item?.extract()?;
+                let val = value_type.encode(&key, val)?;
+                sorter.push(key.as_bytes().to_vec(), val)?;
+            }
+            py.allow_threads(|| sorter.finish(|key, val| builder.insert_bytes(key, val)))?;
         }
+        util::instance_of(cls, Map::finish_open(py, builder, path)?)
+    }
+
+    /// Build a Map from a `pandas.Series` of non-negative integers indexed by
+    /// key, or of values of another `value_type`, in memory or, if `path` is
+    /// given, on disk. The index does not need to be sorted.
+    ///
+    /// :rtype: Map
+    #[classmethod]
+    #[pyo3(signature = (series, path=None, value_type="u64"))]
+    fn from_series(
+        cls: &PyType,
+        series: &PyAny,
+        path: Option<String>,
+        value_type: &str,
+    ) -> PyResult<PyObject> {
+        let keys = series.getattr("index")?.call_method0("tolist")?;
+        let values = series.call_method0("tolist")?;
+        let map = Map::build_from_columns(cls.py(), keys, values, path, value_type)?;
+        util::instance_of(cls, map)
+    }
+
+    /// Build a Map from the `key_column` and `value_column` columns of a
+    /// `pandas.DataFrame`, in memory or, if `path` is given, on disk.
+    ///
+    /// :rtype: Map
+    #[classmethod]
+    #[pyo3(signature = (frame, key_column="key", value_column="value", path=None, value_type="u64"))]
+    fn from_frame(
+        cls: &PyType,
+        frame: &PyAny,
+        key_column: &str,
+        value_column: &str,
+        path: Option<String>,
+        value_type: &str,
+    ) -> PyResult<PyObject> {
+        let keys = frame.get_item(key_column)?.call_method0("tolist")?;
+        let values = frame.get_item(value_column)?.call_method0("tolist")?;
+        let map = Map::build_from_columns(cls.py(), keys, values, path, value_type)?;
+        util::instance_of(cls, map)
+    }
+
+    /// The values of the keys starting with `prefix` that are `>= ge` and
+    /// `< lt`, in key order, as a `numpy.uint64`, `int64` or `float64` array
+    /// as the `value_type` says.
+    #[pyo3(signature = (prefix=None, ge=None, lt=None))]
+    fn values_array<'py>(
+        &self,
+        py: Python<'py>,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let range = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let values = py.allow_threads(|| {
+            let mut values = Vec::new();
+            let mut stream = util::search_range(self.inner.as_fst(), &AlwaysMatch, range);
+            while let Some((_, out)) = stream.next() {
+                values.push(out.value());
+            }
+            values
+        });
+        export::value_array(py, &values, self.value_type)
+    }
+
+    /// Write the items to `path` as JSON Lines, one
+    /// `{"<key_field>": key, "<value_field>": value}` object per line. Float
+    /// values that JSON cannot hold, NaN and the infinities, are written as
+    /// `null`.
+    #[pyo3(signature = (path, key_field="key", value_field="value"))]
+    fn write_jsonl(
+        &self,
+        py: Python,
+        path: &str,
+        key_field: &str,
+        value_field: &str,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        py.allow_threads(|| -> std::io::Result<()> {
+            let mut out = BufWriter::new(File::create(path)?);
+            let mut key_prefix = b"{".to_vec();
+            export::write_json_str(&mut key_prefix, key_field)?;
+            key_prefix.push(b':');
+            let mut value_prefix = b",".to_vec();
+            export::write_json_str(&mut value_prefix, value_field)?;
+            value_prefix.push(b':');
+            let mut stream = self.inner.stream();
+            while let Some((key, val)) = stream.next() {
+                out.write_all(&key_prefix)?;
+                export::write_json_str(&mut out, &String::from_utf8_lossy(key))?;
+                out.write_all(&value_prefix)?;
+                self.value_type.write_json(&mut out, val)?;
+                out.write_all(b"}\n")?;
+            }
+            out.flush()
+        })?;
+        Ok(())
+    }
+
+    /// The serialized FST, which `Map(data)` opens again.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        self.check_open()?;
+        Ok(PyBytes::new(py, self.inner.as_fst().as_bytes()))
+    }
+
+    /// Write the serialized FST to `path`, for opening with `Map(path)`.
+    fn write(&self, py: Python, path: &str) -> PyResult<()> {
+        self.check_open()?;
+        let bytes = self.inner.as_fst().as_bytes();
+        py.allow_threads(|| std::fs::write(path, bytes))?;
+        Ok(())
+    }
+
+    /// Write the FST's nodes and transitions to `path` as a Graphviz DOT
+    /// graph, drawing at most `max_nodes` nodes, nearest to the root first.
+    #[pyo3(signature = (path, max_nodes=1000))]
+    fn export_dot(&self, py: Python, path: &str, max_nodes: usize) -> PyResult<()> {
+        self.check_open()?;
+        py.allow_threads(|| -> std::io::Result<()> {
+            let mut out = BufWriter::new(File::create(path)?);
+            export::write_dot(&mut out, self.inner.as_fst(), max_nodes)?;
+            out.flush()
+        })?;
+        Ok(())
     }
+
+    /// A `pyarrow.RecordBatchReader` over the items, in batches of
+    /// `batch_size` rows with a `key: large_string` and a `value` column of
+    /// `uint64`, `int64` or `float64` as the `value_type` says.
+    #[pyo3(signature = (batch_size=65536))]
+    fn to_arrow<'py>(&self, py: Python<'py>, batch_size: usize) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let fst = self.inner.as_fst().clone();
+        let items = arrow::ItemChunks::new(fst, batch_size, self.value_type, lossy::log(py)?);
+        let stream = Py::new(py, arrow::ArrowStream::new(Box::new(items)))?;
+        py.import("pyarrow")?
+            .getattr("RecordBatchReader")?
+            .call_method1("from_stream", (stream,))
+    }
+
+    /// Only keys starting with `prefix`, `>= ge` and `< lt` are searched,
+    /// when those are given, and only the matches with values between
+    /// `min_value` and `max_value`, inclusive, are yielded. Both are checked
+    /// as the FST is walked, so the other matches never reach Python.
+    ///
+    /// By default `regex` has to match a whole key; `match_mode="prefix"`
+    /// matches keys starting with a match and `match_mode="substring"` keys
+    /// containing one, like `re.match` and `re.search`. With
+    /// `case_insensitive`, case is ignored.
+    ///
+    /// :type min_value: Optional[Union[int, float]]
+    /// :type max_value: Optional[Union[int, float]]
+    /// :rtype: Iterator[Tuple[str, int]]
+    #[pyo3(signature = (
+        regex,
+        parallel=false,
+        prefix=None,
+        ge=None,
+        lt=None,
+        match_mode="anchored",
+        case_insensitive=false,
+        limit=None,
+        offset=0,
+        min_value=None,
+        max_value=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_re(
+        &self,
+        py: Python,
+        regex: Key,
+        parallel: bool,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        match_mode: &str,
+        case_insensitive: bool,
+        limit: Option<usize>,
+        offset: usize,
+        min_value: Option<&PyAny>,
+        max_value: Option<&PyAny>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let window = Window::new(offset, limit);
+        let filter = ValueFilter::parse(self.value_type, min_value, max_value)?;
+        let mode = MatchMode::parse(match_mode)?;
+        let dfa = util::search_dfa(regex.as_str()?, mode, case_insensitive)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        if parallel {
+            let fst = self.inner.as_fst();
+            let start = Instant::now();
+            let items = py.allow_threads(|| {
+                let mut items = util::par_search(fst, &dfa, &bounds);
+                items.retain(|&(_, val)| filter.admits(val));
+                items
+            });
+            trace::emit(py, "Map.search_re", start.elapsed(), items.len())?;
+            let tally = Counters::stream(&self.counters);
+            let stream = MapCollectedStream::new(window.apply(items), tally, self.value_type);
+            return Ok(stream.into_py(py));
+        }
+        let dfa = Box::new(dfa);
+        let map = Box::new(self.inner.clone());
+        let (ge, lt) = bounds;
+        let mut builder = map.search(&*dfa);
+        if let Some(ge) = &ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = &lt {
+            builder = builder.lt(lt);
+        }
+        let stream = builder.into_stream();
+        let stream = unsafe {
+            std::mem::transmute::<
+                fst::map::Stream<'_, &DenseDFA<Vec<usize>, usize>>,
+                fst::map::Stream<'static, &'static DenseDFA<Vec<usize>, usize>>,
+            >(stream)
+        };
+        Ok(MapRegexStream {
+            stream,
+            _map: map,
+            _dfa: dfa,
+            span: Span::new("Map.search_re"),
+            prefetch: Prefetch::new(window),
+            filter,
+            tally: Counters::stream(&self.counters),
+            value_type: self.value_type,
+            raw: false,
+        }
+        .into_py(py))
+    }
+
+    /// Stream the `(key, value)` items of the keys containing the characters of `query` in
+    /// order, though not necessarily next to each other, like the fuzzy
+    /// finders of editors: `"hpf"` matches `"/home/projects/foo"`.
+    #[pyo3(signature = (query, limit=None, offset=0))]
+    fn search_subsequence(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapSubsequenceStream> {
+        self.check_open()?;
+        let query: Box<str> = query.into();
+        let map = Box::new(self.inner.clone());
+        let stream = map.search(Subsequence::new(&query)).into_stream();
+        let stream = unsafe {
+            std::mem::transmute::<
+                fst::map::Stream<'_, Subsequence<'_>>,
+                fst::map::Stream<'static, Subsequence<'static>>,
+            >(stream)
+        };
+        Ok(MapSubsequenceStream {
+            stream,
+            _map: map,
+            _query: query,
+            span: Span::new("Map.search_subsequence"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            tally: Counters::stream(&self.counters),
+            value_type: self.value_type,
+            raw: false,
+        })
+    }
+
+    /// Only keys starting with `prefix`, `>= ge` and `< lt` are searched,
+    /// when those are given, and only the matches with values between
+    /// `min_value` and `max_value`, inclusive, are yielded. Both are checked
+    /// as the FST is walked, so the other matches never reach Python.
+    ///
+    /// :type min_value: Optional[Union[int, float]]
+    /// :type max_value: Optional[Union[int, float]]
+    /// :rtype: Iterator[Tuple[str, int]]
+    #[pyo3(signature = (
+        key,
+        max_dist,
+        parallel=false,
+        prefix=None,
+        ge=None,
+        lt=None,
+        limit=None,
+        offset=0,
+        min_value=None,
+        max_value=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_lev(
+        &self,
+        py: Python,
+        key: Key,
+        max_dist: u32,
+        parallel: bool,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+        min_value: Option<&PyAny>,
+        max_value: Option<&PyAny>,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let filter = ValueFilter::parse(self.value_type, min_value, max_value)?;
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        self.lev_search(py, Arc::new(lev), parallel, bounds, filter, window)
+    }
+
+    /// Search for the keys within `query`'s distance of its key, like
+    /// `search_lev`, with an automaton built once for any number of
+    /// searches.
+    ///
+    /// :rtype: Iterator[Tuple[str, int]]
+    #[pyo3(signature = (query, parallel=false, prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        py: Python,
+        query: PyRef<LevenshteinQuery>,
+        parallel: bool,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        let filter = ValueFilter::default();
+        self.lev_search(py, query.automaton(), parallel, bounds, filter, window)
+    }
+
+    /// Search with an automaton written in Python: an object with `start()`,
+    /// `is_match(state)` and `accept(state, byte)` methods, and optionally
+    /// `can_match(state)` to stop walking paths that cannot lead to a match.
+    /// `start` returns the state before any byte, `accept` the state after
+    /// `byte`, and a key matches if `is_match` is true for the state after
+    /// its last byte.
+    ///
+    /// Every byte tried calls back into Python with the GIL held, so this is
+    /// much slower than `search_re` or `search_lev`; `can_match` and a
+    /// `prefix` keep the walk small. The matches are collected before they
+    /// are yielded, and an exception raised by a callback is raised here.
+    ///
+    /// :rtype: Iterator[Tuple[str, int]]
+    #[pyo3(signature = (automaton, prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_custom(
+        &self,
+        py: Python,
+        automaton: &PyAny,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapCollectedStream> {
+        self.check_open()?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        let fst = self.inner.as_fst();
+        let matches = custom::search(py, "Map.search_custom", fst, automaton, bounds, window)?;
+        Ok(MapCollectedStream::new(
+            matches,
+            Counters::stream(&self.counters),
+            self.value_type,
+        ))
+    }
+
+    /// Look up `keys` on a worker thread and return an awaitable resolving to
+    /// the list of their values, with `default` for missing keys.
+    ///
+    /// :type keys: Iterable[Union[str, bytes]]
+    /// :rtype: Awaitable[List[Optional[Union[int, float]]]]
+    #[pyo3(signature = (keys, default=None))]
+    fn aget_many<'py>(
+        &self,
+        py: Python<'py>,
+        keys: &PyAny,
+        default: Option<PyObject>,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let lookups = KeyBuffer::from_keys(keys)?;
+        let map = self.clone();
+        let value_type = self.value_type;
+        aio::run(
+            py,
+            "Map.aget_many",
+            move || {
+                let values: Vec<_> = lookups.iter().map(|key| map.lookup(key)).collect();
+                Ok(values)
+            },
+            move |py, values| {
+                let list = value_list(py, &values, default, value_type);
+                Ok((list.into_py(py), values.len()))
+            },
+        )
+    }
+
+    /// Stream the differences from this map to `other` as
+    /// `(key, old, new)` tuples in key order, where `old` is `None` for keys
+    /// only in `other` and `new` is `None` for keys missing from it.
+    #[pyo3(signature = (other, limit=None, offset=0))]
+    fn diff(&self, other: &Map, limit: Option<usize>, offset: usize) -> PyResult<MapDiff> {
+        self.check_open()?;
+        other.check_open()?;
+        if other.value_type != self.value_type {
+            return Err(PyValueError::new_err(format!(
+                "cannot compare Maps with value types {:?} and {:?}",
+                self.value_type.name(),
+                other.value_type.name()
+            )));
+        }
+        let maps = Box::new([self.inner.clone(), other.inner.clone()]);
+        let stream = maps[0].op().add(&maps[1]).union();
+        let stream = unsafe {
+            SendOp::new(std::mem::transmute::<
+                fst::map::Union<'_>,
+                fst::map::Union<'static>,
+            >(stream))
+        };
+        Ok(MapDiff {
+            stream,
+            _maps: maps,
+            window: Window::new(offset, limit),
+            span: Span::new("Map.diff"),
+            value_type: self.value_type,
+            raw: false,
+        })
+    }
+
+    /// The items of the keys in this Map or any of `others`, streamed in one
+    /// pass over all of them. The values of a key in several Maps are
+    /// combined by `merge`: `"first"` or `"last"` keeps the value from the
+    /// first or last of them in argument order, and `"sum"` (saturating),
+    /// `"min"` and `"max"` combine them.
+    ///
+    /// Like the other operations, it takes an `offset` and `limit` to skip
+    /// and cap the items streamed.
+    ///
+    /// :type others: Map
+    #[pyo3(signature = (*others, merge="first", limit=None, offset=0))]
+    fn union(
+        &self,
+        others: &PyTuple,
+        merge: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapUnion> {
+        let merge = Merge::parse(merge, self.value_type)?;
+        let maps = self.operands(others)?;
+        let op = maps.iter().collect::<OpBuilder>().union();
+        let stream = unsafe {
+            SendOp::new(std::mem::transmute::<
+                fst::map::Union<'_>,
+                fst::map::Union<'static>,
+            >(op))
+        };
+        Ok(MapUnion::new(
+            stream,
+            maps,
+            merge,
+            self.value_type,
+            "Map.union",
+            Window::new(offset, limit),
+        ))
+    }
+
+    /// The items of the keys in this Map and in every one of `others`, with
+    /// their values combined by `merge` as in `union`.
+    ///
+    /// :type others: Map
+    #[pyo3(signature = (*others, merge="first", limit=None, offset=0))]
+    fn intersection(
+        &self,
+        others: &PyTuple,
+        merge: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapIntersection> {
+        let merge = Merge::parse(merge, self.value_type)?;
+        let maps = self.operands(others)?;
+        let op = maps.iter().collect::<OpBuilder>().intersection();
+        let stream = unsafe {
+            SendOp::new(std::mem::transmute::<
+                fst::map::Intersection<'_>,
+                fst::map::Intersection<'static>,
+            >(op))
+        };
+        Ok(MapIntersection {
+            stream,
+            _maps: maps,
+            merge,
+            span: Span::new("Map.intersection"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            value_type: self.value_type,
+            raw: false,
+        })
+    }
+
+    /// The items of this Map whose keys are in none of `others`.
+    ///
+    /// :type others: Map
+    #[pyo3(signature = (*others, limit=None, offset=0))]
+    fn difference(
+        &self,
+        others: &PyTuple,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapDifference> {
+        let maps = self.operands(others)?;
+        let op = maps.iter().collect::<OpBuilder>().difference();
+        let stream = unsafe {
+            SendOp::new(std::mem::transmute::<
+                fst::map::Difference<'_>,
+                fst::map::Difference<'static>,
+            >(op))
+        };
+        Ok(MapDifference {
+            stream,
+            _maps: maps,
+            span: Span::new("Map.difference"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            value_type: self.value_type,
+            raw: false,
+        })
+    }
+
+    /// The items of the keys in an odd number of this Map and `others`, with
+    /// their values combined by `merge` as in `union`.
+    ///
+    /// :type others: Map
+    #[pyo3(signature = (*others, merge="first", limit=None, offset=0))]
+    fn symmetric_difference(
+        &self,
+        others: &PyTuple,
+        merge: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<MapSymmetricDifference> {
+        let merge = Merge::parse(merge, self.value_type)?;
+        let maps = self.operands(others)?;
+        let op = maps.iter().collect::<OpBuilder>().symmetric_difference();
+        let stream = unsafe {
+            SendOp::new(std::mem::transmute::<
+                fst::map::SymmetricDifference<'_>,
+                fst::map::SymmetricDifference<'static>,
+            >(op))
+        };
+        Ok(MapSymmetricDifference {
+            stream,
+            _maps: maps,
+            merge,
+            span: Span::new("Map.symmetric_difference"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            value_type: self.value_type,
+            raw: false,
+        })
+    }
+
+    /// Like `search_re`, but searches on a worker thread and returns an
+    /// awaitable resolving to the list of matches.
+    ///
+    /// :rtype: Awaitable[List[Tuple[str, int]]]
+    #[pyo3(signature = (regex, parallel=false))]
+    fn asearch_re<'py>(&self, py: Python<'py>, regex: Key, parallel: bool) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let dfa = util::regex_dfa(regex.as_str()?)?;
+        let fst = self.inner.as_fst().clone();
+        let value_type = self.value_type;
+        aio::run(
+            py,
+            "Map.asearch_re",
+            move || {
+                Ok(if parallel {
+                    util::par_search(&fst, &dfa, &(None, None))
+                } else {
+                    util::search(&fst, &dfa)
+                })
+            },
+            move |py, matches| match_list(py, matches, value_type),
+        )
+    }
+
+    /// Like `search_lev`, but searches on a worker thread and returns an
+    /// awaitable resolving to the list of matches.
+    ///
+    /// :rtype: Awaitable[List[Tuple[str, int]]]
+    #[pyo3(signature = (key, max_dist, parallel=false))]
+    fn asearch_lev<'py>(
+        &self,
+        py: Python<'py>,
+        key: Key,
+        max_dist: u32,
+        parallel: bool,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let fst = self.inner.as_fst().clone();
+        let value_type = self.value_type;
+        aio::run(
+            py,
+            "Map.asearch_lev",
+            move || {
+                Ok(if parallel {
+                    util::par_search(&fst, &lev, &(None, None))
+                } else {
+                    util::search(&fst, &lev)
+                })
+            },
+            move |py, matches| match_list(py, matches, value_type),
+        )
+    }
+
+    /// The number of keys `search_re` would find.
+    #[pyo3(signature = (regex, parallel=false, match_mode="anchored", case_insensitive=false))]
+    fn count_re(
+        &self,
+        py: Python,
+        regex: Key,
+        parallel: bool,
+        match_mode: &str,
+        case_insensitive: bool,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        let mode = MatchMode::parse(match_mode)?;
+        let dfa = util::search_dfa(regex.as_str()?, mode, case_insensitive)?;
+        let fst = self.inner.as_fst();
+        Ok(py.allow_threads(|| {
+            if parallel {
+                util::par_count(fst, &dfa)
+            } else {
+                util::count(fst, &dfa, (None, None))
+            }
+        }))
+    }
+
+    #[pyo3(signature = (key, max_dist, parallel=false))]
+    fn count_lev(&self, py: Python, key: Key, max_dist: u32, parallel: bool) -> PyResult<usize> {
+        self.check_open()?;
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let fst = self.inner.as_fst();
+        Ok(py.allow_threads(|| {
+            if parallel {
+                util::par_count(fst, &lev)
+            } else {
+                util::count(fst, &lev, (None, None))
+            }
+        }))
+    }
+
+    /// The number of keys starting with `prefix`, at least `ge` and less than
+    /// `lt`, and fully matching `regex`, where given. The keys are counted in
+    /// Rust with the GIL released, without being returned to Python.
+    #[pyo3(signature = (prefix=None, regex=None, ge=None, lt=None))]
+    fn count(
+        &self,
+        py: Python,
+        prefix: Option<Key>,
+        regex: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        let range = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let fst = self.inner.as_fst();
+        match regex {
+            Some(regex) => {
+                let dfa = util::regex_dfa(regex.as_str()?)?;
+                Ok(py.allow_threads(|| util::count(fst, &dfa, range)))
+            }
+            None => Ok(py.allow_threads(|| util::count(fst, &AlwaysMatch, range))),
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapKeysView {
+    map: Map,
+}
+
+#[pymethods]
+impl MapKeysView {
+    fn __iter__(&self) -> MapKeys {
+        self.map.key_stream()
+    }
+    fn __len__(&self) -> usize {
+        self.map.inner.len()
+    }
+    fn __contains__(&self, key: Key) -> bool {
+        self.map.lookup(key.as_bytes()).is_some()
+    }
+
+    /// Like the views of a dict, the keys compare with any
+    /// `collections.abc.Set`, and the operators take any iterable and
+    /// return a `set`.
+    fn __richcmp__(slf: &PyCell<Self>, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        view_richcmp(slf, other, op)
+    }
+
+    fn isdisjoint(slf: &PyCell<Self>, other: &PyAny) -> PyResult<bool> {
+        setlike::isdisjoint(slf, other)
+    }
+
+    fn __and__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "intersection", false)
+    }
+
+    fn __rand__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "intersection", true)
+    }
+
+    fn __or__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "union", false)
+    }
+
+    fn __ror__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "union", true)
+    }
+
+    fn __sub__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "difference", false)
+    }
+
+    fn __rsub__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "difference", true)
+    }
+
+    fn __xor__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "symmetric_difference", false)
+    }
+
+    fn __rxor__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "symmetric_difference", true)
+    }
+
+    /// Export the keys through the Arrow C stream interface, as chunks of a
+    /// string array.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<&PyAny>,
+    ) -> PyResult<&'py PyCapsule> {
+        let _ = requested_schema;
+        let keys = arrow::KeyChunks::new(self.map.inner.as_fst().clone(), lossy::log(py)?);
+        arrow::stream_capsule(py, Box::new(keys))
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapValuesView {
+    map: Map,
+}
+
+#[pymethods]
+impl MapValuesView {
+    fn __iter__(&self) -> MapValues {
+        self.map.value_stream()
+    }
+    fn __len__(&self) -> usize {
+        self.map.inner.len()
+    }
+    fn __contains__(&self, value: &PyAny) -> bool {
+        let Some(value) = self.map.value_type.try_encode(value) else {
+            return false;
+        };
+        let mut stream = self.map.inner.values();
+        while let Some(val) = stream.next() {
+            if val == value {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapItemsView {
+    map: Map,
+    reverse: bool,
+}
+
+#[pymethods]
+impl MapItemsView {
+    fn __iter__(&self) -> MapItems {
+        self.map.range_stream(None, None, None, None, self.reverse)
+    }
+
+    fn __reversed__(&self) -> MapItems {
+        self.map.range_stream(None, None, None, None, !self.reverse)
+    }
+    fn __len__(&self) -> usize {
+        self.map.inner.len()
+    }
+    fn __contains__(&self, item: &PyAny) -> bool {
+        match item.extract::<(Key, &PyAny)>() {
+            Ok((key, value)) => {
+                let value = self.map.value_type.try_encode(value);
+                value.is_some() && self.map.lookup(key.as_bytes()) == value
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Like the items of a dict, the items compare with any
+    /// `collections.abc.Set`, and the operators take any iterable and
+    /// return a `set`.
+    fn __richcmp__(slf: &PyCell<Self>, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        view_richcmp(slf, other, op)
+    }
+
+    fn isdisjoint(slf: &PyCell<Self>, other: &PyAny) -> PyResult<bool> {
+        setlike::isdisjoint(slf, other)
+    }
+
+    fn __and__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "intersection", false)
+    }
+
+    fn __rand__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "intersection", true)
+    }
+
+    fn __or__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "union", false)
+    }
+
+    fn __ror__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "union", true)
+    }
+
+    fn __sub__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "difference", false)
+    }
+
+    fn __rsub__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "difference", true)
+    }
+
+    fn __xor__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "symmetric_difference", false)
+    }
+
+    fn __rxor__(slf: &PyCell<Self>, other: &PyAny) -> PyResult<PyObject> {
+        setlike::set_op(slf, other, "symmetric_difference", true)
+    }
+}
+
+/// Compare a keys or items view with `other` as `collections.abc.Set` does.
+fn view_richcmp(view: &PyAny, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+    let py = view.py();
+    Ok(match setlike::compare(view, other, op)? {
+        Some(result) => result.into_py(py),
+        None => py.NotImplemented(),
+    })
+}
+
+#[pyclass(weakref)]
+pub struct MapKeys {
+    stream: fst::map::Keys<'static>,
+    _map: Box<FstMap<MapData>>,
+    window: Window,
+    tally: Tally,
+    raw: bool,
+}
+
+impl Collect for MapKeys {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let window = &mut self.window;
+        let stream = &mut self.stream;
+        let keys = py.allow_threads(|| {
+            let mut keys = KeyBuffer::default();
+            while window.take() {
+                match stream.next() {
+                    Some(key) => keys.push(key),
+                    None => break,
+                }
+            }
+            keys
+        });
+        self.tally.count_n(keys.len());
+        (keys, Vec::new())
+    }
+}
+
+impl Remaining for MapKeys {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
+    }
+}
+
+stream_class! {
+    keys impl MapKeys {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            if !this.window.take() {
+                return None;
+            }
+            let bytes = this.tally.count(this.stream.next())?;
+            Some(lossy::key_object(py, bytes, raw))
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapSplitKeys {
+    stream: fst::map::Keys<'static>,
+    _map: Box<FstMap<MapData>>,
+    window: Window,
+    decoder: PrefixDecoder,
+}
+
+impl Remaining for MapSplitKeys {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
+    }
+}
+
+stream_class! {
+    impl MapSplitKeys {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(Py<PyString>, String)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            while this.window.skip() {
+                this.stream.next()?;
+            }
+            if !this.window.take() {
+                return None;
+            }
+            let bytes = this.stream.next()?;
+            Some(this.decoder.split(py, bytes))
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapValues {
+    stream: fst::map::Values<'static>,
+    _map: Box<FstMap<MapData>>,
+    window: Window,
+    tally: Tally,
+    value_type: ValueType,
+}
+
+impl Remaining for MapValues {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
+    }
+}
+
+stream_class! {
+    impl MapValues {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<PyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            if !this.window.take() {
+                return None;
+            }
+            let val = this.tally.count(this.stream.next())?;
+            Some(this.value_type.decode(py, val))
+        }
+    }
+}
+
+/// The items of a Map, in either direction.
+enum MapEntries {
+    Forward(fst::map::Stream<'static>),
+    Backward(Backward<'static, MapData>),
+}
+
+impl<'a> Streamer<'a> for MapEntries {
+    type Item = (&'a [u8], u64);
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        match self {
+            MapEntries::Forward(stream) => stream.next(),
+            MapEntries::Backward(stream) => stream.next().map(|(key, out)| (key, out.value())),
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapItems {
+    stream: MapEntries,
+    _map: Box<FstMap<MapData>>,
+    window: Window,
+    filter: ValueFilter,
+    tally: Tally,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl MapItems {
+    /// Yield only the items passing `filter`. How many that leaves is not
+    /// known up front.
+    fn filtered(mut self, filter: ValueFilter) -> Self {
+        if !filter.is_open() {
+            self.filter = filter;
+            self.window = Window::default();
+        }
+        self
+    }
+}
+
+impl Collect for MapItems {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let window = &mut self.window;
+        let stream = &mut self.stream;
+        let filter = self.filter;
+        let (keys, values) = py.allow_threads(|| {
+            let mut keys = KeyBuffer::default();
+            let mut values = Vec::new();
+            while window.skip() {
+                if !filter.pull(stream, &mut |_, _| {}) {
+                    return (keys, values);
+                }
+            }
+            let mut sink = |key: &[u8], val| {
+                keys.push(key);
+                values.push(val);
+            };
+            while window.take() && filter.pull(stream, &mut sink) {}
+            (keys, values)
+        });
+        self.tally.count_n(keys.len());
+        (keys, values)
+    }
+}
+
+impl Remaining for MapItems {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
+    }
+}
+
+stream_class! {
+    items impl MapItems {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            while this.window.skip() {
+                if !this.filter.pull(&mut this.stream, &mut |_, _| {}) {
+                    return None;
+                }
+            }
+            if !this.window.take() {
+                return None;
+            }
+            let mut item = None;
+            this.filter.pull(&mut this.stream, &mut |bytes, val| {
+                item = Some((
+                    lossy::key_object(py, bytes, raw),
+                    this.value_type.decode(py, val),
+                ));
+            });
+            this.tally.count(item)
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapRegexStream {
+    stream: fst::map::Stream<'static, &'static DenseDFA<Vec<usize>, usize>>,
+    _map: Box<FstMap<MapData>>,
+    _dfa: Box<DenseDFA<Vec<usize>, usize>>,
+    prefetch: Prefetch,
+    filter: ValueFilter,
+    span: Span,
+    tally: Tally,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Collect for MapRegexStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let filter = self.filter;
+        let items = self
+            .prefetch
+            .collect(py, &mut self.stream, |s, sink| filter.pull(s, sink));
+        self.tally.count_n(items.0.len());
+        items
+    }
+}
+
+impl Remaining for MapRegexStream {}
+
+stream_class! {
+    items impl MapRegexStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let filter = this.filter;
+            let tally = &this.tally;
+            this.span.record(py, || {
+                let (key, val) = tally.count(
+                    this.prefetch
+                        .next(py, &mut this.stream, |s, sink| filter.pull(s, sink)),
+                )?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapLevStream {
+    stream: fst::map::Stream<'static, &'static Levenshtein>,
+    _map: Box<FstMap<MapData>>,
+    _lev: Arc<Levenshtein>,
+    prefetch: Prefetch,
+    filter: ValueFilter,
+    span: Span,
+    tally: Tally,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Collect for MapLevStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let filter = self.filter;
+        let items = self
+            .prefetch
+            .collect(py, &mut self.stream, |s, sink| filter.pull(s, sink));
+        self.tally.count_n(items.0.len());
+        items
+    }
+}
+
+impl Remaining for MapLevStream {}
+
+stream_class! {
+    items impl MapLevStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let filter = this.filter;
+            let tally = &this.tally;
+            this.span.record(py, || {
+                let (key, val) = tally.count(
+                    this.prefetch
+                        .next(py, &mut this.stream, |s, sink| filter.pull(s, sink)),
+                )?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapDiff {
+    stream: SendOp<fst::map::Union<'static>>,
+    _maps: Box<[FstMap<MapData>; 2]>,
+    window: Window,
+    span: Span,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Remaining for MapDiff {}
+
+stream_class! {
+    raw impl MapDiff {
+        fn __next__(
+            mut slf: PyRefMut<Self>,
+        ) -> Option<(KeyObject, Option<PyObject>, Option<PyObject>)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            this.span.record(py, || {
+                while this.window.skip() {
+                    if !pull_change(&mut this.stream, |_, _, _| {}) {
+                        return None;
+                    }
+                }
+                if !this.window.take() {
+                    return None;
+                }
+                let decode = |val: Option<u64>| val.map(|val| this.value_type.decode(py, val));
+                let mut change = None;
+                pull_change(&mut this.stream, |key, old, new| {
+                    change = Some((lossy::key_object(py, key, raw), decode(old), decode(new)));
+                });
+                change
+            })
+        }
+    }
+}
+
+/// Advance the union of two Maps to the next key whose value differs between
+/// them and pass it to `sink` with its value in each, or return false at the
+/// end of the stream.
+fn pull_change<F>(stream: &mut fst::map::Union, sink: F) -> bool
+where
+    F: FnOnce(&[u8], Option<u64>, Option<u64>),
+{
+    while let Some((key, values)) = stream.next() {
+        let mut old = None;
+        let mut new = None;
+        for value in values {
+            if value.index == 0 {
+                old = Some(value.value);
+            } else {
+                new = Some(value.value);
+            }
+        }
+        if old != new {
+            sink(key, old, new);
+            return true;
+        }
+    }
+    false
+}
+
+/// How the values of a key found in several Maps are combined.
+#[derive(Clone, Copy)]
+pub enum Merge {
+    First,
+    Last,
+    Sum,
+    Min,
+    Max,
+}
+
+impl Merge {
+    /// The merge called `name`, for values stored as `value_type`. Only
+    /// `u64` values can be summed, since the outputs of other types do not
+    /// add up like the values.
+    pub fn parse(name: &str, value_type: ValueType) -> PyResult<Self> {
+        Ok(match name {
+            "first" => Merge::First,
+            "last" => Merge::Last,
+            "sum" => {
+                value_type.require_u64("merge=\"sum\"")?;
+                Merge::Sum
+            }
+            "min" => Merge::Min,
+            "max" => Merge::Max,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown merge {:?}, expected \"first\", \"last\", \"sum\", \"min\" or \"max\"",
+                    name
+                )))
+            }
+        })
+    }
+
+    /// Combine the values of one key. `values` is never empty and is not
+    /// necessarily in index order.
+    pub fn apply(self, values: &[IndexedValue]) -> u64 {
+        let by_index = values.iter().map(|v| (v.index, v.value));
+        match self {
+            Merge::First => by_index.min().map_or(0, |(_, value)| value),
+            Merge::Last => by_index.max().map_or(0, |(_, value)| value),
+            Merge::Sum => values.iter().fold(0, |sum, v| sum.saturating_add(v.value)),
+            Merge::Min => values.iter().map(|v| v.value).min().unwrap_or(0),
+            Merge::Max => values.iter().map(|v| v.value).max().unwrap_or(0),
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapUnion {
+    stream: SendOp<fst::map::Union<'static>>,
+    _maps: Box<[FstMap<MapData>]>,
+    merge: Merge,
+    prefetch: Prefetch,
+    span: Span,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl MapUnion {
+    /// A stream of `op`, a union of streams over `maps` with values of
+    /// `value_type` combined by `merge`, yielded through `window` and traced
+    /// as `op_name`.
+    pub fn new(
+        op: SendOp<fst::map::Union<'static>>,
+        maps: Box<[FstMap<MapData>]>,
+        merge: Merge,
+        value_type: ValueType,
+        op_name: &'static str,
+        window: Window,
+    ) -> Self {
+        MapUnion {
+            stream: op,
+            _maps: maps,
+            merge,
+            span: Span::new(op_name),
+            prefetch: Prefetch::new(window),
+            value_type,
+            raw: false,
+        }
+    }
+}
+
+impl Collect for MapUnion {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let merge = self.merge;
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next()
+                .map(|(key, values)| sink(key, merge.apply(values)))
+                .is_some()
+        })
+    }
+}
+
+impl Remaining for MapUnion {}
+
+stream_class! {
+    items impl MapUnion {
+        /// Build a Map of the items not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_map(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Map> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let merge = this.merge;
+            let mut builder = MapBuilder::create(path.clone(), this.value_type)?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, merge.apply(values)))
+                        .is_some()
+                },
+                |key, value| builder.insert_bytes(key, value),
+            )?;
+            Map::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let merge = this.merge;
+            this.span.record(py, || {
+                let (key, val) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, merge.apply(values)))
+                        .is_some()
+                })?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapIntersection {
+    stream: SendOp<fst::map::Intersection<'static>>,
+    _maps: Box<[FstMap<MapData>]>,
+    merge: Merge,
+    prefetch: Prefetch,
+    span: Span,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Collect for MapIntersection {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let merge = self.merge;
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next()
+                .map(|(key, values)| sink(key, merge.apply(values)))
+                .is_some()
+        })
+    }
+}
+
+impl Remaining for MapIntersection {}
+
+stream_class! {
+    items impl MapIntersection {
+        /// Build a Map of the items not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_map(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Map> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let merge = this.merge;
+            let mut builder = MapBuilder::create(path.clone(), this.value_type)?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, merge.apply(values)))
+                        .is_some()
+                },
+                |key, value| builder.insert_bytes(key, value),
+            )?;
+            Map::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let merge = this.merge;
+            this.span.record(py, || {
+                let (key, val) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, merge.apply(values)))
+                        .is_some()
+                })?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapDifference {
+    stream: SendOp<fst::map::Difference<'static>>,
+    _maps: Box<[FstMap<MapData>]>,
+    prefetch: Prefetch,
+    span: Span,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Collect for MapDifference {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next()
+                .map(|(key, values)| sink(key, values[0].value))
+                .is_some()
+        })
+    }
+}
+
+impl Remaining for MapDifference {}
+
+stream_class! {
+    items impl MapDifference {
+        /// Build a Map of the items not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_map(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Map> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let mut builder = MapBuilder::create(path.clone(), this.value_type)?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, values[0].value))
+                        .is_some()
+                },
+                |key, value| builder.insert_bytes(key, value),
+            )?;
+            Map::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            this.span.record(py, || {
+                let (key, val) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, values[0].value))
+                        .is_some()
+                })?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapSymmetricDifference {
+    stream: SendOp<fst::map::SymmetricDifference<'static>>,
+    _maps: Box<[FstMap<MapData>]>,
+    merge: Merge,
+    prefetch: Prefetch,
+    span: Span,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Collect for MapSymmetricDifference {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let merge = self.merge;
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next()
+                .map(|(key, values)| sink(key, merge.apply(values)))
+                .is_some()
+        })
+    }
+}
+
+impl Remaining for MapSymmetricDifference {}
+
+stream_class! {
+    items impl MapSymmetricDifference {
+        /// Build a Map of the items not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_map(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Map> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let merge = this.merge;
+            let mut builder = MapBuilder::create(path.clone(), this.value_type)?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, merge.apply(values)))
+                        .is_some()
+                },
+                |key, value| builder.insert_bytes(key, value),
+            )?;
+            Map::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let merge = this.merge;
+            this.span.record(py, || {
+                let (key, val) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next()
+                        .map(|(key, values)| sink(key, merge.apply(values)))
+                        .is_some()
+                })?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapSubsequenceStream {
+    stream: fst::map::Stream<'static, Subsequence<'static>>,
+    _map: Box<FstMap<MapData>>,
+    _query: Box<str>,
+    prefetch: Prefetch,
+    span: Span,
+    tally: Tally,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl Collect for MapSubsequenceStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let items = self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|(key, val)| sink(key, val)).is_some()
+        });
+        self.tally.count_n(items.0.len());
+        items
+    }
+}
+
+impl Remaining for MapSubsequenceStream {}
+
+stream_class! {
+    items impl MapSubsequenceStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let tally = &this.tally;
+            this.span.record(py, || {
+                let (key, val) = tally.count(this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|(key, val)| sink(key, val)).is_some()
+                }))?;
+                Some((
+                    lossy::key_object(py, key, raw),
+                    this.value_type.decode(py, val),
+                ))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct MapCollectedStream {
+    items: std::vec::IntoIter<(Vec<u8>, u64)>,
+    tally: Tally,
+    value_type: ValueType,
+    raw: bool,
+}
+
+impl MapCollectedStream {
+    pub fn new(items: Vec<(Vec<u8>, u64)>, tally: Tally, value_type: ValueType) -> Self {
+        MapCollectedStream {
+            items: items.into_iter(),
+            tally,
+            value_type,
+            raw: false,
+        }
+    }
+}
+
+impl Collect for MapCollectedStream {
+    fn collect(&mut self, _py: Python) -> (KeyBuffer, Vec<u64>) {
+        let mut keys = KeyBuffer::default();
+        let mut values = Vec::new();
+        for (key, val) in self.items.by_ref() {
+            keys.push(&key);
+            values.push(val);
+        }
+        self.tally.count_n(keys.len());
+        (keys, values)
+    }
+}
+
+impl Remaining for MapCollectedStream {
+    fn remaining(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+stream_class! {
+    items impl MapCollectedStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(KeyObject, PyObject)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let (bytes, val) = this.tally.count(this.items.next())?;
+            Some((
+                lossy::key_object(py, &bytes, raw),
+                this.value_type.decode(py, val),
+            ))
+        }
+    }
+}
+
+enum BuilderInner {
+    Memory(FstMapBuilder<Vec<u8>>),
+    File(FstMapBuilder<BufWriter<File>>),
+    Writer(FstMapBuilder<BufWriter<PyWriter>>),
+}
+
+#[pyclass(weakref)]
+pub struct MapBuilder {
+    inner: Option<BuilderInner>,
+    count: usize,
+    started: Instant,
+    value_type: ValueType,
+}
+
+impl MapBuilder {
+    /// Start a build in memory or, if `path` is given, to a file there.
+    pub fn create(path: Option<String>, value_type: ValueType) -> PyResult<Self> {
+        MapBuilder::create_into(path.into(), value_type)
+    }
+
+    fn create_into(target: Target, value_type: ValueType) -> PyResult<Self> {
+        let inner = match target {
+            Target::Memory => BuilderInner::Memory(FstMapBuilder::memory()),
+            Target::Path(p) => {
+                let file = File::create(p)?;
+                let wtr = BufWriter::new(file);
+                let builder = FstMapBuilder::new(wtr).map_err(error::fst_error)?;
+                BuilderInner::File(builder)
+            }
+            Target::Writer(wtr) => {
+                let builder = FstMapBuilder::new(wtr).map_err(error::fst_error)?;
+                BuilderInner::Writer(builder)
+            }
+        };
+        Ok(MapBuilder {
+            inner: Some(inner),
+            count: 0,
+            started: Instant::now(),
+            value_type,
+        })
+    }
+
+    pub fn insert_bytes(&mut self, key: &[u8], val: u64) -> PyResult<()> {
+        match self.inner.as_mut() {
+            Some(BuilderInner::Memory(b)) => b.insert(key, val).map_err(error::fst_error),
+            Some(BuilderInner::File(b)) => b.insert(key, val).map_err(error::fst_error),
+            Some(BuilderInner::Writer(b)) => b.insert(key, val).map_err(error::fst_error),
+            None => Err(PyValueError::new_err("Builder already finished")),
+        }?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl MapBuilder {
+    /// Build in memory, at `path` if given or into a binary file object
+    /// passed as `path`, as for `SetBuilder`. Values are non-negative
+    /// integers by default; `value_type="i64"` stores signed integers and
+    /// `"f64"` floats, which the Map has to be opened with again.
+    ///
+    /// :type path: Union[str, BinaryIO, None]
+    #[new]
+    #[pyo3(signature = (path=None, value_type="u64"))]
+    fn new(path: Option<&PyAny>, value_type: &str) -> PyResult<Self> {
+        MapBuilder::create_into(Target::parse(path)?, ValueType::parse(value_type)?)
+    }
+
+    /// :type val: Union[int, float]
+    fn insert(&mut self, key: Key, val: &PyAny) -> PyResult<()> {
+        let val = self.value_type.encode(&key, val)?;
+        self.insert_bytes(key.as_bytes(), val)
+    }
+
+    /// Insert the items of the text file at `path`, optionally gzipped, with
+    /// a key and an integer value separated by the last `delimiter` on each
+    /// line, in sorted order, without going through Python. Blank lines are
+    /// skipped. Returns the number of items inserted.
+    #[pyo3(signature = (path, delimiter="\t"))]
+    fn insert_from_file(&mut self, py: Python, path: &str, delimiter: &str) -> PyResult<usize> {
+        if delimiter.is_empty() {
+            return Err(PyValueError::new_err("delimiter must not be empty"));
+        }
+        self.value_type.require_u64("insert_from_file()")?;
+        py.allow_threads(|| {
+            let mut count = 0;
+            tools::for_each_line(path, |line, lineno| {
+                let (key, val) = tools::parse_line(line, lineno, true, delimiter.as_bytes())?;
+                self.insert_bytes(&key, val)?;
+                count += 1;
+                Ok(())
+            })?;
+            Ok(count)
+        })
+    }
+
+    /// Finish the build, with the GIL released. Returns the Map if it was
+    /// built in memory.
+    fn finish(&mut self, py: Python) -> PyResult<Option<Map>> {
+        let inner = self
+            .inner
+            .take()
+            .ok_or_else(|| PyValueError::new_err("Builder already finished"))?;
+        let map = py.allow_threads(|| match inner {
+            BuilderInner::Memory(b) => {
+                let bytes = b.into_inner().map_err(error::fst_error)?;
+                FstMap::new(MapData::Vec(Arc::new(bytes)))
+                    .map(Some)
+                    .map_err(error::fst_error)
+            }
+            BuilderInner::File(b) => b.finish().map(|_| None).map_err(error::fst_error),
+            BuilderInner::Writer(b) => {
+                writer::finish(b.into_inner().map_err(error::fst_error)?)?;
+                Ok(None)
+            }
+        })?;
+        trace::emit(py, "MapBuilder.finish", self.started.elapsed(), self.count)?;
+        Ok(map.map(|inner| Map {
+            value_type: self.value_type,
+            ..Map::from_fst(inner)
+        }))
+    }
+}
+
+/// `matches` as a list of `(key, value)` tuples, and their count.
+fn match_list(
+    py: Python,
+    matches: Vec<(Vec<u8>, u64)>,
+    value_type: ValueType,
+) -> PyResult<(PyObject, usize)> {
+    let items = matches
+        .iter()
+        .map(|(key, val)| (lossy::decode(py, key), value_type.decode(py, *val)).to_object(py));
+    Ok((PyList::new(py, items).into_py(py), matches.len()))
+}
+
+/// The looked up `values` as a list, with `default` for missing ones.
+fn value_list<'py>(
+    py: Python<'py>,
+    values: &[Option<u64>],
+    default: Option<PyObject>,
+    value_type: ValueType,
+) -> &'py PyList {
+    PyList::new(
+        py,
+        values.iter().map(|val| match val {
+            Some(val) => value_type.decode(py, *val),
+            None => default
+                .as_ref()
+                .map_or_else(|| py.None(), |default| default.clone_ref(py)),
+        }),
+    )
 }
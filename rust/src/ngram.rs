@@ -0,0 +1,148 @@
+//! N-gram counts for next-token completion.
+//!
+//! An n-gram is stored as its tokens joined by single spaces, so the n-grams
+//! extending a context are the keys starting with the context and a space, and
+//! those extending it by exactly one token have no further space.
+
+use fst::automaton::Automaton;
+use fst::Map as FstMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::map::{Map, MapData};
+use crate::trace;
+use crate::util;
+
+/// Matches keys with at least one byte and no space after the first `skip`
+/// bytes, that is a context of `skip` bytes followed by a single token.
+struct OneToken {
+    skip: usize,
+}
+
+impl Automaton for OneToken {
+    /// The number of bytes read, or `None` once a space follows the context.
+    type State = Option<usize>;
+
+    fn start(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Option<usize>) -> bool {
+        matches!(state, Some(n) if *n > self.skip)
+    }
+
+    fn can_match(&self, state: &Option<usize>) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Option<usize>, byte: u8) -> Option<usize> {
+        let n = (*state)?;
+        if n >= self.skip && byte == b' ' {
+            None
+        } else {
+            Some(n + 1)
+        }
+    }
+}
+
+/// The tokens of an n-gram or context, given as a space-separated string or
+/// a sequence of tokens.
+fn tokens(obj: &PyAny) -> PyResult<Vec<String>> {
+    let tokens: Vec<String> = match obj.extract::<&str>() {
+        Ok(text) => text.split_whitespace().map(str::to_owned).collect(),
+        Err(_) => obj.extract()?,
+    };
+    for token in &tokens {
+        if token.is_empty() || token.contains(char::is_whitespace) {
+            return Err(PyValueError::new_err(format!(
+                "invalid token {:?}: tokens must be non-empty and without whitespace",
+                token
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Predicts the next token after a context from the counts of the n-grams
+/// in a Map.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct NgramModel {
+    map: FstMap<MapData>,
+}
+
+#[pymethods]
+impl NgramModel {
+    /// Wrap a Map written by `NgramModel.build`.
+    #[new]
+    fn new(map: PyRef<Map>) -> PyResult<Self> {
+        map.check_open()?;
+        map.value_type.require_u64("NgramModel()")?;
+        Ok(NgramModel {
+            map: map.inner.clone(),
+        })
+    }
+
+    /// Build a model from `(ngram, count)` pairs, in memory or, if `path` is
+    /// given, in a file that can later be opened with
+    /// `NgramModel(Map(path))`. Each n-gram is a space-separated string or a
+    /// sequence of tokens; the counts of repeated n-grams are added up.
+    ///
+    /// :type ngrams: Iterable[Tuple[Union[str, Sequence[str]], int]]
+    #[staticmethod]
+    #[pyo3(signature = (ngrams, path=None))]
+    fn build(py: Python, ngrams: &PyAny, path: Option<String>) -> PyResult<NgramModel> {
+        let mut counts = BTreeMap::new();
+        for item in ngrams.iter()? {
+            let (ngram, count): (&PyAny, u64) = item?.extract()?;
+            let key = tokens(ngram)?.join(" ");
+            if key.is_empty() {
+                return Err(PyValueError::new_err("an n-gram needs at least one token"));
+            }
+            let total: &mut u64 = counts.entry(key.into_bytes()).or_default();
+            *total = total.saturating_add(count);
+        }
+        let items: Vec<(Vec<u8>, u64)> = counts.into_iter().collect();
+        let map = Map::build_sorted(py, &items, path)?;
+        Ok(NgramModel { map: map.inner })
+    }
+
+    /// The `k` most frequent tokens following `context` as `(token, count)`
+    /// tuples, most frequent first. `context` is a space-separated string or a
+    /// sequence of tokens; an empty context ranks the unigrams.
+    ///
+    /// :type context: Union[str, Sequence[str]]
+    #[pyo3(signature = (context, k=10))]
+    fn next_tokens(&self, py: Python, context: &PyAny, k: usize) -> PyResult<Vec<(String, u64)>> {
+        let start = Instant::now();
+        let mut prefix = tokens(context)?.join(" ");
+        if !prefix.is_empty() {
+            prefix.push(' ');
+        }
+        let aut = OneToken { skip: prefix.len() };
+        let range = util::bounded_range(Some(prefix.as_bytes()), None, None);
+        let fst = self.map.as_fst();
+        let matches = py.allow_threads(|| util::top_k(fst, &aut, range, k));
+        trace::emit(py, "NgramModel.next_tokens", start.elapsed(), matches.len())?;
+        Ok(matches
+            .into_iter()
+            .map(|(key, count)| {
+                let token = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+                (token, count)
+            })
+            .collect())
+    }
+
+    /// The count of `ngram`, or 0 if it was never seen.
+    ///
+    /// :type ngram: Union[str, Sequence[str]]
+    fn count(&self, ngram: &PyAny) -> PyResult<u64> {
+        let key = tokens(ngram)?.join(" ");
+        Ok(self.map.get(key).unwrap_or(0))
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.len()
+    }
+}
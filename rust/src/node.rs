@@ -0,0 +1,127 @@
+//! Low-level access to the nodes of an FST, for custom traversals.
+
+use fst::raw::{CompiledAddr, Output};
+use pyo3::exceptions::PyIndexError;
+use pyo3::prelude::*;
+use std::sync::Arc;
+
+use crate::lexicon::{with_fst, Lexicon};
+use crate::ordinal;
+
+/// A node (state) of the FST of a Set or Map, from `Set.root()` or
+/// `Map.root()`. The node keeps the FST alive, even after its Set or Map is
+/// closed.
+///
+/// The output of a key is the sum of the outputs of the transitions along its
+/// path plus the final output of its last node; for a Set every output is 0.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct Node {
+    fst: Arc<Lexicon>,
+    addr: CompiledAddr,
+}
+
+impl Node {
+    pub fn root(fst: Lexicon) -> Self {
+        let addr = with_fst!(&fst, fst => fst.root().addr());
+        Node {
+            fst: Arc::new(fst),
+            addr,
+        }
+    }
+
+    fn at(&self, addr: CompiledAddr) -> Node {
+        Node {
+            fst: Arc::clone(&self.fst),
+            addr,
+        }
+    }
+}
+
+#[pymethods]
+impl Node {
+    /// The address of the node, unique within its FST.
+    #[getter]
+    fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Whether a key ends at this node.
+    #[getter]
+    fn is_final(&self) -> bool {
+        with_fst!(&*self.fst, fst => fst.node(self.addr).is_final())
+    }
+
+    /// The output added when a key ends at this node.
+    #[getter]
+    fn final_output(&self) -> u64 {
+        with_fst!(&*self.fst, fst => fst.node(self.addr).final_output().value())
+    }
+
+    /// The number of transitions out of the node.
+    fn __len__(&self) -> usize {
+        with_fst!(&*self.fst, fst => fst.node(self.addr).len())
+    }
+
+    /// The transitions out of the node as `(byte, output, node)` tuples, in
+    /// byte order.
+    fn transitions(&self) -> Vec<(u8, u64, Node)> {
+        with_fst!(&*self.fst, fst => {
+            fst.node(self.addr)
+                .transitions()
+                .map(|t| (t.inp, t.out.value(), self.at(t.addr)))
+                .collect()
+        })
+    }
+
+    /// The transition at `index`, in byte order, as a `(byte, output, node)`
+    /// tuple; negative indices count from the end.
+    fn transition(&self, index: isize) -> PyResult<(u8, u64, Node)> {
+        with_fst!(&*self.fst, fst => {
+            let node = fst.node(self.addr);
+            let i = ordinal::resolve(index, node.len())
+                .ok_or_else(|| PyIndexError::new_err("transition index out of range"))?;
+            let t = node.transition(i);
+            Ok((t.inp, t.out.value(), self.at(t.addr)))
+        })
+    }
+
+    /// The index of the transition on `byte`, or `None` if there is none.
+    fn find_input(&self, byte: u8) -> Option<usize> {
+        with_fst!(&*self.fst, fst => fst.node(self.addr).find_input(byte))
+    }
+
+    /// Follow the transition on `byte`, returning its `(output, node)`, or
+    /// `None` if there is none.
+    fn follow(&self, byte: u8) -> Option<(u64, Node)> {
+        with_fst!(&*self.fst, fst => {
+            let node = fst.node(self.addr);
+            let t = node.transition(node.find_input(byte)?);
+            Some((t.out.value(), self.at(t.addr)))
+        })
+    }
+
+    /// Follow the bytes of `key` from this node, returning the summed output
+    /// of the transitions taken and the node reached, or `None` if the path
+    /// leaves the FST.
+    fn walk(&self, key: &[u8]) -> Option<(u64, Node)> {
+        with_fst!(&*self.fst, fst => {
+            let mut node = fst.node(self.addr);
+            let mut out = Output::zero();
+            for b in key {
+                let t = node.transition(node.find_input(*b)?);
+                out = out.cat(t.out);
+                node = fst.node(t.addr);
+            }
+            Some((out.value(), self.at(node.addr())))
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<rust_fst.Node addr={} transitions={}{}>",
+            self.addr,
+            self.__len__(),
+            if self.is_final() { " final" } else { "" }
+        )
+    }
+}
@@ -0,0 +1,150 @@
+//! Ordinal access to the keys of an FST: the position of a key in key order,
+//! and the key at a position.
+//!
+//! The FST does not count the keys below its nodes, so every `SAMPLE`th key
+//! is sampled in one pass, the first time an ordinal is asked for. A lookup
+//! then scans at most `SAMPLE` keys onwards from the nearest sample.
+
+use crate::util;
+use fst::raw::{Fst, Output};
+use fst::{IntoStreamer, Streamer};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::BuildHasher;
+
+/// Keys between samples.
+const SAMPLE: usize = 256;
+
+/// Every `SAMPLE`th key of an FST, starting with the first.
+pub struct OrdinalIndex {
+    samples: Vec<Vec<u8>>,
+    len: usize,
+}
+
+impl OrdinalIndex {
+    pub fn build<D: AsRef<[u8]>>(fst: &Fst<D>) -> Self {
+        let mut samples = Vec::with_capacity(fst.len().div_ceil(SAMPLE));
+        let mut stream = fst.stream();
+        let mut i = 0;
+        while let Some((key, _)) = stream.next() {
+            if i % SAMPLE == 0 {
+                samples.push(key.to_vec());
+            }
+            i += 1;
+        }
+        OrdinalIndex { samples, len: i }
+    }
+
+    /// The position of `key` among the keys of `fst`, or `None` if it is not
+    /// one of them.
+    pub fn index_of<D: AsRef<[u8]>>(&self, fst: &Fst<D>, key: &[u8]) -> Option<usize> {
+        fst.get(key)?;
+        let sample = self
+            .samples
+            .partition_point(|sample| sample.as_slice() <= key)
+            .checked_sub(1)?;
+        let mut stream = fst.range().ge(&self.samples[sample]).lt(key).into_stream();
+        let mut index = sample * SAMPLE;
+        while stream.next().is_some() {
+            index += 1;
+        }
+        Some(index)
+    }
+
+    /// The key at position `index` of `fst`, or `None` if there is none.
+    pub fn key_at<D: AsRef<[u8]>>(&self, fst: &Fst<D>, index: usize) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        let mut stream = fst.range().ge(&self.samples[index / SAMPLE]).into_stream();
+        for _ in 0..index % SAMPLE {
+            stream.next();
+        }
+        stream.next().map(|(key, _)| key.to_vec())
+    }
+}
+
+/// `k` distinct positions below `len`, drawn uniformly at random and in
+/// ascending order. The same `seed` always draws the same positions; without
+/// one, every call draws afresh.
+pub fn sample(len: u64, k: u64, seed: Option<u64>) -> Vec<u64> {
+    let mut rng = SplitMix64(seed.unwrap_or_else(|| RandomState::new().hash_one(len)));
+    // Floyd's algorithm: one draw per position, whatever `k` is.
+    let mut picked = BTreeSet::new();
+    for top in len - k..len {
+        let pick = rng.below(top + 1);
+        if !picked.insert(pick) {
+            picked.insert(top);
+        }
+    }
+    picked.into_iter().collect()
+}
+
+/// The items at the ascending `positions` of `fst`. Each is found by walking
+/// down from the root, skipping the transitions whose subtrees hold too few
+/// keys. The keys below each node are counted once, over the nodes of the
+/// FST rather than its keys, and shared between the walks.
+pub fn items_at<D: AsRef<[u8]>>(fst: &Fst<D>, positions: &[u64]) -> Vec<(Vec<u8>, u64)> {
+    let mut counts = HashMap::new();
+    let mut items = Vec::with_capacity(positions.len());
+    'positions: for &position in positions {
+        let (mut node, mut rest) = (fst.root(), position);
+        let (mut key, mut out) = (Vec::new(), Output::zero());
+        'node: loop {
+            if node.is_final() {
+                if rest == 0 {
+                    items.push((key, out.cat(node.final_output()).value()));
+                    continue 'positions;
+                }
+                rest -= 1;
+            }
+            for t in node.transitions() {
+                let below = util::count_keys(fst, t.addr, &mut counts);
+                if rest < below {
+                    key.push(t.inp);
+                    out = out.cat(t.out);
+                    node = fst.node(t.addr);
+                    continue 'node;
+                }
+                rest -= below;
+            }
+            // Only a position past the last key runs off the FST.
+            break 'positions;
+        }
+    }
+    items
+}
+
+/// A small, fast generator of uniformly distributed integers; sampling needs
+/// nothing stronger.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`, without the bias of a plain modulo.
+    fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+/// Resolve a possibly negative Python index against `len`.
+pub fn resolve(index: isize, len: usize) -> Option<usize> {
+    if index < 0 {
+        len.checked_sub(index.unsigned_abs())
+    } else {
+        Some(index as usize).filter(|index| *index < len)
+    }
+}
@@ -0,0 +1,197 @@
+//! Posting lists of document ids, kept beside a Map of terms.
+//!
+//! The Map holds, for every term, the offset of its posting list in a payload
+//! stored next to it (`<path>.postings` for an index on disk). A posting list
+//! is its length followed by the gaps between its sorted document ids, all as
+//! LEB128 varints.
+
+use fst::Map as FstMap;
+use memmap2::Mmap;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::FormatError;
+use crate::export;
+use crate::map::{Map, MapData};
+use crate::trace;
+use crate::util::Key;
+
+fn payload_path(path: &str) -> String {
+    format!("{}.postings", path)
+}
+
+pub fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        n |= ((byte & 0x7F) as u64) << shift;
+        if byte < 0x80 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+/// Append the posting list of the sorted, distinct `ids` to `buf`.
+fn encode(buf: &mut Vec<u8>, ids: &[u64]) {
+    write_varint(buf, ids.len() as u64);
+    let mut prev = 0;
+    for id in ids {
+        write_varint(buf, id - prev);
+        prev = *id;
+    }
+}
+
+/// The posting list starting at `offset` in `buf`.
+fn decode(buf: &[u8], offset: u64) -> PyResult<Vec<u64>> {
+    let corrupt = || FormatError::new_err(format!("corrupt posting list at offset {}", offset));
+    let mut pos = usize::try_from(offset).map_err(|_| corrupt())?;
+    let len = read_varint(buf, &mut pos).ok_or_else(corrupt)?;
+    // Every id takes at least one byte, which bounds a bogus length.
+    if len > (buf.len() - pos) as u64 {
+        return Err(corrupt());
+    }
+    let mut ids = Vec::with_capacity(len as usize);
+    let mut id = 0u64;
+    for _ in 0..len {
+        let gap = read_varint(buf, &mut pos).ok_or_else(corrupt)?;
+        id = id.checked_add(gap).ok_or_else(corrupt)?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// An inverted index from terms to the sorted ids of the documents
+/// containing them.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct PostingsIndex {
+    terms: FstMap<MapData>,
+    payload: MapData,
+}
+
+impl PostingsIndex {
+    /// The posting list of `term`, empty if the term is not indexed.
+    fn lookup(&self, term: &[u8]) -> PyResult<Vec<u64>> {
+        match self.terms.get(term) {
+            Some(offset) => decode(self.payload.as_ref(), offset),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn lookup_all(&self, terms: &PyAny) -> PyResult<Vec<Vec<u64>>> {
+        let mut lists = Vec::new();
+        for term in terms.iter()? {
+            lists.push(self.lookup(term?.extract::<Key>()?.as_bytes())?);
+        }
+        Ok(lists)
+    }
+}
+
+#[pymethods]
+impl PostingsIndex {
+    /// Open an index written by `PostingsIndex.build` to `path`, along with
+    /// its payload at `<path>.postings`.
+    #[new]
+    fn new(py: Python, path: &str) -> PyResult<Self> {
+        let terms = Map::open(PyString::new(py, path))?.inner;
+        let file = File::open(payload_path(path))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(PostingsIndex {
+            terms,
+            payload: MapData::Mmap(Arc::new(mmap)),
+        })
+    }
+
+    /// Build an index from `(term, doc_id)` pairs, in any order and with
+    /// repeats, in memory or, if `path` is given, in files at `path` and
+    /// `<path>.postings`.
+    ///
+    /// :type pairs: Iterable[Tuple[Union[str, bytes], int]]
+    #[staticmethod]
+    #[pyo3(signature = (pairs, path=None))]
+    fn build(py: Python, pairs: &PyAny, path: Option<String>) -> PyResult<PostingsIndex> {
+        let mut postings: BTreeMap<Vec<u8>, Vec<u64>> = BTreeMap::new();
+        for pair in pairs.iter()? {
+            let (term, id): (Key, u64) = pair?.extract()?;
+            postings
+                .entry(term.as_bytes().to_vec())
+                .or_default()
+                .push(id);
+        }
+        let (items, payload) = py.allow_threads(|| {
+            let mut items = Vec::with_capacity(postings.len());
+            let mut payload = Vec::new();
+            for (term, mut ids) in postings {
+                ids.sort_unstable();
+                ids.dedup();
+                items.push((term, payload.len() as u64));
+                encode(&mut payload, &ids);
+            }
+            (items, payload)
+        });
+        if let Some(path) = &path {
+            std::fs::write(payload_path(path), &payload)?;
+        }
+        let terms = Map::build_sorted(py, &items, path)?.inner;
+        Ok(PostingsIndex {
+            terms,
+            payload: MapData::Vec(Arc::new(payload)),
+        })
+    }
+
+    /// The sorted ids of the documents containing `term`, as a
+    /// `numpy.uint64` array.
+    fn postings<'py>(&self, py: Python<'py>, term: Key) -> PyResult<&'py PyAny> {
+        export::u64_array(py, &self.lookup(term.as_bytes())?)
+    }
+
+    /// The sorted ids of the documents containing all of `terms`.
+    ///
+    /// :type terms: Iterable[Union[str, bytes]]
+    fn intersect<'py>(&self, py: Python<'py>, terms: &PyAny) -> PyResult<&'py PyAny> {
+        let start = Instant::now();
+        let mut lists = self.lookup_all(terms)?;
+        lists.sort_unstable_by_key(Vec::len);
+        let mut lists = lists.into_iter();
+        let mut ids = lists.next().unwrap_or_default();
+        for list in lists {
+            ids.retain(|id| list.binary_search(id).is_ok());
+        }
+        trace::emit(py, "PostingsIndex.intersect", start.elapsed(), ids.len())?;
+        export::u64_array(py, &ids)
+    }
+
+    /// The sorted ids of the documents containing any of `terms`.
+    ///
+    /// :type terms: Iterable[Union[str, bytes]]
+    fn union<'py>(&self, py: Python<'py>, terms: &PyAny) -> PyResult<&'py PyAny> {
+        let start = Instant::now();
+        let mut ids: Vec<u64> = self.lookup_all(terms)?.concat();
+        ids.sort_unstable();
+        ids.dedup();
+        trace::emit(py, "PostingsIndex.union", start.elapsed(), ids.len())?;
+        export::u64_array(py, &ids)
+    }
+
+    fn __contains__(&self, term: Key) -> bool {
+        self.terms.contains_key(term.as_bytes())
+    }
+
+    /// The number of distinct terms.
+    fn __len__(&self) -> usize {
+        self.terms.len()
+    }
+}
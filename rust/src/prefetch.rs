@@ -0,0 +1,150 @@
+//! Advancing streams in batches with the GIL released.
+//!
+//! A stream that does real work per key, such as a search or a set operation
+//! over several FSTs, would otherwise hold the GIL for all of it. Instead the
+//! stream is advanced up to `BATCH` items at a time inside `allow_threads`,
+//! and `__next__` hands the buffered items out one by one.
+
+use pyo3::Python;
+use std::convert::Infallible;
+
+use crate::export::KeyBuffer;
+use crate::window::Window;
+
+/// Items pulled per batch.
+const BATCH: usize = 1024;
+
+/// The items of a stream pulled ahead of `__next__`, as keys and values.
+#[derive(Default)]
+pub struct Prefetch {
+    keys: Vec<u8>,
+    ends: Vec<usize>,
+    values: Vec<u64>,
+    next: usize,
+    done: bool,
+    window: Window,
+}
+
+impl Prefetch {
+    /// Prefetch only the items of a stream within `window`.
+    pub fn new(window: Window) -> Self {
+        Prefetch {
+            window,
+            ..Prefetch::default()
+        }
+    }
+
+    /// The next item of `stream`, pulling the next batch with the GIL
+    /// released once the current one is used up. `pull` advances the stream
+    /// by one item, passing it to the sink, and returns false at its end.
+    pub fn next<S, F>(&mut self, py: Python, stream: &mut S, mut pull: F) -> Option<(&[u8], u64)>
+    where
+        S: Send,
+        F: FnMut(&mut S, &mut dyn FnMut(&[u8], u64)) -> bool + Send,
+    {
+        if self.next == self.ends.len() {
+            if self.done {
+                return None;
+            }
+            self.keys.clear();
+            self.ends.clear();
+            self.values.clear();
+            self.next = 0;
+            let Prefetch {
+                keys,
+                ends,
+                values,
+                done,
+                window,
+                ..
+            } = self;
+            py.allow_threads(move || {
+                while window.skip() {
+                    if !pull(stream, &mut |_, _| {}) {
+                        *done = true;
+                        return;
+                    }
+                }
+                let mut sink = |key: &[u8], value| {
+                    keys.extend_from_slice(key);
+                    ends.push(keys.len());
+                    values.push(value);
+                };
+                for _ in 0..BATCH {
+                    if !window.take() || !pull(stream, &mut sink) {
+                        *done = true;
+                        break;
+                    }
+                }
+            });
+            if self.ends.is_empty() {
+                return None;
+            }
+        }
+        let i = self.next;
+        self.next += 1;
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+        Some((&self.keys[start..self.ends[i]], self.values[i]))
+    }
+
+    /// Pass the items not yet handed out, buffered and then the rest of
+    /// `stream`, to `sink` with the GIL released, and return the first error
+    /// of `sink`, after which it is not called again. `pull` is as for
+    /// `next`.
+    pub fn drain<S, F, G, E>(
+        &mut self,
+        py: Python,
+        stream: &mut S,
+        mut pull: F,
+        mut sink: G,
+    ) -> Result<(), E>
+    where
+        S: Send,
+        F: FnMut(&mut S, &mut dyn FnMut(&[u8], u64)) -> bool + Send,
+        G: FnMut(&[u8], u64) -> Result<(), E> + Send,
+        E: Send,
+    {
+        let this = &mut *self;
+        py.allow_threads(move || {
+            for i in this.next..this.ends.len() {
+                let start = if i == 0 { 0 } else { this.ends[i - 1] };
+                sink(&this.keys[start..this.ends[i]], this.values[i])?;
+            }
+            this.next = this.ends.len();
+            if std::mem::replace(&mut this.done, true) {
+                return Ok(());
+            }
+            while this.window.skip() {
+                if !pull(stream, &mut |_, _| {}) {
+                    return Ok(());
+                }
+            }
+            let mut result = Ok(());
+            let mut feed = |key: &[u8], value| {
+                if result.is_ok() {
+                    result = sink(key, value);
+                }
+            };
+            while this.window.take() && pull(stream, &mut feed) {}
+            result
+        })
+    }
+
+    /// The keys and values of the items not yet handed out, collected like
+    /// `drain` with the GIL released.
+    pub fn collect<S, F>(&mut self, py: Python, stream: &mut S, pull: F) -> (KeyBuffer, Vec<u64>)
+    where
+        S: Send,
+        F: FnMut(&mut S, &mut dyn FnMut(&[u8], u64)) -> bool + Send,
+    {
+        let mut keys = KeyBuffer::default();
+        let mut values = Vec::new();
+        let collected = self.drain(py, stream, pull, |key, value| {
+            keys.push(key);
+            values.push(value);
+            Ok::<_, Infallible>(())
+        });
+        let Ok(()) = collected;
+        (keys, values)
+    }
+}
@@ -0,0 +1,32 @@
+//! How much of a memory-mapped FST is resident in RAM.
+
+use pyo3::prelude::*;
+
+/// The fraction of the pages spanned by `data`, such as a memory-mapped file,
+/// that are resident in memory, from `mincore(2)`.
+#[cfg(unix)]
+pub fn resident_fraction(data: &[u8]) -> PyResult<f64> {
+    if data.is_empty() {
+        return Ok(1.0);
+    }
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    let page = usize::try_from(page).map_err(|_| std::io::Error::last_os_error())?;
+    // mincore takes a range starting on a page boundary.
+    let offset = data.as_ptr() as usize % page;
+    let len = offset + data.len();
+    let mut pages = vec![0; len.div_ceil(page)];
+    let start = data.as_ptr().wrapping_sub(offset);
+    let ret = unsafe { libc::mincore(start as *mut _, len, pages.as_mut_ptr()) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let resident = pages.iter().filter(|page| *page & 1 != 0).count();
+    Ok(resident as f64 / pages.len() as f64)
+}
+
+#[cfg(not(unix))]
+pub fn resident_fraction(_data: &[u8]) -> PyResult<f64> {
+    Err(pyo3::exceptions::PyNotImplementedError::new_err(
+        "page residency is only available on Unix",
+    ))
+}
@@ -0,0 +1,39 @@
+//! Set operation streams that can move between threads.
+//!
+//! `fst` boxes the input streams of an operation as `dyn Streamer` without a
+//! `Send` bound, so its unions, intersections and so on are never `Send`, even
+//! though every input here is a stream over an FST whose data is `Send` and
+//! `Sync`.
+
+use std::ops::{Deref, DerefMut};
+
+/// A set operation stream, declared `Send`.
+pub struct SendOp<T>(T);
+
+// Only constructed by `new`, whose callers vouch for the inputs.
+unsafe impl<T> Send for SendOp<T> {}
+
+impl<T> SendOp<T> {
+    /// # Safety
+    ///
+    /// Every input of `op` must be a stream of `fst` over data that is
+    /// `Send` and `Sync`, such as the streams of an `OpBuilder` over Sets or
+    /// Maps.
+    pub unsafe fn new(op: T) -> Self {
+        SendOp(op)
+    }
+}
+
+impl<T> Deref for SendOp<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for SendOp<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
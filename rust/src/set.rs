@@ -1,17 +1,99 @@
-use fst::automaton::Levenshtein;
+use fst::automaton::{AlwaysMatch, Levenshtein, Subsequence};
+use fst::set::OpBuilder;
 use fst::{IntoStreamer, Set as FstSet, SetBuilder as FstSetBuilder, Streamer};
 use memmap2::Mmap;
-use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::exceptions::{PyIndexError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::pyclass::CompareOp;
+use pyo3::types::{
+    IntoPyDict, PyBytes, PyCapsule, PyDict, PyFrozenSet, PyList, PyString, PyTuple, PyType,
+};
 use regex_automata::DenseDFA;
+use std::collections::BTreeSet;
 use std::fs::File;
-use std::io::BufWriter;
-use std::sync::Arc;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use crate::aio;
+use crate::arrow;
+use crate::backward::{self, Backward};
+use crate::buffer::Buffer;
+use crate::cache::LookupCache;
+use crate::companion;
+use crate::counters::{Counters, Tally};
+use crate::custom;
+use crate::error;
+use crate::export::{self, KeyBuffer};
+use crate::kmer;
+use crate::levenshtein::LevenshteinQuery;
+use crate::lexicon::Lexicon;
+use crate::lossy::{self, KeyObject};
+use crate::node::Node;
+use crate::ordinal::{self, OrdinalIndex};
+use crate::prefetch::Prefetch;
+use crate::residency;
+use crate::send::SendOp;
+use crate::setlike;
+use crate::sort::{ExternalSorter, DEFAULT_MEMORY_LIMIT};
+use crate::stream::{stream_class, Collect, Remaining};
+use crate::tools;
+use crate::trace::{self, Span};
+use crate::translit::Scheme;
+use crate::util::{self, Key, KeyRange, MatchMode, PrefixDecoder};
+use crate::window::Window;
+use crate::writer::{self, PyWriter, Target};
 
 #[derive(Clone)]
 pub enum SetData {
     Vec(Arc<Vec<u8>>),
     Mmap(Arc<Mmap>),
+    Buffer(Arc<Buffer>),
+}
+
+impl SetData {
+    /// Bytes of FST data held on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            SetData::Vec(v) => v.len(),
+            SetData::Mmap(_) | SetData::Buffer(_) => 0,
+        }
+    }
+
+    /// Bytes of FST data backed by a memory-mapped file.
+    pub fn mapped_bytes(&self) -> usize {
+        match self {
+            SetData::Mmap(m) => m.len(),
+            SetData::Vec(_) | SetData::Buffer(_) => 0,
+        }
+    }
+
+    /// Bytes of FST data borrowed from another Python object's buffer.
+    pub fn buffer_bytes(&self) -> usize {
+        match self {
+            SetData::Buffer(b) => b.len(),
+            SetData::Vec(_) | SetData::Mmap(_) => 0,
+        }
+    }
+
+    /// Where the FST data lives: `"memory"`, `"mmap"` or `"buffer"`.
+    pub fn storage(&self) -> &'static str {
+        match self {
+            SetData::Vec(_) => "memory",
+            SetData::Mmap(_) => "mmap",
+            SetData::Buffer(_) => "buffer",
+        }
+    }
+
+    /// The fraction of the FST data resident in memory; always 1 for data
+    /// this process allocated.
+    pub fn resident_fraction(&self) -> PyResult<f64> {
+        match self {
+            SetData::Vec(_) => Ok(1.0),
+            SetData::Mmap(m) => residency::resident_fraction(m),
+            SetData::Buffer(b) => residency::resident_fraction(b),
+        }
+    }
 }
 
 impl AsRef<[u8]> for SetData {
@@ -19,62 +101,1384 @@ impl AsRef<[u8]> for SetData {
         match self {
             SetData::Vec(v) => v,
             SetData::Mmap(m) => m,
+            SetData::Buffer(b) => b,
         }
     }
 }
 
-#[pyclass]
+#[pyclass(subclass, weakref, module = "rust_fst")]
 #[derive(Clone)]
 pub struct Set {
     pub inner: FstSet<SetData>,
+    cache: Option<Arc<LookupCache<bool>>>,
+    /// Usage counters, when enabled with `counters=True`.
+    counters: Option<Arc<Counters>>,
+    path: Option<String>,
+    /// Sampled keys for ordinal lookups, built on the first one and shared
+    /// with shallow copies.
+    ordinals: Arc<OnceLock<OrdinalIndex>>,
+    /// The keys with their bytes reversed, once suffix search is enabled.
+    suffixes: Option<FstSet<SetData>>,
+    /// Every suffix of every key, once substring search is enabled. See
+    /// `substring_entries`.
+    substrings: Option<FstSet<SetData>>,
+    /// The transliteration scheme and its companion Set, once transliterated
+    /// search is enabled. See the `translit` module.
+    translit: Option<(Arc<Scheme>, FstSet<SetData>)>,
+    /// The normalizer and its companion Set, once normalized search is
+    /// enabled.
+    normalized: Option<(PyObject, FstSet<SetData>)>,
+    /// The hash of the keys, computed on the first `hash()` and kept when
+    /// the FST is closed, for the dicts and sets it is already in.
+    hash: OnceLock<isize>,
+    closed: bool,
 }
 
-#[pymethods]
 impl Set {
-    #[new]
-    fn new(path: &PyAny) -> PyResult<Self> {
+    /// Raise `ClosedError`, a `ValueError`, if the Set has been closed.
+    pub fn check_open(&self) -> PyResult<()> {
+        if self.closed {
+            return Err(error::ClosedError::new_err("FST is closed"));
+        }
+        Ok(())
+    }
+
+    fn ordinal_index(&self, py: Python) -> &OrdinalIndex {
+        if let Some(index) = self.ordinals.get() {
+            return index;
+        }
+        // Built outside the cell: a thread blocked in `get_or_init` holds the
+        // GIL, which the building thread would need back. Threads racing here
+        // each build the index and the first one stored is kept.
+        let fst = self.inner.as_fst();
+        let built = py.allow_threads(|| OrdinalIndex::build(fst));
+        self.ordinals.get_or_init(|| built)
+    }
+
+    fn lookup(&self, key: &[u8]) -> bool {
+        let (found, cached) = match &self.cache {
+            Some(cache) => cache.get_or_insert_with(key, || self.inner.contains(key)),
+            None => (self.inner.contains(key), false),
+        };
+        if let Some(counters) = &self.counters {
+            counters.lookup(found, cached);
+        }
+        found
+    }
+
+    /// Stream the keys within the given bounds.
+    fn range_stream(
+        &self,
+        ge: Option<&[u8]>,
+        gt: Option<&[u8]>,
+        le: Option<&[u8]>,
+        lt: Option<&[u8]>,
+        reverse: bool,
+    ) -> SetStream {
+        let set = Box::new(self.inner.clone());
+        let stream = if reverse {
+            let (lower, upper) = backward::bounds(ge, gt, le, lt);
+            let stream = Backward::new(set.as_fst(), lower, upper);
+            SetKeys::Backward(unsafe {
+                std::mem::transmute::<Backward<'_, SetData>, Backward<'static, SetData>>(stream)
+            })
+        } else {
+            let mut builder = set.range();
+            if let Some(ge) = ge {
+                builder = builder.ge(ge);
+            }
+            if let Some(gt) = gt {
+                builder = builder.gt(gt);
+            }
+            if let Some(le) = le {
+                builder = builder.le(le);
+            }
+            if let Some(lt) = lt {
+                builder = builder.lt(lt);
+            }
+            let stream = builder.into_stream();
+            SetKeys::Forward(unsafe {
+                std::mem::transmute::<fst::set::Stream<'_>, fst::set::Stream<'static>>(stream)
+            })
+        };
+        let unbounded = ge.is_none() && gt.is_none() && le.is_none() && lt.is_none();
+        SetStream {
+            stream,
+            _set: set,
+            window: if unbounded {
+                Window::default().with_len(self.inner.len())
+            } else {
+                Window::default()
+            },
+            tally: Counters::stream(&self.counters),
+            raw: false,
+        }
+    }
+
+    fn enabled_counters(&self) -> PyResult<&Counters> {
+        self.counters.as_deref().ok_or_else(|| {
+            PyValueError::new_err("counters are not enabled, open the Set with counters=True")
+        })
+    }
+
+    /// The FSTs of this Set and the Sets in `others`, in order, for a set
+    /// operation over all of them.
+    fn operands(&self, others: &PyTuple) -> PyResult<Box<[FstSet<SetData>]>> {
+        self.check_open()?;
+        let mut sets = vec![self.inner.clone()];
+        for other in others {
+            let other: PyRef<Set> = other.extract()?;
+            other.check_open()?;
+            sets.push(other.inner.clone());
+        }
+        Ok(sets.into_boxed_slice())
+    }
+
+    fn cache_bytes(&self) -> usize {
+        self.cache.as_ref().map_or(0, |cache| cache.heap_bytes())
+    }
+
+    fn key_buffer(&self) -> KeyBuffer {
+        let mut keys = KeyBuffer::default();
+        let mut stream = self.inner.stream();
+        while let Some(key) = stream.next() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    /// Stream the keys into a new file at `path`, with the GIL released.
+    fn write_keys(
+        &self,
+        py: Python,
+        path: &str,
+        write: impl Fn(&mut BufWriter<File>, &[u8]) -> std::io::Result<()> + Sync,
+    ) -> PyResult<()> {
+        py.allow_threads(|| {
+            let mut out = BufWriter::new(File::create(path)?);
+            let mut stream = self.inner.stream();
+            while let Some(key) = stream.next() {
+                write(&mut out, key)?;
+            }
+            out.flush()
+        })?;
+        Ok(())
+    }
+
+    fn decoded_keys(&self, py: Python) -> Vec<String> {
+        let mut keys = Vec::with_capacity(self.inner.len());
+        let mut stream = self.inner.stream();
+        while let Some(key) = stream.next() {
+            keys.push(lossy::decode_owned(py, key));
+        }
+        keys
+    }
+
+    /// Build a new in-memory set from a sorted stream of keys.
+    fn collect<S>(mut stream: S) -> PyResult<FstSet<SetData>>
+    where
+        S: for<'a> Streamer<'a, Item = &'a [u8]>,
+    {
+        let mut builder = FstSetBuilder::memory();
+        while let Some(key) = stream.next() {
+            builder.insert(key).map_err(error::fst_error)?;
+        }
+        let bytes = builder.into_inner().map_err(error::fst_error)?;
+        FstSet::new(SetData::Vec(Arc::new(bytes))).map_err(error::fst_error)
+    }
+
+    /// Stream the matches of `lev` within `bounds` and `window`, or collect
+    /// them on the rayon pool if `parallel`.
+    fn lev_search(
+        &self,
+        py: Python,
+        lev: Arc<Levenshtein>,
+        parallel: bool,
+        bounds: KeyRange,
+        window: Window,
+    ) -> PyResult<PyObject> {
+        if parallel {
+            let fst = self.inner.as_fst();
+            let start = Instant::now();
+            let keys = py.allow_threads(|| util::par_search(fst, &*lev, &bounds));
+            trace::emit(py, "Set.search_lev", start.elapsed(), keys.len())?;
+            let tally = Counters::stream(&self.counters);
+            return Ok(SetCollectedStream::new(window.apply(keys), tally).into_py(py));
+        }
+        let set = Box::new(self.inner.clone());
+        let (ge, lt) = bounds;
+        let mut builder = set.search(&*lev);
+        if let Some(ge) = &ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = &lt {
+            builder = builder.lt(lt);
+        }
+        let stream = builder.into_stream();
+        let stream = unsafe {
+            std::mem::transmute::<
+                fst::set::Stream<'_, &Levenshtein>,
+                fst::set::Stream<'static, &'static Levenshtein>,
+            >(stream)
+        };
+        Ok(SetLevStream {
+            stream,
+            _set: set,
+            _lev: lev,
+            span: Span::new("Set.search_lev"),
+            prefetch: Prefetch::new(window),
+            tally: Counters::stream(&self.counters),
+            raw: false,
+        }
+        .into_py(py))
+    }
+
+    fn from_fst(inner: FstSet<SetData>) -> Self {
+        Set {
+            inner,
+            cache: None,
+            counters: None,
+            path: None,
+            ordinals: Arc::default(),
+            suffixes: None,
+            substrings: None,
+            translit: None,
+            normalized: None,
+            hash: OnceLock::new(),
+            closed: false,
+        }
+    }
+
+    /// Build the companion Set of `variant_entry(variant(key), key)` for
+    /// every key, in memory or at `path`. An existing file at `path` built
+    /// from this Set with the same `kind` of variant is reused instead; if
+    /// the kind is `None`, the variants cannot be told apart and the file is
+    /// always rebuilt.
+    fn variant_index(
+        &self,
+        py: Python,
+        path: Option<String>,
+        kind: Option<Vec<u8>>,
+        mut variant: impl FnMut(&str) -> PyResult<String>,
+    ) -> PyResult<FstSet<SetData>> {
+        let fst = self.inner.as_fst();
+        let fingerprint = kind
+            .as_deref()
+            .map(|kind| py.allow_threads(|| companion::fingerprint(fst, kind)));
+        if let Some(fingerprint) = fingerprint {
+            if let Some(entries) = companion::load(py, path.as_deref(), fingerprint)? {
+                return Ok(entries);
+            }
+        }
+        let mut entries = Vec::with_capacity(self.inner.len());
+        let mut stream = self.inner.stream();
+        while let Some(key) = stream.next() {
+            let variant = variant(&String::from_utf8_lossy(key))?;
+            entries.push(variant_entry(&variant, key));
+        }
+        entries.sort_unstable();
+        companion::build(py, &entries, path, fingerprint.unwrap_or(0))
+    }
+
+    /// The keys of `other` as an operand of the set operators: those of a
+    /// Set, or those of any other iterable, built into a Set in memory.
+    /// `None` if `other` is not iterable. Items that are not keys are left
+    /// out if `skip_others`, and raise `TypeError` otherwise.
+    fn operand(other: &PyAny, skip_others: bool) -> PyResult<Option<FstSet<SetData>>> {
+        if let Ok(other) = other.extract::<PyRef<Set>>() {
+            other.check_open()?;
+            return Ok(Some(other.inner.clone()));
+        }
+        let Ok(items) = other.iter() else {
+            return Ok(None);
+        };
+        let mut keys = BTreeSet::new();
+        for item in items {
+            match item?.extract::<Key>() {
+                Ok(key) => {
+                    keys.insert(key.as_bytes().to_vec());
+                }
+                Err(_) if skip_others => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let set = FstSet::from_iter(keys).map_err(error::fst_error)?;
+        let bytes = set.into_fst().into_inner();
+        FstSet::new(SetData::Vec(Arc::new(bytes)))
+            .map(Some)
+            .map_err(error::fst_error)
+    }
+
+    /// Apply `op` to this Set and `other`, or to `other` and this Set if
+    /// `reflected`, for the set operators.
+    fn operator(
+        &self,
+        py: Python,
+        other: &PyAny,
+        op: Operator,
+        reflected: bool,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        // Items that are not keys cannot be in an intersection, nor are they
+        // taken out of this Set by a difference.
+        let skip_others = matches!((op, reflected), (Operator::And, _) | (Operator::Sub, false));
+        let Some(other) = Set::operand(other, skip_others)? else {
+            return Ok(py.NotImplemented());
+        };
+        let (a, b) = if reflected {
+            (&other, &self.inner)
+        } else {
+            (&self.inner, &other)
+        };
+        let set = py.allow_threads(|| {
+            let ops = a.op().add(b);
+            match op {
+                Operator::And => Set::collect(ops.intersection()),
+                Operator::Or => Set::collect(ops.union()),
+                Operator::Sub => Set::collect(ops.difference()),
+                Operator::Xor => Set::collect(ops.symmetric_difference()),
+            }
+        })?;
+        Ok(Set::from_fst(set).into_py(py))
+    }
+
+    /// Build a Set from sorted, distinct `keys`, in memory or at `path`, and
+    /// open it.
+    pub fn build_sorted(py: Python, keys: &[Vec<u8>], path: Option<String>) -> PyResult<Set> {
+        let mut builder = SetBuilder::new(path.clone())?;
+        for key in keys {
+            builder.insert_bytes(key)?;
+        }
+        Set::finish_open(py, builder, path)
+    }
+
+    /// Build a Set from an iterable of keys, as `Set.from_iter()` does.
+    pub fn build_iter(
+        py: Python,
+        iterable: &PyAny,
+        path: Option<String>,
+        sorted: bool,
+    ) -> PyResult<Set> {
+        let mut builder = SetBuilder::new(path.clone())?;
+        if sorted {
+            for key in iterable.iter()? {
+                builder.insert_bytes(key?.extract::<Key>()?.as_bytes())?;
+            }
+        } else {
+            let mut sorter = ExternalSorter::new(DEFAULT_MEMORY_LIMIT);
+            for key in iterable.iter()? {
+                sorter.push(key?.extract::<Key>()?.as_bytes().to_vec(), 0)?;
+            }
+            py.allow_threads(|| {
+                let mut last: Option<Vec<u8>> = None;
+                sorter.finish(|key, _| -> PyResult<()> {
+                    if last.as_deref() != Some(key) {
+                        builder.insert_bytes(key)?;
+                        last = Some(key.to_vec());
+                    }
+                    Ok(())
+                })
+            })?;
+        }
+        Set::finish_open(py, builder, path)
+    }
+
+    /// A copy of this Set that shares its FST data, which never changes, but
+    /// has its own lookup cache and counters.
+    fn deep_copy(&self) -> Set {
+        Set {
+            cache: self
+                .cache
+                .as_ref()
+                .map(|cache| Arc::new(cache.empty_copy())),
+            counters: self.counters.as_ref().map(|_| Arc::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Finish `builder`, started with `path`, and open the Set it built.
+    pub fn finish_open(py: Python, mut builder: SetBuilder, path: Option<String>) -> PyResult<Set> {
+        match (builder.finish(py)?, path) {
+            (Some(set), _) => Ok(set),
+            (None, Some(path)) => Set::open(PyString::new(py, &path)),
+            (None, None) => unreachable!("an in-memory build returns its Set"),
+        }
+    }
+
+    pub fn open(path: &PyAny) -> PyResult<Self> {
         if let Ok(p) = path.extract::<String>() {
-            let file = File::open(p)?;
+            let file = File::open(&p)?;
             let mmap = unsafe { Mmap::map(&file)? };
-            let set = FstSet::new(SetData::Mmap(Arc::new(mmap)))
-                .map_err(|e| PyValueError::new_err(e.to_string()))?;
-            Ok(Set { inner: set })
-        } else if let Ok(bytes) = path.extract::<&[u8]>() {
-            let set = FstSet::new(SetData::Vec(Arc::new(bytes.to_vec())))
-                .map_err(|e| PyValueError::new_err(e.to_string()))?;
-            Ok(Set { inner: set })
+            error::check_fst_data(&mmap, &format!("{:?}", p))?;
+            let set = FstSet::new(SetData::Mmap(Arc::new(mmap))).map_err(error::fst_error)?;
+            Ok(Set {
+                path: Some(p),
+                ..Set::from_fst(set)
+            })
         } else {
-            Err(PyTypeError::new_err(
-                "Argument must be a path (str) or bytes",
-            ))
+            let buffer = Buffer::get(path).map_err(|err| {
+                if err.is_instance_of::<PyTypeError>(path.py()) {
+                    PyTypeError::new_err("Argument must be a path (str) or a bytes-like object")
+                } else {
+                    err
+                }
+            })?;
+            error::check_fst_data(&buffer, "bytes")?;
+            let set = FstSet::new(SetData::Buffer(Arc::new(buffer))).map_err(error::fst_error)?;
+            Ok(Set::from_fst(set))
         }
     }
+}
 
-    fn __contains__(&self, key: &str) -> bool {
-        self.inner.contains(key)
+#[pymethods]
+impl Set {
+    /// :type path: Union[str, bytes, bytearray, memoryview]
+    #[new]
+    #[pyo3(signature = (path, cache_size=None, counters=false))]
+    fn new(path: &PyAny, cache_size: Option<usize>, counters: bool) -> PyResult<Self> {
+        let mut set = Self::open(path)?;
+        set.cache = cache_size.and_then(LookupCache::new).map(Arc::new);
+        set.counters = counters.then(Arc::default);
+        Ok(set)
+    }
+
+    /// The FST is opened in `__new__`; this only lets subclasses call
+    /// `super().__init__(...)` with the constructor arguments.
+    #[pyo3(signature = (*_args, **_kwargs))]
+    fn __init__(&self, _args: &PyTuple, _kwargs: Option<&PyDict>) {}
+
+    fn __contains__(&self, key: Key) -> PyResult<bool> {
+        self.check_open()?;
+        Ok(self.lookup(key.as_bytes()))
+    }
+
+    /// Whether each of `keys` is in the set, as a list of bools, all looked
+    /// up with the GIL released. With `numpy=True` the result is a
+    /// `numpy.bool_` array instead.
+    ///
+    /// :type keys: Iterable[Union[str, bytes]]
+    /// :rtype: Union[List[bool], Any]
+    #[pyo3(signature = (keys, numpy=false))]
+    fn contains_many(&self, py: Python, keys: &PyAny, numpy: bool) -> PyResult<PyObject> {
+        self.check_open()?;
+        let lookups = KeyBuffer::from_keys(keys)?;
+        let found: Vec<_> =
+            py.allow_threads(|| lookups.iter().map(|key| self.lookup(key)).collect());
+        if numpy {
+            Ok(export::bool_array(py, &found)?.into())
+        } else {
+            Ok(PyList::new(py, found).into())
+        }
     }
 
-    fn __len__(&self) -> usize {
-        self.inner.len()
+    fn __len__(&self) -> PyResult<usize> {
+        self.check_open()?;
+        Ok(self.inner.len())
     }
 
-    fn __iter__(&self) -> SetStream {
-        let stream = self.inner.stream();
+    fn is_empty(&self) -> PyResult<bool> {
+        self.check_open()?;
+        Ok(self.inner.is_empty())
+    }
+
+    /// An empty in-memory Set.
+    ///
+    /// :rtype: Set
+    #[classmethod]
+    fn empty(cls: &PyType) -> PyResult<PyObject> {
+        let bytes = FstSetBuilder::memory()
+            .into_inner()
+            .map_err(error::fst_error)?;
+        let inner = FstSet::new(SetData::Vec(Arc::new(bytes))).map_err(error::fst_error)?;
+        util::instance_of(cls, Set::from_fst(inner))
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let this = slf.borrow();
+        if this.closed {
+            return Ok(format!(
+                "<{}.{} closed>",
+                slf.get_type().getattr("__module__")?,
+                slf.get_type().getattr("__qualname__")?,
+            ));
+        }
+        let data = this.inner.as_fst().as_inner();
+        let mut repr = format!(
+            "<{}.{} len={} size={}",
+            slf.get_type().getattr("__module__")?,
+            slf.get_type().getattr("__qualname__")?,
+            this.inner.len(),
+            util::human_size(data.as_ref().len()),
+        );
+        if let Some(path) = &this.path {
+            let path = PyString::new(slf.py(), path).repr()?;
+            repr.push_str(&format!(" source={}", path));
+        }
+        repr.push_str(&format!(" {}>", data.storage()));
+        Ok(repr)
+    }
+
+    /// Release the FST data, unmapping the file once no iterator or view
+    /// created from this Set is still alive. Any later use of the Set raises
+    /// `ClosedError`, a `ValueError`. Closing twice is allowed.
+    fn close(&mut self) -> PyResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let bytes = FstSetBuilder::memory()
+            .into_inner()
+            .map_err(error::fst_error)?;
+        self.inner = FstSet::new(SetData::Vec(Arc::new(bytes))).map_err(error::fst_error)?;
+        self.cache = None;
+        self.ordinals = Arc::default();
+        self.suffixes = None;
+        self.substrings = None;
+        self.translit = None;
+        self.normalized = None;
+        self.closed = true;
+        Ok(())
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.closed
+    }
+
+    fn __enter__(slf: PyRef<Self>) -> PyResult<PyRef<Self>> {
+        slf.check_open()?;
+        Ok(slf)
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+
+    fn __sizeof__(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.inner.as_fst().as_inner().heap_bytes()
+            + self.cache_bytes()
+    }
+
+    /// Pickle a Set opened from a file as its absolute path, to be mapped
+    /// again when unpickled, and an in-memory Set as its bytes. The lookup
+    /// cache size and whether counters are enabled carry over; the search
+    /// indexes built on the Set do not.
+    ///
+    /// :rtype: Tuple[Any, Tuple[Union[str, bytes], Optional[int], bool]]
+    fn __reduce__(slf: &PyCell<Self>) -> PyResult<(PyObject, PyObject)> {
+        let py = slf.py();
+        let this = slf.borrow();
+        this.check_open()?;
+        let source = match (&this.path, this.inner.as_fst().as_inner()) {
+            (Some(path), SetData::Mmap(_)) => std::path::absolute(path)?.into_py(py),
+            (_, data) => PyBytes::new(py, data.as_ref()).into(),
+        };
+        let cache_size = this.cache.as_ref().map(|cache| cache.capacity());
+        let args = (source, cache_size, this.counters.is_some());
+        Ok((slf.get_type().into(), args.into_py(py)))
+    }
+
+    /// The position of `key` in key order, counting from 0. The first
+    /// ordinal lookup samples the keys in one pass; after that a lookup scans
+    /// a few hundred keys at most.
+    fn get_index(&self, py: Python, key: Key) -> PyResult<usize> {
+        self.check_open()?;
+        self.ordinal_index(py)
+            .index_of(self.inner.as_fst(), key.as_bytes())
+            .ok_or_else(|| key.key_error())
+    }
+
+    /// The key at position `index` in key order, where a negative index
+    /// counts from the end. The inverse of `get_index()`.
+    fn get_key(&self, py: Python, index: isize) -> PyResult<String> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        ordinal::resolve(index, fst.len())
+            .and_then(|index| self.ordinal_index(py).key_at(fst, index))
+            .map(|key| lossy::decode_owned(py, &key))
+            .ok_or_else(|| PyIndexError::new_err("Set index out of range"))
+    }
+
+    /// `k` distinct keys drawn uniformly at random, in key order. Passing
+    /// `seed` makes the draw repeatable. Each key is found by its position,
+    /// walking down the FST with the keys below each node counted, so the
+    /// keys are neither scanned nor loaded.
+    ///
+    /// :type k: int
+    /// :type seed: Optional[int]
+    /// :rtype: List[str]
+    #[pyo3(signature = (k, seed=None))]
+    fn sample(&self, py: Python, k: u64, seed: Option<u64>) -> PyResult<Vec<String>> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        if k > fst.len() as u64 {
+            return Err(PyValueError::new_err("sample larger than the Set"));
+        }
+        let items = py
+            .allow_threads(|| ordinal::items_at(fst, &ordinal::sample(fst.len() as u64, k, seed)));
+        Ok(items
+            .into_iter()
+            .map(|(key, _)| lossy::decode_owned(py, &key))
+            .collect())
+    }
+
+    /// :rtype: Set
+    fn __copy__(slf: &PyCell<Self>) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        this.check_open()?;
+        let copy = util::instance_of(slf.get_type(), this.clone())?;
+        util::copy_attrs(slf, copy.as_ref(slf.py()), None)?;
+        Ok(copy)
+    }
+
+    /// Like `copy.copy()`, this shares the FST data rather than copying its
+    /// bytes: the data is never modified, so a byte copy would only cost
+    /// memory. Only the lookup cache and the usage counters are new.
+    ///
+    /// :rtype: Set
+    fn __deepcopy__(slf: &PyCell<Self>, memo: &PyAny) -> PyResult<PyObject> {
+        let this = slf.borrow();
+        this.check_open()?;
+        let copy = util::instance_of(slf.get_type(), this.deep_copy())?;
+        util::copy_attrs(slf, copy.as_ref(slf.py()), Some(memo))?;
+        Ok(copy)
+    }
+
+    /// Structural metrics of the FST: the number of keys, nodes,
+    /// transitions and final nodes, the largest number of transitions out of
+    /// a node, the length of the longest key and the serialized size, both
+    /// in bytes, plus the `fst` format version and where the data lives
+    /// (`"memory"`, `"mmap"` or `"buffer"`). Walks every node once.
+    ///
+    /// :rtype: Dict[str, Union[int, str]]
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        let stats = util::stats(py, fst)?;
+        stats.set_item("storage", fst.as_inner().storage())?;
+        Ok(stats)
+    }
+
+    /// Check the FST data against the checksum written when it was built,
+    /// raising `FormatError` if it does not match, to catch a corrupted file
+    /// before it returns wrong results. Reads all of the data; FSTs written
+    /// by `fst` before format version 3 have no checksum and raise too.
+    fn verify(&self, py: Python) -> PyResult<()> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        py.allow_threads(|| fst.verify()).map_err(error::fst_error)
+    }
+
+    /// The root node of the FST, for walking it node by node.
+    fn root(&self) -> PyResult<Node> {
+        self.check_open()?;
+        Ok(Node::root(Lexicon::Set(self.inner.clone())))
+    }
+
+    /// The `count`, `min`, `max` and `mean` of the key lengths in bytes,
+    /// and a `histogram` of the number of keys of each length, from one pass
+    /// over the keys.
+    ///
+    /// :rtype: Dict[str, Any]
+    fn key_length_stats<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        util::key_length_stats(py, self.inner.as_fst())
+    }
+
+    /// The number of keys starting with each prefix of `depth` characters,
+    /// in key order, for a quick look at how the keys are spread out. Keys
+    /// shorter than `depth` characters count under themselves.
+    ///
+    /// :rtype: Dict[str, int]
+    #[pyo3(signature = (depth=1))]
+    fn prefix_histogram<'py>(&self, py: Python<'py>, depth: usize) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let fst = self.inner.as_fst();
+        let buckets = py.allow_threads(|| util::prefix_histogram(fst, depth));
+        let histogram = PyDict::new(py);
+        for (prefix, count) in buckets {
+            histogram.set_item(String::from_utf8_lossy(&prefix), count)?;
+        }
+        Ok(histogram)
+    }
+
+    /// The number of `lookups` (with their `hits`, `misses` and
+    /// `cache_hits`), of `streams` opened and of `keys` they yielded, shared
+    /// with shallow copies of this Set. Requires `counters=True`.
+    ///
+    /// :rtype: Dict[str, int]
+    fn counters<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.enabled_counters()?.to_dict(py)
+    }
+
+    /// Set all of `counters()` back to zero.
+    fn reset_counters(&self) -> PyResult<()> {
+        self.enabled_counters()?.reset();
+        Ok(())
+    }
+
+    /// :rtype: Dict[str, int]
+    fn memory_usage<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        self.check_open()?;
+        let data = self.inner.as_fst().as_inner();
+        let usage = PyDict::new(py);
+        usage.set_item("heap", data.heap_bytes())?;
+        usage.set_item("mmap", data.mapped_bytes())?;
+        usage.set_item("buffer", data.buffer_bytes())?;
+        usage.set_item("cache", self.cache_bytes())?;
+        Ok(usage)
+    }
+
+    /// The fraction of the FST's pages currently resident in RAM, from 0 to
+    /// 1, for checking that a memory-mapped file has been warmed up. An
+    /// in-memory Set is always fully resident.
+    fn residency(&self, py: Python) -> PyResult<f64> {
+        self.check_open()?;
+        let data = self.inner.as_fst().as_inner();
+        py.allow_threads(|| data.resident_fraction())
+    }
+
+    fn __iter__(&self) -> PyResult<SetStream> {
+        self.iter(false)
+    }
+
+    fn __reversed__(&self) -> PyResult<SetStream> {
+        self.iter(true)
+    }
+
+    /// Stream the keys in key order, or in descending order if `reverse` is
+    /// true.
+    #[pyo3(signature = (reverse=false))]
+    fn iter(&self, reverse: bool) -> PyResult<SetStream> {
+        self.check_open()?;
+        Ok(self.range_stream(None, None, None, None, reverse))
+    }
+
+    /// The keys within the given bounds, in order, or in descending order if
+    /// `reverse` is true: at least `ge`, greater than `gt`, at most `le` and
+    /// less than `lt`. Only the keys in the range are visited. `offset` and
+    /// `limit` skip and cap the keys yielded.
+    #[pyo3(signature = (ge=None, gt=None, le=None, lt=None, reverse=false, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn range(
+        &self,
+        ge: Option<Key>,
+        gt: Option<Key>,
+        le: Option<Key>,
+        lt: Option<Key>,
+        reverse: bool,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetStream> {
+        self.check_open()?;
+        let mut stream = self.range_stream(
+            ge.as_ref().map(Key::as_bytes),
+            gt.as_ref().map(Key::as_bytes),
+            le.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+            reverse,
+        );
+        stream.window.narrow(offset, limit);
+        Ok(stream)
+    }
+
+    /// Stream the keys starting with `prefix`, in key order, or in descending
+    /// order if `reverse` is true. This is a range scan, so it is much cheaper
+    /// than an equivalent `search_re`.
+    #[pyo3(signature = (prefix, reverse=false, limit=None, offset=0))]
+    fn search_prefix(
+        &self,
+        prefix: Key,
+        reverse: bool,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetStream> {
+        self.check_open()?;
+        let (ge, lt) = util::bounded_range(Some(prefix.as_bytes()), None, None);
+        let mut stream = self.range_stream(ge.as_deref(), None, None, lt.as_deref(), reverse);
+        stream.window.narrow(offset, limit);
+        Ok(stream)
+    }
+
+    /// The longest key that is a prefix of `text`, or `None` if no key is.
+    /// The FST is walked along `text` once, so this is as cheap as a lookup.
+    fn longest_prefix(&self, py: Python, text: Key) -> PyResult<Option<String>> {
+        self.check_open()?;
+        let text = text.as_bytes();
+        let prefixes = util::prefixes_of(self.inner.as_fst(), text);
+        Ok(prefixes
+            .last()
+            .map(|&(len, _)| lossy::decode_owned(py, &text[..len])))
+    }
+
+    /// Every key that is a prefix of `text`, shortest first.
+    fn all_prefixes(&self, py: Python, text: Key) -> PyResult<Vec<String>> {
+        self.check_open()?;
+        let text = text.as_bytes();
+        let prefixes = util::prefixes_of(self.inner.as_fst(), text);
+        Ok(prefixes
+            .into_iter()
+            .map(|(len, _)| lossy::decode_owned(py, &text[..len]))
+            .collect())
+    }
+
+    /// The smallest key, or `None` if the Set is empty. Like `last`,
+    /// `floor` and `ceiling`, this walks one path of the FST rather than
+    /// iterating.
+    fn first(&self, py: Python) -> PyResult<Option<String>> {
+        self.check_open()?;
+        let first = util::ceiling(self.inner.as_fst(), None);
+        Ok(first.map(|(key, _)| lossy::decode_owned(py, &key)))
+    }
+
+    /// The largest key, or `None` if the Set is empty.
+    fn last(&self, py: Python) -> PyResult<Option<String>> {
+        self.check_open()?;
+        let last = util::floor(self.inner.as_fst(), None);
+        Ok(last.map(|(key, _)| lossy::decode_owned(py, &key)))
+    }
+
+    /// The greatest key at most `key`, or `None` if there is none.
+    fn floor(&self, py: Python, key: Key) -> PyResult<Option<String>> {
+        self.check_open()?;
+        let floor = util::floor(self.inner.as_fst(), Some(key.as_bytes()));
+        Ok(floor.map(|(key, _)| lossy::decode_owned(py, &key)))
+    }
+
+    /// The least key at least `key`, or `None` if there is none.
+    fn ceiling(&self, py: Python, key: Key) -> PyResult<Option<String>> {
+        self.check_open()?;
+        let ceiling = util::ceiling(self.inner.as_fst(), Some(key.as_bytes()));
+        Ok(ceiling.map(|(key, _)| lossy::decode_owned(py, &key)))
+    }
+
+    /// Build a new Set of these keys plus those of `add` and minus those of
+    /// `remove`, in memory or, if `path` is given, on disk. The edits are
+    /// sorted in memory and merged with this Set in one pass, without
+    /// iterating it from Python. A key in both `add` and `remove` is
+    /// removed, and keys to remove that are missing are ignored.
+    ///
+    /// :type add: Optional[Iterable[Union[str, bytes]]]
+    /// :type remove: Optional[Iterable[Union[str, bytes]]]
+    /// :rtype: Set
+    #[pyo3(signature = (add=None, remove=None, path=None))]
+    fn rebuild(
+        slf: &PyCell<Self>,
+        add: Option<&PyAny>,
+        remove: Option<&PyAny>,
+        path: Option<String>,
+    ) -> PyResult<PyObject> {
+        let py = slf.py();
+        let this = slf.borrow();
+        this.check_open()?;
+        let add = util::key_set(add)?;
+        let remove = util::key_set(remove)?;
+        let fst = this.inner.as_fst();
+        let mut builder = SetBuilder::new(path.clone())?;
+        py.allow_threads(|| {
+            let add = add.iter().map(|key| (key.as_slice(), 0));
+            util::merge_edits(fst, add, &remove, |key, _| builder.insert_bytes(key))
+        })?;
+        util::instance_of(slf.get_type(), Set::finish_open(py, builder, path)?)
+    }
+
+    /// Export the keys through the Arrow C stream interface, as chunks of a
+    /// string array.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<&PyAny>,
+    ) -> PyResult<&'py PyCapsule> {
+        self.check_open()?;
+        let _ = requested_schema;
+        let keys = arrow::KeyChunks::new(self.inner.as_fst().clone(), lossy::log(py)?);
+        arrow::stream_capsule(py, Box::new(keys))
+    }
+
+    /// :rtype: List[str]
+    fn to_list<'py>(&self, py: Python<'py>) -> PyResult<&'py PyList> {
+        self.check_open()?;
+        Ok(PyList::new(py, self.decoded_keys(py)))
+    }
+
+    /// :rtype: FrozenSet[str]
+    fn to_frozenset<'py>(&self, py: Python<'py>) -> PyResult<&'py PyFrozenSet> {
+        self.check_open()?;
+        PyFrozenSet::new(py, &self.decoded_keys(py))
+    }
+
+    /// The keys as a fixed-width numpy array of `dtype` `"S"` (bytes) or
+    /// `"U"` (str), padded to `width` or, by default, to the longest key.
+    /// Keys longer than `width` raise `ValueError`.
+    #[pyo3(signature = (width=None, dtype="S"))]
+    fn keys_array<'py>(
+        &self,
+        py: Python<'py>,
+        width: Option<usize>,
+        dtype: &str,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let keys = py.allow_threads(|| self.key_buffer());
+        match dtype {
+            "S" => keys.to_ndarray(py, width),
+            "U" => keys.to_unicode_ndarray(py, width),
+            _ => Err(PyValueError::new_err(format!(
+                "dtype must be 'S' or 'U', not {:?}",
+                dtype
+            ))),
+        }
+    }
+
+    /// The keys as a `pandas.Index` of strings.
+    #[pyo3(signature = (name=None))]
+    fn to_index<'py>(&self, py: Python<'py>, name: Option<&PyAny>) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let keys = py.allow_threads(|| self.key_buffer());
+        let keys = keys.to_unicode_ndarray(py, None)?;
+        let kwargs = [("name", name.into_py(py))].into_py_dict(py);
+        py.import("pandas")?
+            .getattr("Index")?
+            .call((keys,), Some(kwargs))
+    }
+
+    /// Build a Set from the values of a `pandas.Index` (or any iterable of
+    /// keys), in memory or, if `path` is given, on disk, like `from_iter`.
+    /// The keys do not need to be sorted.
+    ///
+    /// :rtype: Set
+    #[classmethod]
+    #[pyo3(signature = (index, path=None))]
+    fn from_index(cls: &PyType, index: &PyAny, path: Option<String>) -> PyResult<PyObject> {
+        util::instance_of(cls, Set::build_iter(cls.py(), index, path, false)?)
+    }
+
+    /// Build a Set from an iterable of keys, in memory or, if `path` is
+    /// given, on disk, without a Python-level loop. Unless `sorted` is true,
+    /// the keys are sorted first, spilling to temporary files for large
+    /// inputs, and repeated keys are skipped.
+    ///
+    /// :type iterable: Iterable[Union[str, bytes]]
+    /// :rtype: Set
+    #[classmethod]
+    #[pyo3(signature = (iterable, path=None, sorted=false))]
+    fn from_iter(
+        cls: &PyType,
+        iterable: &PyAny,
+        path: Option<String>,
+        sorted: bool,
+    ) -> PyResult<PyObject> {
+        util::instance_of(cls, Set::build_iter(cls.py(), iterable, path, sorted)?)
+    }
+
+    /// Build a Set of `k`-mers from an array of their 2-bit packed codes
+    /// (see `kmer_codes`), in memory or, if `path` is given, on disk. The
+    /// codes do not need to be sorted or distinct.
+    ///
+    /// :type codes: Any
+    /// :rtype: Set
+    #[classmethod]
+    #[pyo3(signature = (codes, k, path=None))]
+    fn from_kmers(
+        cls: &PyType,
+        codes: &PyAny,
+        k: usize,
+        path: Option<String>,
+    ) -> PyResult<PyObject> {
+        kmer::check_k(k)?;
+        let mut codes = export::u64_values(codes)?;
+        codes.sort_unstable();
+        codes.dedup();
+        let keys = codes
+            .into_iter()
+            .map(|code| kmer::key(code, k))
+            .collect::<PyResult<Vec<_>>>()?;
+        util::instance_of(cls, Set::build_sorted(cls.py(), &keys, path)?)
+    }
+
+    /// Whether each `k`-mer of an array of packed codes is in the set, as a
+    /// `numpy.bool_` array.
+    ///
+    /// :type codes: Any
+    fn contains_kmers<'py>(
+        &self,
+        py: Python<'py>,
+        codes: &PyAny,
+        k: usize,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        kmer::check_k(k)?;
+        let codes = export::u64_values(codes)?;
+        let found = py.allow_threads(|| {
+            codes
+                .iter()
+                .map(|code| Ok(self.inner.contains(kmer::key(*code, k)?)))
+                .collect::<PyResult<Vec<_>>>()
+        })?;
+        export::bool_array(py, &found)
+    }
+
+    /// Write the keys to `path`, one per line. Keys are written as raw bytes.
+    fn write_lines(&self, py: Python, path: &str) -> PyResult<()> {
+        self.check_open()?;
+        self.write_keys(py, path, |out, key| {
+            out.write_all(key)?;
+            out.write_all(b"\n")
+        })
+    }
+
+    /// Write the keys to `path`, each preceded by its length in bytes as a
+    /// little-endian u32.
+    fn write_binary(&self, py: Python, path: &str) -> PyResult<()> {
+        self.check_open()?;
+        self.write_keys(py, path, |out, key| {
+            out.write_all(&(key.len() as u32).to_le_bytes())?;
+            out.write_all(key)
+        })
+    }
+
+    /// The serialized FST, which `Set(data)` opens again.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        self.check_open()?;
+        Ok(PyBytes::new(py, self.inner.as_fst().as_bytes()))
+    }
+
+    /// Write the serialized FST to `path`, for opening with `Set(path)`.
+    fn write(&self, py: Python, path: &str) -> PyResult<()> {
+        self.check_open()?;
+        let bytes = self.inner.as_fst().as_bytes();
+        py.allow_threads(|| std::fs::write(path, bytes))?;
+        Ok(())
+    }
+
+    /// Write the FST's nodes and transitions to `path` as a Graphviz DOT
+    /// graph, drawing at most `max_nodes` nodes, nearest to the root first.
+    #[pyo3(signature = (path, max_nodes=1000))]
+    fn export_dot(&self, py: Python, path: &str, max_nodes: usize) -> PyResult<()> {
+        self.check_open()?;
+        py.allow_threads(|| -> std::io::Result<()> {
+            let mut out = BufWriter::new(File::create(path)?);
+            export::write_dot(&mut out, self.inner.as_fst(), max_nodes)?;
+            out.flush()
+        })?;
+        Ok(())
+    }
+
+    #[pyo3(signature = (prefix_len, limit=None, offset=0))]
+    fn iter_split(
+        &self,
+        prefix_len: usize,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetSplitStream> {
+        self.check_open()?;
+        let window = Window::new(offset, limit).with_len(self.inner.len());
+        let set = Box::new(self.inner.clone());
+        let stream = set.stream();
         let stream = unsafe {
             std::mem::transmute::<fst::set::Stream<'_>, fst::set::Stream<'static>>(stream)
         };
-        SetStream {
-            _set: self.inner.clone(),
+        Ok(SetSplitStream {
             stream,
+            _set: set,
+            window,
+            decoder: PrefixDecoder::new(prefix_len),
+        })
+    }
+
+    /// Build the reversed-key companion FST needed by `ends_with`. For a Set
+    /// opened from a file it is stored next to it, at `<path>.rev`, and
+    /// loaded from there on later calls as long as it was built from the
+    /// same Set; other Sets build it in memory unless `path` is given.
+    #[pyo3(signature = (path=None))]
+    fn enable_suffix_search(&mut self, py: Python, path: Option<String>) -> PyResult<()> {
+        self.check_open()?;
+        let path = path.or_else(|| self.path.as_ref().map(|p| format!("{}.rev", p)));
+        let fst = self.inner.as_fst();
+        let fingerprint = py.allow_threads(|| companion::fingerprint(fst, b"rev"));
+        if let Some(suffixes) = companion::load(py, path.as_deref(), fingerprint)? {
+            self.suffixes = Some(suffixes);
+            return Ok(());
         }
+        let keys = py.allow_threads(|| {
+            let mut keys = Vec::with_capacity(self.inner.len());
+            let mut stream = self.inner.stream();
+            while let Some(key) = stream.next() {
+                keys.push(key.iter().rev().copied().collect::<Vec<u8>>());
+            }
+            keys.sort_unstable();
+            keys
+        });
+        self.suffixes = Some(companion::build(py, &keys, path, fingerprint)?);
+        Ok(())
     }
 
-    fn search_re(&self, regex: &str) -> PyResult<SetRegexStream> {
-        let dfa = regex_automata::dense::Builder::new()
-            .anchored(true)
-            .build(regex)
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
-        let stream = self.inner.search(&dfa).into_stream();
+    /// Stream the keys ending with `suffix`, ordered by their reversed bytes
+    /// rather than by key. Requires `enable_suffix_search()`.
+    #[pyo3(signature = (suffix, limit=None, offset=0))]
+    fn ends_with(
+        &self,
+        suffix: Key,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetSuffixStream> {
+        self.check_open()?;
+        let suffixes = self.suffixes.as_ref().ok_or_else(|| {
+            PyValueError::new_err("suffix search is not enabled, call enable_suffix_search() first")
+        })?;
+        let reversed: Vec<u8> = suffix.as_bytes().iter().rev().copied().collect();
+        let (ge, lt) = util::bounded_range(Some(&reversed), None, None);
+        let set = Box::new(suffixes.clone());
+        let mut range = set.range();
+        if let Some(ge) = ge {
+            range = range.ge(ge);
+        }
+        if let Some(lt) = lt {
+            range = range.lt(lt);
+        }
+        let stream = range.into_stream();
+        let stream = unsafe {
+            std::mem::transmute::<fst::set::Stream<'_>, fst::set::Stream<'static>>(stream)
+        };
+        Ok(SetSuffixStream {
+            stream,
+            _set: set,
+            window: Window::new(offset, limit),
+            tally: Counters::stream(&self.counters),
+            raw: false,
+        })
+    }
+
+    /// Build the index of key suffixes needed by `contains_substring`. It
+    /// holds one entry per character of every key, so it can be several
+    /// times larger than the Set itself. Like `enable_suffix_search`, for a
+    /// Set opened from a file it is stored at `<path>.sub` and reused later,
+    /// as long as it was built from the same Set.
+    #[pyo3(signature = (path=None))]
+    fn enable_substring_search(&mut self, py: Python, path: Option<String>) -> PyResult<()> {
+        self.check_open()?;
+        let path = path.or_else(|| self.path.as_ref().map(|p| format!("{}.sub", p)));
+        let fst = self.inner.as_fst();
+        let fingerprint = py.allow_threads(|| companion::fingerprint(fst, b"sub"));
+        if let Some(substrings) = companion::load(py, path.as_deref(), fingerprint)? {
+            self.substrings = Some(substrings);
+            return Ok(());
+        }
+        let entries = py.allow_threads(|| {
+            let mut entries = Vec::new();
+            let mut stream = self.inner.stream();
+            while let Some(key) = stream.next() {
+                entries.extend(substring_entries(key));
+            }
+            entries.sort_unstable();
+            entries
+        });
+        self.substrings = Some(companion::build(py, &entries, path, fingerprint)?);
+        Ok(())
+    }
+
+    /// The keys containing `substring`, in key order. Requires
+    /// `enable_substring_search()`.
+    fn contains_substring(&self, py: Python, substring: Key) -> PyResult<Vec<String>> {
+        self.check_open()?;
+        let substrings = self.substrings.as_ref().ok_or_else(|| {
+            PyValueError::new_err(
+                "substring search is not enabled, call enable_substring_search() first",
+            )
+        })?;
+        let needle = substring.as_bytes();
+        let keys = py.allow_threads(|| {
+            let range = util::bounded_range(Some(needle), None, None);
+            let mut stream = util::search_range(substrings.as_fst(), &AlwaysMatch, range);
+            let mut keys = Vec::new();
+            while let Some((entry, _)) = stream.next() {
+                let key = substring_entry_key(entry).ok_or_else(|| {
+                    error::FormatError::new_err(format!(
+                        "malformed substring index entry {:?}",
+                        String::from_utf8_lossy(entry)
+                    ))
+                })?;
+                if needle.is_empty() || key.windows(needle.len()).any(|w| w == needle) {
+                    keys.push(key.to_vec());
+                }
+            }
+            keys.sort_unstable();
+            keys.dedup();
+            Ok::<_, PyErr>(keys)
+        })?;
+        Ok(keys
+            .iter()
+            .map(|key| String::from_utf8_lossy(key).into_owned())
+            .collect())
+    }
+
+    /// Build the companion Set needed by `search_translit`, holding the
+    /// transliteration of every key under `scheme`: `"cyrillic"` or
+    /// `"latin"` for the built-in tables, a dict of characters to their
+    /// romanizations, or a callable such as a pinyin converter. Unlike the
+    /// other companions it is only written to a file, and reused from there,
+    /// if `path` is given, since it depends on the scheme. It is reused only
+    /// for the same table, or a plain function with the same name and code;
+    /// other callables rebuild it every time.
+    ///
+    /// :type scheme: Union[str, Dict[str, str], Callable[[str], str]]
+    #[pyo3(signature = (scheme, path=None))]
+    fn enable_translit(
+        &mut self,
+        py: Python,
+        scheme: &PyAny,
+        path: Option<String>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        let scheme = Arc::new(Scheme::extract(scheme)?);
+        let kind = scheme.kind(py)?;
+        let entries = self.variant_index(py, path, kind, |key| scheme.apply(py, key))?;
+        self.translit = Some((scheme, entries));
+        Ok(())
+    }
+
+    /// The keys whose transliteration equals that of `query`, ignoring case,
+    /// in key order. Requires `enable_translit()`.
+    #[pyo3(signature = (query, limit=None, offset=0))]
+    fn search_translit(
+        &self,
+        py: Python,
+        query: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<Vec<String>> {
+        self.check_open()?;
+        let (scheme, entries) = self.translit.as_ref().ok_or_else(|| {
+            PyValueError::new_err("transliteration is not enabled, call enable_translit() first")
+        })?;
+        let variant = scheme.apply(py, query)?;
+        Ok(variant_keys(entries, &variant, Window::new(offset, limit)))
+    }
+
+    /// Build the companion Set needed by `search_normalized`, holding
+    /// `normalizer(key)` for every key, where `normalizer` is a callable
+    /// from `str` to `str` such as a stemmer. As with `enable_translit`, it is
+    /// written to a file only if `path` is given, and reused from there only
+    /// for a plain function with the same name and code.
+    ///
+    /// :type normalizer: Callable[[str], str]
+    #[pyo3(signature = (normalizer, path=None))]
+    fn enable_normalized_search(
+        &mut self,
+        py: Python,
+        normalizer: PyObject,
+        path: Option<String>,
+    ) -> PyResult<()> {
+        self.check_open()?;
+        let kind = companion::callable_kind(normalizer.as_ref(py))?
+            .map(|kind| [b"normalized\0".as_slice(), &kind].concat());
+        let entries = self.variant_index(py, path, kind, |key| {
+            normalizer.call1(py, (key,))?.extract(py)
+        })?;
+        self.normalized = Some((normalizer, entries));
+        Ok(())
+    }
+
+    /// The keys whose normalized form equals that of `term`, in key order.
+    /// Requires `enable_normalized_search()`.
+    #[pyo3(signature = (term, limit=None, offset=0))]
+    fn search_normalized(
+        &self,
+        py: Python,
+        term: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<Vec<String>> {
+        self.check_open()?;
+        let (normalizer, entries) = self.normalized.as_ref().ok_or_else(|| {
+            PyValueError::new_err(
+                "normalized search is not enabled, call enable_normalized_search() first",
+            )
+        })?;
+        let term: String = normalizer.call1(py, (term,))?.extract(py)?;
+        Ok(variant_keys(entries, &term, Window::new(offset, limit)))
+    }
+
+    /// Split `text` into tokens by greedy longest match against the keys,
+    /// returning the `(start, end)` character offsets of each token. Where
+    /// no key matches, a single character becomes a token of its own.
+    fn segment(&self, py: Python, text: &str) -> PyResult<Vec<(usize, usize)>> {
+        self.check_open()?;
+        let tokens = py.allow_threads(|| util::segment(self.inner.as_fst(), text));
+        Ok(tokens
+            .into_iter()
+            .map(|(start, end, _)| (start, end))
+            .collect())
+    }
+
+    /// Only keys starting with `prefix`, `>= ge` and `< lt` are searched,
+    /// when those are given.
+    ///
+    /// By default `regex` has to match a whole key; `match_mode="prefix"`
+    /// matches keys starting with a match and `match_mode="substring"` keys
+    /// containing one, like `re.match` and `re.search`. With
+    /// `case_insensitive`, case is ignored.
+    ///
+    /// :rtype: Iterator[str]
+    #[pyo3(signature = (
+        regex,
+        parallel=false,
+        prefix=None,
+        ge=None,
+        lt=None,
+        match_mode="anchored",
+        case_insensitive=false,
+        limit=None,
+        offset=0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_re(
+        &self,
+        py: Python,
+        regex: Key,
+        parallel: bool,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        match_mode: &str,
+        case_insensitive: bool,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let window = Window::new(offset, limit);
+        let mode = MatchMode::parse(match_mode)?;
+        let dfa = util::search_dfa(regex.as_str()?, mode, case_insensitive)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        if parallel {
+            let fst = self.inner.as_fst();
+            let start = Instant::now();
+            let keys = py.allow_threads(|| util::par_search(fst, &dfa, &bounds));
+            trace::emit(py, "Set.search_re", start.elapsed(), keys.len())?;
+            let tally = Counters::stream(&self.counters);
+            return Ok(SetCollectedStream::new(window.apply(keys), tally).into_py(py));
+        }
+        let dfa = Box::new(dfa);
+        let set = Box::new(self.inner.clone());
+        let (ge, lt) = bounds;
+        let mut builder = set.search(&*dfa);
+        if let Some(ge) = &ge {
+            builder = builder.ge(ge);
+        }
+        if let Some(lt) = &lt {
+            builder = builder.lt(lt);
+        }
+        let stream = builder.into_stream();
         let stream = unsafe {
             std::mem::transmute::<
                 fst::set::Stream<'_, &DenseDFA<Vec<usize>, usize>>,
@@ -82,283 +1486,1228 @@ impl Set {
             >(stream)
         };
         Ok(SetRegexStream {
-            _set: self.inner.clone(),
-            _dfa: dfa,
             stream,
-        })
+            _set: set,
+            _dfa: dfa,
+            span: Span::new("Set.search_re"),
+            prefetch: Prefetch::new(window),
+            tally: Counters::stream(&self.counters),
+            raw: false,
+        }
+        .into_py(py))
     }
 
-    fn search_lev(&self, key: &str, max_dist: u32) -> PyResult<SetLevStream> {
-        let lev =
-            Levenshtein::new(key, max_dist).map_err(|e| PyValueError::new_err(e.to_string()))?;
-        let stream = self.inner.search(&lev).into_stream();
+    /// Stream the keys containing the characters of `query` in
+    /// order, though not necessarily next to each other, like the fuzzy
+    /// finders of editors: `"hpf"` matches `"/home/projects/foo"`.
+    #[pyo3(signature = (query, limit=None, offset=0))]
+    fn search_subsequence(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetSubsequenceStream> {
+        self.check_open()?;
+        let query: Box<str> = query.into();
+        let set = Box::new(self.inner.clone());
+        let stream = set.search(Subsequence::new(&query)).into_stream();
         let stream = unsafe {
             std::mem::transmute::<
-                fst::set::Stream<'_, &Levenshtein>,
-                fst::set::Stream<'static, &'static Levenshtein>,
+                fst::set::Stream<'_, Subsequence<'_>>,
+                fst::set::Stream<'static, Subsequence<'static>>,
             >(stream)
         };
-        Ok(SetLevStream {
-            _set: self.inner.clone(),
-            _lev: lev,
+        Ok(SetSubsequenceStream {
             stream,
+            _set: set,
+            _query: query,
+            span: Span::new("Set.search_subsequence"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            tally: Counters::stream(&self.counters),
+            raw: false,
         })
     }
 
-    fn is_disjoint(&self, other: &Set) -> bool {
-        self.inner.is_disjoint(&other.inner)
+    /// Only keys starting with `prefix`, `>= ge` and `< lt` are searched,
+    /// when those are given.
+    ///
+    /// :rtype: Iterator[str]
+    #[pyo3(signature = (
+        key,
+        max_dist,
+        parallel=false,
+        prefix=None,
+        ge=None,
+        lt=None,
+        limit=None,
+        offset=0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_lev(
+        &self,
+        py: Python,
+        key: Key,
+        max_dist: u32,
+        parallel: bool,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        self.lev_search(py, Arc::new(lev), parallel, bounds, window)
     }
 
-    fn is_subset(&self, other: &Set) -> bool {
-        self.inner.is_subset(&other.inner)
+    /// Search for the keys within `query`'s distance of its key, like
+    /// `search_lev`, with an automaton built once for any number of
+    /// searches.
+    ///
+    /// :rtype: Iterator[str]
+    #[pyo3(signature = (query, parallel=false, prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        py: Python,
+        query: PyRef<LevenshteinQuery>,
+        parallel: bool,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<PyObject> {
+        self.check_open()?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        self.lev_search(py, query.automaton(), parallel, bounds, window)
     }
 
-    fn is_superset(&self, other: &Set) -> bool {
-        self.inner.is_superset(&other.inner)
+    /// Search with an automaton written in Python: an object with `start()`,
+    /// `is_match(state)` and `accept(state, byte)` methods, and optionally
+    /// `can_match(state)` to stop walking paths that cannot lead to a match.
+    /// `start` returns the state before any byte, `accept` the state after
+    /// `byte`, and a key matches if `is_match` is true for the state after
+    /// its last byte.
+    ///
+    /// Every byte tried calls back into Python with the GIL held, so this is
+    /// much slower than `search_re` or `search_lev`; `can_match` and a
+    /// `prefix` keep the walk small. The matches are collected before they
+    /// are yielded, and an exception raised by a callback is raised here.
+    ///
+    /// :rtype: Iterator[str]
+    #[pyo3(signature = (automaton, prefix=None, ge=None, lt=None, limit=None, offset=0))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_custom(
+        &self,
+        py: Python,
+        automaton: &PyAny,
+        prefix: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetCollectedStream> {
+        self.check_open()?;
+        let bounds = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let window = Window::new(offset, limit);
+        let fst = self.inner.as_fst();
+        let matches = custom::search(py, "Set.search_custom", fst, automaton, bounds, window)?;
+        Ok(SetCollectedStream::new(
+            matches,
+            Counters::stream(&self.counters),
+        ))
     }
 
-    fn union(&self, other: &Set) -> SetUnion {
-        let sets = vec![self.clone(), other.clone()];
-        let op = self.inner.op().add(&self.inner).add(&other.inner).union();
-        let stream =
-            unsafe { std::mem::transmute::<fst::set::Union<'_>, fst::set::Union<'static>>(op) };
-        SetUnion {
-            _sets: sets,
-            stream,
+    /// Like `search_re`, but searches on a worker thread and returns an
+    /// awaitable resolving to the list of matches.
+    ///
+    /// :rtype: Awaitable[List[str]]
+    #[pyo3(signature = (regex, parallel=false))]
+    fn asearch_re<'py>(&self, py: Python<'py>, regex: Key, parallel: bool) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let dfa = util::regex_dfa(regex.as_str()?)?;
+        let fst = self.inner.as_fst().clone();
+        aio::run(
+            py,
+            "Set.asearch_re",
+            move || {
+                Ok(if parallel {
+                    util::par_search(&fst, &dfa, &(None, None))
+                } else {
+                    util::search(&fst, &dfa)
+                })
+            },
+            match_list,
+        )
+    }
+
+    /// Like `search_lev`, but searches on a worker thread and returns an
+    /// awaitable resolving to the list of matches.
+    ///
+    /// :rtype: Awaitable[List[str]]
+    #[pyo3(signature = (key, max_dist, parallel=false))]
+    fn asearch_lev<'py>(
+        &self,
+        py: Python<'py>,
+        key: Key,
+        max_dist: u32,
+        parallel: bool,
+    ) -> PyResult<&'py PyAny> {
+        self.check_open()?;
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let fst = self.inner.as_fst().clone();
+        aio::run(
+            py,
+            "Set.asearch_lev",
+            move || {
+                Ok(if parallel {
+                    util::par_search(&fst, &lev, &(None, None))
+                } else {
+                    util::search(&fst, &lev)
+                })
+            },
+            match_list,
+        )
+    }
+
+    /// The number of keys `search_re` would find.
+    #[pyo3(signature = (regex, parallel=false, match_mode="anchored", case_insensitive=false))]
+    fn count_re(
+        &self,
+        py: Python,
+        regex: Key,
+        parallel: bool,
+        match_mode: &str,
+        case_insensitive: bool,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        let mode = MatchMode::parse(match_mode)?;
+        let dfa = util::search_dfa(regex.as_str()?, mode, case_insensitive)?;
+        let fst = self.inner.as_fst();
+        Ok(py.allow_threads(|| {
+            if parallel {
+                util::par_count(fst, &dfa)
+            } else {
+                util::count(fst, &dfa, (None, None))
+            }
+        }))
+    }
+
+    #[pyo3(signature = (key, max_dist, parallel=false))]
+    fn count_lev(&self, py: Python, key: Key, max_dist: u32, parallel: bool) -> PyResult<usize> {
+        self.check_open()?;
+        let lev = Levenshtein::new(key.as_str()?, max_dist).map_err(error::lev_error)?;
+        let fst = self.inner.as_fst();
+        Ok(py.allow_threads(|| {
+            if parallel {
+                util::par_count(fst, &lev)
+            } else {
+                util::count(fst, &lev, (None, None))
+            }
+        }))
+    }
+
+    /// The number of keys starting with `prefix`, at least `ge` and less than
+    /// `lt`, and fully matching `regex`, where given. The keys are counted in
+    /// Rust with the GIL released, without being returned to Python.
+    #[pyo3(signature = (prefix=None, regex=None, ge=None, lt=None))]
+    fn count(
+        &self,
+        py: Python,
+        prefix: Option<Key>,
+        regex: Option<Key>,
+        ge: Option<Key>,
+        lt: Option<Key>,
+    ) -> PyResult<usize> {
+        self.check_open()?;
+        let range = util::bounded_range(
+            prefix.as_ref().map(Key::as_bytes),
+            ge.as_ref().map(Key::as_bytes),
+            lt.as_ref().map(Key::as_bytes),
+        );
+        let fst = self.inner.as_fst();
+        match regex {
+            Some(regex) => {
+                let dfa = util::regex_dfa(regex.as_str()?)?;
+                Ok(py.allow_threads(|| util::count(fst, &dfa, range)))
+            }
+            None => Ok(py.allow_threads(|| util::count(fst, &AlwaysMatch, range))),
         }
     }
 
-    fn intersection(&self, other: &Set) -> SetIntersection {
-        let sets = vec![self.clone(), other.clone()];
-        let op = self
-            .inner
-            .op()
-            .add(&self.inner)
-            .add(&other.inner)
-            .intersection();
+    fn is_disjoint(&self, other: &Set) -> PyResult<bool> {
+        self.check_open()?;
+        other.check_open()?;
+        Ok(self.inner.is_disjoint(&other.inner))
+    }
+
+    /// Whether no key of `other`, a Set or any iterable, is in this Set.
+    ///
+    /// :type other: Iterable[Union[str, bytes]]
+    fn isdisjoint(&self, other: &PyAny) -> PyResult<bool> {
+        if let Ok(other) = other.extract::<PyRef<Set>>() {
+            return self.is_disjoint(&other);
+        }
+        self.check_open()?;
+        for item in other.iter()? {
+            if let Ok(key) = item?.extract::<Key>() {
+                if self.lookup(key.as_bytes()) {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// The operators take another Set or any iterable of keys, like those
+    /// of `frozenset`, and return a new in-memory Set.
+    fn __and__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::And, false)
+    }
+
+    fn __rand__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::And, true)
+    }
+
+    fn __or__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::Or, false)
+    }
+
+    fn __ror__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::Or, true)
+    }
+
+    fn __sub__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::Sub, false)
+    }
+
+    fn __rsub__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::Sub, true)
+    }
+
+    fn __xor__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::Xor, false)
+    }
+
+    fn __rxor__(&self, py: Python, other: &PyAny) -> PyResult<PyObject> {
+        self.operator(py, other, Operator::Xor, true)
+    }
+
+    fn is_subset(&self, other: &Set) -> PyResult<bool> {
+        self.check_open()?;
+        other.check_open()?;
+        Ok(self.inner.is_subset(&other.inner))
+    }
+
+    fn is_superset(&self, other: &Set) -> PyResult<bool> {
+        self.check_open()?;
+        other.check_open()?;
+        Ok(self.inner.is_superset(&other.inner))
+    }
+
+    /// Sets compare like Python's `set`: equal if they have the same keys,
+    /// and ordered by the subset relation. Between two Sets the keys are
+    /// compared in Rust, in one pass over both; any other
+    /// `collections.abc.Set`, such as a `set`, is compared key by key.
+    fn __richcmp__(slf: &PyCell<Self>, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+        let py = slf.py();
+        if slf.borrow().closed {
+            return Ok(py.NotImplemented());
+        }
+        let Ok(other) = other.extract::<PyRef<Set>>() else {
+            return Ok(match setlike::compare(slf, other, op)? {
+                Some(result) => result.into_py(py),
+                None => py.NotImplemented(),
+            });
+        };
+        if other.closed {
+            return Ok(py.NotImplemented());
+        }
+        let this = slf.borrow();
+        let (a, b) = (&this.inner, &other.inner);
+        let result = py.allow_threads(|| match op {
+            CompareOp::Eq => util::fst_eq(a.as_fst(), b.as_fst()),
+            CompareOp::Ne => !util::fst_eq(a.as_fst(), b.as_fst()),
+            CompareOp::Le => a.is_subset(b),
+            CompareOp::Lt => a.len() < b.len() && a.is_subset(b),
+            CompareOp::Ge => a.is_superset(b),
+            CompareOp::Gt => a.len() > b.len() && a.is_superset(b),
+        });
+        Ok(result.into_py(py))
+    }
+
+    /// A Set hashes like the `frozenset` of its keys, which it compares
+    /// equal to, so it can be a dict key or a member of a `set`. The hash is
+    /// computed from all the keys on the first call and kept. A closed Set
+    /// only equals itself, and one not hashed before it was closed hashes by
+    /// identity.
+    fn __hash__(slf: &PyCell<Self>) -> PyResult<isize> {
+        let this = slf.borrow();
+        if let Some(hash) = this.hash.get() {
+            return Ok(*hash);
+        }
+        if this.closed {
+            return Ok(slf.as_ptr() as isize >> 4);
+        }
+        let hash = PyFrozenSet::new(slf.py(), &this.decoded_keys(slf.py()))?.hash()?;
+        Ok(*this.hash.get_or_init(|| hash))
+    }
+
+    /// The keys in this Set or any of `others`, streamed in one pass over
+    /// all of them.
+    ///
+    /// Like the other operations, it takes an `offset` and `limit` to skip
+    /// and cap the keys streamed.
+    ///
+    /// :type others: Set
+    #[pyo3(signature = (*others, limit=None, offset=0))]
+    fn union(&self, others: &PyTuple, limit: Option<usize>, offset: usize) -> PyResult<SetUnion> {
+        let sets = self.operands(others)?;
+        let op = sets.iter().collect::<OpBuilder>().union();
         let stream = unsafe {
-            std::mem::transmute::<fst::set::Intersection<'_>, fst::set::Intersection<'static>>(op)
+            SendOp::new(std::mem::transmute::<
+                fst::set::Union<'_>,
+                fst::set::Union<'static>,
+            >(op))
         };
-        SetIntersection {
-            _sets: sets,
+        Ok(SetUnion::new(
             stream,
-        }
+            sets,
+            "Set.union",
+            Window::new(offset, limit),
+        ))
     }
 
-    fn difference(&self, other: &Set) -> SetDifference {
-        let sets = vec![self.clone(), other.clone()];
-        let op = self
-            .inner
-            .op()
-            .add(&self.inner)
-            .add(&other.inner)
-            .difference();
+    /// The keys in this Set and in every one of `others`.
+    ///
+    /// :type others: Set
+    #[pyo3(signature = (*others, limit=None, offset=0))]
+    fn intersection(
+        &self,
+        others: &PyTuple,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetIntersection> {
+        let sets = self.operands(others)?;
+        let op = sets.iter().collect::<OpBuilder>().intersection();
         let stream = unsafe {
-            std::mem::transmute::<fst::set::Difference<'_>, fst::set::Difference<'static>>(op)
+            SendOp::new(std::mem::transmute::<
+                fst::set::Intersection<'_>,
+                fst::set::Intersection<'static>,
+            >(op))
         };
-        SetDifference {
+        Ok(SetIntersection {
+            stream,
             _sets: sets,
+            span: Span::new("Set.intersection"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            raw: false,
+        })
+    }
+
+    /// The keys in this Set and in none of `others`.
+    ///
+    /// :type others: Set
+    #[pyo3(signature = (*others, limit=None, offset=0))]
+    fn difference(
+        &self,
+        others: &PyTuple,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetDifference> {
+        let sets = self.operands(others)?;
+        let op = sets.iter().collect::<OpBuilder>().difference();
+        let stream = unsafe {
+            SendOp::new(std::mem::transmute::<
+                fst::set::Difference<'_>,
+                fst::set::Difference<'static>,
+            >(op))
+        };
+        Ok(SetDifference {
             stream,
-        }
+            _sets: sets,
+            span: Span::new("Set.difference"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            raw: false,
+        })
     }
 
-    fn symmetric_difference(&self, other: &Set) -> SetSymmetricDifference {
-        let sets = vec![self.clone(), other.clone()];
-        let op = self
-            .inner
-            .op()
-            .add(&self.inner)
-            .add(&other.inner)
-            .symmetric_difference();
+    /// The keys in an odd number of this Set and `others`; for two Sets, the
+    /// keys in exactly one of them.
+    ///
+    /// :type others: Set
+    #[pyo3(signature = (*others, limit=None, offset=0))]
+    fn symmetric_difference(
+        &self,
+        others: &PyTuple,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> PyResult<SetSymmetricDifference> {
+        let sets = self.operands(others)?;
+        let op = sets.iter().collect::<OpBuilder>().symmetric_difference();
         let stream = unsafe {
-            std::mem::transmute::<
+            SendOp::new(std::mem::transmute::<
                 fst::set::SymmetricDifference<'_>,
                 fst::set::SymmetricDifference<'static>,
-            >(op)
+            >(op))
         };
-        SetSymmetricDifference {
-            _sets: sets,
+        Ok(SetSymmetricDifference {
             stream,
+            _sets: sets,
+            span: Span::new("Set.symmetric_difference"),
+            prefetch: Prefetch::new(Window::new(offset, limit)),
+            raw: false,
+        })
+    }
+}
+
+/// The keys of a Set, in either direction.
+enum SetKeys {
+    Forward(fst::set::Stream<'static>),
+    Backward(Backward<'static, SetData>),
+}
+
+impl SetKeys {
+    fn next(&mut self) -> Option<&[u8]> {
+        match self {
+            SetKeys::Forward(stream) => stream.next(),
+            SetKeys::Backward(stream) => stream.next().map(|(key, _)| key),
         }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
 pub struct SetStream {
-    _set: FstSet<SetData>,
+    stream: SetKeys,
+    _set: Box<FstSet<SetData>>,
+    window: Window,
+    tally: Tally,
+    raw: bool,
+}
+
+impl Collect for SetStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let window = &mut self.window;
+        let stream = &mut self.stream;
+        let keys = py.allow_threads(|| {
+            let mut keys = KeyBuffer::default();
+            while window.skip() {
+                if stream.next().is_none() {
+                    return keys;
+                }
+            }
+            while window.take() {
+                match stream.next() {
+                    Some(key) => keys.push(key),
+                    None => break,
+                }
+            }
+            keys
+        });
+        self.tally.count_n(keys.len());
+        (keys, Vec::new())
+    }
+}
+
+impl Remaining for SetStream {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
+    }
+}
+
+stream_class! {
+    keys impl SetStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            while this.window.skip() {
+                this.stream.next()?;
+            }
+            if !this.window.take() {
+                return None;
+            }
+            let bytes = this.tally.count(this.stream.next())?;
+            Some(lossy::key_object(py, bytes, raw))
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct SetSuffixStream {
     stream: fst::set::Stream<'static>,
+    _set: Box<FstSet<SetData>>,
+    window: Window,
+    tally: Tally,
+    raw: bool,
 }
 
-#[pymethods]
-impl SetStream {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Collect for SetSuffixStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let window = &mut self.window;
+        let stream = &mut self.stream;
+        let keys = py.allow_threads(|| {
+            let mut keys = KeyBuffer::default();
+            while window.skip() {
+                if stream.next().is_none() {
+                    return keys;
+                }
+            }
+            let mut key = Vec::new();
+            while window.take() {
+                let Some(reversed) = stream.next() else {
+                    break;
+                };
+                key.clear();
+                key.extend(reversed.iter().rev());
+                keys.push(&key);
+            }
+            keys
+        });
+        self.tally.count_n(keys.len());
+        (keys, Vec::new())
+    }
+}
+
+impl Remaining for SetSuffixStream {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
+    }
+}
+
+stream_class! {
+    keys impl SetSuffixStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            while this.window.skip() {
+                this.stream.next()?;
+            }
+            if !this.window.take() {
+                return None;
+            }
+            let reversed = this.tally.count(this.stream.next())?;
+            let key: Vec<u8> = reversed.iter().rev().copied().collect();
+            Some(lossy::key_object(py, &key, this.raw))
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct SetSplitStream {
+    stream: fst::set::Stream<'static>,
+    _set: Box<FstSet<SetData>>,
+    window: Window,
+    decoder: PrefixDecoder,
+}
+
+impl Remaining for SetSplitStream {
+    fn remaining(&self) -> Option<usize> {
+        self.window.remaining()
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+stream_class! {
+    impl SetSplitStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<(Py<PyString>, String)> {
+            let py = slf.py();
+            let this = &mut *slf;
+            while this.window.skip() {
+                this.stream.next()?;
+            }
+            if !this.window.take() {
+                return None;
+            }
+            let bytes = this.stream.next()?;
+            Some(this.decoder.split(py, bytes))
+        }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
 pub struct SetRegexStream {
-    _set: FstSet<SetData>,
-    _dfa: DenseDFA<Vec<usize>, usize>,
     stream: fst::set::Stream<'static, &'static DenseDFA<Vec<usize>, usize>>,
+    _set: Box<FstSet<SetData>>,
+    _dfa: Box<DenseDFA<Vec<usize>, usize>>,
+    prefetch: Prefetch,
+    span: Span,
+    tally: Tally,
+    raw: bool,
 }
 
-#[pymethods]
-impl SetRegexStream {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Collect for SetRegexStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let items = self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        });
+        self.tally.count_n(items.0.len());
+        items
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Remaining for SetRegexStream {}
+
+stream_class! {
+    keys impl SetRegexStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let tally = &this.tally;
+            this.span.record(py, || {
+                let (key, _) = tally.count(this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                }))?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
 pub struct SetLevStream {
-    _set: FstSet<SetData>,
-    _lev: Levenshtein,
     stream: fst::set::Stream<'static, &'static Levenshtein>,
+    _set: Box<FstSet<SetData>>,
+    _lev: Arc<Levenshtein>,
+    prefetch: Prefetch,
+    span: Span,
+    tally: Tally,
+    raw: bool,
 }
 
-#[pymethods]
-impl SetLevStream {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Collect for SetLevStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let items = self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        });
+        self.tally.count_n(items.0.len());
+        items
+    }
+}
+
+impl Remaining for SetLevStream {}
+
+stream_class! {
+    keys impl SetLevStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let tally = &this.tally;
+            this.span.record(py, || {
+                let (key, _) = tally.count(this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                }))?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
+    }
+}
+
+#[pyclass(weakref)]
+pub struct SetSubsequenceStream {
+    stream: fst::set::Stream<'static, Subsequence<'static>>,
+    _set: Box<FstSet<SetData>>,
+    _query: Box<str>,
+    prefetch: Prefetch,
+    span: Span,
+    tally: Tally,
+    raw: bool,
+}
+
+impl Collect for SetSubsequenceStream {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        let items = self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        });
+        self.tally.count_n(items.0.len());
+        items
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Remaining for SetSubsequenceStream {}
+
+stream_class! {
+    keys impl SetSubsequenceStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let tally = &this.tally;
+            this.span.record(py, || {
+                let (key, _) = tally.count(this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                }))?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
+pub struct SetCollectedStream {
+    keys: std::vec::IntoIter<(Vec<u8>, u64)>,
+    tally: Tally,
+    raw: bool,
+}
+
+impl SetCollectedStream {
+    pub fn new(keys: Vec<(Vec<u8>, u64)>, tally: Tally) -> Self {
+        SetCollectedStream {
+            keys: keys.into_iter(),
+            tally,
+            raw: false,
+        }
+    }
+}
+
+impl Collect for SetCollectedStream {
+    fn collect(&mut self, _py: Python) -> (KeyBuffer, Vec<u64>) {
+        let mut keys = KeyBuffer::default();
+        for (key, _) in self.keys.by_ref() {
+            keys.push(&key);
+        }
+        self.tally.count_n(keys.len());
+        (keys, Vec::new())
+    }
+}
+
+impl Remaining for SetCollectedStream {
+    fn remaining(&self) -> Option<usize> {
+        Some(self.keys.len())
+    }
+}
+
+stream_class! {
+    keys impl SetCollectedStream {
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            let (bytes, _) = this.tally.count(this.keys.next())?;
+            Some(lossy::key_object(py, &bytes, raw))
+        }
+    }
+}
+
+#[pyclass(weakref)]
 pub struct SetUnion {
-    _sets: Vec<Set>,
-    stream: fst::set::Union<'static>,
+    stream: SendOp<fst::set::Union<'static>>,
+    _sets: Box<[FstSet<SetData>]>,
+    prefetch: Prefetch,
+    span: Span,
+    raw: bool,
 }
 
-#[pymethods]
 impl SetUnion {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+    /// A stream of `op`, a union of streams over `sets`, yielded through
+    /// `window` and traced as `op_name`.
+    pub fn new(
+        op: SendOp<fst::set::Union<'static>>,
+        sets: Box<[FstSet<SetData>]>,
+        op_name: &'static str,
+        window: Window,
+    ) -> Self {
+        SetUnion {
+            stream: op,
+            _sets: sets,
+            span: Span::new(op_name),
+            prefetch: Prefetch::new(window),
+            raw: false,
+        }
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Collect for SetUnion {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        })
+    }
+}
+
+impl Remaining for SetUnion {}
+
+stream_class! {
+    keys impl SetUnion {
+        /// Build a Set of the keys not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_set(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Set> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let mut builder = SetBuilder::new(path.clone())?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| s.next().map(|key| sink(key, 0)).is_some(),
+                |key, _| builder.insert_bytes(key),
+            )?;
+            Set::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            this.span.record(py, || {
+                let (key, _) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                })?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
 pub struct SetIntersection {
-    _sets: Vec<Set>,
-    stream: fst::set::Intersection<'static>,
+    stream: SendOp<fst::set::Intersection<'static>>,
+    _sets: Box<[FstSet<SetData>]>,
+    prefetch: Prefetch,
+    span: Span,
+    raw: bool,
 }
 
-#[pymethods]
-impl SetIntersection {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Collect for SetIntersection {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        })
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Remaining for SetIntersection {}
+
+stream_class! {
+    keys impl SetIntersection {
+        /// Build a Set of the keys not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_set(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Set> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let mut builder = SetBuilder::new(path.clone())?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| s.next().map(|key| sink(key, 0)).is_some(),
+                |key, _| builder.insert_bytes(key),
+            )?;
+            Set::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            this.span.record(py, || {
+                let (key, _) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                })?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
 pub struct SetDifference {
-    _sets: Vec<Set>,
-    stream: fst::set::Difference<'static>,
+    stream: SendOp<fst::set::Difference<'static>>,
+    _sets: Box<[FstSet<SetData>]>,
+    prefetch: Prefetch,
+    span: Span,
+    raw: bool,
 }
 
-#[pymethods]
-impl SetDifference {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Collect for SetDifference {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        })
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Remaining for SetDifference {}
+
+stream_class! {
+    keys impl SetDifference {
+        /// Build a Set of the keys not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_set(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Set> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let mut builder = SetBuilder::new(path.clone())?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| s.next().map(|key| sink(key, 0)).is_some(),
+                |key, _| builder.insert_bytes(key),
+            )?;
+            Set::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            this.span.record(py, || {
+                let (key, _) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                })?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
     }
 }
 
-#[pyclass(unsendable)]
+#[pyclass(weakref)]
 pub struct SetSymmetricDifference {
-    _sets: Vec<Set>,
-    stream: fst::set::SymmetricDifference<'static>,
+    stream: SendOp<fst::set::SymmetricDifference<'static>>,
+    _sets: Box<[FstSet<SetData>]>,
+    prefetch: Prefetch,
+    span: Span,
+    raw: bool,
 }
 
-#[pymethods]
-impl SetSymmetricDifference {
-    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
-        slf
+impl Collect for SetSymmetricDifference {
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>) {
+        self.prefetch.collect(py, &mut self.stream, |s, sink| {
+            s.next().map(|key| sink(key, 0)).is_some()
+        })
     }
-    fn __next__(mut slf: PyRefMut<Self>) -> Option<String> {
-        let bytes = slf.stream.next()?;
-        Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Remaining for SetSymmetricDifference {}
+
+stream_class! {
+    keys impl SetSymmetricDifference {
+        /// Build a Set of the keys not yet streamed, in memory or at `path`,
+        /// without passing them through Python. The stream is used up.
+        #[pyo3(signature = (path=None))]
+        fn into_set(mut slf: PyRefMut<Self>, path: Option<String>) -> PyResult<Set> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let mut builder = SetBuilder::new(path.clone())?;
+            this.prefetch.drain(
+                py,
+                &mut this.stream,
+                |s, sink| s.next().map(|key| sink(key, 0)).is_some(),
+                |key, _| builder.insert_bytes(key),
+            )?;
+            Set::finish_open(py, builder, path)
+        }
+
+        fn __next__(mut slf: PyRefMut<Self>) -> Option<KeyObject> {
+            let py = slf.py();
+            let this = &mut *slf;
+            let raw = this.raw;
+            this.span.record(py, || {
+                let (key, _) = this.prefetch.next(py, &mut this.stream, |s, sink| {
+                    s.next().map(|key| sink(key, 0)).is_some()
+                })?;
+                Some(lossy::key_object(py, key, raw))
+            })
+        }
     }
 }
 
 enum BuilderInner {
     Memory(FstSetBuilder<Vec<u8>>),
     File(FstSetBuilder<BufWriter<File>>),
+    Writer(FstSetBuilder<BufWriter<PyWriter>>),
 }
 
-#[pyclass]
+#[pyclass(weakref)]
 pub struct SetBuilder {
     inner: Option<BuilderInner>,
+    count: usize,
+    started: Instant,
 }
 
-#[pymethods]
 impl SetBuilder {
-    #[new]
-    fn new(path: Option<String>) -> PyResult<Self> {
-        let inner = if let Some(p) = path {
-            let file = File::create(p)?;
-            let wtr = BufWriter::new(file);
-            let builder =
-                FstSetBuilder::new(wtr).map_err(|e| PyValueError::new_err(e.to_string()))?;
-            BuilderInner::File(builder)
-        } else {
-            let builder = FstSetBuilder::memory();
-            BuilderInner::Memory(builder)
+    /// Start a build in memory or, if `path` is given, to a file there.
+    pub fn new(path: Option<String>) -> PyResult<Self> {
+        SetBuilder::create_into(path.into())
+    }
+
+    fn create_into(target: Target) -> PyResult<Self> {
+        let inner = match target {
+            Target::Memory => BuilderInner::Memory(FstSetBuilder::memory()),
+            Target::Path(p) => {
+                let file = File::create(p)?;
+                let wtr = BufWriter::new(file);
+                let builder = FstSetBuilder::new(wtr).map_err(error::fst_error)?;
+                BuilderInner::File(builder)
+            }
+            Target::Writer(wtr) => {
+                let builder = FstSetBuilder::new(wtr).map_err(error::fst_error)?;
+                BuilderInner::Writer(builder)
+            }
         };
-        Ok(SetBuilder { inner: Some(inner) })
+        Ok(SetBuilder {
+            inner: Some(inner),
+            count: 0,
+            started: Instant::now(),
+        })
     }
 
-    fn insert(&mut self, key: &str) -> PyResult<()> {
+    pub fn insert_bytes(&mut self, key: &[u8]) -> PyResult<()> {
         match self.inner.as_mut() {
-            Some(BuilderInner::Memory(b)) => b
-                .insert(key)
-                .map_err(|e| PyValueError::new_err(e.to_string())),
-            Some(BuilderInner::File(b)) => b
-                .insert(key)
-                .map_err(|e| PyValueError::new_err(e.to_string())),
+            Some(BuilderInner::Memory(b)) => b.insert(key).map_err(error::fst_error),
+            Some(BuilderInner::File(b)) => b.insert(key).map_err(error::fst_error),
+            Some(BuilderInner::Writer(b)) => b.insert(key).map_err(error::fst_error),
             None => Err(PyValueError::new_err("Builder already finished")),
-        }
+        }?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl SetBuilder {
+    /// Build in memory, to a file at `path` or, if `path` is a binary file
+    /// object such as an `io.BytesIO` or an upload stream, by calling its
+    /// `write` method with chunks of the FST. The object is flushed, but not
+    /// closed, by `finish`.
+    ///
+    /// :type path: Union[str, BinaryIO, None]
+    #[new]
+    #[pyo3(signature = (path=None))]
+    fn py_new(path: Option<&PyAny>) -> PyResult<Self> {
+        SetBuilder::create_into(Target::parse(path)?)
     }
 
-    fn finish(&mut self) -> PyResult<Option<Set>> {
-        match self.inner.take() {
-            Some(BuilderInner::Memory(b)) => {
-                let bytes = b
-                    .into_inner()
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                let set = FstSet::new(SetData::Vec(Arc::new(bytes)))
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
-                Ok(Some(Set { inner: set }))
+    fn insert(&mut self, key: Key) -> PyResult<()> {
+        self.insert_bytes(key.as_bytes())
+    }
+
+    /// Insert the keys of the text file at `path`, optionally gzipped, one
+    /// per line and in sorted order, without going through Python. Blank
+    /// lines are skipped. Returns the number of keys inserted.
+    fn insert_from_file(&mut self, py: Python, path: &str) -> PyResult<usize> {
+        py.allow_threads(|| {
+            let mut count = 0;
+            tools::for_each_line(path, |line, _| {
+                self.insert_bytes(line)?;
+                count += 1;
+                Ok(())
+            })?;
+            Ok(count)
+        })
+    }
+
+    /// Finish the build, with the GIL released. Returns the Set if it was
+    /// built in memory.
+    fn finish(&mut self, py: Python) -> PyResult<Option<Set>> {
+        let inner = self
+            .inner
+            .take()
+            .ok_or_else(|| PyValueError::new_err("Builder already finished"))?;
+        let set = py.allow_threads(|| match inner {
+            BuilderInner::Memory(b) => {
+                let bytes = b.into_inner().map_err(error::fst_error)?;
+                FstSet::new(SetData::Vec(Arc::new(bytes)))
+                    .map(Some)
+                    .map_err(error::fst_error)
             }
-            Some(BuilderInner::File(b)) => {
-                b.finish()
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            BuilderInner::File(b) => b.finish().map(|_| None).map_err(error::fst_error),
+            BuilderInner::Writer(b) => {
+                writer::finish(b.into_inner().map_err(error::fst_error)?)?;
                 Ok(None)
             }
-            None => Err(PyValueError::new_err("Builder already finished")),
+        })?;
+        trace::emit(py, "SetBuilder.finish", self.started.elapsed(), self.count)?;
+        Ok(set.map(Set::from_fst))
+    }
+}
+
+/// The set operators of a Set.
+#[derive(Clone, Copy)]
+enum Operator {
+    And,
+    Or,
+    Sub,
+    Xor,
+}
+
+/// The entries of the substring index for `key`: for each suffix of the key
+/// (starting at a character boundary, if the key is UTF-8), the suffix, then
+/// the whole key, then the suffix length as a big-endian u32. A prefix search
+/// for a string then finds every key containing it, plus a few false
+/// positives where the match runs past the suffix into the appended key.
+fn substring_entries(key: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let text = std::str::from_utf8(key).ok();
+    (0..key.len())
+        .filter(move |i| text.is_none_or(|text| text.is_char_boundary(*i)))
+        .map(move |i| {
+            let suffix = &key[i..];
+            let mut entry = Vec::with_capacity(suffix.len() + key.len() + 4);
+            entry.extend_from_slice(suffix);
+            entry.extend_from_slice(key);
+            entry.extend_from_slice(&(suffix.len() as u32).to_be_bytes());
+            entry
+        })
+}
+
+/// The key an entry of the substring index was made from, or `None` if the
+/// entry is not one.
+fn substring_entry_key(entry: &[u8]) -> Option<&[u8]> {
+    let (entry, len) = entry.split_at(entry.len().checked_sub(4)?);
+    entry.get(u32::from_be_bytes(len.try_into().ok()?) as usize..)
+}
+
+/// An entry of a variant index such as the transliteration index: the
+/// variant of a key, a 0 byte and the key itself, so that all keys sharing a
+/// variant are one prefix range.
+fn variant_entry(variant: &str, key: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(variant.len() + 1 + key.len());
+    entry.extend_from_slice(variant.as_bytes());
+    entry.push(0);
+    entry.extend_from_slice(key);
+    entry
+}
+
+/// The keys with `variant` in the variant index `entries` within `window`,
+/// in key order.
+fn variant_keys(entries: &FstSet<SetData>, variant: &str, mut window: Window) -> Vec<String> {
+    let prefix = variant_entry(variant, b"");
+    let range = util::bounded_range(Some(&prefix), None, None);
+    let mut stream = util::search_range(entries.as_fst(), &AlwaysMatch, range);
+    let mut keys = Vec::new();
+    while window.skip() {
+        if stream.next().is_none() {
+            return keys;
         }
     }
+    while window.take() {
+        let Some((entry, _)) = stream.next() else {
+            break;
+        };
+        keys.push(String::from_utf8_lossy(&entry[prefix.len()..]).into_owned());
+    }
+    keys
+}
+
+/// The keys of `matches` as a list of str, and their count.
+fn match_list(py: Python, matches: Vec<(Vec<u8>, u64)>) -> PyResult<(PyObject, usize)> {
+    let keys = matches.iter().map(|(key, _)| lossy::decode(py, key));
+    Ok((PyList::new(py, keys).into_py(py), matches.len()))
 }
@@ -0,0 +1,80 @@
+//! The `collections.abc` protocols that `rust_fst` registers Sets, Maps and
+//! the Map views under, for operands that are not FSTs themselves: Python
+//! sets, dicts and other iterables.
+//!
+//! These follow the mixin methods of `collections.abc.Set`, so they compare
+//! by size and membership and probe the other operand item by item.
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::pyclass::CompareOp;
+use pyo3::types::PySet;
+
+/// Whether `obj` is an instance of the `collections.abc` class `name`.
+pub fn is_abc(obj: &PyAny, name: &str) -> PyResult<bool> {
+    let abc = obj.py().import("collections.abc")?.getattr(name)?;
+    obj.is_instance(abc)
+}
+
+/// Whether `item` is in `set`, taking a `TypeError` for an item the set
+/// cannot hold, such as an int probed in a Set, as no.
+fn holds(set: &PyAny, item: &PyAny) -> PyResult<bool> {
+    match set.contains(item) {
+        Err(err) if err.is_instance_of::<PyTypeError>(set.py()) => Ok(false),
+        result => result,
+    }
+}
+
+/// Whether every item of `a` is in `b`.
+fn within(a: &PyAny, b: &PyAny) -> PyResult<bool> {
+    for item in a.iter()? {
+        if !holds(b, item?)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Compare the set-like `a` with `b` as `collections.abc.Set` does, or
+/// return `None` if `b` is not a `collections.abc.Set`.
+pub fn compare(a: &PyAny, b: &PyAny, op: CompareOp) -> PyResult<Option<bool>> {
+    if !is_abc(b, "Set")? {
+        return Ok(None);
+    }
+    let (len_a, len_b) = (a.len()?, b.len()?);
+    Ok(Some(match op {
+        CompareOp::Eq => len_a == len_b && within(a, b)?,
+        CompareOp::Ne => !(len_a == len_b && within(a, b)?),
+        CompareOp::Le => len_a <= len_b && within(a, b)?,
+        CompareOp::Lt => len_a < len_b && within(a, b)?,
+        CompareOp::Ge => len_a >= len_b && within(b, a)?,
+        CompareOp::Gt => len_a > len_b && within(b, a)?,
+    }))
+}
+
+/// Whether no item of the iterable `b` is in `a`.
+pub fn isdisjoint(a: &PyAny, b: &PyAny) -> PyResult<bool> {
+    for item in b.iter()? {
+        if holds(a, item?)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// The `set` method `method`, such as `"intersection"`, applied to the
+/// items of `a` and the iterable `b`, or to those of `b` and `a` if
+/// `reflected`. The result is a `set`, as for the views of a dict, or
+/// `NotImplemented` if `b` is not iterable.
+pub fn set_op(a: &PyAny, b: &PyAny, method: &str, reflected: bool) -> PyResult<PyObject> {
+    let py = a.py();
+    if b.iter().is_err() {
+        return Ok(py.NotImplemented());
+    }
+    let (first, second) = if reflected { (b, a) } else { (a, b) };
+    let result = py
+        .get_type::<PySet>()
+        .call1((first,))?
+        .call_method1(method, (second,))?;
+    Ok(result.into())
+}
@@ -0,0 +1,101 @@
+//! External merge sort for building FSTs from unsorted input.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+
+/// Default amount of key data buffered in memory before spilling a sorted run
+/// to a temporary file.
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+/// Collects `(key, value)` items in any order and replays them sorted by key,
+/// spilling sorted runs to temporary files once `limit` bytes are buffered.
+pub struct ExternalSorter {
+    items: Vec<(Vec<u8>, u64)>,
+    buffered: usize,
+    limit: usize,
+    runs: Vec<File>,
+}
+
+impl ExternalSorter {
+    pub fn new(limit: usize) -> Self {
+        ExternalSorter {
+            items: Vec::new(),
+            buffered: 0,
+            limit,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: Vec<u8>, value: u64) -> io::Result<()> {
+        // Account for the tuple and the key's heap allocation as well.
+        self.buffered += key.len() + 40;
+        self.items.push((key, value));
+        if self.buffered >= self.limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.items.sort_unstable();
+        let mut file = BufWriter::new(tempfile::tempfile()?);
+        for (key, value) in self.items.drain(..) {
+            file.write_all(&(key.len() as u32).to_le_bytes())?;
+            file.write_all(&key)?;
+            file.write_all(&value.to_le_bytes())?;
+        }
+        let mut file = file.into_inner().map_err(|e| e.into_error())?;
+        file.rewind()?;
+        self.runs.push(file);
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Call `f` with every item, in key order. Items with equal keys are
+    /// passed in value order.
+    pub fn finish<E: From<io::Error>>(
+        mut self,
+        mut f: impl FnMut(&[u8], u64) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.runs.is_empty() {
+            self.items.sort_unstable();
+            for (key, value) in &self.items {
+                f(key, *value)?;
+            }
+            return Ok(());
+        }
+        if !self.items.is_empty() {
+            self.spill()?;
+        }
+        let mut runs: Vec<_> = self.runs.into_iter().map(BufReader::new).collect();
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some((key, value)) = read_item(run)? {
+                heap.push(Reverse((key, value, i)));
+            }
+        }
+        while let Some(Reverse((key, value, i))) = heap.pop() {
+            f(&key, value)?;
+            if let Some((key, value)) = read_item(&mut runs[i])? {
+                heap.push(Reverse((key, value, i)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_item(run: &mut BufReader<File>) -> io::Result<Option<(Vec<u8>, u64)>> {
+    let mut len = [0; 4];
+    match run.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut key = vec![0; u32::from_le_bytes(len) as usize];
+    run.read_exact(&mut key)?;
+    let mut value = [0; 8];
+    run.read_exact(&mut value)?;
+    Ok(Some((key, u64::from_le_bytes(value))))
+}
@@ -0,0 +1,188 @@
+//! Spelling suggestions over a Set or Map lexicon.
+
+use fst::automaton::{Automaton, Levenshtein, Str};
+use fst::raw::Fst;
+use pyo3::prelude::*;
+use std::cmp::Reverse;
+use std::time::Instant;
+
+use crate::error;
+use crate::lexicon::{with_fst, Lexicon};
+use crate::trace;
+use crate::util::{self, Key};
+
+/// A possible correction of a word.
+struct Candidate {
+    word: Vec<u8>,
+    distance: u32,
+    frequency: u64,
+}
+
+/// The Levenshtein distance between `a` and `b`, counting in characters.
+fn edit_distance(a: &[char], b: &[char]) -> u32 {
+    let mut prev: Vec<u32> = (0..=b.len() as u32).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i as u32 + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + (ca != cb) as u32;
+            cur[j + 1] = substitute.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// The words of `fst` within `max_dist` edits of `word`, closest first and,
+/// among equally close words, most frequent first.
+fn rank<D: AsRef<[u8]>>(fst: &Fst<D>, word: &str, max_dist: u32) -> PyResult<Vec<Candidate>> {
+    let lev = Levenshtein::new(word, max_dist).map_err(error::lev_error)?;
+    let chars: Vec<char> = word.chars().collect();
+    let mut candidates: Vec<_> = util::search(fst, &lev)
+        .into_iter()
+        .map(|(key, frequency)| {
+            let key_chars: Vec<char> = String::from_utf8_lossy(&key).chars().collect();
+            Candidate {
+                distance: edit_distance(&chars, &key_chars),
+                word: key,
+                frequency,
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        (a.distance, Reverse(a.frequency), &a.word).cmp(&(
+            b.distance,
+            Reverse(b.frequency),
+            &b.word,
+        ))
+    });
+    Ok(candidates)
+}
+
+/// The most frequent of `matches`, preferring shorter and then smaller keys
+/// among equally frequent ones.
+fn most_frequent(matches: Vec<(Vec<u8>, u64)>) -> Option<(Vec<u8>, u64)> {
+    matches
+        .into_iter()
+        .min_by(|(a, fa), (b, fb)| (Reverse(fa), a.len(), a).cmp(&(Reverse(fb), b.len(), b)))
+}
+
+/// The single best correction of `query` and its confidence: the query itself
+/// if it is a word, else the most frequent word it is a prefix of, else the
+/// most frequent word at the smallest edit distance up to `max_dist`.
+fn best_match<D: AsRef<[u8]>>(
+    fst: &Fst<D>,
+    query: &str,
+    max_dist: u32,
+) -> PyResult<Option<(Vec<u8>, f64)>> {
+    if fst.get(query.as_bytes()).is_some() {
+        return Ok(Some((query.as_bytes().to_vec(), 1.0)));
+    }
+    let len = query.chars().count();
+    if len > 0 {
+        let prefix = Str::new(query).starts_with();
+        if let Some((word, _)) = most_frequent(util::search(fst, &prefix)) {
+            let word_len = String::from_utf8_lossy(&word).chars().count();
+            let confidence = len as f64 / word_len as f64;
+            return Ok(Some((word, confidence)));
+        }
+    }
+    for distance in 1..=max_dist {
+        let lev = Levenshtein::new(query, distance).map_err(error::lev_error)?;
+        if let Some((word, _)) = most_frequent(util::search(fst, &lev)) {
+            let confidence = 1.0 - distance as f64 / len.max(distance as usize) as f64;
+            return Ok(Some((word, confidence)));
+        }
+    }
+    Ok(None)
+}
+
+/// Suggests corrections for misspelled words from the keys of a Set or Map,
+/// ranked by edit distance and then, for a Map, by the value stored for each
+/// key as its frequency. Values of any `value_type` rank in their own order,
+/// which their outputs keep.
+#[pyclass(weakref, module = "rust_fst")]
+pub struct SpellChecker {
+    lexicon: Lexicon,
+}
+
+impl SpellChecker {
+    fn rank(&self, py: Python, word: &str, max_dist: u32) -> PyResult<Vec<Candidate>> {
+        py.allow_threads(|| with_fst!(&self.lexicon, fst => rank(fst, word, max_dist)))
+    }
+
+    fn best_match(
+        &self,
+        py: Python,
+        query: &str,
+        max_dist: u32,
+    ) -> PyResult<Option<(Vec<u8>, f64)>> {
+        py.allow_threads(|| with_fst!(&self.lexicon, fst => best_match(fst, query, max_dist)))
+    }
+}
+
+#[pymethods]
+impl SpellChecker {
+    /// :type lexicon: Union[Set, Map]
+    #[new]
+    fn new(lexicon: &PyAny) -> PyResult<Self> {
+        Ok(SpellChecker {
+            lexicon: Lexicon::extract(lexicon)?,
+        })
+    }
+
+    /// Up to `limit` words within `max_dist` edits of `word`, as
+    /// `(word, distance)` tuples, closest and then most frequent first.
+    #[pyo3(signature = (word, max_dist=2, limit=10))]
+    fn suggest(
+        &self,
+        py: Python,
+        word: Key,
+        max_dist: u32,
+        limit: usize,
+    ) -> PyResult<Vec<(String, u32)>> {
+        let start = Instant::now();
+        let mut candidates = self.rank(py, word.as_str()?, max_dist)?;
+        candidates.truncate(limit);
+        trace::emit(
+            py,
+            "SpellChecker.suggest",
+            start.elapsed(),
+            candidates.len(),
+        )?;
+        Ok(candidates
+            .into_iter()
+            .map(|c| (String::from_utf8_lossy(&c.word).into_owned(), c.distance))
+            .collect())
+    }
+
+    /// The single best suggestion for a search-box `query`, as a
+    /// `(word, confidence)` tuple, or `None`. An exact match has confidence
+    /// 1; failing that, the most frequent word starting with `query` is
+    /// chosen, with confidence `len(query) / len(word)`; failing that, the
+    /// most frequent word at the smallest edit distance `d <= max_dist`, with
+    /// confidence `1 - d / len(query)`.
+    ///
+    /// :rtype: Optional[Tuple[str, float]]
+    #[pyo3(signature = (query, max_dist=2))]
+    fn did_you_mean(
+        &self,
+        py: Python,
+        query: Key,
+        max_dist: u32,
+    ) -> PyResult<Option<(String, f64)>> {
+        let start = Instant::now();
+        let best = self.best_match(py, query.as_str()?, max_dist)?;
+        trace::emit(
+            py,
+            "SpellChecker.did_you_mean",
+            start.elapsed(),
+            best.is_some() as usize,
+        )?;
+        Ok(
+            best.map(|(word, confidence)| {
+                (String::from_utf8_lossy(&word).into_owned(), confidence)
+            }),
+        )
+    }
+}
@@ -0,0 +1,49 @@
+//! State kept per interpreter rather than in process-wide statics.
+//!
+//! Every interpreter that imports the module gets its own module object. The
+//! state lives in a capsule attached to it and is found again through that
+//! interpreter's `sys.modules`, so interpreters never see each other's trace
+//! hook or lossy-decode counts.
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+use std::ffi::{CStr, CString};
+use std::sync::{Arc, Mutex};
+
+use crate::lossy::LossyLog;
+use crate::trace::Hook;
+
+const MODULE: &str = "rust_fst._native";
+const ATTR: &str = "_state";
+const CAPSULE: &str = "rust_fst._native._state";
+
+#[derive(Default)]
+pub struct ModuleState {
+    /// The hook installed by `set_trace_hook()`.
+    pub hook: Mutex<Option<Hook>>,
+    /// The keys decoded with replacement characters, for `lossy_decodes()`.
+    pub lossy: Arc<LossyLog>,
+}
+
+/// Attach fresh state to `m` while it is initialized.
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    let name = CString::new(CAPSULE).unwrap();
+    m.add(
+        ATTR,
+        PyCapsule::new(py, ModuleState::default(), Some(name))?,
+    )
+}
+
+/// The state of the module as imported by the current interpreter.
+pub fn get<'py>(py: Python<'py>) -> PyResult<&'py ModuleState> {
+    let capsule = py.import(MODULE)?.getattr(ATTR)?.downcast::<PyCapsule>()?;
+    if capsule.name()?.map(CStr::to_bytes) != Some(CAPSULE.as_bytes()) {
+        return Err(PyRuntimeError::new_err(format!(
+            "{}.{} was replaced",
+            MODULE, ATTR
+        )));
+    }
+    // The name checked above is only given to capsules made by `init`.
+    Ok(unsafe { capsule.reference::<ModuleState>() })
+}
@@ -0,0 +1,141 @@
+//! The methods every stream class shares.
+//!
+//! `stream_class!` takes the `#[pymethods]` block of a stream, written as
+//! `impl Name { ... }` and led by the kind of stream, and adds the shared
+//! methods to it:
+//!
+//! - every stream implements `Remaining` and gets `__iter__` and
+//!   `__length_hint__`;
+//! - `raw` streams, which have a `raw: bool` field, also get `raw`;
+//! - `keys` streams are `raw` streams that implement `Collect` and get
+//!   `to_list` as well;
+//! - `items` streams are `raw` streams of Map items that implement `Collect`,
+//!   have a `value_type` field and get `to_dict` as well.
+//!
+//! `build.rs` lists the same methods for the type stubs.
+
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+use crate::export::KeyBuffer;
+use crate::lossy;
+use crate::values::ValueType;
+
+/// A stream that may know how many items it has left.
+pub trait Remaining {
+    /// The number of items still to be yielded, if known up front.
+    fn remaining(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// A stream whose items can be taken in one go.
+pub trait Collect {
+    /// The keys not yet streamed and, for the items of a Map, their values,
+    /// counted in the stream's tally. Any walk of an FST this takes runs
+    /// with the GIL released. The stream is used up.
+    fn collect(&mut self, py: Python) -> (KeyBuffer, Vec<u64>);
+}
+
+/// The keys of `keys` as a list, as `bytes` if `raw` is true and decoded
+/// otherwise.
+pub fn key_list<'py>(py: Python<'py>, keys: &KeyBuffer, raw: bool) -> &'py PyList {
+    PyList::new(py, keys.iter().map(|key| lossy::key_object(py, key, raw)))
+}
+
+/// The items of `keys` and `values` as a dict, with keys as `bytes` if `raw`
+/// is true and decoded otherwise.
+pub fn item_dict<'py>(
+    py: Python<'py>,
+    keys: &KeyBuffer,
+    values: &[u64],
+    value_type: ValueType,
+    raw: bool,
+) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    for (key, val) in keys.iter().zip(values) {
+        let key = lossy::key_object(py, key, raw);
+        dict.set_item(key, value_type.decode(py, *val))?;
+    }
+    Ok(dict)
+}
+
+/// The `#[pymethods]` block of a stream class with the shared methods of its
+/// kind added; see the module docs.
+macro_rules! stream_class {
+    (keys impl $name:ident { $($body:tt)* }) => {
+        $crate::stream::stream_class! {
+            raw impl $name {
+                /// Collect the keys not yet streamed into a list, walking the
+                /// stream with the GIL released. The stream is used up.
+                ///
+                /// :rtype: List[Union[str, bytes]]
+                fn to_list<'py>(
+                    mut slf: pyo3::PyRefMut<'py, Self>,
+                ) -> &'py pyo3::types::PyList {
+                    let py = slf.py();
+                    let (keys, _) = $crate::stream::Collect::collect(&mut *slf, py);
+                    $crate::stream::key_list(py, &keys, slf.raw)
+                }
+
+                $($body)*
+            }
+        }
+    };
+    (items impl $name:ident { $($body:tt)* }) => {
+        $crate::stream::stream_class! {
+            raw impl $name {
+                /// Collect the items not yet streamed into a dict, walking the
+                /// stream with the GIL released. The stream is used up.
+                ///
+                /// :rtype: Dict[Union[str, bytes], Union[int, float]]
+                fn to_dict<'py>(
+                    mut slf: pyo3::PyRefMut<'py, Self>,
+                ) -> pyo3::PyResult<&'py pyo3::types::PyDict> {
+                    let py = slf.py();
+                    let (keys, values) = $crate::stream::Collect::collect(&mut *slf, py);
+                    $crate::stream::item_dict(py, &keys, &values, slf.value_type, slf.raw)
+                }
+
+                $($body)*
+            }
+        }
+    };
+    (raw impl $name:ident { $($body:tt)* }) => {
+        $crate::stream::stream_class! {
+            impl $name {
+                /// Yield keys as `bytes`, exactly as stored, instead of
+                /// decoding them.
+                fn raw(mut slf: pyo3::PyRefMut<Self>) -> pyo3::PyRefMut<Self> {
+                    slf.raw = true;
+                    slf
+                }
+
+                $($body)*
+            }
+        }
+    };
+    (impl $name:ident { $($body:tt)* }) => {
+        #[pyo3::pymethods]
+        impl $name {
+            fn __iter__(slf: pyo3::PyRef<Self>) -> pyo3::PyRef<Self> {
+                slf
+            }
+
+            /// The number of items left, or `NotImplemented` if that is not
+            /// known up front.
+            ///
+            /// :rtype: Union[int, NotImplementedType]
+            fn __length_hint__(&self, py: pyo3::Python) -> pyo3::PyObject {
+                match $crate::stream::Remaining::remaining(self) {
+                    Some(len) => pyo3::IntoPy::into_py(len, py),
+                    None => py.NotImplemented(),
+                }
+            }
+
+            $($body)*
+        }
+    };
+}
+
+pub(crate) use stream_class;
@@ -0,0 +1,251 @@
+//! File-to-file operations backing the `python -m rust_fst` command line.
+
+use flate2::bufread::MultiGzDecoder;
+use fst::{
+    IntoStreamer, Map as FstMap, MapBuilder as FstMapBuilder, SetBuilder as FstSetBuilder, Streamer,
+};
+use memmap2::Mmap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::time::Instant;
+
+use crate::error;
+use crate::sort::{ExternalSorter, DEFAULT_MEMORY_LIMIT};
+use crate::trace;
+
+/// Writes a set or a map FST to a file, skipping repeated keys of a set.
+enum FileBuilder {
+    Set(FstSetBuilder<BufWriter<File>>, Option<Vec<u8>>),
+    Map(FstMapBuilder<BufWriter<File>>),
+}
+
+impl FileBuilder {
+    fn create(path: &str, map: bool) -> PyResult<Self> {
+        let wtr = BufWriter::new(File::create(path)?);
+        Ok(if map {
+            FileBuilder::Map(FstMapBuilder::new(wtr).map_err(error::fst_error)?)
+        } else {
+            FileBuilder::Set(FstSetBuilder::new(wtr).map_err(error::fst_error)?, None)
+        })
+    }
+
+    /// Insert `key`, returning whether it was new.
+    fn insert(&mut self, key: &[u8], value: u64) -> PyResult<bool> {
+        match self {
+            FileBuilder::Set(builder, last) => {
+                if last.as_deref() == Some(key) {
+                    return Ok(false);
+                }
+                builder.insert(key).map_err(error::fst_error)?;
+                *last = Some(key.to_vec());
+            }
+            FileBuilder::Map(builder) => builder.insert(key, value).map_err(error::fst_error)?,
+        }
+        Ok(true)
+    }
+
+    fn finish(self) -> PyResult<()> {
+        match self {
+            FileBuilder::Set(builder, _) => builder.finish(),
+            FileBuilder::Map(builder) => builder.finish(),
+        }
+        .map_err(error::fst_error)
+    }
+}
+
+/// Split a line into its key and, for maps, the integer value after the last
+/// `delimiter`.
+pub fn parse_line(
+    line: &[u8],
+    lineno: usize,
+    map: bool,
+    delimiter: &[u8],
+) -> PyResult<(Vec<u8>, u64)> {
+    if !map {
+        return Ok((line.to_vec(), 0));
+    }
+    let split = line
+        .windows(delimiter.len())
+        .rposition(|window| window == delimiter)
+        .ok_or_else(|| {
+            PyValueError::new_err(format!("line {}: missing value delimiter", lineno))
+        })?;
+    let value = &line[split + delimiter.len()..];
+    let value = std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "line {}: invalid value {:?}",
+                lineno,
+                String::from_utf8_lossy(value)
+            ))
+        })?;
+    Ok((line[..split].to_vec(), value))
+}
+
+/// Open the file at `path` for reading, decompressing it if it is gzipped.
+fn open_text(path: &str) -> io::Result<Box<dyn BufRead>> {
+    let mut file = BufReader::new(File::open(path)?);
+    if file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Call `f` with every non-blank line of `path`, gzipped or not, without its
+/// line terminator, and its 1-based line number.
+pub fn for_each_line(path: &str, mut f: impl FnMut(&[u8], usize) -> PyResult<()>) -> PyResult<()> {
+    let mut reader = open_text(path)?;
+    let mut line = Vec::new();
+    let mut lineno = 0;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        lineno += 1;
+        while matches!(line.last(), Some(b'\n' | b'\r')) {
+            line.pop();
+        }
+        if !line.is_empty() {
+            f(&line, lineno)?;
+        }
+    }
+}
+
+/// Build an FST at `output` from the text file `input`, optionally gzipped,
+/// with one key per line
+/// or, if `map` is true, a key and an integer value separated by the last
+/// `delimiter` on the line. Blank lines are skipped. Unless `sorted` is true,
+/// the lines are sorted first, spilling to temporary files for large inputs.
+/// Returns the number of keys written.
+#[pyfunction]
+#[pyo3(signature = (input, output, map=false, delimiter="\t", sorted=false))]
+pub fn build_file(
+    py: Python,
+    input: &str,
+    output: &str,
+    map: bool,
+    delimiter: &str,
+    sorted: bool,
+) -> PyResult<usize> {
+    if delimiter.is_empty() {
+        return Err(PyValueError::new_err("delimiter must not be empty"));
+    }
+    let delimiter = delimiter.as_bytes();
+    let start = Instant::now();
+    let count = py.allow_threads(|| -> PyResult<usize> {
+        let mut builder = FileBuilder::create(output, map)?;
+        let mut count = 0;
+        if sorted {
+            for_each_line(input, |line, lineno| {
+                let (key, value) = parse_line(line, lineno, map, delimiter)?;
+                count += builder.insert(&key, value)? as usize;
+                Ok(())
+            })?;
+        } else {
+            let mut sorter = ExternalSorter::new(DEFAULT_MEMORY_LIMIT);
+            for_each_line(input, |line, lineno| {
+                let (key, value) = parse_line(line, lineno, map, delimiter)?;
+                Ok(sorter.push(key, value)?)
+            })?;
+            sorter.finish(|key, value| -> PyResult<()> {
+                count += builder.insert(key, value)? as usize;
+                Ok(())
+            })?;
+        }
+        builder.finish()?;
+        Ok(count)
+    })?;
+    trace::emit(py, "build_file", start.elapsed(), count)?;
+    Ok(count)
+}
+
+/// Write the union of the FSTs at `inputs` to `output`. A key present in
+/// several inputs gets its value from the last of them. Returns the number of
+/// keys written.
+#[pyfunction]
+pub fn merge_files(py: Python, inputs: Vec<String>, output: &str) -> PyResult<usize> {
+    let start = Instant::now();
+    let count = py.allow_threads(|| -> PyResult<usize> {
+        let mut maps = Vec::with_capacity(inputs.len());
+        for path in &inputs {
+            let mmap = unsafe { Mmap::map(&File::open(path)?)? };
+            error::check_fst_data(&mmap, &format!("{:?}", path))?;
+            maps.push(FstMap::new(mmap).map_err(error::fst_error)?);
+        }
+        let mut op = fst::map::OpBuilder::new();
+        for map in &maps {
+            op = op.add(map);
+        }
+        let mut builder =
+            FstMapBuilder::new(BufWriter::new(File::create(output)?)).map_err(error::fst_error)?;
+        let mut union = op.union().into_stream();
+        let mut count = 0;
+        while let Some((key, values)) = union.next() {
+            let value = values.iter().max_by_key(|v| v.index).map_or(0, |v| v.value);
+            builder.insert(key, value).map_err(error::fst_error)?;
+            count += 1;
+        }
+        builder.finish().map_err(error::fst_error)?;
+        Ok(count)
+    })?;
+    trace::emit(py, "merge_files", start.elapsed(), count)?;
+    Ok(count)
+}
+
+/// A key with its values in two FSTs, `None` where it is missing.
+type Difference = (String, Option<u64>, Option<u64>);
+
+/// Compare the FST files at `path_a` and `path_b` byte for byte, for checking
+/// that a rebuild is reproducible. Returns whether they are identical and,
+/// if not, up to `limit` of the keys whose values differ as
+/// `(key, value_a, value_b)` tuples in key order, with `None` for a key
+/// missing from one side. Set files compare as maps whose values are all 0.
+/// Files that differ only in encoding have no differing keys.
+///
+/// :rtype: Tuple[bool, List[Tuple[str, Optional[int], Optional[int]]]]
+#[pyfunction]
+#[pyo3(signature = (path_a, path_b, limit=10))]
+pub fn compare(
+    py: Python,
+    path_a: &str,
+    path_b: &str,
+    limit: usize,
+) -> PyResult<(bool, Vec<Difference>)> {
+    let start = Instant::now();
+    let result = py.allow_threads(|| -> PyResult<_> {
+        let mut maps = Vec::with_capacity(2);
+        for path in [path_a, path_b] {
+            let mmap = unsafe { Mmap::map(&File::open(path)?)? };
+            error::check_fst_data(&mmap, &format!("{:?}", path))?;
+            maps.push(FstMap::new(mmap).map_err(error::fst_error)?);
+        }
+        if maps[0].as_fst().as_bytes() == maps[1].as_fst().as_bytes() {
+            return Ok((true, Vec::new()));
+        }
+        let mut union = fst::map::OpBuilder::new()
+            .add(&maps[0])
+            .add(&maps[1])
+            .union()
+            .into_stream();
+        let mut differences = Vec::new();
+        while differences.len() < limit {
+            let Some((key, values)) = union.next() else {
+                break;
+            };
+            let value = |index| values.iter().find(|v| v.index == index).map(|v| v.value);
+            let (a, b) = (value(0), value(1));
+            if a != b {
+                differences.push((String::from_utf8_lossy(key).into_owned(), a, b));
+            }
+        }
+        Ok((false, differences))
+    })?;
+    trace::emit(py, "compare", start.elapsed(), result.1.len())?;
+    Ok(result)
+}
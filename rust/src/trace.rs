@@ -0,0 +1,78 @@
+use pyo3::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::state;
+
+/// A trace hook, kept in the state of the interpreter that installed it.
+pub struct Hook {
+    callback: PyObject,
+    threshold: Duration,
+}
+
+/// Install `callback(op, seconds, count)` to be called for every build, merge
+/// and search taking at least `threshold` seconds, or remove it with `None`.
+///
+/// :type callback: Optional[Callable[[str, float, int], Any]]
+#[pyfunction]
+#[pyo3(signature = (callback, threshold=0.0))]
+pub fn set_trace_hook(py: Python, callback: Option<PyObject>, threshold: f64) -> PyResult<()> {
+    let threshold = Duration::try_from_secs_f64(threshold)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    *state::get(py)?.hook.lock().unwrap() = callback.map(|callback| Hook {
+        callback,
+        threshold,
+    });
+    Ok(())
+}
+
+/// Report a finished operation to the trace hook, if one is installed and the
+/// operation took long enough.
+pub fn emit(py: Python, op: &str, elapsed: Duration, count: usize) -> PyResult<()> {
+    let callback = match &*state::get(py)?.hook.lock().unwrap() {
+        Some(hook) if elapsed >= hook.threshold => hook.callback.clone_ref(py),
+        _ => return Ok(()),
+    };
+    callback.call1(py, (op, elapsed.as_secs_f64(), count))?;
+    Ok(())
+}
+
+/// Accumulates the time spent producing a stream's results and reports it
+/// once the stream is exhausted.
+pub struct Span {
+    op: &'static str,
+    elapsed: Duration,
+    count: usize,
+    done: bool,
+}
+
+impl Span {
+    pub fn new(op: &'static str) -> Self {
+        Span {
+            op,
+            elapsed: Duration::ZERO,
+            count: 0,
+            done: false,
+        }
+    }
+
+    /// Time a single `next` call on the traced stream.
+    pub fn record<T>(&mut self, py: Python, next: impl FnOnce() -> Option<T>) -> Option<T> {
+        let start = Instant::now();
+        let item = next();
+        self.elapsed += start.elapsed();
+        match item {
+            Some(item) => {
+                self.count += 1;
+                Some(item)
+            }
+            None => {
+                if !std::mem::replace(&mut self.done, true) {
+                    if let Err(err) = emit(py, self.op, self.elapsed, self.count) {
+                        err.write_unraisable(py, None);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Transliteration schemes for searching keys by their romanized spelling.
+//!
+//! A Set with transliteration enabled keeps a companion Set mapping the
+//! lowercased transliteration of every key back to the key.
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+
+use crate::companion;
+
+const CYRILLIC: &[(char, &str)] = &[
+    ('а', "a"),
+    ('б', "b"),
+    ('в', "v"),
+    ('г', "g"),
+    ('д', "d"),
+    ('е', "e"),
+    ('ё', "e"),
+    ('ж', "zh"),
+    ('з', "z"),
+    ('и', "i"),
+    ('й', "y"),
+    ('к', "k"),
+    ('л', "l"),
+    ('м', "m"),
+    ('н', "n"),
+    ('о', "o"),
+    ('п', "p"),
+    ('р', "r"),
+    ('с', "s"),
+    ('т', "t"),
+    ('у', "u"),
+    ('ф', "f"),
+    ('х', "kh"),
+    ('ц', "ts"),
+    ('ч', "ch"),
+    ('ш', "sh"),
+    ('щ', "shch"),
+    ('ъ', ""),
+    ('ы', "y"),
+    ('ь', ""),
+    ('э', "e"),
+    ('ю', "yu"),
+    ('я', "ya"),
+    ('ґ', "g"),
+    ('є', "ye"),
+    ('і', "i"),
+    ('ї', "yi"),
+];
+
+const LATIN: &[(char, &str)] = &[
+    ('à', "a"),
+    ('á', "a"),
+    ('â', "a"),
+    ('ã', "a"),
+    ('ä', "a"),
+    ('å', "a"),
+    ('ā', "a"),
+    ('ą', "a"),
+    ('æ', "ae"),
+    ('ç', "c"),
+    ('ć', "c"),
+    ('č', "c"),
+    ('ď', "d"),
+    ('đ', "d"),
+    ('ð', "d"),
+    ('è', "e"),
+    ('é', "e"),
+    ('ê', "e"),
+    ('ë', "e"),
+    ('ē', "e"),
+    ('ę', "e"),
+    ('ě', "e"),
+    ('ğ', "g"),
+    ('ì', "i"),
+    ('í', "i"),
+    ('î', "i"),
+    ('ï', "i"),
+    ('ī', "i"),
+    ('ı', "i"),
+    ('ł', "l"),
+    ('ñ', "n"),
+    ('ń', "n"),
+    ('ň', "n"),
+    ('ò', "o"),
+    ('ó', "o"),
+    ('ô', "o"),
+    ('õ', "o"),
+    ('ö', "o"),
+    ('ø', "o"),
+    ('ō', "o"),
+    ('œ', "oe"),
+    ('ř', "r"),
+    ('ś', "s"),
+    ('š', "s"),
+    ('ş', "s"),
+    ('ß', "ss"),
+    ('ť', "t"),
+    ('þ', "th"),
+    ('ù', "u"),
+    ('ú', "u"),
+    ('û', "u"),
+    ('ü', "u"),
+    ('ū', "u"),
+    ('ů', "u"),
+    ('ý', "y"),
+    ('ÿ', "y"),
+    ('ź', "z"),
+    ('ż', "z"),
+    ('ž', "z"),
+];
+
+/// How keys and queries are transliterated before comparing them.
+pub enum Scheme {
+    /// Characters replaced by their romanization; others kept as they are.
+    Table(HashMap<char, String>),
+    /// A Python callable from `str` to `str`, such as a pinyin converter.
+    Callable(PyObject),
+}
+
+impl Scheme {
+    /// A scheme from the name of a built-in table (`"cyrillic"` or
+    /// `"latin"`), a dict of characters to romanizations, or a callable.
+    pub fn extract(obj: &PyAny) -> PyResult<Self> {
+        if let Ok(name) = obj.extract::<&str>() {
+            let table = match name {
+                "cyrillic" => CYRILLIC,
+                "latin" => LATIN,
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown transliteration scheme {:?}, expected \"cyrillic\" or \"latin\"",
+                        name
+                    )))
+                }
+            };
+            let table = table.iter().map(|(c, s)| (*c, s.to_string())).collect();
+            Ok(Scheme::Table(table))
+        } else if let Ok(dict) = obj.downcast::<PyDict>() {
+            let mut table = HashMap::with_capacity(dict.len());
+            for (ch, romanized) in dict {
+                let ch: &str = ch.extract()?;
+                let mut chars = ch.chars();
+                let (Some(ch), None) = (chars.next(), chars.next()) else {
+                    return Err(PyValueError::new_err(format!(
+                        "transliteration table keys must be single characters, not {:?}",
+                        ch
+                    )));
+                };
+                table.insert(ch, romanized.extract()?);
+            }
+            Ok(Scheme::Table(table))
+        } else if obj.is_callable() {
+            Ok(Scheme::Callable(obj.into()))
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "expected a scheme name, a dict or a callable, not {}",
+                obj.get_type().name()?
+            )))
+        }
+    }
+
+    /// The kind of a companion built with this scheme, from its table or
+    /// its callable, or `None` if it cannot be told apart from others.
+    pub fn kind(&self, py: Python) -> PyResult<Option<Vec<u8>>> {
+        match self {
+            Scheme::Table(table) => {
+                let mut pairs: Vec<_> = table.iter().collect();
+                pairs.sort_unstable();
+                let mut kind = b"translit:table".to_vec();
+                for (ch, romanized) in pairs {
+                    kind.extend_from_slice(format!("\0{}\0{}", ch, romanized).as_bytes());
+                }
+                Ok(Some(kind))
+            }
+            Scheme::Callable(func) => Ok(companion::callable_kind(func.as_ref(py))?
+                .map(|kind| [b"translit:callable\0".as_slice(), &kind].concat())),
+        }
+    }
+
+    /// The lowercased transliteration of `text`.
+    pub fn apply(&self, py: Python, text: &str) -> PyResult<String> {
+        let romanized = match self {
+            Scheme::Table(table) => {
+                let mut out = String::with_capacity(text.len());
+                for ch in text.chars().flat_map(char::to_lowercase) {
+                    match table.get(&ch) {
+                        Some(romanized) => out.push_str(romanized),
+                        None => out.push(ch),
+                    }
+                }
+                out
+            }
+            Scheme::Callable(func) => func.call1(py, (text,))?.extract(py)?,
+        };
+        Ok(romanized.to_lowercase())
+    }
+}
@@ -1,2 +1,731 @@
-// Utility functions can be added here if needed.
-// Currently empty as we migrated away from CFFI helpers.
+use fst::raw::{CompiledAddr, Fst, Output};
+use fst::{Automaton, IntoStreamer, Streamer};
+use pyo3::exceptions::{PyKeyError, PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::pyclass::boolean_struct::False;
+use pyo3::types::{IntoPyDict, PyBytes, PyDict, PyLong, PyString, PyType};
+use pyo3::PyClass;
+use rayon::prelude::*;
+use regex_automata::DenseDFA;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+use std::ops::Bound;
+
+use crate::backward::Backward;
+use crate::error;
+
+/// A key argument given as either `str` or `bytes`.
+pub struct Key<'a> {
+    bytes: Cow<'a, [u8]>,
+    obj: &'a PyAny,
+}
+
+impl<'a> Key<'a> {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The key as text, for automata that operate on characters.
+    pub fn as_str(&self) -> PyResult<&str> {
+        std::str::from_utf8(&self.bytes).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Convert a Python int to a map value for this key, raising `TypeError` for
+    /// non-integers and `OverflowError` for integers outside the u64 domain.
+    pub fn value(&self, val: &PyAny) -> PyResult<u64> {
+        if !val.is_instance_of::<PyLong>() {
+            return Err(PyTypeError::new_err(format!(
+                "value for key {} must be an int, not {}",
+                self.obj.repr()?,
+                val.get_type().name()?
+            )));
+        }
+        val.extract::<u64>().map_err(|_| {
+            PyOverflowError::new_err(format!(
+                "value {} for key {} is outside the range of an unsigned 64-bit integer [0, 2**64)",
+                val,
+                self.describe()
+            ))
+        })
+    }
+
+    /// The `repr()` of the key, for error messages.
+    pub fn describe(&self) -> String {
+        self.obj
+            .repr()
+            .map_or_else(|_| "?".into(), |r| r.to_string())
+    }
+
+    /// A `KeyError` for this key, carrying the original Python object.
+    pub fn key_error(&self) -> PyErr {
+        PyKeyError::new_err(self.obj.into_py(self.obj.py()))
+    }
+}
+
+impl<'a> FromPyObject<'a> for Key<'a> {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        let bytes = if let Ok(bytes) = obj.downcast::<PyBytes>() {
+            Cow::Borrowed(bytes.as_bytes())
+        } else if let Ok(text) = obj.downcast::<PyString>() {
+            match text.to_cow()? {
+                Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+            }
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "key must be str or bytes, not {}",
+                obj.get_type().name()?
+            )));
+        };
+        Ok(Key { bytes, obj })
+    }
+}
+
+/// Format a byte count with a binary unit, e.g. `89.1MiB`.
+pub fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1}{}", size, unit)
+}
+
+/// A `(ge, lt)` pair of optional key bounds.
+pub type KeyRange = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// Split the keyspace of `fst` into contiguous `(ge, lt)` ranges on first-byte
+/// boundaries, so that each range can be searched independently.
+pub fn key_ranges<D: AsRef<[u8]>>(fst: &Fst<D>) -> Vec<KeyRange> {
+    let root = fst.root();
+    let inputs: Vec<u8> = root.transitions().map(|t| t.inp).collect();
+    let chunks = (rayon::current_num_threads() * 4).max(1);
+    let step = inputs.len().div_ceil(chunks).max(1);
+    let bounds: Vec<Vec<u8>> = inputs
+        .iter()
+        .step_by(step)
+        .skip(1)
+        .map(|b| vec![*b])
+        .collect();
+
+    let mut ranges = Vec::with_capacity(bounds.len() + 1);
+    let mut lower = None;
+    for bound in bounds {
+        ranges.push((lower, Some(bound.clone())));
+        lower = Some(bound);
+    }
+    ranges.push((lower, None));
+    ranges
+}
+
+/// The range of keys starting with `prefix`, or `None` if every key does.
+fn prefix_range(prefix: &[u8]) -> KeyRange {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < 0xFF {
+            upper.push(last + 1);
+            break;
+        }
+    }
+    let upper = if upper.is_empty() { None } else { Some(upper) };
+    (Some(prefix.to_vec()), upper)
+}
+
+/// The keys starting with `prefix` (if given) that are `>= ge` and `< lt`.
+pub fn bounded_range(prefix: Option<&[u8]>, ge: Option<&[u8]>, lt: Option<&[u8]>) -> KeyRange {
+    narrow(prefix.map_or((None, None), prefix_range), ge, lt)
+}
+
+/// The keys of `range` that are `>= ge` and `< lt`.
+fn narrow(range: KeyRange, ge: Option<&[u8]>, lt: Option<&[u8]>) -> KeyRange {
+    let (mut lower, mut upper) = range;
+    if let Some(ge) = ge {
+        if lower.as_deref().is_none_or(|lower| ge > lower) {
+            lower = Some(ge.to_vec());
+        }
+    }
+    if let Some(lt) = lt {
+        if upper.as_deref().is_none_or(|upper| lt < upper) {
+            upper = Some(lt.to_vec());
+        }
+    }
+    (lower, upper)
+}
+
+/// Stream the matches of `aut` in `fst` that fall within `range`.
+pub fn search_range<'f, D, A>(
+    fst: &'f Fst<D>,
+    aut: &'f A,
+    range: KeyRange,
+) -> fst::raw::Stream<'f, &'f A>
+where
+    D: AsRef<[u8]>,
+    A: Automaton,
+{
+    let (ge, lt) = range;
+    let mut builder = fst.search(aut);
+    if let Some(ge) = ge {
+        builder = builder.ge(ge);
+    }
+    if let Some(lt) = lt {
+        builder = builder.lt(lt);
+    }
+    builder.into_stream()
+}
+
+/// The matches of `aut` in `fst`, in key order.
+pub fn search<D: AsRef<[u8]>, A: Automaton>(fst: &Fst<D>, aut: &A) -> Vec<(Vec<u8>, u64)> {
+    let mut stream = search_range(fst, aut, (None, None));
+    let mut matches = Vec::new();
+    while let Some((key, out)) = stream.next() {
+        matches.push((key.to_vec(), out.value()));
+    }
+    matches
+}
+
+/// The `k` matches of `aut` within `range` with the largest outputs, largest
+/// first and, among equal outputs, in key order.
+pub fn top_k<D: AsRef<[u8]>, A: Automaton>(
+    fst: &Fst<D>,
+    aut: &A,
+    range: KeyRange,
+    k: usize,
+) -> Vec<(Vec<u8>, u64)> {
+    if k == 0 {
+        return Vec::new();
+    }
+    // A min-heap of the best matches so far; its top is the first to evict.
+    let mut best = BinaryHeap::with_capacity(k + 1);
+    let mut stream = search_range(fst, aut, range);
+    while let Some((key, out)) = stream.next() {
+        let value = out.value();
+        if best.len() == k {
+            let Reverse((worst, _)) = best.peek().unwrap();
+            if value <= *worst {
+                continue;
+            }
+        }
+        best.push(Reverse((value, Reverse(key.to_vec()))));
+        if best.len() > k {
+            best.pop();
+        }
+    }
+    best.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((value, Reverse(key)))| (key, value))
+        .collect()
+}
+
+/// Run `aut` against the keys of `fst` within `bounds` on the rayon pool,
+/// one worker per key range, and return the matches in key order.
+pub fn par_search<D, A>(fst: &Fst<D>, aut: &A, bounds: &KeyRange) -> Vec<(Vec<u8>, u64)>
+where
+    D: AsRef<[u8]> + Sync,
+    A: Automaton + Sync,
+{
+    let (ge, lt) = bounds;
+    key_ranges(fst)
+        .into_par_iter()
+        .map(|range| {
+            let range = narrow(range, ge.as_deref(), lt.as_deref());
+            let mut stream = search_range(fst, aut, range);
+            let mut matches = Vec::new();
+            while let Some((key, out)) = stream.next() {
+                matches.push((key.to_vec(), out.value()));
+            }
+            matches
+        })
+        .flatten()
+        .collect()
+}
+
+/// The keys of `fst` that are prefixes of `input`, as `(length, output)`
+/// pairs, shortest first.
+pub fn prefixes_of<D: AsRef<[u8]>>(fst: &Fst<D>, input: &[u8]) -> Vec<(usize, u64)> {
+    let mut node = fst.root();
+    let mut out = Output::zero();
+    let mut prefixes = Vec::new();
+    for (i, b) in input.iter().enumerate() {
+        if node.is_final() {
+            prefixes.push((i, out.cat(node.final_output()).value()));
+        }
+        match node.find_input(*b) {
+            Some(t) => {
+                let t = node.transition(t);
+                out = out.cat(t.out);
+                node = fst.node(t.addr);
+            }
+            None => return prefixes,
+        }
+    }
+    if node.is_final() {
+        prefixes.push((input.len(), out.cat(node.final_output()).value()));
+    }
+    prefixes
+}
+
+/// The least key of `fst` that is at least `key`, or the first key if `key`
+/// is `None`, with its output. The FST is walked down one path, seeking
+/// `key`, rather than scanned.
+pub fn ceiling<D: AsRef<[u8]>>(fst: &Fst<D>, key: Option<&[u8]>) -> Option<(Vec<u8>, u64)> {
+    let mut range = fst.range();
+    if let Some(key) = key {
+        range = range.ge(key);
+    }
+    let mut stream = range.into_stream();
+    stream.next().map(|(key, out)| (key.to_vec(), out.value()))
+}
+
+/// The greatest key of `fst` that is at most `key`, or the last key if
+/// `key` is `None`, with its output, found like `ceiling` from the other
+/// end.
+pub fn floor<D: AsRef<[u8]>>(fst: &Fst<D>, key: Option<&[u8]>) -> Option<(Vec<u8>, u64)> {
+    let upper = key.map_or(Bound::Unbounded, |key| Bound::Included(key.to_vec()));
+    let mut stream = Backward::new(fst, Bound::Unbounded, upper);
+    stream.next().map(|(key, out)| (key.to_vec(), out.value()))
+}
+
+/// Whether `a` and `b` hold the same keys with the same outputs, compared in
+/// one pass over both, or by their bytes if those are the same.
+pub fn fst_eq<D: AsRef<[u8]>, E: AsRef<[u8]>>(a: &Fst<D>, b: &Fst<E>) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    if a.as_bytes() == b.as_bytes() {
+        return true;
+    }
+    let (mut a, mut b) = (a.stream(), b.stream());
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some((key_a, out_a)), Some((key_b, out_b))) if key_a == key_b && out_a == out_b => {}
+            _ => return false,
+        }
+    }
+}
+
+/// `value`, a Set or a Map, as an instance of `cls`, its class or a subclass
+/// of it. As when unpickling, the subclass's own `__new__` and `__init__`
+/// are not called: the instance is made by the base class's `__new__` over
+/// an empty FST, which `value` then replaces.
+pub fn instance_of<T>(cls: &PyType, value: T) -> PyResult<PyObject>
+where
+    T: PyClass<Frozen = False> + IntoPy<PyObject>,
+{
+    let py = cls.py();
+    let base = py.get_type::<T>();
+    if cls.is(base) {
+        return Ok(value.into_py(py));
+    }
+    let empty = fst::raw::Builder::memory()
+        .into_inner()
+        .map_err(error::fst_error)?;
+    let obj = base.call_method1("__new__", (cls, PyBytes::new(py, &empty)))?;
+    *obj.downcast::<PyCell<T>>()?.borrow_mut() = value;
+    Ok(obj.into())
+}
+
+/// Carry the attributes a subclass instance `from` has in its `__dict__`
+/// over to its copy `to`, deep-copied with `memo` if given. `to` is entered
+/// in `memo` first, so that attributes referring back to `from` refer to
+/// `to` in the copy.
+pub fn copy_attrs(from: &PyAny, to: &PyAny, memo: Option<&PyAny>) -> PyResult<()> {
+    let Ok(attrs) = from.getattr("__dict__") else {
+        return Ok(());
+    };
+    let attrs = match memo {
+        Some(memo) => {
+            memo.set_item(from.as_ptr() as usize, to)?;
+            from.py()
+                .import("copy")?
+                .call_method1("deepcopy", (attrs, memo))?
+        }
+        None => attrs,
+    };
+    to.getattr("__dict__")?.call_method1("update", (attrs,))?;
+    Ok(())
+}
+
+/// The distinct keys of an optional iterable, in order.
+pub fn key_set(keys: Option<&PyAny>) -> PyResult<BTreeSet<Vec<u8>>> {
+    let mut set = BTreeSet::new();
+    if let Some(keys) = keys {
+        for key in keys.iter()? {
+            set.insert(key?.extract::<Key>()?.as_bytes().to_vec());
+        }
+    }
+    Ok(set)
+}
+
+/// Pass the keys of `fst` with `add` merged in and `remove` taken out to
+/// `insert`, in order, in a single walk of the FST. `add` must be sorted by
+/// distinct keys; its outputs replace those of keys already in `fst`, and a
+/// key in both `add` and `remove` is removed.
+pub fn merge_edits<'a, D: AsRef<[u8]>, E>(
+    fst: &Fst<D>,
+    add: impl IntoIterator<Item = (&'a [u8], u64)>,
+    remove: &BTreeSet<Vec<u8>>,
+    mut insert: impl FnMut(&[u8], u64) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut add = add.into_iter().peekable();
+    let mut stream = fst.stream();
+    let mut next = || stream.next().map(|(key, out)| (key.to_vec(), out.value()));
+    let mut current = next();
+    loop {
+        let (key, out) = match (current.take(), add.peek()) {
+            (None, None) => return Ok(()),
+            (Some(item), None) => {
+                current = next();
+                item
+            }
+            (Some(item), Some(&(added, _))) if item.0.as_slice() < added => {
+                current = next();
+                item
+            }
+            (item, Some(_)) => {
+                let (added, out) = add.next().expect("peeked");
+                current = match item {
+                    Some((key, _)) if key == added => next(),
+                    item => item,
+                };
+                (added.to_vec(), out)
+            }
+        };
+        if !remove.contains(&key) {
+            insert(&key, out)?;
+        }
+    }
+}
+
+/// Structural metrics of an FST, as returned by `Set.stats()` and
+/// `Map.stats()`.
+#[derive(Default)]
+struct Stats {
+    nodes: usize,
+    transitions: usize,
+    final_nodes: usize,
+    max_out_degree: usize,
+    max_key_length: usize,
+}
+
+/// Walk every node of `fst` once. Nodes are shared between keys, so the
+/// longest key is found as the longest path from the root, remembered per
+/// node; the walk is iterative, since keys can be arbitrarily long.
+fn walk<D: AsRef<[u8]>>(fst: &Fst<D>) -> Stats {
+    let mut stats = Stats::default();
+    // The longest path below each node, or `None` while its children are
+    // still being walked.
+    let mut depth: HashMap<CompiledAddr, Option<usize>> = HashMap::new();
+    let mut stack = vec![(fst.root().addr(), false)];
+    while let Some((addr, children_done)) = stack.pop() {
+        let node = fst.node(addr);
+        if children_done {
+            let longest = node
+                .transitions()
+                .map(|t| 1 + depth[&t.addr].expect("the FST is acyclic"))
+                .max()
+                .unwrap_or(0);
+            depth.insert(addr, Some(longest));
+            continue;
+        }
+        if depth.contains_key(&addr) {
+            continue;
+        }
+        depth.insert(addr, None);
+        stats.nodes += 1;
+        stats.transitions += node.len();
+        stats.final_nodes += node.is_final() as usize;
+        stats.max_out_degree = stats.max_out_degree.max(node.len());
+        stack.push((addr, true));
+        for t in node.transitions() {
+            if !depth.contains_key(&t.addr) {
+                stack.push((t.addr, false));
+            }
+        }
+    }
+    stats.max_key_length = depth[&fst.root().addr()].unwrap_or(0);
+    stats
+}
+
+/// The structural metrics of `fst` as a dict, computed with the GIL
+/// released.
+pub fn stats<'py, D: AsRef<[u8]> + Sync>(py: Python<'py>, fst: &Fst<D>) -> PyResult<&'py PyDict> {
+    let stats = py.allow_threads(|| walk(fst));
+    let dict = PyDict::new(py);
+    dict.set_item("keys", fst.len())?;
+    dict.set_item("nodes", stats.nodes)?;
+    dict.set_item("transitions", stats.transitions)?;
+    dict.set_item("final_nodes", stats.final_nodes)?;
+    dict.set_item("max_out_degree", stats.max_out_degree)?;
+    dict.set_item("max_key_length", stats.max_key_length)?;
+    dict.set_item("size_bytes", fst.as_bytes().len())?;
+    dict.set_item("version", version(fst))?;
+    Ok(dict)
+}
+
+/// The format version `fst` was written with, which `fst` checks on opening
+/// but does not expose: the first eight bytes, little-endian.
+fn version<D: AsRef<[u8]>>(fst: &Fst<D>) -> u64 {
+    let mut header = [0; 8];
+    header.copy_from_slice(&fst.as_bytes()[..8]);
+    u64::from_le_bytes(header)
+}
+
+/// The count, minimum, maximum and mean of the key lengths in bytes, and
+/// the number of keys of each length, as a dict. The minimum, maximum and
+/// mean are `None` for an empty FST.
+pub fn key_length_stats<'py, D: AsRef<[u8]> + Sync>(
+    py: Python<'py>,
+    fst: &Fst<D>,
+) -> PyResult<&'py PyDict> {
+    let histogram = py.allow_threads(|| {
+        let mut histogram: BTreeMap<usize, u64> = BTreeMap::new();
+        let mut stream = fst.stream();
+        while let Some((key, _)) = stream.next() {
+            *histogram.entry(key.len()).or_default() += 1;
+        }
+        histogram
+    });
+    let total: u64 = histogram.iter().map(|(len, n)| *len as u64 * n).sum();
+    let dict = PyDict::new(py);
+    dict.set_item("count", fst.len())?;
+    dict.set_item("min", histogram.keys().next())?;
+    dict.set_item("max", histogram.keys().next_back())?;
+    dict.set_item(
+        "mean",
+        (!fst.is_empty()).then(|| total as f64 / fst.len() as f64),
+    )?;
+    dict.set_item("histogram", histogram.into_py_dict(py))?;
+    Ok(dict)
+}
+
+/// The number of keys at or below the node at `addr`, remembered in
+/// `counts` for every node visited on the way, so that shared nodes are only
+/// counted once.
+pub fn count_keys<D: AsRef<[u8]>>(
+    fst: &Fst<D>,
+    addr: CompiledAddr,
+    counts: &mut HashMap<CompiledAddr, u64>,
+) -> u64 {
+    let mut stack = vec![(addr, false)];
+    while let Some((addr, children_done)) = stack.pop() {
+        let node = fst.node(addr);
+        if children_done {
+            let below: u64 = node.transitions().map(|t| counts[&t.addr]).sum();
+            counts.insert(addr, node.is_final() as u64 + below);
+        } else if !counts.contains_key(&addr) {
+            stack.push((addr, true));
+            for t in node.transitions() {
+                if !counts.contains_key(&t.addr) {
+                    stack.push((t.addr, false));
+                }
+            }
+        }
+    }
+    counts[&addr]
+}
+
+/// The number of keys sharing each prefix of `depth` characters, in key
+/// order. Characters are UTF-8 sequences, or single bytes where a key is not
+/// valid UTF-8; keys shorter than `depth` characters count under themselves.
+/// Only the first `depth` characters are walked: the keys below are counted
+/// per node.
+pub fn prefix_histogram<D: AsRef<[u8]>>(fst: &Fst<D>, depth: usize) -> Vec<(Vec<u8>, u64)> {
+    let mut counts = HashMap::new();
+    let mut buckets = Vec::new();
+    // The node, its prefix, the characters started in the prefix and the
+    // continuation bytes still expected of the last one.
+    let mut stack = vec![(fst.root().addr(), Vec::new(), 0, 0)];
+    while let Some((addr, prefix, chars, pending)) = stack.pop() {
+        let node = fst.node(addr);
+        let mut count = node.is_final() as u64;
+        let mut children = Vec::new();
+        for t in node.transitions() {
+            let continues = pending > 0 && t.inp & 0xC0 == 0x80;
+            if !continues && chars == depth {
+                count += count_keys(fst, t.addr, &mut counts);
+                continue;
+            }
+            let (chars, pending) = if continues {
+                (chars, pending - 1)
+            } else {
+                let len = match t.inp {
+                    0xC0..=0xDF => 1,
+                    0xE0..=0xEF => 2,
+                    0xF0..=0xF7 => 3,
+                    _ => 0,
+                };
+                (chars + 1, len)
+            };
+            let mut prefix = prefix.clone();
+            prefix.push(t.inp);
+            children.push((t.addr, prefix, chars, pending));
+        }
+        if count > 0 {
+            buckets.push((prefix, count));
+        }
+        stack.extend(children.into_iter().rev());
+    }
+    buckets
+}
+
+/// Split `text` greedily into the longest keys of `fst` it starts with,
+/// falling back to single characters where no key matches. Returns each
+/// token's `(start, end)` character offsets and the output of its key, or
+/// `None` for a fallback character.
+pub fn segment<D: AsRef<[u8]>>(fst: &Fst<D>, text: &str) -> Vec<(usize, usize, Option<u64>)> {
+    let mut tokens = Vec::new();
+    let (mut pos, mut chars) = (0, 0);
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let longest = prefixes_of(fst, rest.as_bytes())
+            .into_iter()
+            .rev()
+            .find(|(len, _)| *len > 0 && rest.is_char_boundary(*len));
+        let (len, value) = match longest {
+            Some((len, value)) => (len, Some(value)),
+            None => (rest.chars().next().map_or(1, char::len_utf8), None),
+        };
+        let end = chars + rest[..len].chars().count();
+        tokens.push((chars, end, value));
+        pos += len;
+        chars = end;
+    }
+    tokens
+}
+
+/// Count the keys of `fst` within `range` matched by `aut`.
+pub fn count<D: AsRef<[u8]>, A: Automaton>(fst: &Fst<D>, aut: &A, range: KeyRange) -> usize {
+    let mut stream = search_range(fst, aut, range);
+    let mut count = 0;
+    while stream.next().is_some() {
+        count += 1;
+    }
+    count
+}
+
+/// Count the keys of `fst` matched by `aut` on the rayon pool, one worker per
+/// key range.
+pub fn par_count<D, A>(fst: &Fst<D>, aut: &A) -> usize
+where
+    D: AsRef<[u8]> + Sync,
+    A: Automaton + Sync,
+{
+    key_ranges(fst)
+        .into_par_iter()
+        .map(|range| {
+            let mut stream = search_range(fst, aut, range);
+            let mut count = 0;
+            while stream.next().is_some() {
+                count += 1;
+            }
+            count
+        })
+        .sum()
+}
+
+/// Compile `regex` into an anchored DFA usable as an FST automaton.
+pub fn regex_dfa(regex: &str) -> PyResult<DenseDFA<Vec<usize>, usize>> {
+    search_dfa(regex, MatchMode::Anchored, false)
+}
+
+/// Which part of a key a regex has to match.
+#[derive(Clone, Copy)]
+pub enum MatchMode {
+    /// The whole key, like `re.fullmatch`.
+    Anchored,
+    /// The start of the key, like `re.match`.
+    Prefix,
+    /// Anywhere in the key, like `re.search`.
+    Substring,
+}
+
+impl MatchMode {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        Ok(match name {
+            "anchored" => MatchMode::Anchored,
+            "prefix" => MatchMode::Prefix,
+            "substring" => MatchMode::Substring,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown match_mode {:?}, expected \"anchored\", \"prefix\" or \"substring\"",
+                    name
+                )))
+            }
+        })
+    }
+}
+
+/// Compile `regex` into a DFA matching the keys that `regex` matches as
+/// `mode` says, ignoring case if `case_insensitive`.
+pub fn search_dfa(
+    regex: &str,
+    mode: MatchMode,
+    case_insensitive: bool,
+) -> PyResult<DenseDFA<Vec<usize>, usize>> {
+    // The DFA only matches whole keys, so the rest of the key is matched
+    // explicitly.
+    let pattern = match mode {
+        MatchMode::Anchored => Cow::Borrowed(regex),
+        MatchMode::Prefix => Cow::Owned(format!("(?:{})(?s:.*)", regex)),
+        MatchMode::Substring => Cow::Owned(format!("(?s:.*)(?:{})(?s:.*)", regex)),
+    };
+    // Wrapping an unbalanced regex like `a)|(b` could make it valid, so it
+    // is checked on its own first.
+    if !matches!(mode, MatchMode::Anchored) {
+        regex_syntax::Parser::new()
+            .parse(regex)
+            .map_err(|err| error::FstError::new_err(err.to_string()))?;
+    }
+    regex_automata::dense::Builder::new()
+        .anchored(true)
+        .case_insensitive(case_insensitive)
+        .build(&pattern)
+        .map_err(error::regex_error)
+}
+
+/// Splits keys into a prefix of (up to) a fixed number of bytes and the
+/// remaining suffix, handing out the same Python string object for the prefix
+/// for as long as consecutive keys share it.
+pub struct PrefixDecoder {
+    len: usize,
+    bytes: Vec<u8>,
+    prefix: Option<Py<PyString>>,
+}
+
+impl PrefixDecoder {
+    pub fn new(len: usize) -> Self {
+        PrefixDecoder {
+            len,
+            bytes: Vec::new(),
+            prefix: None,
+        }
+    }
+
+    pub fn split(&mut self, py: Python, key: &[u8]) -> (Py<PyString>, String) {
+        let mut cut = self.len.min(key.len());
+        while cut > 0 && cut < key.len() && (key[cut] & 0xC0) == 0x80 {
+            cut -= 1;
+        }
+        let (head, tail) = key.split_at(cut);
+        let prefix = match &self.prefix {
+            Some(prefix) if self.bytes == head => prefix.clone_ref(py),
+            _ => {
+                let prefix: Py<PyString> = PyString::new(py, &String::from_utf8_lossy(head)).into();
+                self.bytes = head.to_vec();
+                self.prefix = Some(prefix.clone_ref(py));
+                prefix
+            }
+        };
+        (prefix, String::from_utf8_lossy(tail).into_owned())
+    }
+}
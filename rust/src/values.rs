@@ -0,0 +1,247 @@
+//! Signed and floating point Map values.
+//!
+//! FST outputs are unsigned 64-bit integers, so other values are stored as
+//! their image under a bijection onto `u64` that preserves their order: an
+//! `i64` with its sign bit flipped, and an `f64` as its IEEE 754 bits with the
+//! sign bit flipped for positive numbers and all bits flipped for negative
+//! ones. Ranges of values, and the `"min"`, `"max"`, `"first"` and `"last"`
+//! merges, therefore work unchanged; sums do not.
+
+use fst::Streamer;
+use pyo3::exceptions::{PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyFloat, PyLong};
+use std::io::{self, Write};
+
+use crate::util::Key;
+
+const SIGN: u64 = 1 << 63;
+
+/// How the values of a Map are stored, from its `value_type` option.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValueType {
+    #[default]
+    U64,
+    I64,
+    F64,
+}
+
+impl ValueType {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        Ok(match name {
+            "u64" => ValueType::U64,
+            "i64" => ValueType::I64,
+            "f64" => ValueType::F64,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown value_type {:?}, expected \"u64\", \"i64\" or \"f64\"",
+                    name
+                )))
+            }
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::U64 => "u64",
+            ValueType::I64 => "i64",
+            ValueType::F64 => "f64",
+        }
+    }
+
+    /// Convert the Python value `val` for `key` to an output, raising
+    /// `TypeError` for a value of the wrong type and `OverflowError` for an
+    /// integer out of range.
+    pub fn encode(self, key: &Key, val: &PyAny) -> PyResult<u64> {
+        match self {
+            ValueType::U64 => key.value(val),
+            ValueType::I64 => {
+                if !val.is_instance_of::<PyLong>() {
+                    return Err(self.type_error(key, val, "an int"));
+                }
+                let val = val.extract::<i64>().map_err(|_| {
+                    PyOverflowError::new_err(format!(
+                        "value {} for key {} is outside the range of a signed 64-bit integer \
+                         [-2**63, 2**63)",
+                        val,
+                        key.describe()
+                    ))
+                })?;
+                Ok(val as u64 ^ SIGN)
+            }
+            ValueType::F64 => {
+                if !val.is_instance_of::<PyFloat>() && !val.is_instance_of::<PyLong>() {
+                    return Err(self.type_error(key, val, "a float"));
+                }
+                Ok(encode_f64(val.extract()?))
+            }
+        }
+    }
+
+    /// `val` as an output, or `None` if it cannot be a value of this type,
+    /// for membership tests.
+    pub fn try_encode(self, val: &PyAny) -> Option<u64> {
+        match self {
+            ValueType::U64 => val.extract::<u64>().ok(),
+            ValueType::I64 => val.extract::<i64>().ok().map(|val| val as u64 ^ SIGN),
+            ValueType::F64 => val.extract::<f64>().ok().map(encode_f64),
+        }
+    }
+
+    /// The Python value stored as the output `out`.
+    pub fn decode(self, py: Python, out: u64) -> PyObject {
+        match self {
+            ValueType::U64 => out.into_py(py),
+            ValueType::I64 => decode_i64(out).into_py(py),
+            ValueType::F64 => decode_f64(out).into_py(py),
+        }
+    }
+
+    /// The numpy dtype of the values, in native byte order.
+    pub fn dtype(self) -> &'static str {
+        match self {
+            ValueType::U64 => "=u8",
+            ValueType::I64 => "=i8",
+            ValueType::F64 => "=f8",
+        }
+    }
+
+    /// The value stored as the output `out` in native byte order, for an
+    /// array of `dtype()`.
+    pub fn native_bytes(self, out: u64) -> [u8; 8] {
+        match self {
+            ValueType::U64 => out.to_ne_bytes(),
+            ValueType::I64 => decode_i64(out).to_ne_bytes(),
+            ValueType::F64 => decode_f64(out).to_ne_bytes(),
+        }
+    }
+
+    /// Write the value stored as the output `out` as JSON. JSON has no
+    /// NaN or infinities, so those are written as `null`.
+    pub fn write_json<W: Write>(self, wtr: &mut W, out: u64) -> io::Result<()> {
+        match self {
+            ValueType::U64 => write!(wtr, "{}", out),
+            ValueType::I64 => write!(wtr, "{}", decode_i64(out)),
+            ValueType::F64 => match decode_f64(out) {
+                val if val.is_finite() => write!(wtr, "{:?}", val),
+                _ => wtr.write_all(b"null"),
+            },
+        }
+    }
+
+    /// Raise `ValueError` unless the values are plain `u64`, for `what`,
+    /// which works on the outputs directly.
+    pub fn require_u64(self, what: &str) -> PyResult<()> {
+        if self == ValueType::U64 {
+            return Ok(());
+        }
+        Err(PyValueError::new_err(format!(
+            "{} needs value_type \"u64\", not {:?}",
+            what,
+            self.name()
+        )))
+    }
+
+    fn type_error(self, key: &Key, val: &PyAny, expected: &str) -> PyErr {
+        let type_name = val
+            .get_type()
+            .name()
+            .map_or_else(|_| "?".into(), |name| name.to_string());
+        PyTypeError::new_err(format!(
+            "value for key {} must be {} for value_type {:?}, not {}",
+            key.describe(),
+            expected,
+            self.name(),
+            type_name
+        ))
+    }
+}
+
+/// The `min_value` and `max_value` of a Map stream: only items with values
+/// in between, inclusive, are yielded. The bounds are encoded like the
+/// values, so they are compared as outputs while the FST is walked, except
+/// that floats are decoded and compared as floats: the order of the outputs
+/// puts NaN outside the infinities and -0.0 below 0.0, where a comparison
+/// of floats admits no NaN and takes -0.0 and 0.0 as equal.
+#[derive(Clone, Copy, Default)]
+pub struct ValueFilter {
+    min: Option<u64>,
+    max: Option<u64>,
+    value_type: ValueType,
+}
+
+impl ValueFilter {
+    /// The filter of the `min_value` and `max_value` arguments, raising
+    /// `ValueError` for a bound that is not a value of `value_type`.
+    pub fn parse(
+        value_type: ValueType,
+        min_value: Option<&PyAny>,
+        max_value: Option<&PyAny>,
+    ) -> PyResult<Self> {
+        let encode = |name, bound: Option<&PyAny>| {
+            bound
+                .map(|bound| {
+                    value_type.try_encode(bound).ok_or_else(|| {
+                        PyValueError::new_err(format!(
+                            "{} {} is not a value of value_type {:?}",
+                            name,
+                            bound,
+                            value_type.name()
+                        ))
+                    })
+                })
+                .transpose()
+        };
+        Ok(ValueFilter {
+            min: encode("min_value", min_value)?,
+            max: encode("max_value", max_value)?,
+            value_type,
+        })
+    }
+
+    /// Whether the filter lets every item through.
+    pub fn is_open(self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    pub fn admits(self, out: u64) -> bool {
+        if self.value_type == ValueType::F64 {
+            let val = decode_f64(out);
+            return self.min.is_none_or(|min| val >= decode_f64(min))
+                && self.max.is_none_or(|max| val <= decode_f64(max));
+        }
+        self.min.is_none_or(|min| out >= min) && self.max.is_none_or(|max| out <= max)
+    }
+
+    /// Advance `stream` to its next item that passes the filter and pass it
+    /// to `sink`, or return false at the end of the stream.
+    pub fn pull<S>(self, stream: &mut S, sink: &mut dyn FnMut(&[u8], u64)) -> bool
+    where
+        S: for<'a> Streamer<'a, Item = (&'a [u8], u64)>,
+    {
+        while let Some((key, out)) = stream.next() {
+            if self.admits(out) {
+                sink(key, out);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn encode_f64(val: f64) -> u64 {
+    let bits = val.to_bits();
+    if bits & SIGN == 0 {
+        bits | SIGN
+    } else {
+        !bits
+    }
+}
+
+pub fn decode_i64(out: u64) -> i64 {
+    (out ^ SIGN) as i64
+}
+
+pub fn decode_f64(out: u64) -> f64 {
+    f64::from_bits(if out & SIGN == 0 { !out } else { out ^ SIGN })
+}
@@ -0,0 +1,81 @@
+//! The `offset` and `limit` of a stream, applied in Rust so that a stream
+//! stops walking the FST as soon as its last item is yielded.
+
+/// Which items of a stream are yielded: `limit` items (all if `None`) after
+/// skipping the first `offset`.
+#[derive(Clone, Copy, Default)]
+pub struct Window {
+    skip: usize,
+    left: Option<usize>,
+    /// The number of items left in the underlying stream, if known.
+    len: Option<usize>,
+}
+
+impl Window {
+    pub fn new(offset: usize, limit: Option<usize>) -> Self {
+        Window {
+            skip: offset,
+            left: limit,
+            len: None,
+        }
+    }
+
+    /// The window of a stream known to have `len` items.
+    pub fn with_len(self, len: usize) -> Self {
+        Window {
+            len: Some(len),
+            ..self
+        }
+    }
+
+    /// Narrow the window to `offset` and `limit`, keeping the known length.
+    pub fn narrow(&mut self, offset: usize, limit: Option<usize>) {
+        self.skip = offset;
+        self.left = limit;
+    }
+
+    /// Whether the next item of the stream comes before the window and is to
+    /// be skipped.
+    pub fn skip(&mut self) -> bool {
+        if self.skip == 0 {
+            return false;
+        }
+        self.skip -= 1;
+        self.pulled();
+        true
+    }
+
+    /// Whether the next item of the stream is to be yielded, which is false
+    /// once the window is full. Call after `skip` returns false.
+    pub fn take(&mut self) -> bool {
+        match &mut self.left {
+            Some(0) => return false,
+            Some(left) => *left -= 1,
+            None => {}
+        }
+        self.pulled();
+        true
+    }
+
+    fn pulled(&mut self) {
+        if let Some(len) = &mut self.len {
+            *len = len.saturating_sub(1);
+        }
+    }
+
+    /// The number of items still to be yielded, if known.
+    pub fn remaining(&self) -> Option<usize> {
+        let len = self.len?.saturating_sub(self.skip);
+        Some(self.left.map_or(len, |left| left.min(len)))
+    }
+
+    /// The items of `items` within the window, for results collected in
+    /// full anyway.
+    pub fn apply<T>(self, mut items: Vec<T>) -> Vec<T> {
+        items.drain(..self.skip.min(items.len()));
+        if let Some(limit) = self.left {
+            items.truncate(limit);
+        }
+        items
+    }
+}
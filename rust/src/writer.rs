@@ -0,0 +1,91 @@
+//! Building FSTs into Python file-like objects.
+//!
+//! A builder can write to any object with a `write` method, such as an
+//! `io.BytesIO`, a socket file or an upload stream, instead of a path. Each
+//! write takes the GIL, so the output is buffered into large chunks first.
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+use std::io::{self, BufWriter, Write};
+
+/// The bytes buffered per call to the object's `write`.
+const CHUNK: usize = 1 << 16;
+
+/// Where a builder writes the FST: to memory, to a file at a path or to a
+/// Python file-like object.
+pub enum Target {
+    Memory,
+    Path(String),
+    Writer(BufWriter<PyWriter>),
+}
+
+impl Target {
+    /// The target named by the `path` argument of a builder: `None`, a path
+    /// or an object with a `write` method.
+    pub fn parse(path: Option<&PyAny>) -> PyResult<Self> {
+        let Some(path) = path else {
+            return Ok(Target::Memory);
+        };
+        if let Ok(path) = path.downcast::<PyString>() {
+            return Ok(Target::Path(path.to_str()?.to_owned()));
+        }
+        if path.hasattr("write")? {
+            let writer = PyWriter {
+                file: path.into_py(path.py()),
+            };
+            return Ok(Target::Writer(BufWriter::with_capacity(CHUNK, writer)));
+        }
+        Err(PyTypeError::new_err(format!(
+            "path must be a str, a binary file object with a write method or None, not {}",
+            path.get_type().name()?
+        )))
+    }
+}
+
+impl From<Option<String>> for Target {
+    fn from(path: Option<String>) -> Self {
+        path.map_or(Target::Memory, Target::Path)
+    }
+}
+
+/// A Python object with a `write(bytes)` method, as a `Write`. An exception
+/// raised by the object is carried through as the `io::Error`, and raised
+/// again unchanged when that is converted back.
+pub struct PyWriter {
+    file: PyObject,
+}
+
+impl Write for PyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let written = self
+                .file
+                .call_method1(py, "write", (PyBytes::new(py, buf),))?;
+            // Objects other than `io` streams often return `None` rather
+            // than the number of bytes taken; they take them all.
+            if written.is_none(py) {
+                return Ok(buf.len());
+            }
+            written.extract::<usize>(py)
+        })
+        .map_err(io::Error::other)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::with_gil(|py| -> PyResult<()> {
+            if self.file.as_ref(py).hasattr("flush")? {
+                self.file.call_method0(py, "flush")?;
+            }
+            Ok(())
+        })
+        .map_err(io::Error::other)
+    }
+}
+
+/// Flush `writer` to its object, once the FST has been written to it.
+pub fn finish(writer: BufWriter<PyWriter>) -> PyResult<()> {
+    let mut writer = writer.into_inner().map_err(|err| err.into_error())?;
+    writer.flush()?;
+    Ok(())
+}
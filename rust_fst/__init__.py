@@ -1,3 +1,42 @@
-from ._native import Set, Map, SetBuilder, MapBuilder
+import collections.abc
+import logging
 
-__all__ = ["Set", "Map", "SetBuilder", "MapBuilder"]
+from ._native import (
+    Set, Map, SetBuilder, MapBuilder, MapKeysView, MapValuesView, MapItemsView,
+    AliasedMap, Autocomplete, DomainMatcher, GeoIndex, IpMatcher,
+    KeyValueStore, LevenshteinQuery, MapCollection, NgramModel, Node,
+    PostingsIndex, SetCollection, SpellChecker, set_trace_hook, build_file, merge_files, pack_kmer,
+    unpack_kmer, kmer_codes, geohash_encode, geohash_decode, compare,
+    lossy_decodes, reset_lossy_decodes, FstError, OutOfOrderError,
+    FormatError, RegexTooLargeError, LevenshteinTooLargeError, ClosedError)
+
+__all__ = ["Set", "Map", "SetBuilder", "MapBuilder", "AliasedMap",
+           "Autocomplete", "DomainMatcher", "GeoIndex", "IpMatcher",
+           "KeyValueStore", "LevenshteinQuery", "MapCollection",
+           "NgramModel", "Node", "PostingsIndex", "SetCollection",
+           "SpellChecker", "set_trace_hook",
+           "log_slow_operations", "build_file", "merge_files", "pack_kmer",
+           "unpack_kmer", "kmer_codes", "geohash_encode", "geohash_decode",
+           "compare", "lossy_decodes", "reset_lossy_decodes", "FstError",
+           "OutOfOrderError", "FormatError", "RegexTooLargeError",
+           "LevenshteinTooLargeError", "ClosedError"]
+
+collections.abc.Mapping.register(Map)
+collections.abc.Set.register(Set)
+collections.abc.KeysView.register(MapKeysView)
+collections.abc.ValuesView.register(MapValuesView)
+collections.abc.ItemsView.register(MapItemsView)
+
+
+def log_slow_operations(threshold=1.0, logger=None, level=logging.WARNING):
+    """Log every build, merge and search taking at least `threshold` seconds.
+
+    Installs a trace hook (replacing any existing one) that reports the
+    operation name, its duration and the number of keys it produced.
+    """
+    logger = logger or logging.getLogger("rust_fst")
+
+    def hook(op, seconds, count):
+        logger.log(level, "%s took %.3fs (%d keys)", op, seconds, count)
+
+    set_trace_hook(hook, threshold)
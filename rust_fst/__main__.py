@@ -0,0 +1,183 @@
+"""Command line interface: ``python -m rust_fst <command> ...``."""
+import argparse
+import os
+import sys
+
+from . import Map, build_file, compare, merge_files
+
+
+def human_size(size):
+    for unit in ("B", "KiB", "MiB", "GiB", "TiB"):
+        if size < 1024 or unit == "TiB":
+            break
+        size /= 1024
+    return "%d%s" % (size, unit) if unit == "B" else "%.1f%s" % (size, unit)
+
+
+def print_matches(matches, values):
+    for key, value in matches:
+        print("%s\t%d" % (key, value) if values else key)
+
+
+def cmd_build(args):
+    delimiter = args.delimiter
+    if delimiter is None:
+        delimiter = "," if args.input.endswith((".csv", ".csv.gz")) else "\t"
+    count = build_file(args.input, args.output, map=args.map,
+                       delimiter=delimiter, sorted=args.sorted)
+    print("wrote %d keys to %s" % (count, args.output), file=sys.stderr)
+    return 0
+
+
+def cmd_query(args):
+    fst = Map(args.fst)
+    missing = False
+    for key in args.keys:
+        value = fst.get(key)
+        if value is None:
+            print("%s: not found" % key, file=sys.stderr)
+            missing = True
+        else:
+            print("%s\t%d" % (key, value))
+    return 1 if missing else 0
+
+
+def cmd_grep(args):
+    fst = Map(args.fst)
+    print_matches(fst.search_re(args.regex, parallel=args.parallel),
+                  args.values)
+    return 0
+
+
+def cmd_fuzzy(args):
+    fst = Map(args.fst)
+    print_matches(fst.search_lev(args.key, args.distance,
+                                 parallel=args.parallel),
+                  args.values)
+    return 0
+
+
+def cmd_merge(args):
+    count = merge_files(args.inputs, args.output)
+    print("wrote %d keys to %s" % (count, args.output), file=sys.stderr)
+    return 0
+
+
+def cmd_diff(args):
+    changed = False
+    for key, old, new in Map(args.old).diff(Map(args.new)):
+        changed = True
+        if old is None:
+            print("+%s\t%d" % (key, new))
+        elif new is None:
+            print("-%s\t%d" % (key, old))
+        else:
+            print("~%s\t%d\t%d" % (key, old, new))
+    return 1 if changed else 0
+
+
+def cmd_compare(args):
+    identical, differences = compare(args.a, args.b, limit=args.limit)
+    if identical:
+        print("identical", file=sys.stderr)
+        return 0
+    for key, a, b in differences:
+        print("%s\t%s\t%s" % (key, "-" if a is None else a,
+                              "-" if b is None else b))
+    if not differences:
+        print("files differ, but hold the same keys and values",
+              file=sys.stderr)
+    return 1
+
+
+def cmd_stats(args):
+    fst = Map(args.fst)
+    print("keys\t%d" % len(fst))
+    print("size\t%s" % human_size(os.path.getsize(args.fst)))
+    return 0
+
+
+def parser():
+    parser = argparse.ArgumentParser(
+        prog="python -m rust_fst",
+        description="Build and inspect FST sets and maps.")
+    commands = parser.add_subparsers(dest="command", required=True)
+
+    build = commands.add_parser(
+        "build", help="build an FST from a text or CSV file",
+        description="Build an FST from one key per line or, with --map, "
+                    "key/value lines. The input does not need to be sorted.")
+    build.add_argument("input")
+    build.add_argument("output")
+    build.add_argument("--map", action="store_true",
+                       help="lines hold a key and an integer value")
+    build.add_argument("--delimiter",
+                       help="key/value delimiter (default: ',' for .csv "
+                            "files, tab otherwise)")
+    build.add_argument("--sorted", action="store_true",
+                       help="the input is already sorted, skip sorting it")
+    build.set_defaults(func=cmd_build)
+
+    query = commands.add_parser("query", help="look up keys")
+    query.add_argument("fst")
+    query.add_argument("keys", nargs="+")
+    query.set_defaults(func=cmd_query)
+
+    for name, func, help in (("grep", cmd_grep, "search with a regex"),
+                             ("fuzzy", cmd_fuzzy, "search by edit distance")):
+        search = commands.add_parser(name, help=help)
+        search.add_argument("fst")
+        if name == "grep":
+            search.add_argument("regex")
+        else:
+            search.add_argument("key")
+            search.add_argument("-d", "--distance", type=int, default=1)
+        search.add_argument("--values", action="store_true",
+                            help="print the values of maps")
+        search.add_argument("--parallel", action="store_true",
+                            help="search on all cores")
+        search.set_defaults(func=func)
+
+    merge = commands.add_parser(
+        "merge", help="write the union of several FSTs",
+        description="Write the union of several FSTs. Keys present in more "
+                    "than one input take the value from the last of them.")
+    merge.add_argument("output")
+    merge.add_argument("inputs", nargs="+")
+    merge.set_defaults(func=cmd_merge)
+
+    diff = commands.add_parser(
+        "diff", help="show keys added, removed or changed between two FSTs")
+    diff.add_argument("old")
+    diff.add_argument("new")
+    diff.set_defaults(func=cmd_diff)
+
+    compare_ = commands.add_parser(
+        "compare", help="check that two FST files are byte-identical",
+        description="Check that two FST files are byte-identical, as when "
+                    "verifying that a rebuild is reproducible. If they are "
+                    "not, print the first differing keys with their values "
+                    "in each file ('-' if missing) and exit with status 1.")
+    compare_.add_argument("a")
+    compare_.add_argument("b")
+    compare_.add_argument("-n", "--limit", type=int, default=10,
+                          help="differing keys to print (default: 10)")
+    compare_.set_defaults(func=cmd_compare)
+
+    stats = commands.add_parser("stats", help="show key count and size")
+    stats.add_argument("fst")
+    stats.set_defaults(func=cmd_stats)
+    return parser
+
+
+def main(argv=None):
+    args = parser().parse_args(argv)
+    try:
+        return args.func(args)
+    except (OSError, ValueError) as e:
+        print("error: %s" % e, file=sys.stderr)
+        return 2
+
+
+if __name__ == "__main__":
+    sys.exit(main())
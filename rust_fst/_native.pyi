@@ -1,38 +1,576 @@
-from typing import Iterator, List, Optional, Tuple, Union
+# This file is generated by rust/build.rs from the Rust sources. Do not edit.
+from types import NotImplementedType
+from typing import Any, Awaitable, BinaryIO, Callable, Dict, FrozenSet, Iterable, Iterator, List, Optional, Tuple, Union
 
-class Map:
-    def __init__(self, path: Union[str, bytes]) -> None: ...
-    def __contains__(self, key: str) -> bool: ...
-    def __getitem__(self, key: str) -> int: ...
+__all__ = ["AliasedMap", "ArrowStream", "Autocomplete", "SetCollection", "MapCollection", "MapCollectionKeys", "DomainMatcher", "FstError", "OutOfOrderError", "FormatError", "RegexTooLargeError", "LevenshteinTooLargeError", "ClosedError", "GeoIndex", "IpMatcher", "KeyValueStore", "KeyValueStream", "LevenshteinQuery", "Map", "MapKeysView", "MapValuesView", "MapItemsView", "MapKeys", "MapSplitKeys", "MapValues", "MapItems", "MapRegexStream", "MapLevStream", "MapDiff", "MapUnion", "MapIntersection", "MapDifference", "MapSymmetricDifference", "MapSubsequenceStream", "MapCollectedStream", "MapBuilder", "NgramModel", "Node", "PostingsIndex", "Set", "SetStream", "SetSuffixStream", "SetSplitStream", "SetRegexStream", "SetLevStream", "SetSubsequenceStream", "SetCollectedStream", "SetUnion", "SetIntersection", "SetDifference", "SetSymmetricDifference", "SetBuilder", "SpellChecker", "geohash_encode", "geohash_decode", "pack_kmer", "unpack_kmer", "kmer_codes", "lossy_decodes", "reset_lossy_decodes", "build_file", "merge_files", "compare", "set_trace_hook"]
+
+class AliasedMap:
+    def __init__(self, main: Map, aliases: Map) -> None: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
+    def __getitem__(self, key: Union[str, bytes]) -> int: ...
+    def get(self, key: Union[str, bytes], default: Optional[int] = None) -> Optional[int]: ...
+    def canonicalize(self, key: Union[str, bytes]) -> Optional[str]: ...
+
+class ArrowStream:
+    def __arrow_c_stream__(self, requested_schema: Optional[Any] = None) -> Any: ...
+
+class Autocomplete:
+    def __init__(self, map: Map) -> None: ...
+    def complete(self, prefix: Union[str, bytes], k: int = 10, fuzzy: int = 0) -> List[Tuple[str, Union[int, float]]]: ...
+
+class SetCollection:
+    def __init__(self, segments: Iterable[Set]) -> None: ...
+    @property
+    def num_segments(self) -> int: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
+    def __iter__(self) -> SetUnion: ...
+    def range(self, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> SetUnion: ...
+    def search_prefix(self, prefix: Union[str, bytes], limit: Optional[int] = None, offset: int = 0) -> SetUnion: ...
+    def search_re(self, regex: Union[str, bytes], prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, match_mode: str = 'anchored', case_insensitive: bool = False, limit: Optional[int] = None, offset: int = 0) -> Iterator[str]: ...
+    def search_lev(self, key: Union[str, bytes], max_dist: int, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[str]: ...
+    def compact(self, path: Optional[str] = None) -> Set: ...
+
+class MapCollection:
+    def __init__(self, segments: Iterable[Map], merge: str = 'last') -> None: ...
+    @property
+    def num_segments(self) -> int: ...
+    @property
+    def value_type(self) -> str: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
+    def __getitem__(self, key: Union[str, bytes]) -> Union[int, float]: ...
+    def get(self, key: Union[str, bytes], default: Optional[Any] = None) -> Optional[Union[int, float]]: ...
+    def __iter__(self) -> MapCollectionKeys: ...
+    def keys(self) -> MapCollectionKeys: ...
+    def items(self, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> MapUnion: ...
+    def range(self, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> MapUnion: ...
+    def search_prefix(self, prefix: Union[str, bytes], limit: Optional[int] = None, offset: int = 0) -> MapUnion: ...
+    def search_re(self, regex: Union[str, bytes], prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, match_mode: str = 'anchored', case_insensitive: bool = False, limit: Optional[int] = None, offset: int = 0) -> Iterator[Tuple[str, Union[int, float]]]: ...
+    def search_lev(self, key: Union[str, bytes], max_dist: int, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[Tuple[str, Union[int, float]]]: ...
+    def compact(self, path: Optional[str] = None) -> Map: ...
+
+class MapCollectionKeys(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> MapCollectionKeys: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapCollectionKeys: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class DomainMatcher:
+    def __init__(self, set: Set) -> None: ...
+    @staticmethod
+    def build(rules: Iterable[str], path: Optional[str] = None) -> DomainMatcher: ...
+    def match_rule(self, domain: str) -> Optional[str]: ...
+    def is_blocked(self, domain: str) -> bool: ...
+    def __contains__(self, domain: str) -> bool: ...
+    def __len__(self) -> int: ...
+
+class FstError(ValueError): ...
+
+class OutOfOrderError(FstError): ...
+
+class FormatError(FstError): ...
+
+class RegexTooLargeError(FstError): ...
+
+class LevenshteinTooLargeError(FstError): ...
+
+class ClosedError(FstError): ...
+
+class GeoIndex:
+    def __init__(self, lexicon: Union[Set, Map]) -> None: ...
+    def within_cell(self, geohash: str) -> Union[List[str], List[Tuple[str, Union[int, float]]]]: ...
+    def near(self, lat: float, lon: float, precision: int = 6) -> Union[List[str], List[Tuple[str, Union[int, float]]]]: ...
+
+class IpMatcher:
+    def __init__(self, map: Map) -> None: ...
+    @staticmethod
+    def build(networks: Iterable[Tuple[Any, int]], path: Optional[str] = None) -> IpMatcher: ...
+    def lookup(self, ip: Any) -> Optional[int]: ...
+    def __len__(self) -> int: ...
+
+class KeyValueStore:
+    def __init__(self, path: str) -> None: ...
+    @staticmethod
+    def build(items: Iterable[Tuple[Union[str, bytes], bytes]], path: Optional[str] = None) -> KeyValueStore: ...
+    def __getitem__(self, key: Union[str, bytes]) -> bytes: ...
+    def get(self, key: Union[str, bytes], default: Optional[Any] = None) -> Optional[bytes]: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
     def __len__(self) -> int: ...
-    def get(self, key: str, default: Optional[int] = None) -> Optional[int]: ...
+    def __iter__(self) -> KeyValueStream: ...
     def keys(self) -> Iterator[str]: ...
-    def values(self) -> Iterator[int]: ...
-    def items(self) -> Iterator[Tuple[str, int]]: ...
-    def search_re(self, regex: str) -> Iterator[Tuple[str, int]]: ...
-    def search_lev(self, key: str, max_dist: int) -> Iterator[Tuple[str, int]]: ...
+    def items(self, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None) -> Iterator[Tuple[str, bytes]]: ...
+    @property
+    def payload_bytes(self) -> int: ...
+
+class KeyValueStream(Iterator[Any]):
+    def __iter__(self) -> KeyValueStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> KeyValueStream: ...
+    def __next__(self) -> Any: ...
+
+class LevenshteinQuery:
+    def __init__(self, key: Union[str, bytes], max_dist: int, state_limit: Optional[int] = None) -> None: ...
+    @property
+    def key(self) -> str: ...
+    @property
+    def max_dist(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+class Map:
+    def __init__(self, path: Union[str, bytes, bytearray, memoryview], cache_size: Optional[int] = None, counters: bool = False, value_type: str = 'u64') -> None: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
+    def __getitem__(self, key: Union[str, bytes]) -> Union[int, float]: ...
+    @property
+    def value_type(self) -> str: ...
+    def __len__(self) -> int: ...
+    def __richcmp__(self, other: Any, op: CompareOp) -> Any: ...
+    def is_empty(self) -> bool: ...
+    @classmethod
+    def empty(cls) -> Map: ...
+    def __repr__(self) -> str: ...
+    def close(self) -> None: ...
+    @property
+    def closed(self) -> bool: ...
+    def __enter__(self) -> Map: ...
+    def __exit__(self, _exc_type: Any, _exc_value: Any, _traceback: Any) -> bool: ...
+    def __sizeof__(self) -> int: ...
+    def __reduce__(self) -> Tuple[Any, Tuple[Union[str, bytes], Optional[int], bool, str]]: ...
+    def get_index(self, key: Union[str, bytes]) -> int: ...
+    def get_key(self, index: int) -> str: ...
+    def sample(self, k: int, seed: Optional[int] = None) -> List[Tuple[str, Union[int, float]]]: ...
+    def __copy__(self) -> Map: ...
+    def __deepcopy__(self, memo: Any) -> Map: ...
+    def stats(self) -> Dict[str, Union[int, str]]: ...
+    def verify(self) -> None: ...
+    def root(self) -> Node: ...
+    def key_length_stats(self) -> Dict[str, Any]: ...
+    def prefix_histogram(self, depth: int = 1) -> Dict[str, int]: ...
+    def counters(self) -> Dict[str, int]: ...
+    def reset_counters(self) -> None: ...
+    def memory_usage(self) -> Dict[str, int]: ...
+    def residency(self) -> float: ...
+    def get(self, key: Union[str, bytes], default: Optional[Any] = None) -> Optional[Union[int, float]]: ...
+    def get_many(self, keys: Iterable[Union[str, bytes]], default: Optional[Any] = None, numpy: bool = False) -> Union[List[Optional[Union[int, float]]], Any]: ...
+    def __iter__(self) -> MapKeys: ...
+    def keys(self) -> MapKeysView: ...
+    def keys_split(self, prefix_len: int, limit: Optional[int] = None, offset: int = 0) -> MapSplitKeys: ...
+    def values(self) -> MapValuesView: ...
+    def items(self, reverse: bool = False) -> MapItemsView: ...
+    def range(self, ge: Optional[Union[str, bytes]] = None, gt: Optional[Union[str, bytes]] = None, le: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, reverse: bool = False, limit: Optional[int] = None, offset: int = 0, min_value: Optional[Union[int, float]] = None, max_value: Optional[Union[int, float]] = None) -> MapItems: ...
+    def search_prefix(self, prefix: Union[str, bytes], reverse: bool = False, limit: Optional[int] = None, offset: int = 0, min_value: Optional[Union[int, float]] = None, max_value: Optional[Union[int, float]] = None) -> MapItems: ...
+    def longest_prefix(self, text: Union[str, bytes]) -> Optional[Tuple[str, Union[int, float]]]: ...
+    def segment(self, text: str) -> List[Tuple[int, int, Optional[Union[int, float]]]]: ...
+    def all_prefixes(self, text: Union[str, bytes]) -> List[Tuple[str, Union[int, float]]]: ...
+    def first(self) -> Optional[Tuple[str, Union[int, float]]]: ...
+    def last(self) -> Optional[Tuple[str, Union[int, float]]]: ...
+    def floor(self, key: Union[str, bytes]) -> Optional[Tuple[str, Union[int, float]]]: ...
+    def ceiling(self, key: Union[str, bytes]) -> Optional[Tuple[str, Union[int, float]]]: ...
+    def rebuild(self, add: Optional[Iterable[Tuple[Union[str, bytes], Union[int, float]]]] = None, remove: Optional[Iterable[Union[str, bytes]]] = None, path: Optional[str] = None) -> Map: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def to_arrays(self) -> Tuple[Any, Any]: ...
+    def to_series(self, name: Optional[Any] = None) -> Any: ...
+    def to_frame(self, key_column: str = 'key', value_column: str = 'value') -> Any: ...
+    @classmethod
+    def from_iter(cls, iterable: Iterable[Tuple[Union[str, bytes], Union[int, float]]], path: Optional[str] = None, sorted: bool = False, value_type: str = 'u64') -> Map: ...
+    @classmethod
+    def from_series(cls, series: Any, path: Optional[str] = None, value_type: str = 'u64') -> Map: ...
+    @classmethod
+    def from_frame(cls, frame: Any, key_column: str = 'key', value_column: str = 'value', path: Optional[str] = None, value_type: str = 'u64') -> Map: ...
+    def values_array(self, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None) -> Any: ...
+    def write_jsonl(self, path: str, key_field: str = 'key', value_field: str = 'value') -> None: ...
+    def to_bytes(self) -> bytes: ...
+    def write(self, path: str) -> None: ...
+    def export_dot(self, path: str, max_nodes: int = 1000) -> None: ...
+    def to_arrow(self, batch_size: int = 65536) -> Any: ...
+    def search_re(self, regex: Union[str, bytes], parallel: bool = False, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, match_mode: str = 'anchored', case_insensitive: bool = False, limit: Optional[int] = None, offset: int = 0, min_value: Optional[Union[int, float]] = None, max_value: Optional[Union[int, float]] = None) -> Iterator[Tuple[str, int]]: ...
+    def search_subsequence(self, query: str, limit: Optional[int] = None, offset: int = 0) -> MapSubsequenceStream: ...
+    def search_lev(self, key: Union[str, bytes], max_dist: int, parallel: bool = False, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0, min_value: Optional[Union[int, float]] = None, max_value: Optional[Union[int, float]] = None) -> Iterator[Tuple[str, int]]: ...
+    def search(self, query: LevenshteinQuery, parallel: bool = False, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[Tuple[str, int]]: ...
+    def search_custom(self, automaton: Any, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[Tuple[str, int]]: ...
+    def aget_many(self, keys: Iterable[Union[str, bytes]], default: Optional[Any] = None) -> Awaitable[List[Optional[Union[int, float]]]]: ...
+    def diff(self, other: Map, limit: Optional[int] = None, offset: int = 0) -> MapDiff: ...
+    def union(self, *others: Map, merge: str = 'first', limit: Optional[int] = None, offset: int = 0) -> MapUnion: ...
+    def intersection(self, *others: Map, merge: str = 'first', limit: Optional[int] = None, offset: int = 0) -> MapIntersection: ...
+    def difference(self, *others: Map, limit: Optional[int] = None, offset: int = 0) -> MapDifference: ...
+    def symmetric_difference(self, *others: Map, merge: str = 'first', limit: Optional[int] = None, offset: int = 0) -> MapSymmetricDifference: ...
+    def asearch_re(self, regex: Union[str, bytes], parallel: bool = False) -> Awaitable[List[Tuple[str, int]]]: ...
+    def asearch_lev(self, key: Union[str, bytes], max_dist: int, parallel: bool = False) -> Awaitable[List[Tuple[str, int]]]: ...
+    def count_re(self, regex: Union[str, bytes], parallel: bool = False, match_mode: str = 'anchored', case_insensitive: bool = False) -> int: ...
+    def count_lev(self, key: Union[str, bytes], max_dist: int, parallel: bool = False) -> int: ...
+    def count(self, prefix: Optional[Union[str, bytes]] = None, regex: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None) -> int: ...
+
+class MapKeysView:
+    def __iter__(self) -> MapKeys: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
+    def __richcmp__(self, other: Any, op: CompareOp) -> Any: ...
+    def isdisjoint(self, other: Any) -> bool: ...
+    def __and__(self, other: Any) -> Any: ...
+    def __rand__(self, other: Any) -> Any: ...
+    def __or__(self, other: Any) -> Any: ...
+    def __ror__(self, other: Any) -> Any: ...
+    def __sub__(self, other: Any) -> Any: ...
+    def __rsub__(self, other: Any) -> Any: ...
+    def __xor__(self, other: Any) -> Any: ...
+    def __rxor__(self, other: Any) -> Any: ...
+    def __arrow_c_stream__(self, requested_schema: Optional[Any] = None) -> Any: ...
+
+class MapValuesView:
+    def __iter__(self) -> MapValues: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, value: Any) -> bool: ...
+
+class MapItemsView:
+    def __iter__(self) -> MapItems: ...
+    def __reversed__(self) -> MapItems: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, item: Any) -> bool: ...
+    def __richcmp__(self, other: Any, op: CompareOp) -> Any: ...
+    def isdisjoint(self, other: Any) -> bool: ...
+    def __and__(self, other: Any) -> Any: ...
+    def __rand__(self, other: Any) -> Any: ...
+    def __or__(self, other: Any) -> Any: ...
+    def __ror__(self, other: Any) -> Any: ...
+    def __sub__(self, other: Any) -> Any: ...
+    def __rsub__(self, other: Any) -> Any: ...
+    def __xor__(self, other: Any) -> Any: ...
+    def __rxor__(self, other: Any) -> Any: ...
+
+class MapKeys(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> MapKeys: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapKeys: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class MapSplitKeys(Iterator[Tuple[str, str]]):
+    def __iter__(self) -> MapSplitKeys: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def __next__(self) -> Tuple[str, str]: ...
+
+class MapValues(Iterator[Any]):
+    def __iter__(self) -> MapValues: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def __next__(self) -> Any: ...
+
+class MapItems(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapItems: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapItems: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapRegexStream(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapRegexStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapRegexStream: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapLevStream(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapLevStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapLevStream: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapDiff(Iterator[Tuple[Union[str, bytes], Optional[Any], Optional[Any]]]):
+    def __iter__(self) -> MapDiff: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapDiff: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Optional[Any], Optional[Any]]: ...
+
+class MapUnion(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapUnion: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapUnion: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def into_map(self, path: Optional[str] = None) -> Map: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapIntersection(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapIntersection: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapIntersection: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def into_map(self, path: Optional[str] = None) -> Map: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapDifference(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapDifference: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapDifference: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def into_map(self, path: Optional[str] = None) -> Map: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapSymmetricDifference(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapSymmetricDifference: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapSymmetricDifference: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def into_map(self, path: Optional[str] = None) -> Map: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapSubsequenceStream(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapSubsequenceStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapSubsequenceStream: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
+
+class MapCollectedStream(Iterator[Tuple[Union[str, bytes], Any]]):
+    def __iter__(self) -> MapCollectedStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> MapCollectedStream: ...
+    def to_dict(self) -> Dict[Union[str, bytes], Union[int, float]]: ...
+    def __next__(self) -> Tuple[Union[str, bytes], Any]: ...
 
 class MapBuilder:
-    def __init__(self, path: Optional[str] = None) -> None: ...
-    def insert(self, key: str, val: int) -> None: ...
+    def __init__(self, path: Union[str, BinaryIO, None] = None, value_type: str = 'u64') -> None: ...
+    def insert(self, key: Union[str, bytes], val: Union[int, float]) -> None: ...
+    def insert_from_file(self, path: str, delimiter: str = '\t') -> int: ...
     def finish(self) -> Optional[Map]: ...
 
+class NgramModel:
+    def __init__(self, map: Map) -> None: ...
+    @staticmethod
+    def build(ngrams: Iterable[Tuple[Union[str, Sequence[str]], int]], path: Optional[str] = None) -> NgramModel: ...
+    def next_tokens(self, context: Union[str, Sequence[str]], k: int = 10) -> List[Tuple[str, int]]: ...
+    def count(self, ngram: Union[str, Sequence[str]]) -> int: ...
+    def __len__(self) -> int: ...
+
+class Node:
+    @property
+    def addr(self) -> int: ...
+    @property
+    def is_final(self) -> bool: ...
+    @property
+    def final_output(self) -> int: ...
+    def __len__(self) -> int: ...
+    def transitions(self) -> List[Tuple[int, int, Node]]: ...
+    def transition(self, index: int) -> Tuple[int, int, Node]: ...
+    def find_input(self, byte: int) -> Optional[int]: ...
+    def follow(self, byte: int) -> Optional[Tuple[int, Node]]: ...
+    def walk(self, key: bytes) -> Optional[Tuple[int, Node]]: ...
+    def __repr__(self) -> str: ...
+
+class PostingsIndex:
+    def __init__(self, path: str) -> None: ...
+    @staticmethod
+    def build(pairs: Iterable[Tuple[Union[str, bytes], int]], path: Optional[str] = None) -> PostingsIndex: ...
+    def postings(self, term: Union[str, bytes]) -> Any: ...
+    def intersect(self, terms: Iterable[Union[str, bytes]]) -> Any: ...
+    def union(self, terms: Iterable[Union[str, bytes]]) -> Any: ...
+    def __contains__(self, term: Union[str, bytes]) -> bool: ...
+    def __len__(self) -> int: ...
+
 class Set:
-    def __init__(self, path: Union[str, bytes]) -> None: ...
-    def __contains__(self, key: str) -> bool: ...
-    def __len__(self) -> int: ...
-    def __iter__(self) -> Iterator[str]: ...
-    def search_re(self, regex: str) -> Iterator[str]: ...
-    def search_lev(self, key: str, max_dist: int) -> Iterator[str]: ...
-    def is_disjoint(self, other: 'Set') -> bool: ...
-    def is_subset(self, other: 'Set') -> bool: ...
-    def is_superset(self, other: 'Set') -> bool: ...
-    def union(self, other: 'Set') -> Iterator[str]: ...
-    def intersection(self, other: 'Set') -> Iterator[str]: ...
-    def difference(self, other: 'Set') -> Iterator[str]: ...
-    def symmetric_difference(self, other: 'Set') -> Iterator[str]: ...
+    def __init__(self, path: Union[str, bytes, bytearray, memoryview], cache_size: Optional[int] = None, counters: bool = False) -> None: ...
+    def __contains__(self, key: Union[str, bytes]) -> bool: ...
+    def contains_many(self, keys: Iterable[Union[str, bytes]], numpy: bool = False) -> Union[List[bool], Any]: ...
+    def __len__(self) -> int: ...
+    def is_empty(self) -> bool: ...
+    @classmethod
+    def empty(cls) -> Set: ...
+    def __repr__(self) -> str: ...
+    def close(self) -> None: ...
+    @property
+    def closed(self) -> bool: ...
+    def __enter__(self) -> Set: ...
+    def __exit__(self, _exc_type: Any, _exc_value: Any, _traceback: Any) -> bool: ...
+    def __sizeof__(self) -> int: ...
+    def __reduce__(self) -> Tuple[Any, Tuple[Union[str, bytes], Optional[int], bool]]: ...
+    def get_index(self, key: Union[str, bytes]) -> int: ...
+    def get_key(self, index: int) -> str: ...
+    def sample(self, k: int, seed: Optional[int] = None) -> List[str]: ...
+    def __copy__(self) -> Set: ...
+    def __deepcopy__(self, memo: Any) -> Set: ...
+    def stats(self) -> Dict[str, Union[int, str]]: ...
+    def verify(self) -> None: ...
+    def root(self) -> Node: ...
+    def key_length_stats(self) -> Dict[str, Any]: ...
+    def prefix_histogram(self, depth: int = 1) -> Dict[str, int]: ...
+    def counters(self) -> Dict[str, int]: ...
+    def reset_counters(self) -> None: ...
+    def memory_usage(self) -> Dict[str, int]: ...
+    def residency(self) -> float: ...
+    def __iter__(self) -> SetStream: ...
+    def __reversed__(self) -> SetStream: ...
+    def iter(self, reverse: bool = False) -> SetStream: ...
+    def range(self, ge: Optional[Union[str, bytes]] = None, gt: Optional[Union[str, bytes]] = None, le: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, reverse: bool = False, limit: Optional[int] = None, offset: int = 0) -> SetStream: ...
+    def search_prefix(self, prefix: Union[str, bytes], reverse: bool = False, limit: Optional[int] = None, offset: int = 0) -> SetStream: ...
+    def longest_prefix(self, text: Union[str, bytes]) -> Optional[str]: ...
+    def all_prefixes(self, text: Union[str, bytes]) -> List[str]: ...
+    def first(self) -> Optional[str]: ...
+    def last(self) -> Optional[str]: ...
+    def floor(self, key: Union[str, bytes]) -> Optional[str]: ...
+    def ceiling(self, key: Union[str, bytes]) -> Optional[str]: ...
+    def rebuild(self, add: Optional[Iterable[Union[str, bytes]]] = None, remove: Optional[Iterable[Union[str, bytes]]] = None, path: Optional[str] = None) -> Set: ...
+    def __arrow_c_stream__(self, requested_schema: Optional[Any] = None) -> Any: ...
+    def to_list(self) -> List[str]: ...
+    def to_frozenset(self) -> FrozenSet[str]: ...
+    def keys_array(self, width: Optional[int] = None, dtype: str = 'S') -> Any: ...
+    def to_index(self, name: Optional[Any] = None) -> Any: ...
+    @classmethod
+    def from_index(cls, index: Any, path: Optional[str] = None) -> Set: ...
+    @classmethod
+    def from_iter(cls, iterable: Iterable[Union[str, bytes]], path: Optional[str] = None, sorted: bool = False) -> Set: ...
+    @classmethod
+    def from_kmers(cls, codes: Any, k: int, path: Optional[str] = None) -> Set: ...
+    def contains_kmers(self, codes: Any, k: int) -> Any: ...
+    def write_lines(self, path: str) -> None: ...
+    def write_binary(self, path: str) -> None: ...
+    def to_bytes(self) -> bytes: ...
+    def write(self, path: str) -> None: ...
+    def export_dot(self, path: str, max_nodes: int = 1000) -> None: ...
+    def iter_split(self, prefix_len: int, limit: Optional[int] = None, offset: int = 0) -> SetSplitStream: ...
+    def enable_suffix_search(self, path: Optional[str] = None) -> None: ...
+    def ends_with(self, suffix: Union[str, bytes], limit: Optional[int] = None, offset: int = 0) -> SetSuffixStream: ...
+    def enable_substring_search(self, path: Optional[str] = None) -> None: ...
+    def contains_substring(self, substring: Union[str, bytes]) -> List[str]: ...
+    def enable_translit(self, scheme: Union[str, Dict[str, str], Callable[[str], str]], path: Optional[str] = None) -> None: ...
+    def search_translit(self, query: str, limit: Optional[int] = None, offset: int = 0) -> List[str]: ...
+    def enable_normalized_search(self, normalizer: Callable[[str], str], path: Optional[str] = None) -> None: ...
+    def search_normalized(self, term: str, limit: Optional[int] = None, offset: int = 0) -> List[str]: ...
+    def segment(self, text: str) -> List[Tuple[int, int]]: ...
+    def search_re(self, regex: Union[str, bytes], parallel: bool = False, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, match_mode: str = 'anchored', case_insensitive: bool = False, limit: Optional[int] = None, offset: int = 0) -> Iterator[str]: ...
+    def search_subsequence(self, query: str, limit: Optional[int] = None, offset: int = 0) -> SetSubsequenceStream: ...
+    def search_lev(self, key: Union[str, bytes], max_dist: int, parallel: bool = False, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[str]: ...
+    def search(self, query: LevenshteinQuery, parallel: bool = False, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[str]: ...
+    def search_custom(self, automaton: Any, prefix: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None, limit: Optional[int] = None, offset: int = 0) -> Iterator[str]: ...
+    def asearch_re(self, regex: Union[str, bytes], parallel: bool = False) -> Awaitable[List[str]]: ...
+    def asearch_lev(self, key: Union[str, bytes], max_dist: int, parallel: bool = False) -> Awaitable[List[str]]: ...
+    def count_re(self, regex: Union[str, bytes], parallel: bool = False, match_mode: str = 'anchored', case_insensitive: bool = False) -> int: ...
+    def count_lev(self, key: Union[str, bytes], max_dist: int, parallel: bool = False) -> int: ...
+    def count(self, prefix: Optional[Union[str, bytes]] = None, regex: Optional[Union[str, bytes]] = None, ge: Optional[Union[str, bytes]] = None, lt: Optional[Union[str, bytes]] = None) -> int: ...
+    def is_disjoint(self, other: Set) -> bool: ...
+    def isdisjoint(self, other: Iterable[Union[str, bytes]]) -> bool: ...
+    def __and__(self, other: Any) -> Any: ...
+    def __rand__(self, other: Any) -> Any: ...
+    def __or__(self, other: Any) -> Any: ...
+    def __ror__(self, other: Any) -> Any: ...
+    def __sub__(self, other: Any) -> Any: ...
+    def __rsub__(self, other: Any) -> Any: ...
+    def __xor__(self, other: Any) -> Any: ...
+    def __rxor__(self, other: Any) -> Any: ...
+    def is_subset(self, other: Set) -> bool: ...
+    def is_superset(self, other: Set) -> bool: ...
+    def __richcmp__(self, other: Any, op: CompareOp) -> Any: ...
+    def __hash__(self) -> int: ...
+    def union(self, *others: Set, limit: Optional[int] = None, offset: int = 0) -> SetUnion: ...
+    def intersection(self, *others: Set, limit: Optional[int] = None, offset: int = 0) -> SetIntersection: ...
+    def difference(self, *others: Set, limit: Optional[int] = None, offset: int = 0) -> SetDifference: ...
+    def symmetric_difference(self, *others: Set, limit: Optional[int] = None, offset: int = 0) -> SetSymmetricDifference: ...
+
+class SetStream(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetStream: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetSuffixStream(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetSuffixStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetSuffixStream: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetSplitStream(Iterator[Tuple[str, str]]):
+    def __iter__(self) -> SetSplitStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def __next__(self) -> Tuple[str, str]: ...
+
+class SetRegexStream(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetRegexStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetRegexStream: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetLevStream(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetLevStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetLevStream: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetSubsequenceStream(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetSubsequenceStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetSubsequenceStream: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetCollectedStream(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetCollectedStream: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetCollectedStream: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetUnion(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetUnion: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetUnion: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def into_set(self, path: Optional[str] = None) -> Set: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetIntersection(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetIntersection: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetIntersection: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def into_set(self, path: Optional[str] = None) -> Set: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetDifference(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetDifference: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetDifference: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def into_set(self, path: Optional[str] = None) -> Set: ...
+    def __next__(self) -> Union[str, bytes]: ...
+
+class SetSymmetricDifference(Iterator[Union[str, bytes]]):
+    def __iter__(self) -> SetSymmetricDifference: ...
+    def __length_hint__(self) -> Union[int, NotImplementedType]: ...
+    def raw(self) -> SetSymmetricDifference: ...
+    def to_list(self) -> List[Union[str, bytes]]: ...
+    def into_set(self, path: Optional[str] = None) -> Set: ...
+    def __next__(self) -> Union[str, bytes]: ...
 
 class SetBuilder:
-    def __init__(self, path: Optional[str] = None) -> None: ...
-    def insert(self, key: str) -> None: ...
+    def __init__(self, path: Union[str, BinaryIO, None] = None) -> None: ...
+    def insert(self, key: Union[str, bytes]) -> None: ...
+    def insert_from_file(self, path: str) -> int: ...
     def finish(self) -> Optional[Set]: ...
+
+class SpellChecker:
+    def __init__(self, lexicon: Union[Set, Map]) -> None: ...
+    def suggest(self, word: Union[str, bytes], max_dist: int = 2, limit: int = 10) -> List[Tuple[str, int]]: ...
+    def did_you_mean(self, query: Union[str, bytes], max_dist: int = 2) -> Optional[Tuple[str, float]]: ...
+
+def geohash_encode(lat: float, lon: float, precision: int = 9) -> str: ...
+
+def geohash_decode(geohash: str) -> Tuple[float, float]: ...
+
+def pack_kmer(seq: str) -> bytes: ...
+
+def unpack_kmer(key: bytes, k: int) -> str: ...
+
+def kmer_codes(seq: str, k: int) -> Any: ...
+
+def lossy_decodes() -> Tuple[int, List[bytes]]: ...
+
+def reset_lossy_decodes() -> None: ...
+
+def build_file(input: str, output: str, map: bool = False, delimiter: str = '\t', sorted: bool = False) -> int: ...
+
+def merge_files(inputs: List[str], output: str) -> int: ...
+
+def compare(path_a: str, path_b: str, limit: int = 10) -> Tuple[bool, List[Tuple[str, Optional[int], Optional[int]]]]: ...
+
+def set_trace_hook(callback: Optional[Callable[[str, float, int], Any]], threshold: float = 0.0) -> None: ...
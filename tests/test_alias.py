@@ -0,0 +1,89 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import AliasedMap, Map, MapBuilder
+
+
+def build_map(items):
+    builder = MapBuilder(None)
+    for key, value in sorted(items):
+        builder.insert(key, value)
+    return builder.finish()
+
+
+@pytest.fixture
+def countries():
+    main = build_map([("Germany", 1), ("United Kingdom", 2),
+                      ("United States", 3)])
+    aliases = build_map([("Deutschland", 1), ("UK", 2), ("USA", 3),
+                         ("United States", 9)])
+    return AliasedMap(main, aliases)
+
+
+def test_lookup(countries):
+    assert countries["Germany"] == 1
+    assert countries["Deutschland"] == 1
+    assert countries.get("USA") == 3
+    assert countries.get("France") is None
+    assert countries.get("France", 0) == 0
+    with pytest.raises(KeyError):
+        countries["France"]
+
+
+def test_canonical_takes_precedence(countries):
+    assert countries["United States"] == 3
+
+
+def test_contains(countries):
+    assert "UK" in countries
+    assert "United Kingdom" in countries
+    assert "France" not in countries
+
+
+def test_canonicalize(countries):
+    assert countries.canonicalize("UK") == "United Kingdom"
+    assert countries.canonicalize("Deutschland") == "Germany"
+    assert countries.canonicalize("Germany") == "Germany"
+    assert countries.canonicalize("France") is None
+
+
+def test_canonicalize_across_threads():
+    # The first alias resolved builds the index of canonical keys with the
+    # GIL released; other threads arriving meanwhile must not deadlock.
+    import threading
+    from rust_fst import Map
+    main = Map.from_iter(("%07d" % i, i) for i in range(200000))
+    aliases = AliasedMap(main, Map.from_iter([("five", 5)]))
+    barrier = threading.Barrier(4)
+    found = []
+
+    def resolve():
+        barrier.wait()
+        found.append(aliases.canonicalize("five"))
+
+    threads = [threading.Thread(target=resolve, daemon=True) for _ in range(4)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join(60)
+    assert found == ["0000005"] * 4
+
+
+def test_canonicalize_dangling_alias():
+    aliases = AliasedMap(build_map([("a", 1)]), build_map([("b", 2)]))
+    assert aliases["b"] == 2
+    assert aliases.canonicalize("b") is None
+
+
+def test_closed_map():
+    main = build_map([("a", 1)])
+    main.close()
+    with pytest.raises(ValueError):
+        AliasedMap(main, build_map([]))
+
+
+def test_non_u64_ids():
+    signed = Map.from_iter([("a", -1)], value_type="i64")
+    with pytest.raises(ValueError):
+        AliasedMap(signed, build_map([]))
+    with pytest.raises(ValueError):
+        AliasedMap(build_map([]), signed)
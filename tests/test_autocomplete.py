@@ -0,0 +1,44 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import Autocomplete, Map, MapBuilder
+
+TERMS = [(u"python", 900), (u"pytorch", 700), (u"pyramid", 40),
+         (u"pylint", 40), (u"perl", 300), (u"rust", 800)]
+
+
+@pytest.fixture
+def completer():
+    builder = MapBuilder()
+    for term, weight in sorted(TERMS):
+        builder.insert(term, weight)
+    return Autocomplete(builder.finish())
+
+
+def test_complete_top_k(completer):
+    assert completer.complete("py", k=2) == [("python", 900), ("pytorch", 700)]
+    assert completer.complete("py") == [
+        ("python", 900), ("pytorch", 700), ("pylint", 40), ("pyramid", 40)]
+    assert completer.complete("p", k=3) == [
+        ("python", 900), ("pytorch", 700), ("perl", 300)]
+
+
+def test_complete_no_match(completer):
+    assert completer.complete("java") == []
+    assert completer.complete("py", k=0) == []
+
+
+def test_complete_fuzzy(completer):
+    assert completer.complete("pyht", k=1) == []
+    assert completer.complete("pyht", k=1, fuzzy=1) == [("python", 900)]
+    assert completer.complete("rsut", fuzzy=2) == [("rust", 800)]
+
+
+def test_complete_value_types():
+    signed = Map.from_iter([(u"peach", -3), (u"pear", -1), (u"pecan", 2)],
+                           value_type="i64")
+    assert Autocomplete(signed).complete(u"pe") == [
+        (u"pecan", 2), (u"pear", -1), (u"peach", -3)]
+    floats = Map.from_iter([(u"peach", -3.0), (u"pear", -0.5), (u"pecan", 2.5)],
+                           value_type="f64")
+    assert Autocomplete(floats).complete(u"pe", k=2) == [
+        (u"pecan", 2.5), (u"pear", -0.5)]
@@ -0,0 +1,99 @@
+# -*- coding: utf-8 -*-
+import gzip
+import pytest
+from rust_fst import Map, Set, compare
+from rust_fst.__main__ import main
+
+
+@pytest.fixture
+def words(tmpdir):
+    path = tmpdir.join('words.txt')
+    path.write_text(u"foo\nbar\nmöö\n\nbaz\nbar\n", "utf8")
+    return str(path)
+
+
+@pytest.fixture
+def counts(tmpdir):
+    path = tmpdir.join('counts.csv')
+    path.write_text(u"foo,3\nbar,1\nbaz,2\n", "utf8")
+    return str(path)
+
+
+def test_build_set(words, tmpdir, capsys):
+    out = str(tmpdir.join('words.fst'))
+    assert main(["build", words, out]) == 0
+    assert list(Set(out)) == ["bar", "baz", "foo", u"möö"]
+    assert "wrote 4 keys" in capsys.readouterr().err
+
+
+def test_build_map_csv(counts, tmpdir, capsys):
+    out = str(tmpdir.join('counts.fst'))
+    assert main(["build", "--map", counts, out]) == 0
+    assert dict(Map(out).items()) == {"foo": 3, "bar": 1, "baz": 2}
+
+
+def test_build_map_csv_gz(tmpdir, capsys):
+    path = str(tmpdir.join('counts.csv.gz'))
+    with gzip.open(path, "wt") as f:
+        f.write(u"foo,3\nbar,1\n")
+    out = str(tmpdir.join('counts.fst'))
+    assert main(["build", "--map", path, out]) == 0
+    assert dict(Map(out).items()) == {"foo": 3, "bar": 1}
+
+
+def test_build_bad_value(tmpdir, capsys):
+    path = tmpdir.join('bad.tsv')
+    path.write_text(u"foo\tx\n", "utf8")
+    assert main(["build", "--map", str(path),
+                 str(tmpdir.join('bad.fst'))]) == 2
+    assert "line 1: invalid value" in capsys.readouterr().err
+
+
+def test_query_grep_fuzzy(counts, tmpdir, capsys):
+    out = str(tmpdir.join('counts.fst'))
+    main(["build", "--map", counts, out])
+    capsys.readouterr()
+    assert main(["query", out, "foo", "nope"]) == 1
+    captured = capsys.readouterr()
+    assert captured.out == "foo\t3\n"
+    assert "nope: not found" in captured.err
+    assert main(["grep", "--values", out, "ba.*"]) == 0
+    assert capsys.readouterr().out == "bar\t1\nbaz\t2\n"
+    assert main(["fuzzy", out, "bam", "-d", "1"]) == 0
+    assert capsys.readouterr().out == "bar\nbaz\n"
+
+
+def test_merge_diff_stats(tmpdir, capsys):
+    old, new = tmpdir.join('old.tsv'), tmpdir.join('new.tsv')
+    old.write_text(u"a\t1\nb\t2\nc\t3\n", "utf8")
+    new.write_text(u"b\t2\nc\t4\nd\t5\n", "utf8")
+    for path in (old, new):
+        main(["build", "--map", str(path), str(path) + ".fst"])
+    old, new = str(old) + ".fst", str(new) + ".fst"
+    merged = str(tmpdir.join('merged.fst'))
+    assert main(["merge", merged, old, new]) == 0
+    assert dict(Map(merged).items()) == {"a": 1, "b": 2, "c": 4, "d": 5}
+    capsys.readouterr()
+    assert main(["diff", old, new]) == 1
+    assert capsys.readouterr().out == "-a\t1\n~c\t3\t4\n+d\t5\n"
+    assert main(["stats", merged]) == 0
+    assert capsys.readouterr().out.startswith("keys\t4\n")
+
+
+def test_compare(tmpdir, capsys):
+    a, b = tmpdir.join('a.tsv'), tmpdir.join('b.tsv')
+    a.write_text(u"a\t1\nb\t2\nc\t3\n", "utf8")
+    b.write_text(u"b\t2\nc\t4\nd\t5\n", "utf8")
+    for path in (a, b):
+        main(["build", "--map", str(path), str(path) + ".fst"])
+    rebuilt = str(tmpdir.join('a2.fst'))
+    main(["build", "--map", str(a), rebuilt])
+    a, b = str(a) + ".fst", str(b) + ".fst"
+    assert compare(a, rebuilt) == (True, [])
+    assert compare(a, b) == (False, [("a", 1, None), ("c", 3, 4),
+                                     ("d", None, 5)])
+    assert compare(a, b, limit=1) == (False, [("a", 1, None)])
+    capsys.readouterr()
+    assert main(["compare", a, rebuilt]) == 0
+    assert main(["compare", "-n", "2", a, b]) == 1
+    assert capsys.readouterr().out == "a\t1\t-\nc\t3\t4\n"
@@ -0,0 +1,90 @@
+import pytest
+
+from rust_fst import Map, MapCollection, Set, SetCollection
+
+
+@pytest.fixture
+def segments():
+    return SetCollection([
+        Set.from_iter(["apple", "banana", "cherry"]),
+        Set.from_iter(["banana", "date"]),
+        Set.from_iter([]),
+        Set.from_iter(["apricot", "fig"]),
+    ])
+
+
+def test_set_collection(segments):
+    assert segments.num_segments == 4
+    assert "date" in segments and "apricot" in segments
+    assert "grape" not in segments
+    assert list(segments) == [
+        "apple", "apricot", "banana", "cherry", "date", "fig"]
+    assert list(segments.range(ge="b", lt="d")) == ["banana", "cherry"]
+    assert list(segments.range(offset=1, limit=2)) == ["apricot", "banana"]
+    assert list(segments.search_prefix("ap")) == ["apple", "apricot"]
+    assert list(segments.search_re(".*an.*")) == ["banana"]
+    assert list(segments.search_re("a.*", limit=1)) == ["apple"]
+    assert list(segments.search_lev("dare", 1)) == ["date"]
+    assert list(SetCollection([])) == []
+
+
+def test_set_collection_compact(tmpdir, segments):
+    path = str(tmpdir.join("compact.fst"))
+    compacted = segments.compact(path)
+    assert list(compacted) == list(segments)
+    assert list(Set(path)) == list(segments)
+    assert len(segments.compact()) == 6
+
+
+@pytest.fixture
+def counts():
+    return MapCollection([
+        Map.from_iter([("apple", 1), ("banana", 2), ("cherry", 3)]),
+        Map.from_iter([("banana", 20), ("date", 4)]),
+    ])
+
+
+def test_map_collection(counts):
+    assert counts.num_segments == 2
+    assert counts["banana"] == 20
+    assert counts["apple"] == 1
+    assert counts.get("grape", -1) == -1
+    with pytest.raises(KeyError):
+        counts["grape"]
+    assert "date" in counts and "grape" not in counts
+    assert list(counts) == ["apple", "banana", "cherry", "date"]
+    assert counts.keys().to_list() == ["apple", "banana", "cherry", "date"]
+    assert list(counts.items()) == [
+        ("apple", 1), ("banana", 20), ("cherry", 3), ("date", 4)]
+    assert list(counts.items(prefix="b")) == [("banana", 20)]
+    assert list(counts.range(ge="c")) == [("cherry", 3), ("date", 4)]
+    assert list(counts.search_prefix("ch")) == [("cherry", 3)]
+    assert list(counts.search_re("[ab].*")) == [("apple", 1), ("banana", 20)]
+    assert list(counts.search_lev("bandana", 1)) == [("banana", 20)]
+    assert dict(counts.compact().items()) == dict(counts.items())
+
+
+def test_map_collection_merge():
+    maps = [Map.from_iter([("a", 1), ("b", 5)]), Map.from_iter([("a", 2)])]
+    assert MapCollection(maps, merge="first")["a"] == 1
+    assert MapCollection(maps, merge="sum")["a"] == 3
+    assert list(MapCollection(maps, merge="max").items()) == [
+        ("a", 2), ("b", 5)]
+    with pytest.raises(ValueError):
+        MapCollection(maps, merge="median")
+
+
+def test_map_collection_value_types():
+    scores = MapCollection([
+        Map.from_iter([("a", -1.5)], value_type="f64"),
+        Map.from_iter([("a", 0.5), ("b", 2.0)], value_type="f64"),
+    ])
+    assert scores.value_type == "f64"
+    assert scores["a"] == 0.5
+    assert scores.compact().value_type == "f64"
+    with pytest.raises(ValueError):
+        MapCollection([Map.from_iter([("a", 1)]),
+                       Map.from_iter([("a", 1)], value_type="i64")])
+    with pytest.raises(ValueError):
+        MapCollection([Map.from_iter([("a", 1)], value_type="i64")],
+                      merge="sum")
@@ -0,0 +1,47 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import DomainMatcher, Set
+
+RULES = ["ads.example.com", "*.tracker.net", "*.cdn.tracker.net",
+         "Evil.ORG.", "*.evil.org"]
+
+
+@pytest.fixture
+def matcher():
+    return DomainMatcher.build(RULES)
+
+
+def test_exact_rules(matcher):
+    assert matcher.is_blocked("ads.example.com")
+    assert matcher.is_blocked("ADS.example.com.")
+    assert not matcher.is_blocked("example.com")
+    assert not matcher.is_blocked("x.ads.example.com")
+
+
+def test_wildcard_rules(matcher):
+    assert matcher.is_blocked("a.tracker.net")
+    assert matcher.is_blocked("a.b.c.tracker.net")
+    assert not matcher.is_blocked("tracker.net")
+    assert "pixel.tracker.net" in matcher
+    assert "nottracker.net" not in matcher
+
+
+def test_match_rule(matcher):
+    assert matcher.match_rule("x.cdn.tracker.net") == "*.cdn.tracker.net"
+    assert matcher.match_rule("x.tracker.net") == "*.tracker.net"
+    assert matcher.match_rule("evil.org") == "evil.org"
+    assert matcher.match_rule("www.evil.org") == "*.evil.org"
+    assert matcher.match_rule("good.org") is None
+
+
+def test_build_file(tmpdir):
+    path = str(tmpdir.join("rules.fst"))
+    matcher = DomainMatcher.build(RULES + ["ads.example.com"], path)
+    assert len(matcher) == len(RULES)
+    assert DomainMatcher(Set(path)).is_blocked("www.evil.org")
+
+
+def test_invalid_rules():
+    for rule in ("", "a..b", "*.", "a.*.b"):
+        with pytest.raises(ValueError):
+            DomainMatcher.build([rule])
@@ -0,0 +1,61 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import GeoIndex, Map, MapBuilder, SetBuilder, geohash_decode, geohash_encode
+
+# (name, lat, lon)
+PLACES = [("aalborg", 57.04880, 9.92160), ("hals", 57.00000, 10.30000),
+          ("skagen", 57.72090, 10.58390), ("paris", 48.85660, 2.35220)]
+
+
+@pytest.fixture
+def places():
+    builder = MapBuilder()
+    keys = sorted((geohash_encode(lat, lon, 9) + ":" + name, i)
+                  for i, (name, lat, lon) in enumerate(PLACES))
+    for key, value in keys:
+        builder.insert(key, value)
+    return GeoIndex(builder.finish())
+
+
+def test_encode_decode():
+    assert geohash_encode(57.64911, 10.40744, 11) == "u4pruydqqvj"
+    assert geohash_encode(57.64911, 10.40744) == "u4pruydqq"
+    lat, lon = geohash_decode("u4pruydqqvj")
+    assert lat == pytest.approx(57.64911, abs=1e-5)
+    assert lon == pytest.approx(10.40744, abs=1e-5)
+    for args in ((91, 0), (0, 181), (0, 0, 13), (0, 0, 0)):
+        with pytest.raises(ValueError):
+            geohash_encode(*args)
+    with pytest.raises(ValueError):
+        geohash_decode("u4a")
+
+
+def test_within_cell(places):
+    names = [key.split(":")[1] for key, _ in places.within_cell("u4")]
+    assert sorted(names) == ["aalborg", "hals", "skagen"]
+    assert [v for _, v in places.within_cell("u09")] == [3]
+    assert places.within_cell("zz") == []
+
+
+def test_near(places):
+    # Hals is in the cell next to Aalborg's at precision 4
+    lat, lon = PLACES[0][1:]
+    cell = geohash_encode(lat, lon, 4)
+    assert [v for _, v in places.within_cell(cell)] == [0]
+    assert sorted(v for _, v in places.near(lat, lon, 4)) == [0, 1]
+    assert places.near(0.0, 0.0, 5) == []
+
+
+def test_geo_set():
+    builder = SetBuilder()
+    builder.insert(geohash_encode(48.8566, 2.3522))
+    index = GeoIndex(builder.finish())
+    assert index.near(48.8566, 2.3522, 7) == [geohash_encode(48.8566, 2.3522)]
+
+
+def test_geo_value_types():
+    cell = geohash_encode(48.8566, 2.3522)
+    signed = Map.from_iter([(cell, -7)], value_type="i64")
+    assert GeoIndex(signed).within_cell(cell[:4]) == [(cell, -7)]
+    floats = Map.from_iter([(cell, 35.5)], value_type="f64")
+    assert GeoIndex(floats).near(48.8566, 2.3522, 7) == [(cell, 35.5)]
@@ -0,0 +1,49 @@
+# -*- coding: utf-8 -*-
+import ipaddress
+import pytest
+from rust_fst import IpMatcher, Map
+
+NETWORKS = [("10.0.0.0/8", 1), ("10.1.0.0/16", 2), ("10.1.2.0/24", 3),
+            ("192.168.1.1", 4), ("2001:db8::/32", 6), ("::/0", 0)]
+
+
+@pytest.fixture
+def matcher():
+    return IpMatcher.build(NETWORKS)
+
+
+def test_lookup_longest_prefix(matcher):
+    assert matcher.lookup("10.2.3.4") == 1
+    assert matcher.lookup("10.1.3.4") == 2
+    assert matcher.lookup("10.1.2.200") == 3
+    assert matcher.lookup("192.168.1.1") == 4
+    assert matcher.lookup("192.168.1.2") is None
+    assert matcher.lookup("11.0.0.1") is None
+
+
+def test_lookup_ipv6(matcher):
+    assert matcher.lookup("2001:db8::1") == 6
+    assert matcher.lookup("2001:db9::1") == 0
+    assert matcher.lookup(ipaddress.ip_address("2001:db8:ffff::")) == 6
+
+
+def test_build_file(tmpdir):
+    path = str(tmpdir.join("networks.fst"))
+    networks = [(ipaddress.ip_network("172.16.0.0/12"), 7),
+                ("172.16.0.0/12", 8), ("172.16.5.5/16", 9)]
+    matcher = IpMatcher.build(networks, path)
+    assert len(matcher) == 2
+    reopened = IpMatcher(Map(path))
+    for m in (matcher, reopened):
+        assert m.lookup("172.20.0.1") == 8
+        assert m.lookup("172.16.9.9") == 9
+
+
+def test_invalid(matcher):
+    for network in ("10.0.0.0/33", "10.0.0/8", "nope", "::/129"):
+        with pytest.raises(ValueError):
+            IpMatcher.build([(network, 1)])
+    with pytest.raises(ValueError):
+        matcher.lookup("10.0.0.0/8")
+    with pytest.raises(ValueError):
+        IpMatcher(Map.from_iter([("a", -1)], value_type="i64"))
@@ -0,0 +1,48 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import Set, SetBuilder, kmer_codes, pack_kmer, unpack_kmer
+
+
+def test_pack_unpack():
+    assert pack_kmer("ACGT") == b"\x1b"
+    assert pack_kmer("acgta") == b"\x00\x6c"
+    assert unpack_kmer(pack_kmer("GATTACA"), 7) == "GATTACA"
+    assert unpack_kmer(pack_kmer("T" * 32), 32) == "T" * 32
+    assert len(pack_kmer("A" * 32)) == 8
+
+
+def test_pack_order():
+    kmers = ["AAC", "ACA", "CAA", "GTT", "TTT"]
+    assert sorted(kmers, key=pack_kmer) == kmers
+
+
+def test_pack_invalid():
+    for seq in ("", "ACGN", "A" * 33):
+        with pytest.raises(ValueError):
+            pack_kmer(seq)
+    with pytest.raises(ValueError):
+        unpack_kmer(b"\x00\x00", 3)
+
+
+def test_kmer_set_lookup():
+    builder = SetBuilder()
+    for kmer in sorted(["GATT", "ATTA", "TTAC"], key=pack_kmer):
+        builder.insert(pack_kmer(kmer))
+    kmers = builder.finish()
+    assert pack_kmer("ATTA") in kmers
+    assert pack_kmer("ACAG") not in kmers
+
+
+def test_kmer_arrays():
+    np = pytest.importorskip("numpy")
+    codes = kmer_codes("GATTACANGATT", 4)
+    assert codes.dtype == np.uint64
+    assert [unpack_kmer(int(c).to_bytes(1, "big"), 4) for c in codes] == [
+        "GATT", "ATTA", "TTAC", "TACA", "GATT"]
+    kmers = Set.from_kmers(codes, 4)
+    assert len(kmers) == 4
+    assert pack_kmer("TACA") in kmers
+    query = kmer_codes("ATTACC", 4)
+    assert kmers.contains_kmers(query, 4).tolist() == [True, True, False]
+    with pytest.raises(ValueError):
+        Set.from_kmers(np.array([256], dtype=np.uint64), 4)
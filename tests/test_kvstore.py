@@ -0,0 +1,64 @@
+# -*- coding: utf-8 -*-
+import os
+import pytest
+from rust_fst import FormatError, KeyValueStore
+
+ITEMS = [("fox", b"\x00quick brown"), ("dog", b"lazy"), ("cat", b""),
+         ("fox", b"red"), ("möö", b"lazy")]
+
+
+@pytest.fixture
+def store():
+    return KeyValueStore.build(ITEMS)
+
+
+def test_lookup(store):
+    assert store["fox"] == b"red"
+    assert store[b"dog"] == b"lazy"
+    assert store["cat"] == b""
+    assert store.get("emu") is None
+    assert store.get("emu", b"?") == b"?"
+    with pytest.raises(KeyError):
+        store["emu"]
+    assert "möö" in store
+    assert "emu" not in store
+    assert len(store) == 4
+
+
+def test_iteration(store):
+    assert list(store) == ["cat", "dog", "fox", "möö"]
+    assert list(store.keys().raw())[-1] == "möö".encode()
+    assert list(store.items()) == [
+        ("cat", b""), ("dog", b"lazy"), ("fox", b"red"), ("möö", b"lazy")]
+    assert list(store.items(prefix="f")) == [("fox", b"red")]
+    assert list(store.items(ge="d", lt="g")) == [
+        ("dog", b"lazy"), ("fox", b"red")]
+
+
+def test_shared_values(store):
+    # "lazy" is stored once: 1 + 4, "red" 1 + 3 and "" 1 byte.
+    assert store.payload_bytes == 10
+
+
+def test_build_file(tmpdir):
+    path = str(tmpdir.join("store.fst"))
+    KeyValueStore.build(ITEMS, path)
+    assert os.path.exists(path + ".values")
+    store = KeyValueStore(path)
+    assert store["fox"] == b"red"
+    assert dict(store.items())["möö"] == b"lazy"
+
+
+def test_corrupt_values(tmpdir):
+    path = str(tmpdir.join("store.fst"))
+    KeyValueStore.build(ITEMS, path)
+    with open(path + ".values", "wb") as fp:
+        fp.write(b"\x7f")
+    store = KeyValueStore(path)
+    with pytest.raises(FormatError):
+        store["fox"]
+
+
+def test_values_must_be_bytes():
+    with pytest.raises(TypeError):
+        KeyValueStore.build([("a", "not bytes")])
@@ -1,7 +1,11 @@
 # -*- coding: utf-8 -*-
+import collections.abc
+import copy
+import io
 import pytest
 import os
-from rust_fst import Map, MapBuilder
+import pickle
+from rust_fst import Map, MapBuilder, OutOfOrderError, LevenshteinQuery
 
 TEST_ITEMS = [(u"möö", 1), (u"bar", 2), (u"baz", 1337), (u"foo", 2**16)]
 
@@ -40,6 +44,27 @@ def test_build_outoforder(tmpdir):
         do_build(fst_path, sorted_=False)
 
 
+def test_build_duplicate():
+    builder = MapBuilder(None)
+    builder.insert("foo", 1)
+    with pytest.raises(OutOfOrderError):
+        builder.insert("foo", 2)
+
+
+def test_build_value_range():
+    builder = MapBuilder(None)
+    with pytest.raises(OverflowError, match="'foo'"):
+        builder.insert("foo", -1)
+    with pytest.raises(OverflowError, match="'foo'"):
+        builder.insert("foo", 2**64)
+    with pytest.raises(TypeError, match="'foo'"):
+        builder.insert("foo", "1")
+    with pytest.raises(TypeError):
+        builder.insert("foo", 1.0)
+    builder.insert("foo", 2**64 - 1)
+    assert builder.finish()["foo"] == 2**64 - 1
+
+
 def test_build_baddir():
     fst_path = "/guaranteed-to-not-exist/set.fst"
     # Rust File::create throws OSError (PyOSError)
@@ -51,6 +76,49 @@ def test_build_memory(fst_map):
     assert len(fst_map) == 4
 
 
+def test_map_abc(fst_map):
+    assert isinstance(fst_map, collections.abc.Mapping)
+    assert fst_map.get("qux") is None
+    assert dict(fst_map) == dict(TEST_ITEMS)
+    assert fst_map == dict(TEST_ITEMS) and dict(TEST_ITEMS) == fst_map
+    assert fst_map != dict(TEST_ITEMS[1:]) and fst_map != TEST_ITEMS
+    assert Map.from_iter([("a", 1)]) == {"a": 1}
+    assert Map.from_iter([("a", 1)]) != {"a": 1.5}
+
+
+def test_map_subclass(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+
+    class CountMap(Map):
+        def total(self):
+            return sum(self.values())
+
+    counts = CountMap(fst_path)
+    assert counts.total() == sum(v for _, v in TEST_ITEMS)
+    assert counts["bar"] == 2
+
+    counts.note = ["seen"]
+    shallow, deep = copy.copy(counts), copy.deepcopy(counts)
+    for dup in (shallow, deep):
+        assert type(dup) is CountMap
+        assert dup.total() == counts.total()
+        assert dup.note == ["seen"]
+    assert shallow.note is counts.note
+    assert deep.note is not counts.note
+    rebuilt = counts.rebuild(add=[("zzz", 5)])
+    assert type(rebuilt) is CountMap
+    assert rebuilt.total() == counts.total() + 5
+    assert type(CountMap.from_iter([("a", 1)])) is CountMap
+    assert type(CountMap.empty()) is CountMap
+    assert type(Map.from_iter([("a", 1)])) is Map
+
+
+def test_map_repr(fst_map):
+    assert repr(fst_map).startswith("<rust_fst.Map len=4 size=")
+    assert repr(fst_map).endswith("B memory>")
+
+
 def test_map_contains(fst_map):
     for key, _ in TEST_ITEMS:
         assert key in fst_map
@@ -66,6 +134,342 @@ def test_map_getitem(fst_map):
         assert fst_map[key] == val
 
 
+def test_map_getitem_cached(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+    cached = Map(fst_path, cache_size=2)
+    for _ in range(3):
+        for key, val in TEST_ITEMS:
+            assert cached[key] == val
+            assert cached.get(key) == val
+        assert "qux" not in cached
+        assert cached.get("qux", 7) == 7
+
+
+def test_map_counters(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+    with pytest.raises(ValueError):
+        Map(fst_path).counters()
+    fst_map = Map(fst_path, counters=True)
+    assert fst_map["bar"] == 2
+    assert fst_map.get("qux") is None
+    assert list(fst_map.values())
+    copy.copy(fst_map).items()
+    assert fst_map.counters() == {
+        "lookups": 2, "hits": 1, "misses": 1, "cache_hits": 0,
+        "streams": 1, "keys": 4}
+    assert copy.deepcopy(fst_map).counters()["lookups"] == 0
+
+
+def test_map_range(fst_map):
+    assert list(fst_map.range(ge="baz", lt="foo")) == [("baz", 1337)]
+    assert list(fst_map.range(gt="bar", le="foo")) == [
+        ("baz", 1337), ("foo", 2**16)]
+    assert list(fst_map.range(ge="zzz")) == []
+
+
+def test_map_reverse_iteration(fst_map):
+    items = sorted(TEST_ITEMS, reverse=True)
+    assert list(fst_map.items(reverse=True)) == items
+    assert list(reversed(fst_map.items())) == items
+    assert list(reversed(fst_map.items(reverse=True))) == sorted(TEST_ITEMS)
+    assert list(fst_map.range(gt="bar", le="foo", reverse=True)) == [
+        ("foo", 2**16), ("baz", 1337)]
+    assert list(fst_map.search_prefix("ba", reverse=True)) == [
+        ("baz", 1337), ("bar", 2)]
+
+
+def test_map_bounded_search(fst_map):
+    assert list(fst_map.search_lev("bat", 1, ge="baz")) == [("baz", 1337)]
+    assert list(fst_map.search_re(r"ba.", parallel=True, prefix="bar")) == [
+        ("bar", 2)]
+
+
+
+def test_map_value_filter(fst_map):
+    assert list(fst_map.range(min_value=2)) == [
+        ("bar", 2), ("baz", 1337), ("foo", 2**16)]
+    assert list(fst_map.range(min_value=2, max_value=1337, reverse=True)) == [
+        ("baz", 1337), ("bar", 2)]
+    assert list(fst_map.range(min_value=2, offset=1, limit=1)) == [
+        ("baz", 1337)]
+    assert fst_map.range(max_value=1).to_dict() == {u"möö": 1}
+    assert list(fst_map.search_prefix("ba", min_value=3)) == [("baz", 1337)]
+    for parallel in (False, True):
+        assert list(fst_map.search_lev("bax", 1, parallel=parallel,
+                                       max_value=100)) == [("bar", 2)]
+        assert list(fst_map.search_re("ba.|foo", parallel=parallel,
+                                      min_value=3, offset=1)) == [
+            ("foo", 2**16)]
+    assert fst_map.search_re("...", min_value=1000).to_dict() == {
+        "baz": 1337, "foo": 2**16}
+    with pytest.raises(ValueError, match="min_value"):
+        fst_map.range(min_value=-1)
+    scores = Map.from_iter([("a", -1.5), ("b", 0.5), ("c", 2.0)],
+                           value_type="f64")
+    assert list(scores.range(min_value=-1, max_value=1)) == [("b", 0.5)]
+    nan = float("nan")
+    signed = Map.from_iter([("n", nan), ("m", -nan), ("z", -0.0),
+                            ("p", 0.0), ("i", float("inf"))], value_type="f64")
+    assert dict(signed.range(min_value=0.0)) == {
+        "z": -0.0, "p": 0.0, "i": float("inf")}
+    assert dict(signed.range(max_value=-0.0)) == {"z": -0.0, "p": 0.0}
+    assert dict(signed.range(min_value=0.0, max_value=0.0)) == {
+        "z": -0.0, "p": 0.0}
+    assert list(signed.range(min_value=nan)) == []
+
+def test_map_from_iter(tmpdir):
+    m = Map.from_iter(iter(TEST_ITEMS))
+    assert list(m.items()) == sorted(TEST_ITEMS)
+    fst_path = str(tmpdir.join("from_iter.fst"))
+    m = Map.from_iter(sorted(TEST_ITEMS), path=fst_path, sorted=True)
+    assert dict(Map(fst_path).items()) == dict(TEST_ITEMS)
+    with pytest.raises(OutOfOrderError):
+        Map.from_iter([("a", 1), ("a", 2)])
+    with pytest.raises(TypeError):
+        Map.from_iter([("a", "1")])
+
+
+def test_map_insert_from_file(tmpdir):
+    path = tmpdir.join("counts.tsv")
+    path.write_text(u"a b\t1\nc\t2\n", "utf8")
+    builder = MapBuilder(None)
+    assert builder.insert_from_file(str(path)) == 2
+    assert list(builder.finish().items()) == [("a b", 1), ("c", 2)]
+    path.write_text(u"a;x\n", "utf8")
+    with pytest.raises(ValueError):
+        MapBuilder(None).insert_from_file(str(path), delimiter=";")
+
+
+def test_map_search_prefix(fst_map):
+    assert list(fst_map.search_prefix("ba")) == [("bar", 2), ("baz", 1337)]
+    assert list(fst_map.search_prefix(b"\xff")) == []
+
+
+def test_map_longest_prefix(fst_map):
+    assert fst_map.longest_prefix("bazaar") == ("baz", 1337)
+    assert fst_map.longest_prefix("ba") is None
+    assert fst_map.all_prefixes("food") == [("foo", 2**16)]
+    assert fst_map.all_prefixes("qux") == []
+
+
+def test_map_segment():
+    ids = do_build(items=[(u"北京", 1), (u"北京大学", 2), (u"大学", 3)])
+    text = u"北京大学生"
+    assert ids.segment(text) == [(0, 4, 2), (4, 5, None)]
+    assert ids.segment(u"大学北京") == [(0, 2, 3), (2, 4, 1)]
+    assert ids.segment("") == []
+
+
+def test_map_search_re_match_mode(fst_map):
+    assert list(fst_map.search_re("a", match_mode="substring")) == [
+        ("bar", 2), ("baz", 1337)]
+    assert list(fst_map.search_re("F", match_mode="prefix",
+                                  case_insensitive=True)) == [("foo", 2**16)]
+    assert fst_map.count_re("a", match_mode="substring") == 2
+
+
+def test_map_levenshtein_query(fst_map):
+    query = LevenshteinQuery("bam", 1)
+    assert list(fst_map.search(query)) == [("bar", 2), ("baz", 1337)]
+    assert list(fst_map.search(query, parallel=True, lt="baz")) == [("bar", 2)]
+
+
+def test_map_search_subsequence(fst_map):
+    assert list(fst_map.search_subsequence("bz")) == [("baz", 1337)]
+    assert list(fst_map.search_subsequence(u"mö")) == [(u"möö", 1)]
+    assert list(fst_map.search_subsequence("", offset=1, limit=1)) == [
+        ("baz", 1337)]
+
+
+def test_map_operations():
+    a = do_build(items=[("bar", 1), ("baz", 2), ("foo", 3)])
+    b = do_build(items=[("baz", 10), ("foo", 20), ("qux", 30)])
+    c = do_build(items=[("foo", 5)])
+    assert list(a.union(b, c)) == [
+        ("bar", 1), ("baz", 2), ("foo", 3), ("qux", 30)]
+    assert list(a.union(b, c, merge="last")) == [
+        ("bar", 1), ("baz", 10), ("foo", 5), ("qux", 30)]
+    assert list(a.union(b, c, merge="sum")) == [
+        ("bar", 1), ("baz", 12), ("foo", 28), ("qux", 30)]
+    assert list(a.intersection(b, merge="min")) == [("baz", 2), ("foo", 3)]
+    assert list(a.intersection(b, c, merge="max")) == [("foo", 20)]
+    assert list(a.difference(c)) == [("bar", 1), ("baz", 2)]
+    assert list(a.symmetric_difference(b, merge="sum")) == [
+        ("bar", 1), ("qux", 30)]
+    with pytest.raises(ValueError):
+        a.union(b, merge="avg")
+
+
+def test_map_operations_across_batches():
+    a = do_build(items=[("%05d" % i, i) for i in range(3000)])
+    b = do_build(items=[("%05d" % i, 1) for i in range(1000, 4000)])
+    union = list(a.union(b, merge="sum"))
+    assert len(union) == 4000
+    assert union[999] == ("00999", 999)
+    assert union[1000] == ("01000", 1001)
+    assert union[3999] == ("03999", 1)
+
+
+def test_map_into_map(tmpdir):
+    a = do_build(items=[("bar", 1), ("baz", 2), ("foo", 3)])
+    b = do_build(items=[("baz", 10), ("foo", 20), ("qux", 30)])
+    union = a.union(b, merge="sum").into_map()
+    assert isinstance(union, Map)
+    assert dict(union.items()) == {"bar": 1, "baz": 12, "foo": 23, "qux": 30}
+    assert list(a.intersection(b, merge="last").into_map().items()) == [
+        ("baz", 10), ("foo", 20)]
+    assert list(a.difference(b).into_map().items()) == [("bar", 1)]
+    path = str(tmpdir.join("sym.fst"))
+    sym = a.symmetric_difference(b).into_map(path)
+    assert list(Map(path).items()) == list(sym.items()) == [
+        ("bar", 1), ("qux", 30)]
+
+
+def test_map_limit_offset(fst_map):
+    assert list(fst_map.range(offset=1, limit=2)) == [
+        ("baz", 1337), ("foo", 2**16)]
+    assert list(fst_map.search_prefix("ba", offset=1)) == [("baz", 1337)]
+    assert list(fst_map.search_re(r"ba.", limit=1, parallel=True)) == [
+        ("bar", 2)]
+    assert list(fst_map.search_lev("bam", 1, offset=1)) == [("baz", 1337)]
+    other = do_build(items=[("qux", 1)])
+    assert list(fst_map.union(other, merge="sum", offset=3, limit=1)) == [
+        (u"möö", 1)]
+    assert list(fst_map.difference(other, limit=1).into_map().items()) == [
+        ("bar", 2)]
+    import operator
+    assert operator.length_hint(iter(fst_map.items())) == len(TEST_ITEMS)
+    assert operator.length_hint(fst_map.range(offset=1)) == 3
+    assert operator.length_hint(iter(fst_map)) == len(TEST_ITEMS)
+    assert operator.length_hint(iter(fst_map.values())) == len(TEST_ITEMS)
+    assert operator.length_hint(fst_map.keys_split(1, limit=2)) == 2
+    streams = [fst_map.diff(other), fst_map.search_re("ba."),
+               fst_map.search_subsequence("b"), fst_map.union(other),
+               fst_map.difference(other)]
+    for stream in streams:
+        assert stream.__length_hint__() is NotImplemented
+        assert operator.length_hint(stream, -1) == -1
+
+
+def test_map_memory_usage(fst_map):
+    usage = fst_map.memory_usage()
+    assert usage["heap"] > 0
+    assert usage["mmap"] == 0
+
+
+def test_map_stats(fst_map):
+    stats = fst_map.stats()
+    assert stats["keys"] == len(fst_map)
+    assert stats["max_key_length"] == max(len(k.encode()) for k in fst_map)
+    assert stats["final_nodes"] >= 1
+    assert stats["nodes"] <= stats["transitions"] + 1
+    assert stats["storage"] == "memory"
+    assert stats["version"] == 3
+
+
+def test_map_verify(fst_map):
+    fst_map.verify()
+
+
+def test_map_key_length_stats(fst_map):
+    stats = fst_map.key_length_stats()
+    assert stats["count"] == len(fst_map)
+    assert stats["max"] == max(len(k.encode()) for k in fst_map)
+    assert sum(stats["histogram"].values()) == len(fst_map)
+
+
+def test_map_prefix_histogram(fst_map):
+    histogram = fst_map.prefix_histogram()
+    assert sum(histogram.values()) == len(fst_map)
+    assert list(histogram) == sorted(set(k[0] for k in fst_map))
+
+
+def test_map_export_dot(tmpdir):
+    builder = MapBuilder(None)
+    builder.insert("a", 5)
+    builder.insert("b", 7)
+    path = str(tmpdir.join("map.dot"))
+    builder.finish().export_dot(path)
+    dot = tmpdir.join("map.dot").read()
+    assert 'label = "a/5"' in dot
+    assert 'label = "b/7"' in dot
+
+
+def test_map_residency(fst_map):
+    assert fst_map.residency() == 1.0
+
+
+def test_map_copy(fst_map):
+    for dup in (copy.copy(fst_map), copy.deepcopy(fst_map)):
+        assert list(dup.items()) == list(fst_map.items())
+
+
+def test_map_to_bytes_and_write(fst_map, tmpdir):
+    assert list(Map(fst_map.to_bytes()).items()) == list(fst_map.items())
+    path = str(tmpdir.join("copy.fst"))
+    fst_map.write(path)
+    assert list(Map(path).items()) == list(fst_map.items())
+
+
+def test_map_ordinals(fst_map):
+    for i, key in enumerate(fst_map.keys()):
+        assert fst_map.get_index(key) == i
+        assert fst_map.get_key(i) == key
+    with pytest.raises(KeyError):
+        fst_map.get_index("nope")
+    with pytest.raises(IndexError):
+        fst_map.get_key(len(fst_map))
+
+
+
+def test_map_sample(fst_map):
+    assert fst_map.sample(4, seed=1) == list(fst_map.items())
+    items = fst_map.sample(2, seed=1)
+    assert len(items) == 2
+    assert all(fst_map[key] == value for key, value in items)
+    assert fst_map.sample(2, seed=1) == items
+    with pytest.raises(ValueError):
+        fst_map.sample(5)
+
+def test_map_pickle(fst_map, tmpdir):
+    restored = pickle.loads(pickle.dumps(fst_map))
+    assert list(restored.items()) == list(fst_map.items())
+    path = str(tmpdir.join("test.fst"))
+    mapped = pickle.loads(pickle.dumps(do_build(path)))
+    assert list(mapped.items()) == list(fst_map.items())
+    assert mapped.memory_usage()["mmap"] > 0
+
+
+def test_map_bytes_keys():
+    builder = MapBuilder(None)
+    builder.insert(b"\x00\xff", 1)
+    builder.insert("foo", 2)
+    m = builder.finish()
+    assert m[b"\x00\xff"] == 1
+    assert m[b"foo"] == m["foo"] == 2
+    assert m.get(b"\x01") is None
+    assert b"\x00\xff" in m.keys()
+    with pytest.raises(KeyError) as excinfo:
+        m[b"bar"]
+    assert excinfo.value.args == (b"bar",)
+
+
+def test_map_raw_streams():
+    builder = MapBuilder(None)
+    builder.insert(b"\x00\xff", 1)
+    builder.insert("foo", 2)
+    m = builder.finish()
+    assert list(m.items().__iter__().raw()) == [(b"\x00\xff", 1), (b"foo", 2)]
+    assert list(iter(m).raw()) == [b"\x00\xff", b"foo"]
+    assert list(m.diff(Map.empty()).raw()) == [
+        (b"\x00\xff", 1, None), (b"foo", 2, None)]
+    assert list(m.diff(Map.empty(), offset=1)) == [("foo", 2, None)]
+    assert list(m.diff(Map.empty(), limit=1).raw()) == [
+        (b"\x00\xff", 1, None)]
+
+
 def test_map_keys(fst_map):
     keys = list(fst_map.keys())
     assert keys == sorted([k for k, _ in TEST_ITEMS])
@@ -73,6 +477,162 @@ def test_map_keys(fst_map):
 
 def test_map_iter(fst_map):
     assert list(fst_map.keys()) == sorted([k for k, _ in TEST_ITEMS])
+    assert [k for k in fst_map] == sorted([k for k, _ in TEST_ITEMS])
+    assert set(fst_map) == set(k for k, _ in TEST_ITEMS)
+
+
+def test_map_views(fst_map):
+    keys, values, items = fst_map.keys(), fst_map.values(), fst_map.items()
+    for view in (keys, values, items):
+        assert len(view) == 4
+        assert list(view) == list(view)
+        assert isinstance(view, collections.abc.MappingView)
+    assert "bar" in keys and "qux" not in keys
+    assert 1337 in values and 3 not in values and "x" not in values
+    assert ("baz", 1337) in items and ("baz", 1) not in items
+    assert (b"baz", 1337) in items
+    assert "baz" not in items
+    assert keys == {k for k, _ in TEST_ITEMS} and keys != {"bar"}
+    assert keys & {"bar", "qux"} == {"bar"} and {"qux"} | keys >= keys
+    assert keys - ["bar", "baz", "foo"] == {u"möö"}
+    assert keys.isdisjoint(["qux", 1]) and not keys.isdisjoint(["bar"])
+    assert items == set(TEST_ITEMS) and items <= set(TEST_ITEMS) | {("x", 1)}
+    assert items & {("bar", 2), ("bar", 3)} == {("bar", 2)}
+    assert {("x", 1)} ^ items == set(TEST_ITEMS) | {("x", 1)}
+
+
+def test_map_keys_split(fst_map):
+    pairs = list(fst_map.keys_split(2))
+    assert [p + s for p, s in pairs] == sorted([k for k, _ in TEST_ITEMS])
+    assert pairs[0][0] is pairs[1][0]
+    pairs = list(fst_map.keys_split(2, offset=3, limit=5))
+    assert [p + s for p, s in pairs] == [u"möö"]
+
+
+def test_map_to_dict(fst_map):
+    assert fst_map.to_dict() == dict(TEST_ITEMS)
+    assert Map.empty().to_dict() == {}
+    signed = Map.from_iter([("a", -1), ("b", 2)], value_type="i64")
+    assert signed.to_dict() == {"a": -1, "b": 2}
+
+
+def test_map_to_arrays(fst_map):
+    np = pytest.importorskip("numpy")
+    keys, values = fst_map.to_arrays()
+    assert keys.dtype == np.dtype("S6")
+    assert values.dtype == np.uint64
+    expected = sorted(TEST_ITEMS)
+    assert [k.decode("utf8") for k in keys] == [k for k, _ in expected]
+    assert values.tolist() == [v for _, v in expected]
+    values += 1
+    assert values[0] == expected[0][1] + 1
+
+
+def test_map_values_array(fst_map):
+    np = pytest.importorskip("numpy")
+    values = fst_map.values_array()
+    assert values.dtype == np.uint64
+    assert values.tolist() == [v for _, v in sorted(TEST_ITEMS)]
+    assert fst_map.values_array(prefix="ba").tolist() == [2, 1337]
+    assert fst_map.values_array(ge="baz", lt="m").tolist() == [1337, 2**16]
+    assert fst_map.values_array(prefix="ba", ge="bb").tolist() == []
+
+
+def test_map_keys_arrow_c_stream(fst_map):
+    pa = pytest.importorskip("pyarrow")
+    keys = pa.chunked_array(fst_map.keys())
+    assert keys.to_pylist() == sorted(k for k, _ in TEST_ITEMS)
+
+
+def test_map_pandas(fst_map, tmpdir):
+    pd = pytest.importorskip("pandas")
+    series = fst_map.to_series(name="count")
+    assert series.name == "count"
+    assert list(series.items()) == sorted(TEST_ITEMS)
+    frame = fst_map.to_frame(key_column="term")
+    assert list(frame.columns) == ["term", "value"]
+    assert frame["term"].tolist() == [k for k, _ in sorted(TEST_ITEMS)]
+    assert list(Map.from_series(series[::-1]).items()) == sorted(TEST_ITEMS)
+    rebuilt = Map.from_frame(frame, key_column="term")
+    assert list(rebuilt.items()) == sorted(TEST_ITEMS)
+    path = str(tmpdir.join("series.fst"))
+    assert list(Map.from_series(series, path=path).items()) == sorted(TEST_ITEMS)
+    path = str(tmpdir.join("frame.fst"))
+    rebuilt = Map.from_frame(frame, key_column="term", path=path)
+    assert list(rebuilt.items()) == sorted(TEST_ITEMS)
+
+
+def test_map_get_many(fst_map):
+    assert fst_map.get_many(["bar", b"foo", "nope"]) == [2, 2**16, None]
+    assert fst_map.get_many(["nope"], default=7) == [7]
+    np = pytest.importorskip("numpy")
+    values = fst_map.get_many(["baz", "nope"], numpy=True)
+    assert values.dtype == np.uint64
+    assert values.tolist() == [1337, 0]
+
+
+def test_map_async(fst_map):
+    import asyncio
+
+    async def lookup():
+        return await asyncio.gather(
+            fst_map.aget_many(["bar", b"foo", "nope"], default=0),
+            fst_map.asearch_re(r'ba.*'))
+
+    values, matches = asyncio.run(lookup())
+    assert values == [2, 2**16, 0]
+    assert matches == [("bar", 2), ("baz", 1337)]
+
+
+def test_map_empty(fst_map):
+    empty = Map.empty()
+    assert empty.is_empty()
+    assert len(empty) == 0
+    assert empty.get("foo") is None
+    assert list(empty.items()) == []
+    assert not fst_map.is_empty()
+
+
+def test_map_close(tmpdir):
+    fst_map = do_build(str(tmpdir.join('test.fst')))
+    with fst_map as entered:
+        assert entered is fst_map
+        assert fst_map["bar"] == 2
+    assert fst_map.closed
+    for op in (len, lambda m: m["bar"], lambda m: m.get("bar"),
+               lambda m: m.items(), lambda m: m.diff(Map.empty())):
+        with pytest.raises(ValueError):
+            op(fst_map)
+    with pytest.raises(ValueError):
+        with fst_map:
+            pass
+
+
+def test_map_weakref(fst_map):
+    import weakref
+    for obj in (fst_map, fst_map.keys(), iter(fst_map.items()),
+                fst_map.search_lev("bam", 1), MapBuilder()):
+        ref = weakref.ref(obj)
+        assert ref() is obj
+
+
+def test_map_write_jsonl(fst_map, tmpdir):
+    import json
+    path = str(tmpdir.join('items.jsonl'))
+    fst_map.write_jsonl(path, key_field="term")
+    with open(path, encoding="utf8") as fp:
+        records = [json.loads(line) for line in fp]
+    assert records == [{"term": k, "value": v} for k, v in sorted(TEST_ITEMS)]
+
+
+def test_map_to_arrow(fst_map):
+    pa = pytest.importorskip("pyarrow")
+    batches = list(fst_map.to_arrow(batch_size=2))
+    assert [len(b) for b in batches] == [2, 2]
+    assert batches[0].schema.field("key").type == pa.large_string()
+    assert batches[0].schema.field("value").type == pa.uint64()
+    table = pa.Table.from_batches(batches)
+    assert list(zip(table["key"].to_pylist(), table["value"].to_pylist())) == sorted(TEST_ITEMS)
 
 
 def test_map_values(fst_map):
@@ -80,6 +640,21 @@ def test_map_values(fst_map):
     assert values == [v for _, v in sorted(TEST_ITEMS)]
 
 
+def test_map_search_parallel(fst_map):
+    assert list(fst_map.search_lev("bam", 1, parallel=True)) == \
+        [(u"bar", 2), (u"baz", 1337)]
+    assert list(fst_map.search_re(r'.*o.*', parallel=True)) == \
+        list(fst_map.search_re(r'.*o.*'))
+
+
+def test_map_count(fst_map):
+    assert fst_map.count_lev("bam", 1) == 2
+    assert fst_map.count_re(r'.*o.*', parallel=True) == fst_map.count_re(r'.*o.*') == 1
+    assert fst_map.count() == len(TEST_ITEMS)
+    assert fst_map.count(prefix="ba", regex=r'.*z') == 1
+    assert fst_map.count(ge="baz", lt="foo") == 1
+
+
 # def test_map_search(fst_map):
 #     matches = list(fst_map.search_lev("bam", 1))
 #     assert matches == [(u"bar", 2), (u"baz", 1337)]
@@ -122,3 +697,224 @@ def test_map_values(fst_map):
 # So range queries via slicing are NOT supported in this Rust implementation.
 # I will comment out test_range.
 
+
+
+def test_map_search_custom(fst_map):
+    class EndsWith:
+        def __init__(self, last):
+            self.last = ord(last)
+
+        def start(self):
+            return False
+
+        def is_match(self, state):
+            return state
+
+        def accept(self, state, byte):
+            return byte == self.last
+
+    assert list(fst_map.search_custom(EndsWith("z"))) == [("baz", 1337)]
+    assert list(fst_map.search_custom(EndsWith("o"), ge="c")) == \
+        [("foo", 65536)]
+
+
+def test_value_type_i64(tmpdir):
+    items = [("a", -5), ("b", 0), ("c", 2**63 - 1), ("d", -2**63)]
+    builder = MapBuilder(value_type="i64")
+    for key, value in items:
+        builder.insert(key, value)
+    m = builder.finish()
+    assert m.value_type == "i64"
+    assert m["a"] == -5
+    assert m.get("d") == -2**63
+    assert m.get("x", -1) == -1
+    assert list(m.items()) == items
+    assert list(m.values()) == [v for _, v in items]
+    assert -5 in m.values()
+    assert ("b", 0) in m.items()
+    assert list(m.range(ge="c")) == items[2:]
+    assert m.get_many(["a", "x"]) == [-5, None]
+    assert "value_type=i64" in repr(m)
+    with pytest.raises(OverflowError):
+        MapBuilder(value_type="i64").insert("a", 2**63)
+    with pytest.raises(TypeError):
+        MapBuilder(value_type="i64").insert("a", 1.5)
+
+
+def test_value_type_f64(tmpdir):
+    path = str(tmpdir.join("scores.fst"))
+    items = [("a", -1.5), ("b", 0.0), ("c", 2), ("d", float("inf"))]
+    builder = MapBuilder(path, value_type="f64")
+    for key, value in items:
+        builder.insert(key, value)
+    builder.finish()
+    m = Map(path, value_type="f64")
+    assert m["a"] == -1.5
+    assert m["c"] == 2.0 and isinstance(m["c"], float)
+    assert dict(m.items()) == dict(items)
+    assert m.to_dict()["d"] == float("inf")
+    assert pickle.loads(pickle.dumps(m))["a"] == -1.5
+    with pytest.raises(TypeError):
+        MapBuilder(value_type="f64").insert("a", "1.5")
+
+
+def test_value_type_ordering():
+    # The outputs preserve the order of the values, so min and max merges
+    # compare them correctly.
+    a = Map.from_iter([("k", -3.0), ("l", 1.0)], value_type="f64")
+    b = Map.from_iter([("k", 2.5), ("l", -7.25)], value_type="f64")
+    assert list(a.union(b, merge="min")) == [("k", -3.0), ("l", -7.25)]
+    assert list(a.union(b, merge="max")) == [("k", 2.5), ("l", 1.0)]
+    assert list(a.diff(b)) == [("k", -3.0, 2.5), ("l", 1.0, -7.25)]
+    merged = a.union(b, merge="max").into_map()
+    assert merged.value_type == "f64"
+    assert merged["k"] == 2.5
+    with pytest.raises(ValueError):
+        a.union(b, merge="sum")
+    with pytest.raises(ValueError):
+        a.union(Map.from_iter([("k", 1)]))
+
+
+def test_value_type_unsupported():
+    with pytest.raises(ValueError):
+        MapBuilder(value_type="i32")
+    with pytest.raises(ValueError):
+        MapBuilder(value_type="i64").insert_from_file("items.tsv")
+
+
+def test_value_type_jsonl(tmpdir):
+    import json
+    path = str(tmpdir.join("items.jsonl"))
+    Map.from_iter([("a", -1), ("b", 2)], value_type="i64").write_jsonl(path)
+    with open(path, encoding="utf8") as fp:
+        assert [json.loads(line) for line in fp] == [
+            {"key": "a", "value": -1}, {"key": "b", "value": 2}]
+    scores = Map.from_iter([("a", -1.5), ("b", 2.0), ("c", float("nan")),
+                            ("d", float("inf"))], value_type="f64")
+    scores.write_jsonl(path)
+    with open(path, encoding="utf8") as fp:
+        assert [json.loads(line)["value"] for line in fp] == [
+            -1.5, 2.0, None, None]
+
+
+def test_value_type_numpy():
+    np = pytest.importorskip("numpy")
+    counts = Map.from_iter([("a", -1), ("b", 2)], value_type="i64")
+    values = counts.get_many(["b", "nope", "a"], numpy=True)
+    assert values.dtype == np.int64
+    assert values.tolist() == [2, 0, -1]
+    assert counts.get_many(["nope"], numpy=True, default=-7).tolist() == [-7]
+    with pytest.raises(ValueError):
+        counts.get_many(["nope"], numpy=True, default=1.5)
+    _, values = counts.to_arrays()
+    assert values.dtype == np.int64
+    assert values.tolist() == [-1, 2]
+    scores = Map.from_iter([("a", -1.5), ("b", 2.0)], value_type="f64")
+    values = scores.values_array()
+    assert values.dtype == np.float64
+    assert values.tolist() == [-1.5, 2.0]
+    values = scores.get_many(["a", "nope"], numpy=True, default=float("nan"))
+    assert values[0] == -1.5 and np.isnan(values[1])
+
+
+def test_value_type_pandas_and_arrow():
+    pytest.importorskip("pandas")
+    scores = Map.from_iter([("a", -1.5), ("b", 2.0)], value_type="f64")
+    assert scores.to_series().dtype == "float64"
+    assert scores.to_series().to_dict() == {"a": -1.5, "b": 2.0}
+    counts = Map.from_iter([("a", -1), ("b", 2)], value_type="i64")
+    assert counts.to_frame()["value"].tolist() == [-1, 2]
+    assert counts.to_frame()["value"].dtype == "int64"
+    pa = pytest.importorskip("pyarrow")
+    for m, arrow_type in ((counts, pa.int64()), (scores, pa.float64())):
+        table = m.to_arrow().read_all()
+        assert table.schema.field("value").type == arrow_type
+        assert dict(zip(table["key"].to_pylist(),
+                        table["value"].to_pylist())) == dict(m.items())
+
+
+def test_map_first_last_floor_ceiling(fst_map):
+    assert fst_map.first() == ("bar", 2)
+    assert fst_map.last() == ("möö", 1)
+    assert fst_map.floor("bay") == ("bar", 2)
+    assert fst_map.ceiling("bay") == ("baz", 1337)
+    assert fst_map.floor("a") is None
+    assert fst_map.ceiling("zzz") is None
+    scores = Map.from_iter([("a", -1.5), ("b", 2.0)], value_type="f64")
+    assert scores.last() == ("b", 2.0)
+
+
+def test_map_rebuild(tmpdir, fst_map):
+    rebuilt = fst_map.rebuild(add=[("qux", 7), ("bar", 20), ("bar", 21)],
+                              remove=["baz"])
+    assert list(rebuilt.items()) == [
+        ("bar", 21), ("foo", 65536), ("möö", 1), ("qux", 7)]
+    assert list(fst_map.rebuild(add=[("a", 1)], remove=["a"]).keys()) == [
+        "bar", "baz", "foo", "möö"]
+    path = str(tmpdir.join("rebuilt.fst"))
+    on_disk = fst_map.rebuild(remove=["foo", "möö"], path=path)
+    assert dict(on_disk.items()) == {"bar": 2, "baz": 1337}
+    assert os.path.exists(path)
+    with pytest.raises(TypeError):
+        fst_map.rebuild(add=[("a", "b")])
+    scores = Map.from_iter([("a", -1.5), ("b", 2.0)], value_type="f64")
+    scores = scores.rebuild(add=[("b", -0.25), ("c", 3)])
+    assert scores.value_type == "f64"
+    assert list(scores.items()) == [("a", -1.5), ("b", -0.25), ("c", 3.0)]
+
+
+def test_stream_to_dict(fst_map):
+    assert fst_map.range().to_dict() == dict(TEST_ITEMS)
+    assert fst_map.range(ge="baz", limit=2).to_dict() == {
+        "baz": 1337, "foo": 65536}
+    assert fst_map.search_re("ba.*").to_dict() == {"bar": 2, "baz": 1337}
+    assert fst_map.search_lev("bam", 1).raw().to_dict() == {
+        b"bar": 2, b"baz": 1337}
+    assert fst_map.search_subsequence("bz").to_dict() == {"baz": 1337}
+    assert iter(fst_map.keys()).to_list() == sorted(dict(TEST_ITEMS))
+    assert fst_map.search_re("ba.*", parallel=True).to_dict() == {
+        "bar": 2, "baz": 1337}
+    other = Map.from_iter([("baz", 1), ("qux", 5)])
+    assert fst_map.union(other, merge="sum").to_dict()["baz"] == 1338
+    assert fst_map.intersection(other, merge="min").to_dict() == {"baz": 1}
+    assert fst_map.difference(other).to_dict() == {
+        "bar": 2, "foo": 65536, "möö": 1}
+    assert fst_map.symmetric_difference(other).to_dict()["qux"] == 5
+    scores = Map.from_iter([("a", -1.5), ("b", 2.0)], value_type="f64")
+    assert scores.range().to_dict() == {"a": -1.5, "b": 2.0}
+
+
+def test_values_array_matches_to_dict(fst_map):
+    np = pytest.importorskip("numpy")
+    values = fst_map.values_array(prefix="ba")
+    assert values.dtype == np.uint64
+    assert values.tolist() == list(fst_map.search_prefix("ba").to_dict().values())
+
+
+def test_map_comparisons(fst_map):
+    same = do_build()
+    assert fst_map == same and not fst_map != same
+    assert fst_map != fst_map.rebuild(add=[("bar", 3)])
+    assert fst_map != fst_map.rebuild(remove=["bar"])
+    assert fst_map != [k for k, _ in TEST_ITEMS]
+    assert fst_map == dict(TEST_ITEMS)
+    with pytest.raises(TypeError):
+        fst_map < same
+    with pytest.raises(TypeError):
+        hash(fst_map)
+    ints = Map.from_iter([("a", 1), ("b", 2)])
+    floats = Map.from_iter([("a", 1.0), ("b", 2.0)], value_type="f64")
+    assert ints == floats
+    assert ints != Map.from_iter([("a", 1), ("b", -2)], value_type="i64")
+    same.close()
+    assert fst_map != same and same == same and same != dict(TEST_ITEMS)
+
+
+def test_map_builder_to_file_object():
+    out = io.BytesIO()
+    builder = MapBuilder(out, value_type="i64")
+    builder.insert("a", -1)
+    builder.insert("b", 2)
+    assert builder.finish() is None
+    m = Map(out.getvalue(), value_type="i64")
+    assert list(m.items()) == [("a", -1), ("b", 2)]
@@ -0,0 +1,46 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import Map, NgramModel
+
+NGRAMS = [("the", 10), ("the cat", 4), ("the dog", 5), ("the cat sat", 3),
+          ("the cat ran", 1), ("a cat", 2), (["the", "dog"], 2)]
+
+
+@pytest.fixture
+def model():
+    return NgramModel.build(NGRAMS)
+
+
+def test_next_tokens(model):
+    assert model.next_tokens("the") == [("dog", 7), ("cat", 4)]
+    assert model.next_tokens(["the", "cat"], k=1) == [("sat", 3)]
+    assert model.next_tokens("the mouse") == []
+
+
+def test_next_tokens_unigrams(model):
+    assert model.next_tokens("") == [("the", 10)]
+
+
+def test_count(model):
+    assert model.count("the dog") == 7
+    assert model.count(["the", "cat", "sat"]) == 3
+    assert model.count("cat") == 0
+    assert len(model) == 6
+
+
+def test_invalid_tokens():
+    with pytest.raises(ValueError):
+        NgramModel.build([(["the cat"], 1)])
+    with pytest.raises(ValueError):
+        NgramModel.build([("", 1)])
+
+
+def test_non_u64_counts():
+    with pytest.raises(ValueError):
+        NgramModel(Map.from_iter([("a", 1.5)], value_type="f64"))
+
+
+def test_build_file(tmpdir):
+    path = str(tmpdir.join("ngrams.fst"))
+    NgramModel.build(NGRAMS, path)
+    assert NgramModel(Map(path)).next_tokens("a") == [("cat", 2)]
@@ -0,0 +1,65 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import MapBuilder, SetBuilder
+
+
+@pytest.fixture
+def fst_map():
+    builder = MapBuilder(None)
+    for key, value in [("bar", 1), ("baz", 2), ("foo", 3)]:
+        builder.insert(key, value)
+    return builder.finish()
+
+
+def keys_below(node, prefix=b"", out=0):
+    """Enumerate (key, value) pairs by walking the nodes depth-first."""
+    if node.is_final:
+        yield prefix, out + node.final_output
+    for byte, t_out, child in node.transitions():
+        yield from keys_below(child, prefix + bytes([byte]), out + t_out)
+
+
+def test_walk_all(fst_map):
+    assert list(keys_below(fst_map.root())) == [
+        (b"bar", 1), (b"baz", 2), (b"foo", 3)]
+
+
+def test_follow(fst_map):
+    root = fst_map.root()
+    assert len(root) == 2
+    assert not root.is_final
+    assert root.follow(ord("x")) is None
+    out, node = root.follow(ord("b"))
+    assert [t[0] for t in node.transitions()] == [ord("a")]
+    total, end = root.walk(b"baz")
+    assert end.is_final
+    assert total + end.final_output == 2
+    assert root.walk(b"bx") is None
+    assert root.walk(b"")[1].addr == root.addr
+
+
+def test_set_node_outputs_zero():
+    builder = SetBuilder()
+    for key in ["a", "ab"]:
+        builder.insert(key)
+    s = builder.finish()
+    root = s.root()
+    out, node = root.follow(ord("a"))
+    assert (out, node.is_final, node.final_output) == (0, True, 0)
+    assert "final" in repr(node)
+    s.close()
+    assert node.follow(ord("b"))[1].is_final
+    with pytest.raises(ValueError):
+        s.root()
+
+
+def test_transition_index(fst_map):
+    root = fst_map.root()
+    i = root.find_input(ord("f"))
+    byte, out, node = root.transition(i)
+    assert (i, byte) == (1, ord("f"))
+    assert node.addr == root.follow(ord("f"))[1].addr
+    assert root.transition(-1)[0] == ord("f")
+    assert root.find_input(ord("x")) is None
+    with pytest.raises(IndexError):
+        root.transition(2)
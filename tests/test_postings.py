@@ -0,0 +1,48 @@
+# -*- coding: utf-8 -*-
+import os
+import pytest
+from rust_fst import PostingsIndex
+
+np = pytest.importorskip("numpy")
+
+PAIRS = [("fox", 3), ("dog", 1), ("fox", 1), ("quick", 3), ("dog", 7),
+         ("fox", 200), ("fox", 3), ("quick", 70000)]
+
+
+@pytest.fixture
+def index():
+    return PostingsIndex.build(PAIRS)
+
+
+def test_postings(index):
+    assert index.postings("fox").tolist() == [1, 3, 200]
+    assert index.postings("quick").tolist() == [3, 70000]
+    assert index.postings("cat").tolist() == []
+    assert index.postings("fox").dtype == np.uint64
+
+
+def test_intersect(index):
+    assert index.intersect(["fox", "dog"]).tolist() == [1]
+    assert index.intersect(["fox", "quick"]).tolist() == [3]
+    assert index.intersect(["fox", "cat"]).tolist() == []
+    assert index.intersect([]).tolist() == []
+
+
+def test_union(index):
+    assert index.union(["dog", "quick"]).tolist() == [1, 3, 7, 70000]
+    assert index.union(["cat"]).tolist() == []
+
+
+def test_contains_len(index):
+    assert "dog" in index
+    assert "cat" not in index
+    assert len(index) == 3
+
+
+def test_build_file(tmpdir):
+    path = str(tmpdir.join("terms.fst"))
+    PostingsIndex.build(PAIRS, path)
+    assert os.path.exists(path + ".postings")
+    index = PostingsIndex(path)
+    assert index.postings("fox").tolist() == [1, 3, 200]
+    assert index.intersect(["dog", "fox"]).tolist() == [1]
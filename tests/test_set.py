@@ -1,8 +1,19 @@
 # -*- coding: utf-8 -*-
 import pytest
+import collections.abc
+import copy
+import gzip
+import io
+import mmap
+import operator
 import os
+import pickle
+import sys
 from contextlib import contextmanager
-from rust_fst import Set, SetBuilder
+from rust_fst import (
+    Set, SetBuilder, set_trace_hook, lossy_decodes, reset_lossy_decodes,
+    FstError, FormatError, OutOfOrderError, LevenshteinTooLargeError,
+    LevenshteinQuery, ClosedError)
 
 TEST_KEYS = [u"möö", "bar", "baz", "foo"]
 
@@ -36,6 +47,8 @@ def test_build_outoforder(tmpdir):
     fst_path = str(tmpdir.join('test.fst'))
     with pytest.raises(ValueError):
         do_build(fst_path, sorted_=False)
+    with pytest.raises(OutOfOrderError):
+        do_build(fst_path, sorted_=False)
 
 
 def test_build_baddir():
@@ -61,6 +74,59 @@ def test_load_badfile(tmpdir):
     # Rust FstSet::new might throw ValueError when loading invalid data
     with pytest.raises(ValueError):
         Set(str(bad_path))
+    with pytest.raises(FormatError):
+        Set(str(bad_path))
+    assert issubclass(FormatError, FstError)
+
+
+def test_load_badfile_diagnostics(tmpdir):
+    bad_path = str(tmpdir.join("bad.fst"))
+    with open(bad_path, "wb") as fp:
+        fp.write(b"not an fst, just some text\n" * 4)
+    with pytest.raises(FormatError, match="not an FST: .* at offset 0"):
+        Set(bad_path)
+    with pytest.raises(FormatError, match=r"\(108 bytes\)"):
+        Set(bad_path)
+
+    good_path = str(tmpdir.join("good.fst"))
+    do_build(good_path)
+    with open(good_path, "rb") as fp:
+        data = fp.read()
+    with pytest.raises(FormatError, match="truncated or corrupt: .* at offset"):
+        Set(data[:-7])
+    with pytest.raises(FormatError, match="truncated: a version 3 FST"):
+        Set(data[:20])
+    with pytest.raises(FormatError, match="truncated"):
+        Set(data[:3])
+    assert list(Set(data)) == sorted(TEST_KEYS)
+
+
+def test_lossy_decodes():
+    s = build_set([b"ok", b"bad\xff", b"bad\xfe"])
+    reset_lossy_decodes()
+    assert list(s) == [u"bad\ufffd", u"bad\ufffd", "ok"]
+    assert lossy_decodes() == (2, [b"bad\xfe", b"bad\xff"])
+    assert s.to_list() == list(s)
+    assert lossy_decodes()[0] == 6
+    reset_lossy_decodes()
+    assert lossy_decodes() == (0, [])
+
+
+def test_module_state():
+    from rust_fst import _native
+    s = build_set([b"bad\xff"])
+    reset_lossy_decodes()
+    assert s.first() == u"bad\ufffd"
+    assert lossy_decodes()[0] == 1
+    state = _native._state
+    try:
+        _native._state = None
+        with pytest.raises(TypeError):
+            lossy_decodes()
+    finally:
+        _native._state = state
+    assert lossy_decodes() == (1, [b"bad\xff"])
+    reset_lossy_decodes()
 
 
 def test_iter(fst_set):
@@ -68,6 +134,382 @@ def test_iter(fst_set):
     assert stored_keys == sorted(TEST_KEYS)
 
 
+def test_to_list(fst_set):
+    assert fst_set.to_list() == sorted(TEST_KEYS)
+
+
+def test_to_frozenset(fst_set):
+    assert fst_set.to_frozenset() == frozenset(TEST_KEYS)
+
+
+def test_contains_many(fst_set):
+    assert fst_set.contains_many(["bar", b"foo", "nope"]) == [True, True, False]
+    assert fst_set.contains_many(iter([])) == []
+    with pytest.raises(TypeError):
+        fst_set.contains_many(["bar", 1])
+    np = pytest.importorskip("numpy")
+    found = fst_set.contains_many(["nope", "baz"], numpy=True)
+    assert found.dtype == np.bool_
+    assert found.tolist() == [False, True]
+
+
+def test_keys_array(fst_set):
+    np = pytest.importorskip("numpy")
+    keys = fst_set.keys_array()
+    assert keys.dtype == np.dtype("S6")
+    assert [k.decode("utf8") for k in keys] == sorted(TEST_KEYS)
+    keys = fst_set.keys_array(dtype="U")
+    assert keys.dtype == np.dtype("U3")
+    assert keys.tolist() == sorted(TEST_KEYS)
+    assert fst_set.keys_array(width=8).dtype == np.dtype("S8")
+    with pytest.raises(ValueError, match="longer than the array width"):
+        fst_set.keys_array(width=2)
+
+
+def test_pandas_index(fst_set):
+    pd = pytest.importorskip("pandas")
+    index = fst_set.to_index(name="key")
+    assert index.name == "key"
+    assert index.tolist() == sorted(TEST_KEYS)
+    rebuilt = Set.from_index(pd.Index(list(reversed(index))))
+    assert list(rebuilt) == sorted(TEST_KEYS)
+
+
+def test_from_index_to_path(tmpdir):
+    path = str(tmpdir.join("index.fst"))
+    built = Set.from_index(["b", "a", "a"], path=path)
+    assert list(built) == ["a", "b"]
+    assert list(Set(path)) == ["a", "b"]
+
+
+def test_asearch(fst_set):
+    import asyncio
+
+    async def search():
+        return await asyncio.gather(
+            fst_set.asearch_re(r'ba.*'),
+            fst_set.asearch_lev("bam", 1, parallel=True))
+
+    assert asyncio.run(search()) == [["bar", "baz"], ["bar", "baz"]]
+
+
+def test_asearch_error(fst_set):
+    import asyncio
+
+    async def search():
+        return await fst_set.asearch_lev("a" * 100, 8)
+
+    with pytest.raises(LevenshteinTooLargeError):
+        asyncio.run(search())
+
+
+def test_empty(fst_set):
+    empty = Set.empty()
+    assert empty.is_empty()
+    assert len(empty) == 0
+    assert list(empty) == []
+    assert "foo" not in empty
+    assert not fst_set.is_empty()
+    assert list(fst_set | empty) == sorted(TEST_KEYS)
+
+
+def test_ends_with(fst_set):
+    with pytest.raises(ValueError):
+        fst_set.ends_with("z")
+    fst_set.enable_suffix_search()
+    assert sorted(fst_set.ends_with("z")) == ["baz"]
+    assert sorted(fst_set.ends_with(u"öö")) == [u"möö"]
+    assert sorted(fst_set.ends_with("")) == sorted(TEST_KEYS)
+    assert list(fst_set.ends_with("x")) == []
+    assert list(fst_set.ends_with("", offset=1, limit=2)) == ["bar", "baz"]
+    assert fst_set.ends_with("", limit=1).to_list() == ["foo"]
+
+
+def test_ends_with_companion_file(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+    Set(fst_path).enable_suffix_search()
+    assert tmpdir.join('test.fst.rev').exists()
+    # A stale companion is rebuilt
+    do_build(fst_path, keys=TEST_KEYS + ["quux"])
+    fst_set = Set(fst_path)
+    fst_set.enable_suffix_search()
+    assert list(fst_set.ends_with("ux")) == ["quux"]
+    assert len(Set(fst_path + '.rev')) == 5
+    # A companion of another Set with as many keys is rebuilt too
+    do_build(fst_path, keys=["apple", "banana"])
+    Set(fst_path).enable_suffix_search()
+    do_build(fst_path, keys=["cherry", "date"])
+    fst_set = Set(fst_path)
+    fst_set.enable_suffix_search()
+    assert list(fst_set.ends_with("ana")) == []
+    assert list(fst_set.ends_with("rry")) == ["cherry"]
+    # An up-to-date companion is reused
+    mtime = tmpdir.join('test.fst.rev').mtime()
+    Set(fst_path).enable_suffix_search()
+    assert tmpdir.join('test.fst.rev').mtime() == mtime
+
+
+def test_contains_substring(fst_set):
+    with pytest.raises(ValueError):
+        fst_set.contains_substring("a")
+    fst_set.enable_substring_search()
+    assert fst_set.contains_substring("a") == ["bar", "baz"]
+    assert fst_set.contains_substring("oo") == ["foo"]
+    assert fst_set.contains_substring(u"ö") == [u"möö"]
+    assert fst_set.contains_substring("") == sorted(TEST_KEYS)
+    assert fst_set.contains_substring("q") == []
+
+
+def test_contains_substring_verifies(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path, keys=["xab", "zzabxq"])
+    fst_set = Set(fst_path)
+    fst_set.enable_substring_search()
+    assert tmpdir.join('test.fst.sub').exists()
+    # "abx" is a prefix of the index entry "ab" + "xab" for "xab"
+    assert fst_set.contains_substring("abx") == ["zzabxq"]
+    reopened = Set(fst_path)
+    reopened.enable_substring_search()
+    assert reopened.contains_substring("xa") == ["xab"]
+
+
+def test_contains_substring_stale_companion(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path, keys=["ab", "cd"])
+    Set(fst_path).enable_substring_search()
+    # Same keys lengths, so as many index entries, but different keys
+    do_build(fst_path, keys=["ef", "gh"])
+    fst_set = Set(fst_path)
+    fst_set.enable_substring_search()
+    assert fst_set.contains_substring("b") == []
+    assert fst_set.contains_substring("h") == ["gh"]
+    # An unrelated FST with as many entries is not read as an index
+    other = str(tmpdir.join('other.fst'))
+    do_build(other, keys=["w", "x", "y", "z"])
+    fst_set.enable_substring_search(path=other)
+    assert fst_set.contains_substring("e") == ["ef"]
+
+
+def build_set(keys):
+    builder = SetBuilder()
+    for key in sorted(keys):
+        builder.insert(key)
+    return builder.finish()
+
+
+def test_search_translit_builtin():
+    cities = build_set([u"Москва", u"Львів", u"Zürich", u"Zurich", u"Berlin"])
+    with pytest.raises(ValueError):
+        cities.search_translit("moskva")
+    cities.enable_translit("cyrillic")
+    assert cities.search_translit("moskva") == [u"Москва"]
+    assert cities.search_translit("LVIV") == [u"Львів"]
+    assert cities.search_translit("berlin") == ["Berlin"]
+    assert cities.search_translit("paris") == []
+    cities.enable_translit("latin")
+    assert cities.search_translit("zurich") == ["Zurich", u"Zürich"]
+    assert cities.search_translit(u"Zürich") == ["Zurich", u"Zürich"]
+    assert cities.search_translit("zurich", limit=1) == ["Zurich"]
+    assert cities.search_translit("zurich", offset=1) == [u"Zürich"]
+
+
+def test_search_translit_custom():
+    cities = build_set([u"北京", u"上海"])
+    cities.enable_translit({u"北": "bei", u"京": "jing", u"上": "shang",
+                            u"海": "hai"})
+    assert cities.search_translit("beijing") == [u"北京"]
+    cities.enable_translit(lambda s: s.replace(u"北京", "peking"))
+    assert cities.search_translit("Peking") == [u"北京"]
+    with pytest.raises(ValueError):
+        cities.enable_translit("klingon")
+    with pytest.raises(ValueError):
+        cities.enable_translit({u"北京": "beijing"})
+    with pytest.raises(TypeError):
+        cities.enable_translit(3)
+
+
+def test_search_translit_file(tmpdir):
+    path = str(tmpdir.join("cities.translit"))
+    cities = build_set([u"Москва", u"Минск"])
+    cities.enable_translit("cyrillic", path)
+    assert os.path.exists(path)
+    cities.enable_translit("cyrillic", path)
+    assert cities.search_translit("minsk") == [u"Минск"]
+    # A file built with another scheme is rebuilt, not reused
+    mtime = os.path.getmtime(path)
+    cities.enable_translit({u"м": "x"}, path)
+    assert os.path.getmtime(path) != mtime
+    assert cities.search_translit(u"xинск") == [u"Минск"]
+    assert cities.search_translit("minsk") == []
+
+
+def stem(word):
+    for suffix in ("ing", "es", "s"):
+        if word.endswith(suffix):
+            return word[:-len(suffix)]
+    return word
+
+
+def test_search_normalized(tmpdir):
+    words = build_set(["box", "boxes", "run", "runs", "walk"])
+    with pytest.raises(ValueError):
+        words.search_normalized("boxes")
+    path = str(tmpdir.join("words.norm"))
+    words.enable_normalized_search(stem, path)
+    assert words.search_normalized("boxing") == ["box", "boxes"]
+    assert words.search_normalized("run") == ["run", "runs"]
+    assert words.search_normalized("run", offset=1, limit=5) == ["runs"]
+    assert words.search_normalized("talks") == []
+    words.enable_normalized_search(stem, path)
+    assert words.search_normalized("walks") == ["walk"]
+    # A file built with another normalizer is rebuilt, not reused
+    words.enable_normalized_search(str.upper, path)
+    assert words.search_normalized("boxes") == ["boxes"]
+    words.enable_normalized_search(lambda word: word[:2], path)
+    assert words.search_normalized("rub") == ["run", "runs"]
+
+
+def test_segment():
+    builder = SetBuilder()
+    for word in sorted([u"北京", u"北京大学", u"大学", u"生", u"new", u"newyork"]):
+        builder.insert(word)
+    lexicon = builder.finish()
+    text = u"北京大学生"
+    tokens = lexicon.segment(text)
+    assert [text[s:e] for s, e in tokens] == [u"北京大学", u"生"]
+    text = u"newyork!new"
+    assert [text[s:e] for s, e in lexicon.segment(text)] == [
+        "newyork", "!", "new"]
+    assert lexicon.segment("") == []
+
+
+def test_longest_prefix():
+    lexicon = from_iter([u"北京", u"北京大学", u"大学", u"new", u"newyork"])
+    assert lexicon.longest_prefix(u"北京大学生") == u"北京大学"
+    assert lexicon.longest_prefix(u"北京大") == u"北京"
+    assert lexicon.longest_prefix(b"newyorker") == "newyork"
+    assert lexicon.longest_prefix("york") is None
+    assert lexicon.longest_prefix("") is None
+    assert lexicon.all_prefixes("newyorker") == ["new", "newyork"]
+    assert lexicon.all_prefixes("ne") == []
+    assert from_iter(["", "a"]).all_prefixes("ab") == ["", "a"]
+
+
+def test_close(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+    with Set(fst_path) as fst_set:
+        assert not fst_set.closed
+        assert "bar" in fst_set
+        keys = iter(fst_set)
+    assert fst_set.closed
+    assert "closed" in repr(fst_set)
+    for op in (len, list, lambda s: "bar" in s, lambda s: s.search_re("ba.*"),
+               lambda s: s | Set.empty(), lambda s: Set.empty() | s):
+        with pytest.raises(ClosedError, match="FST is closed"):
+            op(fst_set)
+    assert issubclass(ClosedError, ValueError)
+    fst_set.close()
+    # Iterators created before closing keep their own reference to the data
+    assert list(keys) == sorted(TEST_KEYS)
+
+
+def test_weakref(fst_set):
+    import weakref
+    for obj in (fst_set, iter(fst_set), fst_set.search_re(r'ba.*'),
+                fst_set.union(fst_set), SetBuilder()):
+        ref = weakref.ref(obj)
+        assert ref() is obj
+    cache = weakref.WeakValueDictionary()
+    cache["set"] = fst_set
+    assert cache["set"] is fst_set
+
+
+def test_write_lines(fst_set, tmpdir):
+    path = tmpdir.join('keys.txt')
+    fst_set.write_lines(str(path))
+    assert path.read_text("utf8").splitlines() == sorted(TEST_KEYS)
+
+
+def test_write_binary(fst_set, tmpdir):
+    import struct
+    path = tmpdir.join('keys.bin')
+    fst_set.write_binary(str(path))
+    data = path.read_binary()
+    keys = []
+    while data:
+        (length,) = struct.unpack("<I", data[:4])
+        keys.append(data[4:4 + length].decode("utf8"))
+        data = data[4 + length:]
+    assert keys == sorted(TEST_KEYS)
+
+
+def test_arrow_c_stream(fst_set):
+    pa = pytest.importorskip("pyarrow")
+    keys = pa.chunked_array(fst_set)
+    assert keys.type == pa.string()
+    assert keys.to_pylist() == sorted(TEST_KEYS)
+
+
+def test_iter_split():
+    keys = ["http://example.com/a", "http://example.com/b", "http://other.org/",
+            "möö", "x"]
+    s = from_iter(keys)
+    pairs = list(s.iter_split(19))
+    assert [p + s_ for p, s_ in pairs] == sorted(keys)
+    assert pairs[0] == ("http://example.com/", "a")
+    assert pairs[0][0] is pairs[1][0]
+    # Prefixes are never cut inside a multi-byte character
+    assert ("mö", "ö") in list(s.iter_split(4))
+    pairs = list(s.iter_split(19, offset=1, limit=2))
+    assert [p + s_ for p, s_ in pairs] == sorted(keys)[1:3]
+
+
+def test_subclass(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+
+    class GazetteerSet(Set):
+        def __new__(cls, path, lang):
+            return super().__new__(cls, path)
+
+        def __init__(self, path, lang):
+            super().__init__(path)
+            self.lang = lang
+
+        def has_all(self, keys):
+            return all(k in self for k in keys)
+
+    gazetteer = GazetteerSet(fst_path, "de")
+    assert isinstance(gazetteer, Set)
+    assert gazetteer.lang == "de"
+    assert gazetteer.has_all(TEST_KEYS)
+    assert list(gazetteer) == sorted(TEST_KEYS)
+
+    for dup in (copy.copy(gazetteer), copy.deepcopy(gazetteer)):
+        assert type(dup) is GazetteerSet
+        assert dup.lang == "de"
+        assert list(dup) == sorted(TEST_KEYS)
+    rebuilt = gazetteer.rebuild(add=["zzz"])
+    assert type(rebuilt) is GazetteerSet
+    assert "zzz" in rebuilt
+    for built in (GazetteerSet.from_iter(["b", "a"]), GazetteerSet.from_index(["a"]),
+                  GazetteerSet.empty()):
+        assert type(built) is GazetteerSet
+        assert built.has_all([])
+    assert type(Set.from_iter(["a"])) is Set
+
+
+def test_repr(tmpdir, fst_set):
+    fst_path = str(tmpdir.join('test.fst'))
+    size = os.path.getsize(fst_path)
+    assert repr(fst_set) == "<rust_fst.Set len=4 size=%dB source=%r mmap>" % (
+        size, fst_path)
+    assert repr(from_iter(["a"])).startswith("<rust_fst.Set len=1 size=")
+    assert repr(from_iter(["a"])).endswith(" memory>")
+
+
 def test_len(fst_set):
     assert len(fst_set) == 4
 
@@ -77,6 +519,339 @@ def test_contains(fst_set):
         assert key in fst_set
 
 
+def test_memory_usage(fst_set):
+    usage = fst_set.memory_usage()
+    assert usage["heap"] == 0
+    assert usage["mmap"] > 0
+    assert usage["cache"] == 0
+    memset = from_iter(TEST_KEYS)
+    assert memset.memory_usage()["heap"] > 0
+    assert sys.getsizeof(memset) > memset.memory_usage()["heap"]
+
+
+def test_stats(tmpdir):
+    stats = build_set(["ab", "cb"]).stats()
+    # The suffix "b" is shared, so both keys end in the same two nodes.
+    assert stats["keys"] == 2
+    assert stats["nodes"] == 3
+    assert stats["transitions"] == 3
+    assert stats["final_nodes"] == 1
+    assert stats["max_out_degree"] == 2
+    assert stats["max_key_length"] == 2
+    path = str(tmpdir.join("stats.fst"))
+    do_build(path)
+    assert Set(path).stats()["size_bytes"] == os.path.getsize(path)
+    empty = build_set([]).stats()
+    assert (empty["keys"], empty["nodes"], empty["max_key_length"]) == (0, 1, 0)
+    assert Set(path).stats()["storage"] == "mmap"
+    assert stats["storage"] == "memory"
+    assert stats["version"] == 3
+
+
+def test_verify(tmpdir):
+    path = str(tmpdir.join("verify.fst"))
+    do_build(path)
+    Set(path).verify()
+    with open(path, "rb") as fp:
+        data = bytearray(fp.read())
+    data[len(data) // 2] ^= 0xFF
+    corrupt = Set(bytes(data))
+    with pytest.raises(FormatError):
+        corrupt.verify()
+
+
+def test_key_length_stats():
+    stats = build_set(["a", "bb", "cc", u"é" * 3]).key_length_stats()
+    assert stats == {"count": 4, "min": 1, "max": 6, "mean": 2.75,
+                     "histogram": {1: 1, 2: 2, 6: 1}}
+    assert build_set([]).key_length_stats() == {
+        "count": 0, "min": None, "max": None, "mean": None, "histogram": {}}
+
+
+def test_prefix_histogram():
+    s = build_set(["apple", "apricot", "b", "banana", "c", u"übel", u"über"])
+    assert s.prefix_histogram() == {"a": 2, "b": 2, "c": 1, u"ü": 2}
+    assert list(s.prefix_histogram(2).items()) == [
+        ("ap", 2), ("b", 1), ("ba", 1), ("c", 1), (u"üb", 2)]
+    assert s.prefix_histogram(0) == {"": 7}
+    assert build_set([]).prefix_histogram() == {}
+
+
+def test_export_dot(tmpdir):
+    path = str(tmpdir.join("set.dot"))
+    build_set(["ab", "cb", 'q"\x01']).export_dot(path)
+    dot = tmpdir.join("set.dot").read()
+    assert dot.startswith("digraph fst {") and dot.rstrip().endswith("}")
+    assert '0 -> 1 [label = "a"];' in dot
+    assert 'label = "\\""' in dot
+    assert 'label = "\\\\x01"' in dot
+    assert "doublecircle" in dot
+    assert "more" not in dot
+    build_set(["ab", "cb"]).export_dot(path, max_nodes=2)
+    assert '-> more [label = "b"]' in tmpdir.join("set.dot").read()
+
+
+def test_residency(fst_set):
+    assert 0.0 <= fst_set.residency() <= 1.0
+    list(fst_set)
+    assert fst_set.residency() == 1.0
+    assert build_set(["a"]).residency() == 1.0
+
+
+def test_copy(fst_set):
+    shallow = copy.copy(fst_set)
+    assert list(shallow) == list(fst_set)
+    assert shallow.memory_usage() == fst_set.memory_usage()
+    deep = copy.deepcopy(fst_set)
+    assert list(deep) == list(fst_set)
+    # The FST data is shared, not copied
+    assert deep.memory_usage() == fst_set.memory_usage()
+    fst_set.close()
+    assert list(deep) == list(shallow) == sorted(TEST_KEYS)
+
+
+def test_to_bytes_and_write(fst_set, tmpdir):
+    data = fst_set.to_bytes()
+    assert data == tmpdir.join("test.fst").read_binary()
+    assert list(Set(data)) == list(fst_set)
+    path = str(tmpdir.join("copy.fst"))
+    build_set(["bar", "foo"]).write(path)
+    assert list(Set(path)) == ["bar", "foo"]
+
+
+def test_open_buffer(fst_set, tmpdir):
+    data = tmpdir.join("test.fst").read_binary()
+    s = Set(data)
+    assert list(s) == list(fst_set)
+    assert s.memory_usage()["heap"] == 0
+    assert s.memory_usage()["buffer"] == len(data)
+    assert list(Set(memoryview(data)[:])) == list(fst_set)
+    array = bytearray(data)
+    s = Set(array)
+    assert "bar" in s
+    with pytest.raises(BufferError):
+        array.extend(b"x")
+    with tmpdir.join("test.fst").open("rb") as f:
+        mapped = mmap.mmap(f.fileno(), 0, access=mmap.ACCESS_READ)
+        assert list(Set(mapped)) == list(fst_set)
+    with pytest.raises(TypeError, match="bytes-like"):
+        Set(42)
+
+
+def test_ordinals(fst_set):
+    keys = list(fst_set)
+    for i, key in enumerate(keys):
+        assert fst_set.get_index(key) == i
+        assert fst_set.get_key(i) == key
+    assert fst_set.get_key(-1) == keys[-1]
+    with pytest.raises(KeyError):
+        fst_set.get_index("nope")
+    with pytest.raises(IndexError):
+        fst_set.get_key(len(keys))
+    with pytest.raises(IndexError):
+        fst_set.get_key(-len(keys) - 1)
+    big = from_iter(["%05d" % i for i in range(0, 3000, 3)])
+    assert big.get_index("01500") == 500
+    assert big.get_key(999) == "02997"
+    assert [big.get_index(big.get_key(i)) for i in (255, 256, 257)] == [
+        255, 256, 257]
+
+
+
+def test_ordinals_across_threads():
+    # The first lookups build the ordinal index with the GIL released; other
+    # threads arriving meanwhile must not deadlock with the builder.
+    import threading
+    big = from_iter(["%07d" % i for i in range(300000)])
+    barrier = threading.Barrier(4)
+    found = []
+
+    def lookup():
+        barrier.wait()
+        found.append(big.get_key(5))
+
+    threads = [threading.Thread(target=lookup, daemon=True) for _ in range(4)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join(60)
+    assert found == ["0000005"] * 4
+
+
+def test_sample(fst_set):
+    assert fst_set.sample(4) == list(fst_set)
+    assert fst_set.sample(0) == []
+    with pytest.raises(ValueError):
+        fst_set.sample(5)
+    big = from_iter(["%05d" % i for i in range(0, 30000, 3)])
+    sample = big.sample(100, seed=7)
+    assert sample == sorted(set(sample)) and len(sample) == 100
+    assert all(key in big for key in sample)
+    assert big.sample(100, seed=7) == sample
+    assert big.sample(100, seed=8) != sample
+    firsts = {big.sample(1)[0] for _ in range(20)}
+    assert len(firsts) > 1
+
+def test_pickle(fst_set, tmpdir):
+    mapped = pickle.loads(pickle.dumps(fst_set))
+    assert list(mapped) == list(fst_set)
+    assert mapped.memory_usage()["mmap"] > 0
+    s = Set(fst_set.to_bytes(), cache_size=16, counters=True)
+    restored = pickle.loads(pickle.dumps(s))
+    assert list(restored) == list(fst_set)
+    assert restored.memory_usage()["mmap"] == 0
+    assert "foo" in restored
+    assert restored.counters()["lookups"] == 1
+    fst_set.close()
+    with pytest.raises(ValueError):
+        pickle.dumps(fst_set)
+
+
+def test_bytes_keys():
+    builder = SetBuilder(None)
+    for key in sorted([b"\x00\x01", b"\xff\xfe", "z\xfc".encode("latin1")]):
+        builder.insert(key)
+    s = builder.finish()
+    assert b"\xff\xfe" in s
+    assert b"\x00\x01" in s
+    assert b"\x00" not in s
+    with pytest.raises(TypeError):
+        1 in s
+    with pytest.raises(ValueError):
+        s.search_lev(b"\xff", 1)
+
+
+def test_raw_streams():
+    s = build_set([b"ok", b"bad\xff"])
+    reset_lossy_decodes()
+    assert list(s.__iter__().raw()) == [b"bad\xff", b"ok"]
+    assert list(s.search_prefix("bad").raw()) == [b"bad\xff"]
+    assert list(s.range(ge=b"c").raw()) == [b"ok"]
+    assert list(s.union(build_set([b"\x00"])).raw()) == [
+        b"\x00", b"bad\xff", b"ok"]
+    assert list(s.search_re(r"ok", parallel=True).raw()) == [b"ok"]
+    assert lossy_decodes() == (0, [])
+
+
+def test_bytes_search(fst_set):
+    assert list(fst_set.search_re(rb"ba.")) == ["bar", "baz"]
+    assert list(fst_set.search_lev(b"bam", 1)) == ["bar", "baz"]
+    assert u"möö".encode("utf8") in fst_set
+
+
+def test_contains_cached(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+    cached = Set(fst_path, cache_size=2)
+    for _ in range(3):
+        for key in TEST_KEYS:
+            assert key in cached
+        assert "qux" not in cached
+
+
+def test_counters(tmpdir):
+    fst_path = str(tmpdir.join('test.fst'))
+    do_build(fst_path)
+    with pytest.raises(ValueError):
+        Set(fst_path).counters()
+    fst_set = Set(fst_path, cache_size=10, counters=True)
+    assert "bar" in fst_set
+    assert "bar" in fst_set
+    assert "qux" not in fst_set
+    assert list(fst_set.search_re(r"ba.")) == ["bar", "baz"]
+    list(fst_set)
+    assert fst_set.counters() == {
+        "lookups": 3, "hits": 2, "misses": 1, "cache_hits": 1,
+        "streams": 2, "keys": 6}
+    fst_set.reset_counters()
+    assert set(fst_set.counters().values()) == {0}
+
+
+def test_range(fst_set):
+    assert list(fst_set.range()) == list(fst_set)
+    assert list(fst_set.range(ge="baz")) == ["baz", "foo", u"möö"]
+    assert list(fst_set.range(gt="baz", lt=u"möö")) == ["foo"]
+    assert list(fst_set.range(ge=b"bar", le="foo")) == ["bar", "baz", "foo"]
+    assert list(fst_set.range(gt="foo", lt="bar")) == []
+
+
+def test_reverse_iteration(fst_set):
+    assert list(fst_set.iter(reverse=True)) == sorted(TEST_KEYS, reverse=True)
+    assert list(reversed(fst_set)) == sorted(TEST_KEYS, reverse=True)
+    assert list(fst_set.iter()) == list(fst_set)
+    assert list(fst_set.search_prefix("ba", reverse=True)) == ["baz", "bar"]
+    keys = ["", "a", "ab", "abc", "abd", "b", "ba", "bb", "c"]
+    s = build_set(keys)
+    assert list(s.iter(reverse=True)) == keys[::-1]
+    assert list(s.search_prefix("ab", reverse=True)) == ["abd", "abc", "ab"]
+    bounds = [None, "", "a", "ab", "abc", "abcd", "b", "bz", "c", "d"]
+    for lower in ("ge", "gt"):
+        for upper in ("le", "lt"):
+            for lo in bounds:
+                for hi in bounds:
+                    kwargs = {lower: lo, upper: hi}
+                    forward = list(s.range(**kwargs))
+                    assert list(s.range(reverse=True, **kwargs)) == (
+                        forward[::-1]), kwargs
+
+
+def test_bounded_search(fst_set):
+    assert list(fst_set.search_lev("baz", 1, prefix="bar")) == ["bar"]
+    assert list(fst_set.search_lev("baz", 1, ge="bas")) == ["baz"]
+    assert list(fst_set.search_re(r".*", ge="baz", lt="foo")) == ["baz"]
+    assert list(fst_set.search_re(r"ba.", prefix="ba", lt="baz")) == ["bar"]
+    for parallel in (False, True):
+        assert list(fst_set.search_re(
+            r".*o.*", parallel=parallel, prefix="f")) == ["foo"]
+        assert list(fst_set.search_lev(
+            "fop", 1, parallel=parallel, lt="foo")) == []
+
+
+def test_from_iter(tmpdir):
+    keys = (k for k in ["foo", b"bar", "baz", "foo"])
+    assert list(Set.from_iter(keys)) == ["bar", "baz", "foo"]
+    fst_path = str(tmpdir.join("from_iter.fst"))
+    s = Set.from_iter(sorted(TEST_KEYS), path=fst_path, sorted=True)
+    assert list(s) == sorted(TEST_KEYS)
+    assert list(Set(fst_path)) == sorted(TEST_KEYS)
+    with pytest.raises(OutOfOrderError):
+        Set.from_iter(["b", "a"], sorted=True)
+    with pytest.raises(TypeError):
+        Set.from_iter([1])
+
+
+def test_insert_from_file(tmpdir):
+    path = str(tmpdir.join("keys.txt.gz"))
+    with gzip.open(path, "wb") as f:
+        f.write(b"bar\n\nbaz\r\nfoo\n")
+    builder = SetBuilder(None)
+    assert builder.insert_from_file(path) == 3
+    builder.insert("qux")
+    assert list(builder.finish()) == ["bar", "baz", "foo", "qux"]
+    unsorted = tmpdir.join("unsorted.txt")
+    unsorted.write_text(u"b\na\n", "utf8")
+    with pytest.raises(OutOfOrderError):
+        SetBuilder(None).insert_from_file(str(unsorted))
+
+
+def test_search_prefix(fst_set):
+    assert list(fst_set.search_prefix("ba")) == ["bar", "baz"]
+    assert list(fst_set.search_prefix(u"mö")) == [u"möö"]
+    assert list(fst_set.search_prefix("")) == list(fst_set)
+    assert list(fst_set.search_prefix("qux")) == []
+
+
+def test_search_subsequence():
+    paths = build_set(["/home/projects/bar", "/home/projects/foo", "/tmp/foo"])
+    assert list(paths.search_subsequence("hpf")) == ["/home/projects/foo"]
+    assert list(paths.search_subsequence("foo")) == [
+        "/home/projects/foo", "/tmp/foo"]
+    assert list(paths.search_subsequence("")) == list(paths)
+    assert list(paths.search_subsequence("foo", offset=1)) == ["/tmp/foo"]
+    assert paths.search_subsequence("", limit=1).to_list() == [
+        "/home/projects/bar"]
+
+
 def test_issubset(tmpdir, fst_set):
     oth_path = tmpdir.join('other.fst')
     do_build(str(oth_path), keys=TEST_KEYS[:-2])
@@ -109,6 +884,106 @@ def test_isdisjoint(tmpdir, fst_set):
 #     assert matches == ["bar", "baz"]
 
 
+def test_search_parallel():
+    keys = ["%s%03d" % (c, i) for c in "abcdefghij" for i in range(50)]
+    s = from_iter(keys)
+    assert list(s.search_re(r'[ace]0.5', parallel=True)) == \
+        list(s.search_re(r'[ace]0.5'))
+    assert list(s.search_lev("f010", 1, parallel=True)) == \
+        list(s.search_lev("f010", 1))
+
+
+def test_count_parallel():
+    keys = ["%s%03d" % (c, i) for c in "abcdefghij" for i in range(50)]
+    s = from_iter(keys)
+    assert s.count_re(r'[ace]0.5') == 15
+    assert s.count_re(r'[ace]0.5', parallel=True) == 15
+    assert s.count_lev("f010", 1, parallel=True) == s.count_lev("f010", 1) == 23
+
+
+def test_count():
+    keys = ["%s%03d" % (c, i) for c in "abcdefghij" for i in range(50)]
+    s = from_iter(keys)
+    assert s.count() == len(s) == 500
+    assert s.count(prefix="c") == 50
+    assert s.count(prefix="c", regex=r'.*5') == 5
+    assert s.count(ge="b010", lt="c") == 40
+    assert s.count(prefix="c", ge="b", lt="c010") == 10
+    assert s.count(regex=r'[ace]0.5', lt="b") == 5
+    assert s.count(ge="c", lt="b") == 0
+    with pytest.raises(FstError):
+        s.count(regex="(")
+
+
+def test_trace_hook(fst_set):
+    events = []
+    set_trace_hook(lambda *event: events.append(event))
+    try:
+        from_iter(TEST_KEYS)
+        list(fst_set.search_lev("bam", 1))
+        list(fst_set.union(fst_set))
+    finally:
+        set_trace_hook(None)
+    assert [(op, count) for op, _, count in events] == [
+        ("SetBuilder.finish", 4), ("Set.search_lev", 2), ("Set.union", 4)]
+    assert all(seconds >= 0 for _, seconds, _ in events)
+
+
+def test_trace_hook_threshold(fst_set):
+    events = []
+    set_trace_hook(lambda *event: events.append(event), threshold=3600)
+    try:
+        list(fst_set.search_re(r'ba.*'))
+    finally:
+        set_trace_hook(None)
+    assert events == []
+
+
+def test_levautomaton_too_big(fst_set):
+    with pytest.raises(LevenshteinTooLargeError):
+        fst_set.search_lev("areallylongstring", 8)
+
+
+def test_search_re_match_mode():
+    s = from_iter(["Foobar", "barfoo", "foo", "xfooy", "bar"])
+    assert list(s.search_re("foo")) == ["foo"]
+    assert list(s.search_re("foo", match_mode="prefix")) == ["foo"]
+    assert list(s.search_re("foo", match_mode="substring")) == [
+        "barfoo", "foo", "xfooy"]
+    assert list(s.search_re("foo", case_insensitive=True)) == ["foo"]
+    assert list(s.search_re("foo", match_mode="prefix",
+                            case_insensitive=True)) == ["Foobar", "foo"]
+    assert list(s.search_re("o+b|rf", match_mode="substring",
+                            parallel=True)) == ["Foobar", "barfoo"]
+    assert s.count_re("foo", match_mode="substring") == 3
+    with pytest.raises(ValueError):
+        s.search_re("foo", match_mode="fuzzy")
+    with pytest.raises(FstError):
+        s.search_re("a)|(b", match_mode="substring")
+
+
+def test_levenshtein_query(fst_set):
+    query = LevenshteinQuery("bam", 1)
+    assert (query.key, query.max_dist) == ("bam", 1)
+    assert repr(query) == '<rust_fst.LevenshteinQuery key="bam" max_dist=1>'
+    other = from_iter(["bag", "ham", "spam"])
+    for _ in range(2):
+        assert list(fst_set.search(query)) == ["bar", "baz"]
+        assert list(other.search(query)) == ["bag", "ham"]
+    assert list(fst_set.search(query, parallel=True)) == ["bar", "baz"]
+    assert list(fst_set.search(query, prefix="baz")) == ["baz"]
+    assert list(other.search(query, ge="b", lt="h")) == ["bag"]
+    with pytest.raises(LevenshteinTooLargeError):
+        LevenshteinQuery("abcdef", 2, state_limit=10)
+    with pytest.raises(TypeError):
+        fst_set.search("bam")
+
+
+def test_bad_regex(fst_set):
+    with pytest.raises(FstError):
+        fst_set.search_re(r'ba(')
+
+
 # def test_levautomaton_too_big(fst_set):
 #     # Rust implementation might not throw error for large distance, or throws ValueError
 #     # Let's assume ValueError if it fails, or maybe it just works?
@@ -135,30 +1010,385 @@ def from_iter(keys):
         builder.insert(key)
     return builder.finish()
 
-# def test_union():
-#     a = from_iter(["bar", "foo"])
-#     b = from_iter(["baz", "foo"])
-#     assert list(a.union(b)) == ["bar", "baz", "foo"]
+def test_abc():
+    a = from_iter(["bar", "foo"])
+    assert isinstance(a, collections.abc.Set)
+    assert a.isdisjoint(from_iter(["baz"]))
+    assert a.isdisjoint(["x", 1]) and not a.isdisjoint(iter(["foo"]))
+    assert a == {"bar", "foo"} and {"bar", "foo"} == a
+    assert a != {"bar"} and a != {"bar", "foo", 1}
+    assert a <= {"bar", "foo", "qux"} and a < {"bar", "foo", 1}
+    assert a >= frozenset(["bar"]) and not a > {"bar", "foo"}
+    assert not a <= {"bar"} and a != ["bar", "foo"]
+
+
+def test_operators():
+    a = from_iter(["bar", "foo"])
+    b = from_iter(["baz", "foo"])
+    assert list(a | b) == ["bar", "baz", "foo"]
+    assert list(a & b) == ["foo"]
+    assert list(a - b) == ["bar"]
+    assert list(a ^ b) == ["bar", "baz"]
+    assert isinstance(a & b, Set)
+    assert list(a | {"qux"}) == ["bar", "foo", "qux"]
+    assert list({"qux"} | a) == ["bar", "foo", "qux"]
+    assert list(a & {"foo", 1}) == ["foo"]
+    assert list(["foo", 1] & a) == ["foo"]
+    assert list(a - {"foo", 1}) == ["bar"]
+    assert list({"foo", "qux"} - a) == ["qux"]
+    assert list(a ^ ["foo", "qux"]) == ["bar", "qux"]
+    assert isinstance({"qux"} ^ a, Set)
+    with pytest.raises(TypeError):
+        a | {1}
+    with pytest.raises(TypeError):
+        a & 1
+
+
+def test_union():
+    a = from_iter(["bar", "foo"])
+    b = from_iter(["baz", "foo"])
+    assert list(a.union(b)) == ["bar", "baz", "foo"]
+
+
+def test_difference():
+    a = from_iter(["bar", "foo"])
+    b = from_iter(["baz", "foo"])
+    assert list(a.difference(b)) == ["bar"]
+
+
+def test_symmetric_difference():
+    a = from_iter(["bar", "foo"])
+    b = from_iter(["baz", "foo"])
+    assert list(a.symmetric_difference(b)) == ["bar", "baz"]
+
+
+def test_intersection():
+    a = from_iter(["bar", "foo"])
+    b = from_iter(["baz", "foo"])
+    assert list(a.intersection(b)) == ["foo"]
+
+
+
+def test_multiway_operations():
+    a = from_iter(["bar", "baz", "foo"])
+    b = from_iter(["baz", "foo", "qux"])
+    c = from_iter(["foo", "zap"])
+    assert list(a.union(b, c)) == ["bar", "baz", "foo", "qux", "zap"]
+    assert list(a.intersection(b, c)) == ["foo"]
+    assert list(a.difference(b, c)) == ["bar"]
+    assert list(a.symmetric_difference(b, c)) == ["bar", "foo", "qux", "zap"]
+    assert list(a.union()) == ["bar", "baz", "foo"]
+    with pytest.raises(TypeError):
+        a.union(["qux"])
+
+
+def test_into_set(tmpdir):
+    a = from_iter(["bar", "baz", "foo"])
+    b = from_iter(["baz", "foo", "qux"])
+    union = a.union(b).into_set()
+    assert isinstance(union, Set)
+    assert list(union) == ["bar", "baz", "foo", "qux"]
+    assert list(a.intersection(b).into_set()) == ["baz", "foo"]
+    assert list(a.difference(b).into_set()) == ["bar"]
+    path = str(tmpdir.join("sym.fst"))
+    sym = a.symmetric_difference(b).into_set(path)
+    assert list(sym) == list(Set(path)) == ["bar", "qux"]
+    # Only the keys not yet streamed are built, and the stream is used up.
+    stream = a.union(b)
+    assert next(stream) == "bar"
+    assert list(stream.into_set()) == ["baz", "foo", "qux"]
+    assert list(stream) == []
+    evens = from_iter(["%05d" % i for i in range(0, 5000, 2)])
+    threes = from_iter(["%05d" % i for i in range(0, 5000, 3)])
+    stream = evens.union(threes)
+    head = [next(stream) for _ in range(1500)]
+    assert head + list(stream.into_set()) == list(evens | threes)
+
+
+def test_limit_offset():
+    keys = ["%05d" % i for i in range(3000)]
+    s = from_iter(keys)
+    assert list(s.range(ge="00100", limit=3)) == keys[100:103]
+    assert list(s.range(ge="00100", lt="00110", offset=8)) == keys[108:110]
+    assert list(s.range(offset=5, limit=2, reverse=True)) == ["02994", "02993"]
+    assert list(s.search_prefix("001", offset=10, limit=5)) == keys[110:115]
+    assert list(s.search_prefix("001", limit=0)) == []
+    assert list(s.range(offset=5000)) == []
+    for parallel in (False, True):
+        assert list(s.search_re(r"0.*7", parallel=parallel, limit=3)) == [
+            "00007", "00017", "00027"]
+        assert list(s.search_re(r"0.*7", parallel=parallel, offset=299)) == [
+            "02997"]
+        assert list(s.search_lev("00000", 1, parallel=parallel, offset=1,
+                                 limit=2)) == ["00001", "00002"]
+    evens = from_iter(keys[::2])
+    odds = from_iter(keys[1::2])
+    assert list(evens.union(odds, offset=1024, limit=1100)) == keys[1024:2124]
+    assert list(evens.union(odds, offset=2990)) == keys[2990:]
+    assert list(evens.intersection(odds, limit=1)) == []
+    assert list(s.difference(evens, limit=2)) == ["00001", "00003"]
+    assert list(s.symmetric_difference(odds, offset=1, limit=1)) == ["00002"]
+    union = evens.union(odds, offset=10, limit=20)
+    assert next(union) == "00010"
+    assert list(union.into_set()) == keys[11:30]
+
+
+def test_length_hint():
+    import operator
+    s = from_iter(["%03d" % i for i in range(100)])
+    stream = iter(s)
+    assert operator.length_hint(stream) == 100
+    next(stream)
+    assert operator.length_hint(stream) == 99
+    assert operator.length_hint(s.range(offset=10, limit=20)) == 20
+    assert operator.length_hint(s.range(offset=95, limit=20)) == 5
+    assert operator.length_hint(s.range(ge="050"), -1) == -1
+    assert operator.length_hint(s.search_re(r".*5", parallel=True)) == 10
+    stream = s.range(offset=98)
+    assert operator.length_hint(stream) == 2
+    assert list(stream) == ["098", "099"]
+    assert operator.length_hint(stream) == 0
+
+
+def test_streams_across_threads():
+    from concurrent.futures import ThreadPoolExecutor
+    evens = from_iter(["%05d" % i for i in range(0, 5000, 2)])
+    threes = from_iter(["%05d" % i for i in range(0, 5000, 3)])
+    streams = [evens.union(threes), evens.search_re("0.*8"),
+               evens.range(ge="01000"), iter(threes),
+               evens.search_lev("00100", 1)]
+    expected = [list(evens.union(threes)), list(evens.search_re("0.*8")),
+                list(evens.range(ge="01000")), list(threes),
+                list(evens.search_lev("00100", 1))]
+    with ThreadPoolExecutor(4) as pool:
+        assert list(pool.map(list, streams)) == expected
+        assert list(pool.map(len, [evens, threes])) == [2500, 1667]
+
+
+def test_streams_across_batches():
+    # Searches and operations are advanced in batches of keys with the GIL
+    # released; the results must not depend on where a batch ends.
+    evens = from_iter(["%05d" % i for i in range(0, 5000, 2)])
+    threes = from_iter(["%05d" % i for i in range(0, 5000, 3)])
+    assert list(evens.union(threes)) == [
+        "%05d" % i for i in range(5000) if i % 2 == 0 or i % 3 == 0]
+    assert list(evens.intersection(threes)) == [
+        "%05d" % i for i in range(0, 5000, 6)]
+    assert list(evens.search_re("0.*8")) == [
+        "%05d" % i for i in range(0, 5000, 2) if str(i).endswith("8")]
+    assert list(evens | threes) == list(evens.union(threes))
 
 
-# def test_difference():
-#     a = from_iter(["bar", "foo"])
-#     b = from_iter(["baz", "foo"])
-#     assert list(a.difference(b)) == ["bar"]
+class Wildcard:
+    """Match keys against a pattern where "?" stands for any byte."""
 
+    def __init__(self, pattern, prune=True):
+        self.pattern = pattern.encode()
+        self.accepted = 0
+        if prune:
+            self.can_match = lambda state: state >= 0
 
-# def test_symmetric_difference():
-#     a = from_iter(["bar", "foo"])
-#     b = from_iter(["baz", "foo"])
-#     assert list(a.symmetric_difference(b)) == ["bar", "baz"]
+    def start(self):
+        return 0
 
+    def is_match(self, state):
+        return state == len(self.pattern)
+
+    def accept(self, state, byte):
+        self.accepted += 1
+        if 0 <= state < len(self.pattern) \
+                and self.pattern[state] in (byte, ord("?")):
+            return state + 1
+        return -1
+
+
+def test_search_custom():
+    s = build_set(TEST_KEYS + ["ba", "bazz"])
+    assert list(s.search_custom(Wildcard("ba?"))) == ["bar", "baz"]
+    assert list(s.search_custom(Wildcard("???"))) == ["bar", "baz", "foo"]
+    assert list(s.search_custom(Wildcard("???"), limit=1, offset=1)) == \
+        ["baz"]
+    assert list(s.search_custom(Wildcard("???"), prefix="f")) == ["foo"]
+    pruned, unpruned = Wildcard("ba?"), Wildcard("ba?", prune=False)
+    assert list(s.search_custom(unpruned)) == ["bar", "baz"]
+    list(s.search_custom(pruned))
+    assert pruned.accepted < unpruned.accepted
+
+
+def test_search_custom_error():
+    class Failing(Wildcard):
+        def accept(self, state, byte):
+            if byte == ord("z"):
+                raise RuntimeError("no z")
+            return super().accept(state, byte)
+
+    s = build_set(TEST_KEYS)
+    with pytest.raises(RuntimeError, match="no z"):
+        s.search_custom(Failing("ba?"))
+    with pytest.raises(AttributeError):
+        s.search_custom(object())
+
+
+def test_first_last_floor_ceiling():
+    s = build_set(["2024-01-03", "2024-01-07", "2024-01-07T12", "2024-02-01"])
+    assert s.first() == "2024-01-03"
+    assert s.last() == "2024-02-01"
+    assert s.floor("2024-01-07") == "2024-01-07"
+    assert s.floor("2024-01-07T00") == "2024-01-07"
+    assert s.floor("2024-01-08") == "2024-01-07T12"
+    assert s.floor("2024-01-01") is None
+    assert s.floor("9") == "2024-02-01"
+    assert s.ceiling("2024-01-07") == "2024-01-07"
+    assert s.ceiling("2024-01-07T") == "2024-01-07T12"
+    assert s.ceiling("2024-01-08") == "2024-02-01"
+    assert s.ceiling("2024-03") is None
+    assert s.ceiling("") == "2024-01-03"
+    empty = build_set([])
+    assert (empty.first(), empty.last(), empty.floor("a")) == (None,) * 3
+
+
+def test_floor_ceiling_match_scan():
+    keys = sorted({"%x" % (i * 7919 % 4096) for i in range(300)})
+    s = build_set(keys)
+    for probe in ["", "0", "1f", "7", "80", "a0a", "fff", "g"]:
+        below = [k for k in keys if k <= probe]
+        above = [k for k in keys if k >= probe]
+        assert s.floor(probe) == (below[-1] if below else None)
+        assert s.ceiling(probe) == (above[0] if above else None)
+
+
+def test_rebuild(tmpdir):
+    s = build_set(TEST_KEYS)
+    assert list(s.rebuild(add=["qux", "bar", "aaa"], remove=["baz", "nope"])) == [
+        "aaa", "bar", "foo", "möö", "qux"]
+    assert list(s.rebuild(add=["x"], remove=["x", "foo"])) == ["bar", "baz", "möö"]
+    assert list(s.rebuild()) == list(s)
+    assert list(s.rebuild(remove=TEST_KEYS)) == []
+    assert list(build_set([]).rebuild(add=iter(["b", "a", "b"]))) == ["a", "b"]
+    path = str(tmpdir.join("rebuilt.fst"))
+    rebuilt = s.rebuild(add=[b"zzz"], path=path)
+    assert os.path.exists(path)
+    assert "zzz" in rebuilt and len(rebuilt) == 5
+    with pytest.raises(TypeError):
+        s.rebuild(add=[1])
+
+
+def test_stream_to_list():
+    s = build_set(TEST_KEYS)
+    assert iter(s).to_list() == ["bar", "baz", "foo", "möö"]
+    assert s.range(ge="baz", limit=2).to_list() == ["baz", "foo"]
+    assert s.iter(reverse=True).to_list() == ["möö", "foo", "baz", "bar"]
+    assert s.search_re("ba.*").to_list() == ["bar", "baz"]
+    assert s.search_re("ba.*", parallel=True).to_list() == ["bar", "baz"]
+    assert s.search_lev("bam", 1).to_list() == ["bar", "baz"]
+    assert s.search_subsequence("bz").to_list() == ["baz"]
+    s.enable_suffix_search()
+    assert s.ends_with("z").raw().to_list() == [b"baz"]
+    other = build_set(["baz", "qux"])
+    assert s.union(other).to_list() == [
+        "bar", "baz", "foo", "möö", "qux"]
+    assert s.intersection(other).to_list() == ["baz"]
+    assert s.difference(other, offset=1).to_list() == ["foo", "möö"]
+    assert s.symmetric_difference(other).raw().to_list() == [
+        b"bar", b"foo", "möö".encode(), b"qux"]
+    stream = iter(s)
+    next(stream)
+    assert stream.to_list() == ["baz", "foo", "möö"]
+    assert stream.to_list() == []
+
+
+def test_stream_length_hint():
+    s = build_set(TEST_KEYS)
+    assert operator.length_hint(iter(s)) == 4
+    assert operator.length_hint(s.iter_split(1, offset=1)) == 3
+    s.enable_suffix_search()
+    other = build_set(["baz", "qux"])
+    streams = [s.ends_with("z"), s.search_re("ba.*"), s.search_lev("bam", 1),
+               s.search_subsequence("b"), s.union(other),
+               s.intersection(other), s.difference(other),
+               s.symmetric_difference(other)]
+    for stream in streams:
+        assert stream.__length_hint__() is NotImplemented
+        assert operator.length_hint(stream, -1) == -1
+    assert operator.length_hint(s.search_re("ba.*", parallel=True)) == 2
+
+
+def test_stream_to_list_counts():
+    s = Set(build_set(TEST_KEYS).to_bytes(), counters=True)
+    s.search_re("ba.*").to_list()
+    iter(s).to_list()
+    assert s.counters()["keys"] == 6
+    s.enable_suffix_search()
+    s.ends_with("z").to_list()
+    assert list(s.ends_with("o")) == ["foo"]
+    assert s.counters()["streams"] == 4
+    assert s.counters()["keys"] == 8
+
+
+def test_set_comparisons(tmpdir):
+    s = build_set(TEST_KEYS)
+    path = str(tmpdir.join("same.fst"))
+    Set.from_iter(TEST_KEYS, path=path)
+    same = Set(path)
+    smaller = build_set(["bar", "foo"])
+    other = build_set(["bar", "qux"])
+    assert s == same and not s != same
+    assert s != smaller and s != other
+    assert s == s.rebuild(add=["x"], remove=["x"])
+    assert smaller <= s and smaller < s and not s < same and s <= same
+    assert s >= smaller and s > smaller and not s > same
+    assert not other <= s and not other >= s
+    assert (s == TEST_KEYS) is False
+    assert s == frozenset(TEST_KEYS) and not s < {"bar"}
+    assert len({s, same, smaller}) == 2
+    assert hash(build_set([])) == hash(Set.empty()) == hash(frozenset())
+    assert hash(s) == hash(frozenset(TEST_KEYS))
+    assert s in {frozenset(TEST_KEYS)} and frozenset(TEST_KEYS) in {s}
+    members = {same}
+    same.close()
+    assert same in members
+    assert s != same and same != s and same == same
+    assert same != frozenset(TEST_KEYS)
+    with pytest.raises(TypeError):
+        s < same
+    closed = build_set(["a"])
+    closed.close()
+    assert hash(closed) == hash(closed)
+
+
+def test_builder_to_file_object():
+    out = io.BytesIO()
+    builder = SetBuilder(out)
+    for key in sorted(TEST_KEYS):
+        builder.insert(key)
+    assert builder.finish() is None
+    assert list(Set(out.getvalue())) == sorted(TEST_KEYS)
+
+    class Chunks:
+        def __init__(self):
+            self.chunks = []
+
+        def write(self, data):
+            self.chunks.append(bytes(data))
+
+    target = Chunks()
+    builder = SetBuilder(target)
+    keys = sorted({"%08x" % (i * 2654435761 % 2**32) for i in range(50000)})
+    for key in keys:
+        builder.insert(key)
+    builder.finish()
+    assert len(target.chunks) > 1
+    assert list(Set(b"".join(target.chunks))) == keys
 
-# def test_intersection():
-#     a = from_iter(["bar", "foo"])
-#     b = from_iter(["baz", "foo"])
-#     assert list(a.intersection(b)) == ["foo"]
 
+def test_builder_to_file_object_errors():
+    class Broken:
+        def write(self, data):
+            raise OSError("disk full")
 
-# Range slicing not supported in Rust implementation
-# def test_range(fst_set):
-#     ...
+    builder = SetBuilder(Broken())
+    builder.insert("foo")
+    with pytest.raises(OSError, match="disk full"):
+        builder.finish()
+    with pytest.raises(TypeError):
+        SetBuilder(42)
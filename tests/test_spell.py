@@ -0,0 +1,68 @@
+# -*- coding: utf-8 -*-
+import pytest
+from rust_fst import Map, MapBuilder, Set, SetBuilder, SpellChecker
+
+WORDS = [(u"bar", 10), (u"baz", 500), (u"bat", 500), (u"foo", 3),
+         (u"möö", 7)]
+
+
+@pytest.fixture
+def word_map():
+    builder = MapBuilder()
+    for word, count in sorted(WORDS):
+        builder.insert(word, count)
+    return builder.finish()
+
+
+@pytest.fixture
+def word_set():
+    builder = SetBuilder()
+    for word, _ in sorted(WORDS):
+        builder.insert(word)
+    return builder.finish()
+
+
+def test_suggest_ranks_by_distance_then_frequency(word_map):
+    checker = SpellChecker(word_map)
+    assert checker.suggest("bar") == [("bar", 0), ("bat", 1), ("baz", 1)]
+    assert checker.suggest("bax", max_dist=1) == [
+        ("bat", 1), ("baz", 1), ("bar", 1)]
+
+
+def test_suggest_set(word_set):
+    checker = SpellChecker(word_set)
+    assert checker.suggest("bax", max_dist=1) == [
+        ("bar", 1), ("bat", 1), ("baz", 1)]
+    assert checker.suggest(u"mö", max_dist=1) == [(u"möö", 1)]
+
+
+def test_suggest_limit(word_map):
+    checker = SpellChecker(word_map)
+    assert checker.suggest("bax", limit=2) == [("bat", 1), ("baz", 1)]
+    assert checker.suggest("qqqqqq", max_dist=1) == []
+
+
+def test_spellchecker_invalid_lexicon(word_map):
+    with pytest.raises(TypeError):
+        SpellChecker({"bar": 1})
+    word_map.close()
+    with pytest.raises(ValueError):
+        SpellChecker(word_map)
+
+
+def test_did_you_mean(word_map):
+    checker = SpellChecker(word_map)
+    assert checker.did_you_mean("bar") == ("bar", 1.0)
+    word, confidence = checker.did_you_mean("fo")
+    assert word == "foo"
+    assert confidence == pytest.approx(2 / 3)
+    word, confidence = checker.did_you_mean("bax")
+    assert word == "bat"
+    assert confidence == pytest.approx(2 / 3)
+    assert checker.did_you_mean("bqqx", max_dist=1) is None
+
+
+def test_did_you_mean_prefers_frequent(word_map):
+    checker = SpellChecker(word_map)
+    assert checker.did_you_mean("ba")[0] == "bat"
+    assert checker.did_you_mean("bxx")[0] == "bat"